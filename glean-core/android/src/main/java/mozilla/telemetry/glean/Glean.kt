@@ -264,6 +264,8 @@ open class GleanInternalAPI internal constructor() {
                 trimDataToRegisteredPings = false,
                 logLevel = configuration.logLevel,
                 rateLimit = null,
+                uploadBackoff = null,
+                maxQuarantinedPings = null,
                 enableEventTimestamps = configuration.enableEventTimestamps,
                 experimentationId = configuration.experimentationId,
                 enableInternalPings = configuration.enableInternalPings,
@@ -276,6 +278,11 @@ open class GleanInternalAPI internal constructor() {
                 sessionSampleRate = configuration.sessionSampleRate,
                 sessionInactivityTimeoutMs = configuration.sessionInactivityTimeoutMs.toULong(),
                 eventsPingAccelerationFactor = null,
+                encryptionKeyProvider = configuration.encryptionKeyProvider,
+                maxDatabaseSize = configuration.maxDatabaseSize?.toULong(),
+                inMemory = configuration.inMemory,
+                databaseMaintenanceIntervalMs = configuration.databaseMaintenanceIntervalMs?.toULong(),
+                metricsPingSchedule = null,
             )
             val clientInfo = getClientInfo(configuration, buildInfo)
             val callbacks = OnGleanEventsImpl(this@GleanInternalAPI)
@@ -545,6 +552,21 @@ open class GleanInternalAPI internal constructor() {
      */
     fun getRegisteredPingNames(): Set<String> = gleanGetRegisteredPingNames().toSet()
 
+    /**
+     * Assemble the payload a ping would have if it was submitted now, without submitting it.
+     *
+     * The ping's data is kept and its sequence number is not advanced,
+     * so the next submitted ping contains everything returned here.
+     * Use this to show users what will be sent, e.g. in a "view my data" page.
+     *
+     * **WARNING** This function blocks until previously recorded data is stored.
+     *
+     * @param pingName Name of the ping to preview.
+     * @return The ping payload as JSON, or `null` if the ping is unknown, disabled or
+     *         wouldn't be sent because it has no data.
+     */
+    fun previewPing(pingName: String): String? = gleanPreviewPing(pingName)
+
     /**
      * Set a tag to be applied to headers when uploading pings for debug view.
      *
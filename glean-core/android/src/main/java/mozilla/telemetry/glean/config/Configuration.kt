@@ -4,6 +4,7 @@
 
 package mozilla.telemetry.glean.config
 
+import mozilla.telemetry.glean.internal.EncryptionKeyProvider
 import mozilla.telemetry.glean.internal.LevelFilter
 import mozilla.telemetry.glean.internal.SessionMode
 import mozilla.telemetry.glean.net.HttpURLConnectionUploader
@@ -40,6 +41,14 @@ import mozilla.telemetry.glean.net.PingUploader
  * @property sessionInactivityTimeoutMs Inactivity timeout (milliseconds) before AUTO-mode
  *           sessions expire. Default: 30 minutes.
  * @property eventsPingAccelerationFactor The number of "events" pings to accelerate each session, plus one.
+ * @property encryptionKeyProvider Supplies the key to encrypt all data at rest with.
+ *           Once data was encrypted, Glean fails to initialize without the same key.
+ * @property maxDatabaseSize The maximum size in bytes of the stored metrics.
+ *           When exceeded, metrics are evicted, ping-lifetime metrics with many labels first.
+ * @property inMemory Whether to keep all data in memory only, without touching the data directory.
+ *           Everything is dropped when the process ends, unless backed up before.
+ * @property databaseMaintenanceIntervalMs The interval in milliseconds at which to run database
+ *           maintenance while running. It always runs when the client becomes inactive.
  */
 data class Configuration
     @JvmOverloads
@@ -66,6 +75,10 @@ data class Configuration
         val sessionSampleRate: Double = 1.0,
         val sessionInactivityTimeoutMs: Long = DEFAULT_SESSION_INACTIVITY_TIMEOUT_MS,
         val eventsPingAccelerationFactor: Int? = null,
+        val encryptionKeyProvider: EncryptionKeyProvider? = null,
+        val maxDatabaseSize: Long? = null,
+        val inMemory: Boolean = false,
+        val databaseMaintenanceIntervalMs: Long? = null,
     ) {
         companion object {
             /**
@@ -8,6 +8,7 @@ typealias PingUploadTask = mozilla.telemetry.glean.internal.PingUploadTask
 typealias PingRequest = mozilla.telemetry.glean.internal.PingRequest
 typealias UploadResult = mozilla.telemetry.glean.internal.UploadResult
 typealias HttpStatus = mozilla.telemetry.glean.internal.UploadResult.HttpStatus
+typealias HttpStatusWithRetryAfter = mozilla.telemetry.glean.internal.UploadResult.HttpStatusWithRetryAfter
 typealias UnrecoverableFailure = mozilla.telemetry.glean.internal.UploadResult.UnrecoverableFailure
 typealias RecoverableFailure = mozilla.telemetry.glean.internal.UploadResult.RecoverableFailure
 typealias Incapable = mozilla.telemetry.glean.internal.UploadResult.Incapable
@@ -6,6 +6,9 @@ package mozilla.telemetry.glean.private
 
 import androidx.annotation.VisibleForTesting
 import mozilla.telemetry.glean.Dispatchers
+import mozilla.telemetry.glean.internal.PingCapacity
+import mozilla.telemetry.glean.internal.PingPriority
+import mozilla.telemetry.glean.internal.PingSchedule
 import java.util.concurrent.LinkedBlockingQueue
 import java.util.concurrent.TimeUnit
 import mozilla.telemetry.glean.internal.PingType as GleanPingType
@@ -181,4 +184,46 @@ class PingType<ReasonCodesEnum>(
             this.innerPing.setEnabled(enabled)
         }
     }
+
+    /**
+     * Set the priority this ping is uploaded with.
+     *
+     * Pending pings of a higher priority are uploaded first,
+     * and pings of the lowest priority are the first to be deleted
+     * when the pending pings quota is hit.
+     * Only affects pings submitted afterwards.
+     */
+    fun setPriority(priority: PingPriority) {
+        Dispatchers.Delayed.launch {
+            this.innerPing.setPriority(priority)
+        }
+    }
+
+    /**
+     * Set the thresholds at which this ping is submitted automatically,
+     * with the reason `max_capacity`.
+     *
+     * Only events and metrics with a `ping` lifetime count towards them.
+     *
+     * @param capacity The thresholds, or `null` to never submit this ping automatically.
+     */
+    fun setCapacity(capacity: PingCapacity?) {
+        Dispatchers.Delayed.launch {
+            this.innerPing.setCapacity(capacity)
+        }
+    }
+
+    /**
+     * Set the schedule this ping is submitted on, replacing any previous schedule.
+     *
+     * If a scheduled time passed since the ping was last submitted on its schedule,
+     * it is submitted right away with the reason `overdue`.
+     *
+     * @param schedule The schedule, or `null` to stop submitting the ping on a schedule.
+     */
+    fun setSchedule(schedule: PingSchedule?) {
+        Dispatchers.Delayed.launch {
+            this.innerPing.setSchedule(schedule)
+        }
+    }
 }
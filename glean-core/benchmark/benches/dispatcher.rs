@@ -77,6 +77,8 @@ pub fn metric_dispatcher_benchmark(c: &mut Criterion) {
         trim_data_to_registered_pings: true,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: None,
         enable_internal_pings: false,
@@ -89,6 +91,11 @@ pub fn metric_dispatcher_benchmark(c: &mut Criterion) {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
     let client_info = ClientInfoMetrics::unknown();
 
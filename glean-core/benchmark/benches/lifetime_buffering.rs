@@ -25,6 +25,8 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             trim_data_to_registered_pings: true,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: true,
             experimentation_id: None,
             enable_internal_pings: true,
@@ -37,6 +39,11 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             session_sample_rate: 1.0,
             session_inactivity_timeout_ms: 1_800_000,
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval_ms: None,
+            metrics_ping_schedule: None,
         };
         let glean = Glean::new(cfg).unwrap();
 
@@ -73,6 +80,8 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             trim_data_to_registered_pings: true,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: true,
             experimentation_id: None,
             enable_internal_pings: true,
@@ -85,6 +94,11 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             session_sample_rate: 1.0,
             session_inactivity_timeout_ms: 1_800_000,
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval_ms: None,
+            metrics_ping_schedule: None,
         };
         let glean = Glean::new(cfg).unwrap();
 
@@ -121,6 +135,8 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             trim_data_to_registered_pings: true,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: true,
             experimentation_id: None,
             enable_internal_pings: true,
@@ -133,6 +149,11 @@ pub fn delay_io_benchmark(c: &mut Criterion) {
             session_sample_rate: 1.0,
             session_inactivity_timeout_ms: 1_800_000,
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval_ms: None,
+            metrics_ping_schedule: None,
         };
         let glean = Glean::new(cfg).unwrap();
 
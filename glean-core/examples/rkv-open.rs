@@ -45,6 +45,8 @@ fn main() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: None,
         enable_internal_pings: true,
@@ -57,6 +59,11 @@ fn main() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
 
     let client_info = ClientInfoMetrics::unknown();
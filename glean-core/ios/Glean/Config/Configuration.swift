@@ -23,6 +23,10 @@ public struct Configuration {
     let maxPendingPingsCount: UInt64?
     let maxPendingPingsDirectorySize: UInt64?
     let eventsPingAccelerationFactor: UInt32?
+    let encryptionKeyProvider: EncryptionKeyProvider?
+    let maxDatabaseSize: UInt64?
+    let inMemory: Bool
+    let databaseMaintenanceIntervalMs: UInt64?
 
     struct Constants {
         static let defaultTelemetryEndpoint =
@@ -59,6 +63,14 @@ public struct Configuration {
     ///   * httpClient An http uploader that supports the `PingUploader` protocol
     ///   * eventsPingAccelerationFactor The number of "events" pings to accelerate each session,
     ///     plus one.
+    ///   * encryptionKeyProvider Supplies the key to encrypt all data at rest with.
+    ///   Once data was encrypted, Glean fails to initialize without the same key.
+    ///   * maxDatabaseSize The maximum size in bytes of the stored metrics.
+    ///   When exceeded, metrics are evicted, ping-lifetime metrics with many labels first.
+    ///   * inMemory Whether to keep all data in memory only, without touching the data directory.
+    ///   Everything is dropped when the process ends, unless backed up before.
+    ///   * databaseMaintenanceIntervalMs The interval in milliseconds at which to run database
+    ///   maintenance while running. It always runs when the client becomes inactive.
     public init(
         maxEvents: Int32? = nil,
         channel: String? = nil,
@@ -78,6 +90,10 @@ public struct Configuration {
         sessionInactivityTimeoutMs: UInt64 = 1_800_000,
         httpClient: PingUploader = HttpPingUploader(),
         eventsPingAccelerationFactor: UInt32? = nil,
+        encryptionKeyProvider: EncryptionKeyProvider? = nil,
+        maxDatabaseSize: UInt64? = nil,
+        inMemory: Bool = false,
+        databaseMaintenanceIntervalMs: UInt64? = nil,
     ) {
         self.serverEndpoint =
             serverEndpoint ?? Constants.defaultTelemetryEndpoint
@@ -98,5 +114,9 @@ public struct Configuration {
         self.sessionInactivityTimeoutMs = sessionInactivityTimeoutMs
         self.httpClient = httpClient
         self.eventsPingAccelerationFactor = eventsPingAccelerationFactor
+        self.encryptionKeyProvider = encryptionKeyProvider
+        self.maxDatabaseSize = maxDatabaseSize
+        self.inMemory = inMemory
+        self.databaseMaintenanceIntervalMs = databaseMaintenanceIntervalMs
     }
 }
@@ -207,6 +207,8 @@ public final class Glean: @unchecked Sendable {
             trimDataToRegisteredPings: false,
             logLevel: configuration.logLevel,
             rateLimit: nil,
+            uploadBackoff: nil,
+            maxQuarantinedPings: nil,
             enableEventTimestamps: configuration.enableEventTimestamps,
             experimentationId: configuration.experimentationId,
             enableInternalPings: configuration.enableInternalPings,
@@ -218,7 +220,12 @@ public final class Glean: @unchecked Sendable {
             sessionMode: configuration.sessionMode,
             sessionSampleRate: configuration.sessionSampleRate,
             sessionInactivityTimeoutMs: configuration.sessionInactivityTimeoutMs,
-            eventsPingAccelerationFactor: configuration.eventsPingAccelerationFactor
+            eventsPingAccelerationFactor: configuration.eventsPingAccelerationFactor,
+            encryptionKeyProvider: configuration.encryptionKeyProvider,
+            maxDatabaseSize: configuration.maxDatabaseSize,
+            inMemory: configuration.inMemory,
+            databaseMaintenanceIntervalMs: configuration.databaseMaintenanceIntervalMs,
+            metricsPingSchedule: nil
         )
         let clientInfo = getClientInfo(configuration, buildInfo: buildInfo)
         let callbacks = OnGleanEventsImpl(glean: self)
@@ -421,6 +428,23 @@ public final class Glean: @unchecked Sendable {
         return Set(gleanGetRegisteredPingNames())
     }
 
+    /// Assemble the payload a ping would have if it was submitted now, without submitting it.
+    ///
+    /// The ping's data is kept and its sequence number is not advanced,
+    /// so the next submitted ping contains everything returned here.
+    /// Use this to show users what will be sent, e.g. in a "view my data" page.
+    ///
+    /// **WARNING** This function blocks until previously recorded data is stored.
+    ///
+    /// - parameters:
+    ///     * pingName: Name of the ping to preview.
+    ///
+    /// - returns: The ping payload as JSON, or `nil` if the ping is unknown, disabled or
+    ///            wouldn't be sent because it has no data.
+    public func previewPing(_ pingName: String) -> String? {
+        return gleanPreviewPing(pingName)
+    }
+
     /// Register the pings generated from `pings.yaml` with the Glean SDK.
     ///
     /// - parameters:
@@ -110,4 +110,36 @@ public final class Ping<ReasonCodesEnum: ReasonCodes>: @unchecked Sendable {
     public func setEnabled(enabled: Bool) {
         innerPing.setEnabled(enabled)
     }
+
+    /// Set the priority this ping is uploaded with.
+    ///
+    /// Pending pings of a higher priority are uploaded first,
+    /// and pings of the lowest priority are the first to be deleted
+    /// when the pending pings quota is hit.
+    /// Only affects pings submitted afterwards.
+    public func setPriority(priority: PingPriority) {
+        innerPing.setPriority(priority)
+    }
+
+    /// Set the thresholds at which this ping is submitted automatically,
+    /// with the reason `max_capacity`.
+    ///
+    /// Only events and metrics with a `ping` lifetime count towards them.
+    ///
+    /// - parameters:
+    ///     * capacity: The thresholds, or `nil` to never submit this ping automatically.
+    public func setCapacity(_ capacity: PingCapacity?) {
+        innerPing.setCapacity(capacity)
+    }
+
+    /// Set the schedule this ping is submitted on, replacing any previous schedule.
+    ///
+    /// If a scheduled time passed since the ping was last submitted on its schedule,
+    /// it is submitted right away with the reason `overdue`.
+    ///
+    /// - parameters:
+    ///     * schedule: The schedule, or `nil` to stop submitting the ping on a schedule.
+    public func setSchedule(_ schedule: PingSchedule?) {
+        innerPing.setSchedule(schedule)
+    }
 }
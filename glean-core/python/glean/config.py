@@ -46,6 +46,7 @@ class Configuration:
         session_sample_rate: float = 1.0,
         session_inactivity_timeout_ms: int = DEFAULT_SESSION_INACTIVITY_TIMEOUT_MS,
         events_ping_acceleration_factor: Optional[int] = None,
+        max_database_size: Optional[int] = None,
     ):
         """
         Args:
@@ -78,6 +79,9 @@ class Configuration:
                 before AUTO-mode sessions expire. Default: 30 minutes.
             events_ping_acceleration_factor (int): Optional.
                 The number of "events" pings to accelerate each session, plus one.
+            max_database_size (int): Optional. The maximum size in bytes of the
+                stored metrics. When exceeded, metrics are evicted, ping-lifetime
+                metrics with many labels first. Unbounded by default.
         """
         if server_endpoint is None:
             server_endpoint = DEFAULT_TELEMETRY_ENDPOINT
@@ -97,6 +101,7 @@ class Configuration:
         self._session_sample_rate = session_sample_rate
         self._session_inactivity_timeout_ms = session_inactivity_timeout_ms
         self._events_ping_acceleration_factor = events_ping_acceleration_factor
+        self._max_database_size = max_database_size
 
     @property
     def server_endpoint(self) -> str:
@@ -181,5 +186,10 @@ class Configuration:
         """The number of "events" pings to accelerate each session, plus one."""
         return self._events_ping_acceleration_factor
 
+    @property
+    def max_database_size(self) -> Optional[int]:
+        """The maximum size in bytes of the stored metrics."""
+        return self._max_database_size
+
 
 __all__ = ["Configuration"]
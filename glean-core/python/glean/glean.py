@@ -232,6 +232,8 @@ class Glean:
             trim_data_to_registered_pings=False,
             log_level=None,
             rate_limit=None,
+            upload_backoff=None,
+            max_quarantined_pings=None,
             enable_event_timestamps=configuration.enable_event_timestamps,
             experimentation_id=configuration.experimentation_id,
             enable_internal_pings=configuration.enable_internal_pings,
@@ -244,6 +246,12 @@ class Glean:
             session_sample_rate=configuration.session_sample_rate,
             session_inactivity_timeout_ms=configuration.session_inactivity_timeout_ms,
             events_ping_acceleration_factor=configuration.events_ping_acceleration_factor,
+            encryption_key_provider=None,
+            max_database_size=configuration.max_database_size,
+            # The upload worker process reads pending pings from disk.
+            in_memory=False,
+            database_maintenance_interval_ms=None,
+            metrics_ping_schedule=None,
         )
 
         _uniffi.glean_initialize(cfg, client_info, callbacks)
@@ -6,6 +6,7 @@
 from typing import Callable, List, Optional
 
 
+from .._uniffi import PingPriority
 from .._uniffi import PingType as GleanPingType
 
 
@@ -86,3 +87,14 @@ class PingType:
         and all pending pings of that type to be deleted.
         """
         self._inner.set_enabled(enabled)
+
+    def set_priority(self, priority: PingPriority) -> None:
+        """
+        Set the priority this ping is uploaded with.
+
+        Pending pings of a higher priority are uploaded first,
+        and pings of the lowest priority are the first to be deleted
+        when the pending pings quota is hit.
+        Only affects pings submitted afterwards.
+        """
+        self._inner.set_priority(priority)
@@ -122,6 +122,8 @@ def _process(data_dir: Path, application_id: str, configuration) -> bool:
             trim_data_to_registered_pings=False,
             log_level=None,
             rate_limit=None,
+            upload_backoff=None,
+            max_quarantined_pings=None,
             enable_event_timestamps=False,
             experimentation_id=None,
             enable_internal_pings=False,
@@ -134,6 +136,11 @@ def _process(data_dir: Path, application_id: str, configuration) -> bool:
             session_sample_rate=1.0,
             session_inactivity_timeout_ms=1_800_000,
             events_ping_acceleration_factor=configuration.events_ping_acceleration_factor,
+            encryption_key_provider=None,
+            max_database_size=None,
+            in_memory=False,
+            database_maintenance_interval_ms=None,
+            metrics_ping_schedule=None,
         )
         if not glean_initialize_for_subprocess(cfg):
             log.error("Couldn't initialize Glean in subprocess")
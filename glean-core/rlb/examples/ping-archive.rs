@@ -0,0 +1,92 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Move pending pings off a machine that can't upload them.
+//!
+//! Export all pending pings of a Glean data directory into an archive.
+//! Exported pings are deleted from the data directory.
+//! The application should not be running while exporting.
+//!
+//! ```no_rust
+//! cargo run -p glean --features http-uploader --example ping-archive -- export <data-path> <archive> [<key-file>]
+//! ```
+//!
+//! A data directory encrypted at rest can only be exported with its key,
+//! the 32 bytes in `<key-file>`.
+//!
+//! Upload the pings from an archive, elsewhere.
+//! Pings that should be retried are kept in the archive, run again to retry them.
+//!
+//! ```no_rust
+//! cargo run -p glean --features http-uploader --example ping-archive -- upload <archive> [<server-endpoint>]
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use glean::net::{replay_archive, HttpUploader};
+use glean_core::EncryptionKeyProvider;
+
+const DEFAULT_SERVER_ENDPOINT: &str = "https://incoming.telemetry.mozilla.org";
+
+/// The key of an encrypted data directory, read from a file.
+struct KeyFile(Vec<u8>);
+
+impl EncryptionKeyProvider for KeyFile {
+    fn get_key(&self) -> Option<Vec<u8>> {
+        Some(self.0.clone())
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage:");
+    eprintln!("  ping-archive export <data-path> <archive> [<key-file>]");
+    eprintln!("  ping-archive upload <archive> [<server-endpoint>]");
+    process::exit(2);
+}
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["export", data_path, archive, ref key_file @ ..] if key_file.len() <= 1 => {
+            let key = key_file.first().map(|key_file| match fs::read(key_file) {
+                Ok(key) => KeyFile(key),
+                Err(e) => {
+                    eprintln!("Failed to read the key from {}: {}", key_file, e);
+                    process::exit(1);
+                }
+            });
+            match glean_core::upload::export_pending_pings(
+                Path::new(data_path),
+                Path::new(archive),
+                key.as_ref().map(|key| key as &dyn EncryptionKeyProvider),
+            ) {
+                Ok(exported) => println!("Exported {} pings to {}.", exported.len(), archive),
+                Err(e) => {
+                    eprintln!("Failed to export pings: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        ["upload", archive, ref endpoint @ ..] if endpoint.len() <= 1 => {
+            let endpoint = endpoint.first().copied().unwrap_or(DEFAULT_SERVER_ENDPOINT);
+            match replay_archive(Path::new(archive), endpoint, &HttpUploader::new()) {
+                Ok(summary) => println!(
+                    "Uploaded {} pings, dropped {}, {} left to retry.",
+                    summary.uploaded, summary.failed, summary.remaining
+                ),
+                Err(e) => {
+                    eprintln!("Failed to upload pings: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        _ => usage(),
+    }
+}
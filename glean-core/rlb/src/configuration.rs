@@ -5,10 +5,13 @@
 use log::LevelFilter;
 
 use crate::net::PingUploader;
-use crate::SessionMode;
+#[cfg(feature = "async-uploader")]
+use crate::net::{AsyncPingUploader, AsyncUploader};
+use crate::{EncryptionKeyProvider, SessionMode};
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// The default server pings are sent to.
@@ -33,6 +36,10 @@ pub struct Configuration {
     pub server_endpoint: Option<String>,
     /// The instance of the uploader used to send pings.
     pub uploader: Option<Box<dyn PingUploader + 'static>>,
+    /// The async uploader used to send pings.
+    /// Takes precedence over `uploader`.
+    #[cfg(feature = "async-uploader")]
+    pub async_uploader: Option<AsyncUploader>,
     /// Whether Glean should schedule "metrics" pings for you.
     pub use_core_mps: bool,
     /// Whether Glean should limit its storage to only that of registered pings.
@@ -43,6 +50,11 @@ pub struct Configuration {
     pub log_level: Option<LevelFilter>,
     /// The rate pings may be uploaded before they are throttled.
     pub rate_limit: Option<crate::PingRateLimit>,
+    /// How to back off from uploading a ping again after a recoverable upload failure.
+    /// Pings are retried without delay when not set.
+    pub upload_backoff: Option<crate::UploadBackoff>,
+    /// How many pings that permanently failed to upload to keep for diagnosis.
+    pub max_quarantined_pings: Option<u32>,
     /// Whether to add a wallclock timestamp to all events.
     pub enable_event_timestamps: bool,
     /// An experimentation identifier derived by the application to be sent with all pings, it should
@@ -67,6 +79,16 @@ pub struct Configuration {
     pub session_inactivity_timeout: Duration,
     /// The number of "events" pings to accelerate each session, plus one.
     pub events_ping_acceleration_factor: Option<usize>,
+    /// Supplies the key to encrypt all data at rest with. Requires the `encryption` feature.
+    pub encryption_key_provider: Option<Arc<dyn EncryptionKeyProvider>>,
+    /// Maximum size in bytes of the stored metrics, evicting metrics when exceeded.
+    pub max_database_size: Option<u64>,
+    /// Whether to keep all data in memory only, dropping it on shutdown.
+    pub in_memory: bool,
+    /// The interval at which to run database maintenance while running.
+    pub database_maintenance_interval: Option<Duration>,
+    /// When to schedule the "metrics" ping.
+    pub metrics_ping_schedule: Option<crate::MetricsPingSchedule>,
 }
 
 /// Configuration builder.
@@ -93,6 +115,11 @@ pub struct Builder {
     /// Optional: The instance of the uploader used to send pings.
     /// Default: `None`
     pub uploader: Option<Box<dyn PingUploader + 'static>>,
+    /// Optional: The async uploader used to send pings.
+    /// Takes precedence over `uploader`.
+    /// Default: `None`
+    #[cfg(feature = "async-uploader")]
+    pub async_uploader: Option<AsyncUploader>,
     /// Optional: Whether Glean should schedule "metrics" pings for you.
     /// Default: `false`
     pub use_core_mps: bool,
@@ -107,6 +134,12 @@ pub struct Builder {
     /// Optional: The internal ping upload rate limit.
     /// Default: `None`
     pub rate_limit: Option<crate::PingRateLimit>,
+    /// Optional: The backoff schedule for retrying pings after a recoverable upload failure.
+    /// Default: `None` (retry without delay)
+    pub upload_backoff: Option<crate::UploadBackoff>,
+    /// Optional: How many pings that permanently failed to upload to keep for diagnosis.
+    /// Default: `None` (such pings are deleted)
+    pub max_quarantined_pings: Option<u32>,
     /// Whether to add a wallclock timestamp to all events.
     pub enable_event_timestamps: bool,
     /// An experimentation identifier derived by the application to be sent with all pings, it should
@@ -131,6 +164,22 @@ pub struct Builder {
     pub session_inactivity_timeout: Duration,
     /// The number of "events" pings to accelerate each session, plus one.
     pub events_ping_acceleration_factor: Option<usize>,
+    /// Optional: Supplies the key to encrypt all data at rest with.
+    /// Requires the `encryption` feature.
+    /// Default: `None` (data is stored in plaintext)
+    pub encryption_key_provider: Option<Arc<dyn EncryptionKeyProvider>>,
+    /// Optional: Maximum size in bytes of the stored metrics, evicting metrics when exceeded.
+    /// Default: `None` (unbounded)
+    pub max_database_size: Option<u64>,
+    /// Optional: Whether to keep all data in memory only, dropping it on shutdown.
+    /// Default: `false`
+    pub in_memory: bool,
+    /// Optional: The interval at which to run database maintenance while running.
+    /// Default: `None` (only when the client becomes inactive)
+    pub database_maintenance_interval: Option<Duration>,
+    /// Optional: When to schedule the "metrics" ping.
+    /// Default: `None` (daily at 04:00 local time)
+    pub metrics_ping_schedule: Option<crate::MetricsPingSchedule>,
 }
 
 impl Builder {
@@ -148,10 +197,14 @@ impl Builder {
             delay_ping_lifetime_io: false,
             server_endpoint: None,
             uploader: None,
+            #[cfg(feature = "async-uploader")]
+            async_uploader: None,
             use_core_mps: false,
             trim_data_to_registered_pings: false,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: true,
             experimentation_id: None,
             enable_internal_pings: true,
@@ -162,6 +215,11 @@ impl Builder {
             session_sample_rate: 1.0,
             session_inactivity_timeout: Duration::from_secs(30 * 60),
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval: None,
+            metrics_ping_schedule: None,
         }
     }
 
@@ -175,10 +233,14 @@ impl Builder {
             delay_ping_lifetime_io: self.delay_ping_lifetime_io,
             server_endpoint: self.server_endpoint,
             uploader: self.uploader,
+            #[cfg(feature = "async-uploader")]
+            async_uploader: self.async_uploader,
             use_core_mps: self.use_core_mps,
             trim_data_to_registered_pings: self.trim_data_to_registered_pings,
             log_level: self.log_level,
             rate_limit: self.rate_limit,
+            upload_backoff: self.upload_backoff,
+            max_quarantined_pings: self.max_quarantined_pings,
             enable_event_timestamps: self.enable_event_timestamps,
             experimentation_id: self.experimentation_id,
             enable_internal_pings: self.enable_internal_pings,
@@ -189,6 +251,11 @@ impl Builder {
             session_sample_rate: self.session_sample_rate,
             session_inactivity_timeout: self.session_inactivity_timeout,
             events_ping_acceleration_factor: self.events_ping_acceleration_factor,
+            encryption_key_provider: self.encryption_key_provider,
+            max_database_size: self.max_database_size,
+            in_memory: self.in_memory,
+            database_maintenance_interval: self.database_maintenance_interval,
+            metrics_ping_schedule: self.metrics_ping_schedule,
         }
     }
 
@@ -234,6 +301,20 @@ impl Builder {
         self
     }
 
+    /// Set the async uploader used to send pings.
+    ///
+    /// Uploads are driven by a task on the given tokio runtime
+    /// instead of a dedicated uploader thread.
+    #[cfg(feature = "async-uploader")]
+    pub fn with_async_uploader<U: AsyncPingUploader + 'static>(
+        mut self,
+        uploader: U,
+        runtime: tokio::runtime::Handle,
+    ) -> Self {
+        self.async_uploader = Some(AsyncUploader::new(uploader, runtime));
+        self
+    }
+
     /// Set whether Glean should schedule "metrics" pings for you.
     pub fn with_use_core_mps(mut self, value: bool) -> Self {
         self.use_core_mps = value;
@@ -252,6 +333,23 @@ impl Builder {
         self
     }
 
+    /// Set how to back off from uploading a ping again after a recoverable upload failure.
+    ///
+    /// Pings are retried without delay unless this is set,
+    /// [`UploadBackoff::default`](crate::UploadBackoff::default) is a reasonable schedule.
+    pub fn with_upload_backoff(mut self, backoff: crate::UploadBackoff) -> Self {
+        self.upload_backoff = Some(backoff);
+        self
+    }
+
+    /// Set how many pings that permanently failed to upload to keep for diagnosis.
+    ///
+    /// See [`quarantined_pings`](crate::quarantined_pings).
+    pub fn with_max_quarantined_pings(mut self, max_count: u32) -> Self {
+        self.max_quarantined_pings = Some(max_count);
+        self
+    }
+
     /// Set whether to add a wallclock timestamp to all events (experimental).
     pub fn with_event_timestamps(mut self, value: bool) -> Self {
         self.enable_event_timestamps = value;
@@ -293,4 +391,55 @@ impl Builder {
         self.events_ping_acceleration_factor = Some(factor);
         self
     }
+
+    /// Encrypt all data at rest with the key supplied by the given provider.
+    ///
+    /// Requires the `encryption` feature, Glean fails to initialize otherwise.
+    /// Once data was encrypted, Glean fails to initialize without the same key.
+    pub fn with_encryption_key_provider<P>(mut self, provider: P) -> Self
+    where
+        P: EncryptionKeyProvider + 'static,
+    {
+        self.encryption_key_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set the maximum size in bytes of the stored metrics.
+    ///
+    /// When exceeded, metrics are evicted, ping-lifetime metrics with many labels first,
+    /// and an `invalid_overflow` error is recorded for them.
+    pub fn with_max_database_size(mut self, value: u64) -> Self {
+        self.max_database_size = Some(value);
+        self
+    }
+
+    /// Keep all data in memory only: the database, events and pending pings.
+    ///
+    /// Nothing is read from or written to the data path.
+    /// Everything is dropped on shutdown, unless exported with [`crate::backup`] before.
+    pub fn with_in_memory(mut self, value: bool) -> Self {
+        self.in_memory = value;
+        self
+    }
+
+    /// Run database maintenance at this interval while running.
+    ///
+    /// Maintenance vacuums free pages, optimizes and truncates the write-ahead log.
+    /// It always runs when the client becomes inactive.
+    /// Initialization and shutdown only run a partial vacuum and a passive checkpoint.
+    /// Maintenance runs on Glean's dispatcher and delays the tasks queued after it,
+    /// e.g. recording metrics, which takes longer the more free pages there are to vacuum.
+    pub fn with_database_maintenance_interval(mut self, value: Duration) -> Self {
+        self.database_maintenance_interval = Some(value);
+        self
+    }
+
+    /// Set when to schedule the "metrics" ping: the hour, optionally in a fixed timezone,
+    /// and a window to randomly spread submissions over.
+    ///
+    /// The Server Knobs can override it with `metrics_ping_schedule`.
+    pub fn with_metrics_ping_schedule(mut self, schedule: crate::MetricsPingSchedule) -> Self {
+        self.metrics_ping_schedule = Some(schedule);
+        self
+    }
 }
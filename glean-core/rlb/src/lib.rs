@@ -36,12 +36,14 @@ pub use configuration::{Builder as ConfigurationBuilder, Configuration};
 pub use core_metrics::ClientInfoMetrics;
 pub use glean_core::{
     metrics::{
-        Datetime, DistributionData, MemoryUnit, MetricIdentifier, Rate, RecordedEvent,
-        TestGetValue, TimeUnit, TimerId,
+        Datetime, DistributionData, InvalidScheduleError, MemoryUnit, MetricIdentifier,
+        PingCapacity, PingPriority, PingSchedule, Rate, RecordedEvent, TestGetValue, TimeUnit,
+        TimerId,
     },
-    traits, AttributionMetrics, CommonMetricData, DistributionMetrics, Error, ErrorType, Glean,
-    HistogramType, LabeledMetricData, Lifetime, PingRateLimit, RecordedExperiment, Result,
-    SessionMode,
+    traits, AttributionMetrics, CommonMetricData, ContentEncoding, DistributionMetrics,
+    EncryptionError, EncryptionKeyProvider, Error, ErrorType, Glean, HistogramType,
+    LabeledMetricData, Lifetime, MetricsPingSchedule, NetworkType, PingRateLimit, QuarantinedPing,
+    RecordedExperiment, Result, SessionMode, UploadBackoff, UploadConstraints, UploadWindow,
 };
 
 mod configuration;
@@ -53,7 +55,7 @@ mod system;
 #[cfg(test)]
 mod common_test;
 
-const LANGUAGE_BINDING_NAME: &str = "Rust";
+pub(crate) const LANGUAGE_BINDING_NAME: &str = "Rust";
 
 /// Creates and initializes a new Glean object.
 ///
@@ -102,13 +104,28 @@ impl glean_core::OnGleanEvents for GleanEvents {
 
 fn initialize_internal(cfg: Configuration, client_info: ClientInfoMetrics) -> Option<()> {
     // Initialize the ping uploader.
+    let server_endpoint = cfg
+        .server_endpoint
+        .unwrap_or_else(|| DEFAULT_GLEAN_ENDPOINT.to_string());
+    #[cfg(feature = "async-uploader")]
+    let upload_manager = match cfg.async_uploader {
+        Some(uploader) => net::UploadManager::new_async(server_endpoint, uploader),
+        None => net::UploadManager::new(
+            server_endpoint,
+            cfg.uploader.unwrap_or_else(|| {
+                Box::new(net::HttpUploader::new()) as Box<dyn net::PingUploader>
+            }),
+        ),
+    };
+    #[cfg(not(feature = "async-uploader"))]
     let upload_manager = net::UploadManager::new(
-        cfg.server_endpoint
-            .unwrap_or_else(|| DEFAULT_GLEAN_ENDPOINT.to_string()),
+        server_endpoint,
         cfg.uploader
-            .unwrap_or_else(|| Box::new(net::HttpUploader) as Box<dyn net::PingUploader>),
+            .unwrap_or_else(|| Box::new(net::HttpUploader::new()) as Box<dyn net::PingUploader>),
     );
 
+    let content_encodings = upload_manager.content_encodings();
+
     // Now make this the global object available to others.
     let callbacks = Box::new(GleanEvents { upload_manager });
 
@@ -124,6 +141,8 @@ fn initialize_internal(cfg: Configuration, client_info: ClientInfoMetrics) -> Op
         trim_data_to_registered_pings: cfg.trim_data_to_registered_pings,
         log_level: cfg.log_level,
         rate_limit: cfg.rate_limit,
+        upload_backoff: cfg.upload_backoff,
+        max_quarantined_pings: cfg.max_quarantined_pings,
         enable_event_timestamps: cfg.enable_event_timestamps,
         experimentation_id: cfg.experimentation_id,
         enable_internal_pings: cfg.enable_internal_pings,
@@ -136,9 +155,19 @@ fn initialize_internal(cfg: Configuration, client_info: ClientInfoMetrics) -> Op
         session_sample_rate: cfg.session_sample_rate,
         session_inactivity_timeout_ms: cfg.session_inactivity_timeout.as_millis() as u64,
         events_ping_acceleration_factor: cfg.events_ping_acceleration_factor.map(|x| x as u32),
+        encryption_key_provider: cfg.encryption_key_provider,
+        max_database_size: cfg.max_database_size,
+        in_memory: cfg.in_memory,
+        database_maintenance_interval_ms: cfg
+            .database_maintenance_interval
+            .map(|interval| interval.as_millis() as u64),
+        metrics_ping_schedule: cfg.metrics_ping_schedule,
     };
 
     glean_core::glean_initialize(core_cfg, client_info.into(), callbacks);
+    if !content_encodings.is_empty() {
+        glean_core::glean_set_content_encodings(content_encodings);
+    }
     Some(())
 }
 
@@ -192,6 +221,20 @@ pub fn submit_ping_by_name(ping: &str, reason: Option<&str>) {
     glean_core::glean_submit_ping_by_name(ping, reason)
 }
 
+/// Assembles the payload a ping would have if it was submitted now, without submitting it.
+///
+/// The ping's data is kept and its sequence number is not advanced,
+/// so this can be used to show what will be sent, e.g. in a "view my data" page.
+///
+/// See [`glean_core::Glean::preview_ping`].
+///
+/// # Returns
+///
+/// The ping payload as pretty-printed JSON, or `None` if there is nothing to send.
+pub fn preview_ping(ping: &str) -> Option<String> {
+    glean_core::glean_preview_ping(ping.to_string())
+}
+
 /// Indicate that an experiment is running.  Glean will then add an
 /// experiment annotation to the environment which is sent with pings. This
 /// infomration is not persisted between runs.
@@ -322,6 +365,89 @@ pub fn set_log_pings(value: bool) {
     glean_core::glean_set_log_pings(value)
 }
 
+/// Lists the pings that permanently failed to upload and were kept for diagnosis.
+///
+/// Pings are only kept if [`ConfigurationBuilder::with_max_quarantined_pings`] is used.
+pub fn quarantined_pings() -> Vec<QuarantinedPing> {
+    glean_core::glean_get_quarantined_pings()
+}
+
+/// Enqueues a quarantined ping for upload again.
+///
+/// # Arguments
+///
+/// * `document_id` - The UUID of the ping in question.
+pub fn reenqueue_quarantined_ping(document_id: &str) {
+    glean_core::glean_reenqueue_quarantined_ping(document_id.to_string())
+}
+
+/// Deletes all quarantined pings.
+pub fn purge_quarantined_pings() {
+    glean_core::glean_purge_quarantined_pings()
+}
+
+/// Sets the content encoding for the payloads of a ping,
+/// overriding the encodings the uploader accepts.
+///
+/// Only affects pings submitted afterwards.
+///
+/// # Arguments
+///
+/// * `ping_name` - The name of the ping.
+/// * `encoding` - The encoding to use, `None` to go back to the encodings the uploader accepts.
+pub fn set_ping_content_encoding(ping_name: &str, encoding: Option<ContentEncoding>) {
+    glean_core::glean_set_ping_content_encoding(
+        ping_name.to_string(),
+        encoding.map(|encoding| encoding.name().to_string()),
+    )
+}
+
+/// Reports the kind of network the device is currently connected to.
+///
+/// Pings constrained to unmetered networks (see [`UploadConstraints`])
+/// are only uploaded while an unmetered network is reported.
+///
+/// # Arguments
+///
+/// * `network` - The current network type.
+pub fn set_network_type(network: NetworkType) {
+    glean_core::glean_set_network_type(network)
+}
+
+/// Creates a backup of the data directory, while Glean is running.
+///
+/// # Arguments
+///
+/// * `backup_path` - The directory to create the backup in.
+///   It must not exist and must be outside the data directory.
+///
+/// # Returns
+///
+/// Whether the backup was created.
+pub fn backup(backup_path: &Path) -> bool {
+    glean_core::glean_backup(backup_path.display().to_string())
+}
+
+/// Restores a backup created by [`backup`] into a data directory, replacing its data.
+///
+/// Must be called before [`initialize`].
+/// The current data is only replaced if the backup is valid.
+///
+/// # Arguments
+///
+/// * `backup_path` - The backup.
+/// * `data_path` - The data directory to restore into.
+///
+/// # Returns
+///
+/// Whether the backup was restored.
+pub fn restore_backup(backup_path: &Path, data_path: &Path) -> bool {
+    glean_core::glean_restore_backup(
+        backup_path.display().to_string(),
+        data_path.display().to_string(),
+    )
+}
+
 /// Gets the current log pings value.
 ///
 /// The `log_pings` option may be set from an environment variable (`GLEAN_LOG_PINGS`)
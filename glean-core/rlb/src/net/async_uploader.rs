@@ -0,0 +1,234 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driving ping uploads on an async runtime.
+//!
+//! Instead of a dedicated uploader thread, uploads are driven by a task on the embedder's
+//! tokio runtime.
+//! Calls into Glean that might block (e.g. on disk I/O) are run on the runtime's blocking pool,
+//! waiting is done using the runtime's timers.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use glean_core::upload::PingUploadTask;
+use tokio::runtime::Handle;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+use super::thread_state::State;
+use super::{CapablePingUploadRequest, Inner, UploadResult, UploadTaskAction};
+
+/// The future returned by [`AsyncPingUploader::upload`].
+pub type UploadFuture<'a> = Pin<Box<dyn Future<Output = UploadResult> + Send + 'a>>;
+
+/// A description of a component used to upload pings asynchronously.
+pub trait AsyncPingUploader: std::fmt::Debug + Send + Sync {
+    /// Uploads a ping to a server.
+    ///
+    /// The returned future is polled on the runtime passed to [`AsyncUploader::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `upload_request` - the requested upload.
+    fn upload(&self, upload_request: CapablePingUploadRequest) -> UploadFuture<'_>;
+
+    /// The content encodings this uploader accepts for ping payloads, in order of preference.
+    ///
+    /// See [`PingUploader::content_encodings`](super::PingUploader::content_encodings).
+    fn content_encodings(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// An [`AsyncPingUploader`] together with the runtime its uploads are driven on.
+#[derive(Debug)]
+pub struct AsyncUploader {
+    uploader: Box<dyn AsyncPingUploader + 'static>,
+    runtime: Handle,
+    /// Wakes up the upload task if it is waiting.
+    wakeup: Notify,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AsyncUploader {
+    /// Creates a new async uploader.
+    ///
+    /// # Arguments
+    ///
+    /// * `uploader` - the instance of the uploader used to send pings.
+    /// * `runtime` - a handle to the tokio runtime to run uploads on.
+    pub fn new<U: AsyncPingUploader + 'static>(uploader: U, runtime: Handle) -> Self {
+        Self {
+            uploader: Box::new(uploader),
+            runtime,
+            wakeup: Notify::new(),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// The content encodings the uploader accepts for ping payloads, in order of preference.
+    pub(super) fn content_encodings(&self) -> Vec<String> {
+        self.uploader.content_encodings()
+    }
+
+    /// Spawns the task driving the uploads.
+    ///
+    /// The caller is responsible for marking the upload state as running.
+    pub(super) fn spawn(&self, inner: Arc<Inner>) {
+        let mut task = self.task.lock().unwrap();
+        *task = Some(self.runtime.spawn(async move {
+            if let Some(uploader) = inner.uploader.as_async() {
+                uploader.run(&inner).await;
+            }
+
+            // Clear the running flag to signal that this task is done,
+            // but only if there's no shutdown going on.
+            let _ = inner.thread_running.compare_exchange(
+                State::Running,
+                State::Stopped,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+        }));
+    }
+
+    async fn run(&self, inner: &Inner) {
+        log::trace!("Started glean.upload task");
+        loop {
+            let incoming_task = match self
+                .runtime
+                .spawn_blocking(glean_core::glean_get_upload_task)
+                .await
+            {
+                Ok(task) => task,
+                Err(e) => {
+                    log::warn!("Failed to get an upload task: {e}");
+                    break;
+                }
+            };
+
+            match incoming_task {
+                PingUploadTask::Upload { request } => {
+                    log::trace!("Received upload task with request {:?}", request);
+                    let (doc_id, upload_request) =
+                        CapablePingUploadRequest::new(&inner.server_endpoint, request);
+                    let result = self.uploader.upload(upload_request).await;
+
+                    // Process the upload response.
+                    let action = self
+                        .runtime
+                        .spawn_blocking(move || {
+                            glean_core::glean_process_ping_upload_response(doc_id, result)
+                        })
+                        .await;
+                    match action {
+                        Ok(UploadTaskAction::Next) => (),
+                        Ok(UploadTaskAction::End) => break,
+                        Err(e) => {
+                            log::warn!("Failed to process the upload response: {e}");
+                            break;
+                        }
+                    }
+                }
+                PingUploadTask::Wait { time } => {
+                    log::trace!("Instructed to wait for {:?}ms", time);
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_millis(time)) => (),
+                        _ = self.wakeup.notified() => (),
+                    }
+                }
+                PingUploadTask::Done { .. } => {
+                    log::trace!("Received PingUploadTask::Done. Exiting.");
+                    // Nothing to do here, break out of the loop.
+                    break;
+                }
+            }
+
+            let status = inner.thread_running.load(Ordering::SeqCst);
+            // asked to shut down. let's do it.
+            if status == State::ShuttingDown {
+                break;
+            }
+        }
+    }
+
+    /// Waits for the upload task to finish.
+    ///
+    /// The caller is responsible for marking the upload state as shutting down.
+    pub(super) fn shutdown(&self) {
+        let task = self.task.lock().unwrap().take();
+        let Some(task) = task else {
+            return;
+        };
+
+        // poke the task in case it's in `Wait`.
+        self.wakeup.notify_one();
+
+        // Blocking inside the runtime would panic.
+        if Handle::try_current().is_ok() {
+            log::warn!("Shutdown called from within an async runtime. Not waiting for uploads.");
+            return;
+        }
+
+        if let Err(e) = self.runtime.block_on(task) {
+            log::warn!("The uploader task failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::UploadManager;
+
+    use glean_core::upload::PingRequest;
+
+    #[derive(Debug)]
+    struct NoopUploader;
+
+    impl AsyncPingUploader for NoopUploader {
+        fn upload(&self, _upload_request: CapablePingUploadRequest) -> UploadFuture<'_> {
+            Box::pin(async { UploadResult::http_status(200) })
+        }
+    }
+
+    #[test]
+    fn upload_requests_contain_the_full_url() {
+        let request = PingRequest::builder("Rust", 1024)
+            .document_id("doc-id")
+            .path("/submit/app/ping/1/doc-id")
+            .body("{}")
+            .body_has_info_sections(true)
+            .ping_name("ping")
+            .uploader_capabilities(vec![])
+            .build()
+            .unwrap();
+
+        let (doc_id, upload_request) =
+            CapablePingUploadRequest::new("https://example.com", request);
+        let upload_request = upload_request.capable(|_| true).unwrap();
+        assert_eq!("doc-id", doc_id);
+        assert_eq!(
+            "https://example.com/submit/app/ping/1/doc-id",
+            upload_request.url
+        );
+    }
+
+    #[test]
+    fn shutdown_without_uploads_does_not_block() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let uploader = AsyncUploader::new(NoopUploader, runtime.handle().clone());
+        let manager = UploadManager::new_async("https://example.com".into(), uploader);
+        manager.shutdown();
+    }
+}
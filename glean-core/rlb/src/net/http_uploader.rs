@@ -2,11 +2,58 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+#[cfg(feature = "http-uploader")]
+use std::time::{Duration, SystemTime};
+
 use crate::net::{CapablePingUploadRequest, PingUploader, UploadResult};
 
+/// The default time to wait for a connection to the server to be established.
+#[cfg(feature = "http-uploader")]
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default time a whole upload request (connect, send, receive) may take.
+#[cfg(feature = "http-uploader")]
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// A simple mechanism to upload pings over HTTPS.
+///
+/// With the `http-uploader` feature enabled this sends pings using a blocking HTTP client.
+/// Without it no data is sent and every upload is reported as successful.
 #[derive(Debug)]
-pub struct HttpUploader;
+pub struct HttpUploader {
+    #[cfg(feature = "http-uploader")]
+    agent: ureq::Agent,
+}
+
+impl HttpUploader {
+    /// Creates a new HTTP uploader with the default settings.
+    ///
+    /// With the `http-uploader` feature enabled a proxy is picked up from the
+    /// `ALL_PROXY`/`HTTPS_PROXY`/`HTTP_PROXY` environment variables, if set.
+    pub fn new() -> Self {
+        #[cfg(feature = "http-uploader")]
+        {
+            HttpUploaderBuilder::new().build()
+        }
+
+        #[cfg(not(feature = "http-uploader"))]
+        {
+            Self {}
+        }
+    }
+
+    /// Creates a builder to configure timeouts and proxies of the HTTP uploader.
+    #[cfg(feature = "http-uploader")]
+    pub fn builder() -> HttpUploaderBuilder {
+        HttpUploaderBuilder::new()
+    }
+}
+
+impl Default for HttpUploader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl PingUploader for HttpUploader {
     /// Uploads a ping to a server.
@@ -14,9 +61,375 @@ impl PingUploader for HttpUploader {
     /// # Arguments
     ///
     /// * `upload_request` - the requested upload.
+    #[cfg(not(feature = "http-uploader"))]
     fn upload(&self, upload_request: CapablePingUploadRequest) -> UploadResult {
         let upload_request = upload_request.capable(|_| true).unwrap();
         log::debug!("TODO bug 1675468: submitting to {:?}", upload_request.url);
         UploadResult::http_status(200)
     }
+
+    /// Uploads a ping to a server.
+    ///
+    /// The body is sent as-is, it is already content-encoded.
+    /// All headers of the request are sent along.
+    ///
+    /// Any HTTP response is reported as its status code.
+    /// A `Retry-After` header on an error response is passed along as a hint when to retry.
+    /// Transport errors that might resolve on their own (e.g. DNS or connection failures, timeouts)
+    /// are reported as recoverable failures,
+    /// errors in the request itself (e.g. a malformed URL) as unrecoverable failures.
+    ///
+    /// This uploader has no special capabilities (e.g. OHTTP)
+    /// and reports any ping requiring one as incapable.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload_request` - the requested upload.
+    #[cfg(feature = "http-uploader")]
+    fn upload(&self, upload_request: CapablePingUploadRequest) -> UploadResult {
+        let Some(upload_request) = upload_request.capable(|capabilities| capabilities.is_empty())
+        else {
+            return UploadResult::incapable();
+        };
+
+        log::debug!("Submitting ping to {}", upload_request.url);
+        let mut request = self.agent.post(&upload_request.url);
+        for (name, value) in &upload_request.headers {
+            request = request.set(name, value);
+        }
+
+        match request.send_bytes(&upload_request.body) {
+            Ok(response) => UploadResult::http_status(response.status() as i32),
+            Err(ureq::Error::Status(code, response)) => {
+                match response.header("Retry-After").and_then(parse_retry_after) {
+                    Some(retry_after) => UploadResult::http_status_with_retry_after(
+                        code as i32,
+                        retry_after.as_millis().try_into().unwrap_or(u64::MAX),
+                    ),
+                    None => UploadResult::http_status(code as i32),
+                }
+            }
+            Err(ureq::Error::Transport(transport)) => {
+                log::warn!(
+                    "Failed to upload ping to {}: {}",
+                    upload_request.url,
+                    transport
+                );
+                transport_error_result(transport.kind())
+            }
+        }
+    }
+}
+
+/// Maps a transport error to the upload result reported to Glean.
+#[cfg(feature = "http-uploader")]
+fn transport_error_result(kind: ureq::ErrorKind) -> UploadResult {
+    use ureq::ErrorKind::*;
+
+    match kind {
+        // Retrying the same request won't change anything.
+        InvalidUrl | UnknownScheme | InsecureRequestHttpsOnly | InvalidProxyUrl => {
+            UploadResult::unrecoverable_failure()
+        }
+        // Anything else could be a temporary network or server issue.
+        _ => UploadResult::recoverable_failure(),
+    }
+}
+
+/// Parses the value of a `Retry-After` header into the time to wait.
+///
+/// The value is either a number of seconds or an HTTP date.
+/// A date in the past means no time to wait.
+#[cfg(feature = "http-uploader")]
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// A builder to configure an [`HttpUploader`].
+#[cfg(feature = "http-uploader")]
+#[derive(Debug, Clone)]
+pub struct HttpUploaderBuilder {
+    connect_timeout: Duration,
+    timeout: Duration,
+    proxy: Option<String>,
+    proxy_from_env: bool,
+}
+
+#[cfg(feature = "http-uploader")]
+impl HttpUploaderBuilder {
+    /// A new HTTP uploader builder with default settings.
+    pub fn new() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: DEFAULT_TIMEOUT,
+            proxy: None,
+            proxy_from_env: true,
+        }
+    }
+
+    /// Set the time to wait for a connection to the server to be established.
+    /// Default: 10 seconds.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Set the time a whole upload request may take, including connecting.
+    /// Default: 30 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the proxy to send all requests through.
+    ///
+    /// Supported formats are `http://host:port`, `user:password@host:port`
+    /// and `socks5://host:port`.
+    /// An invalid proxy is ignored with a warning.
+    /// Default: `None`
+    pub fn with_proxy<S: Into<String>>(mut self, proxy: S) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Set whether to pick up a proxy from the environment if none is set explicitly.
+    /// Default: `true`
+    pub fn with_proxy_from_env(mut self, value: bool) -> Self {
+        self.proxy_from_env = value;
+        self
+    }
+
+    /// Generate the HTTP uploader.
+    pub fn build(self) -> HttpUploader {
+        let mut agent = ureq::AgentBuilder::new()
+            .timeout_connect(self.connect_timeout)
+            .timeout(self.timeout)
+            .try_proxy_from_env(self.proxy_from_env)
+            .user_agent(&format!("Glean/{}", env!("CARGO_PKG_VERSION")));
+
+        if let Some(proxy) = self.proxy {
+            match ureq::Proxy::new(&proxy) {
+                Ok(proxy) => agent = agent.proxy(proxy),
+                Err(e) => log::warn!("Invalid proxy {:?}, ignoring it: {}", proxy, e),
+            }
+        }
+
+        HttpUploader {
+            agent: agent.build(),
+        }
+    }
+}
+
+#[cfg(feature = "http-uploader")]
+impl Default for HttpUploaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "http-uploader"))]
+mod test {
+    use super::*;
+    use crate::net::PingUploadRequest;
+
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    /// A request as received by the mock server.
+    struct ReceivedRequest {
+        request_line: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// Starts a mock server on a random local port that responds to a single request
+    /// with the given status and additional headers (each terminated by `\r\n`).
+    ///
+    /// Returns the server's URL and a handle resolving to the received request.
+    fn mock_server(
+        status: u16,
+        response_headers: &'static str,
+    ) -> (String, JoinHandle<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':').unwrap();
+                headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+            }
+
+            let length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .map(|(_, value)| value.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {} Whatever\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+                status, response_headers
+            )
+            .unwrap();
+
+            ReceivedRequest {
+                request_line: request_line.trim_end().to_string(),
+                headers,
+                body,
+            }
+        });
+
+        (url, handle)
+    }
+
+    fn upload_request(url: String, capabilities: Vec<String>) -> CapablePingUploadRequest {
+        CapablePingUploadRequest {
+            request: PingUploadRequest {
+                url,
+                body: b"gzipped-body".to_vec(),
+                headers: vec![
+                    ("Content-Encoding".to_string(), "gzip".to_string()),
+                    ("X-Debug-ID".to_string(), "tag".to_string()),
+                ],
+                body_has_info_sections: true,
+                ping_name: "test".to_string(),
+            },
+            capabilities,
+        }
+    }
+
+    #[test]
+    fn sends_body_and_headers() {
+        let (url, server) = mock_server(200, "");
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request(format!("{}/submit/path", url), vec![]));
+        assert!(matches!(result, UploadResult::HttpStatus { code: 200 }));
+
+        let request = server.join().unwrap();
+        assert_eq!("POST /submit/path HTTP/1.1", request.request_line);
+        assert_eq!(b"gzipped-body".to_vec(), request.body);
+        assert!(request
+            .headers
+            .contains(&("content-encoding".to_string(), "gzip".to_string())));
+        assert!(request
+            .headers
+            .contains(&("x-debug-id".to_string(), "tag".to_string())));
+    }
+
+    #[test]
+    fn reports_error_status_codes() {
+        let (url, server) = mock_server(500, "");
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request(url, vec![]));
+        assert!(matches!(result, UploadResult::HttpStatus { code: 500 }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn reports_retry_after_hints() {
+        let (url, server) = mock_server(429, "Retry-After: 120\r\n");
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request(url, vec![]));
+        assert!(matches!(
+            result,
+            UploadResult::HttpStatusWithRetryAfter {
+                code: 429,
+                retry_after_ms: 120_000
+            }
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn parses_retry_after_values() {
+        assert_eq!(Some(Duration::from_secs(30)), parse_retry_after(" 30 "));
+        assert_eq!(
+            Some(Duration::ZERO),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+        let retry_after = parse_retry_after(&future).unwrap();
+        assert!(
+            retry_after > Duration::from_secs(3500) && retry_after <= Duration::from_secs(3600)
+        );
+
+        assert_eq!(None, parse_retry_after("soon"));
+        assert_eq!(None, parse_retry_after("-1"));
+    }
+
+    #[test]
+    fn connection_failures_are_recoverable() {
+        // Bind and immediately drop a listener to get a port nobody listens on.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request(format!("http://{}", addr), vec![]));
+        assert!(matches!(result, UploadResult::RecoverableFailure { .. }));
+    }
+
+    #[test]
+    fn malformed_urls_are_unrecoverable() {
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request("not a url".to_string(), vec![]));
+        assert!(matches!(result, UploadResult::UnrecoverableFailure { .. }));
+    }
+
+    #[test]
+    fn timeouts_are_recoverable() {
+        // A listener that accepts connections but never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let uploader = HttpUploader::builder()
+            .with_proxy_from_env(false)
+            .with_timeout(Duration::from_millis(200))
+            .build();
+
+        let result = uploader.upload(upload_request(url, vec![]));
+        assert!(matches!(result, UploadResult::RecoverableFailure { .. }));
+    }
+
+    #[test]
+    fn pings_requiring_capabilities_are_not_uploaded() {
+        let uploader = HttpUploader::builder().with_proxy_from_env(false).build();
+
+        let result = uploader.upload(upload_request(
+            "http://127.0.0.1:1".to_string(),
+            vec!["ohttp".to_string()],
+        ));
+        assert!(matches!(result, UploadResult::Incapable { .. }));
+    }
 }
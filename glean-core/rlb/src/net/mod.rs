@@ -12,13 +12,19 @@ use std::sync::{atomic::Ordering, Arc, Mutex};
 use std::thread::JoinHandle;
 use std::time::Duration;
 
-use glean_core::upload::PingUploadTask;
 pub use glean_core::upload::{PingRequest, UploadResult, UploadTaskAction};
+use glean_core::upload::{PingUploadBatchTask, PingUploadTask};
 
+#[cfg(feature = "async-uploader")]
+pub use async_uploader::*;
 pub use http_uploader::*;
+pub use replay::*;
 use thread_state::{AtomicState, State};
 
+#[cfg(feature = "async-uploader")]
+mod async_uploader;
 mod http_uploader;
+mod replay;
 
 /// Everything you need to request a ping to be uploaded.
 pub struct PingUploadRequest {
@@ -41,6 +47,27 @@ pub struct CapablePingUploadRequest {
 }
 
 impl CapablePingUploadRequest {
+    /// Creates the upload request for a ping request handed out by Glean.
+    ///
+    /// Returns the document id of the ping alongside the upload request.
+    fn new(server_endpoint: &str, request: PingRequest) -> (String, Self) {
+        let doc_id = request.document_id.clone();
+        let upload_url = format!("{}{}", server_endpoint, request.path);
+        let headers: Vec<(String, String)> = request.headers.into_iter().collect();
+        let upload_request = PingUploadRequest {
+            url: upload_url,
+            body: request.body,
+            headers,
+            body_has_info_sections: request.body_has_info_sections,
+            ping_name: request.ping_name,
+        };
+        let upload_request = Self {
+            request: upload_request,
+            capabilities: request.uploader_capabilities,
+        };
+        (doc_id, upload_request)
+    }
+
     /// If you are capable of satisfying this ping upload request's capabilities,
     /// obtain the PingUploadRequest.
     pub fn capable<F>(self, func: F) -> Option<PingUploadRequest>
@@ -65,6 +92,60 @@ pub trait PingUploader: std::fmt::Debug + Send + Sync {
     /// * `headers` - a vector of tuples containing the headers to send with
     ///   the request, i.e. (Name, Value).
     fn upload(&self, upload_request: CapablePingUploadRequest) -> UploadResult;
+
+    /// The maximum number of pings this uploader uploads at once.
+    ///
+    /// Uploaders returning more than `1` are handed batches of pings
+    /// through [`upload_batch`](PingUploader::upload_batch).
+    /// The bodies of all pings in a batch together don't exceed the maximum ping body size.
+    ///
+    /// Defaults to `1`.
+    fn max_batch_size(&self) -> usize {
+        1
+    }
+
+    /// The content encodings this uploader accepts for ping payloads, in order of preference.
+    ///
+    /// These are names as used in the `Content-Encoding` header, e.g. `"gzip"`.
+    /// Encodings Glean doesn't support are ignored.
+    ///
+    /// Defaults to none, in which case pings are gzip-encoded.
+    fn content_encodings(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Uploads multiple pings to a server at once.
+    ///
+    /// Defaults to uploading each ping individually.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload_requests` - the requested uploads.
+    ///
+    /// # Returns
+    ///
+    /// The result for each ping, in the order of `upload_requests`.
+    fn upload_batch(&self, upload_requests: Vec<CapablePingUploadRequest>) -> Vec<UploadResult> {
+        upload_requests
+            .into_iter()
+            .map(|upload_request| self.upload(upload_request))
+            .collect()
+    }
+}
+
+/// Gets the next upload task from Glean, as a batch of up to `max_batch_size` pings.
+fn next_upload_batch(max_batch_size: usize) -> PingUploadBatchTask {
+    if max_batch_size > 1 {
+        return glean_core::glean_get_upload_batch(max_batch_size.try_into().unwrap_or(u32::MAX));
+    }
+
+    match glean_core::glean_get_upload_task() {
+        PingUploadTask::Upload { request } => PingUploadBatchTask::Upload {
+            requests: vec![request],
+        },
+        PingUploadTask::Wait { time } => PingUploadBatchTask::Wait { time },
+        PingUploadTask::Done { unused } => PingUploadBatchTask::Done { unused },
+    }
 }
 
 /// The logic for uploading pings: this leaves the actual upload mechanism as
@@ -74,10 +155,38 @@ pub(crate) struct UploadManager {
     inner: Arc<Inner>,
 }
 
+/// The uploader used to send pings, either synchronous or asynchronous.
+#[derive(Debug)]
+enum Uploader {
+    /// Uploads are run on a dedicated thread.
+    Sync(Arc<dyn PingUploader + 'static>),
+    /// Uploads are driven by a task on an async runtime.
+    #[cfg(feature = "async-uploader")]
+    Async(AsyncUploader),
+}
+
+impl Uploader {
+    fn content_encodings(&self) -> Vec<String> {
+        match self {
+            Uploader::Sync(uploader) => uploader.content_encodings(),
+            #[cfg(feature = "async-uploader")]
+            Uploader::Async(uploader) => uploader.content_encodings(),
+        }
+    }
+
+    #[cfg(feature = "async-uploader")]
+    fn as_async(&self) -> Option<&AsyncUploader> {
+        match self {
+            Uploader::Async(uploader) => Some(uploader),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Inner {
     server_endpoint: String,
-    uploader: Box<dyn PingUploader + 'static>,
+    uploader: Uploader,
     thread_running: AtomicState,
     handle: Mutex<Option<JoinHandle<()>>>,
     rx: Receiver<()>,
@@ -95,11 +204,26 @@ impl UploadManager {
         server_endpoint: String,
         new_uploader: Box<dyn PingUploader + 'static>,
     ) -> Self {
+        Self::with_uploader(server_endpoint, Uploader::Sync(Arc::from(new_uploader)))
+    }
+
+    /// Create a new instance of the upload manager driving uploads on an async runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_endpoint` -  the server pings are sent to.
+    /// * `new_uploader` - the async uploader used to send pings.
+    #[cfg(feature = "async-uploader")]
+    pub(crate) fn new_async(server_endpoint: String, new_uploader: AsyncUploader) -> Self {
+        Self::with_uploader(server_endpoint, Uploader::Async(new_uploader))
+    }
+
+    fn with_uploader(server_endpoint: String, uploader: Uploader) -> Self {
         let (tx, rx) = crossbeam_channel::bounded(1);
         Self {
             inner: Arc::new(Inner {
                 server_endpoint,
-                uploader: new_uploader,
+                uploader,
                 thread_running: AtomicState::new(State::Stopped),
                 handle: Mutex::new(None),
                 rx,
@@ -108,6 +232,11 @@ impl UploadManager {
         }
     }
 
+    /// The content encodings the uploader accepts for ping payloads, in order of preference.
+    pub(crate) fn content_encodings(&self) -> Vec<String> {
+        self.inner.uploader.content_encodings()
+    }
+
     /// Signals Glean to upload pings at the next best opportunity.
     pub(crate) fn trigger_upload(&self) {
         // If no other upload process is running, we're the one starting it.
@@ -130,31 +259,30 @@ impl UploadManager {
 
         let inner = Arc::clone(&self.inner);
 
+        let uploader = match &self.inner.uploader {
+            Uploader::Sync(uploader) => Arc::clone(uploader),
+            #[cfg(feature = "async-uploader")]
+            Uploader::Async(uploader) => {
+                uploader.spawn(inner);
+                return;
+            }
+        };
+
         // Need to lock before we start so that noone thinks we're not running.
         let mut handle = self.inner.handle.lock().unwrap();
         let thread = glean_core::thread::spawn("glean.upload", move || {
             log::trace!("Started glean.upload thread");
+            let max_batch_size = uploader.max_batch_size();
             loop {
-                let incoming_task = glean_core::glean_get_upload_task();
+                let incoming_task = next_upload_batch(max_batch_size);
 
                 match incoming_task {
-                    PingUploadTask::Upload { request } => {
+                    PingUploadBatchTask::Upload { mut requests } if max_batch_size <= 1 => {
+                        let request = requests.remove(0);
                         log::trace!("Received upload task with request {:?}", request);
-                        let doc_id = request.document_id.clone();
-                        let upload_url = format!("{}{}", inner.server_endpoint, request.path);
-                        let headers: Vec<(String, String)> = request.headers.into_iter().collect();
-                        let upload_request = PingUploadRequest {
-                            url: upload_url,
-                            body: request.body,
-                            headers,
-                            body_has_info_sections: request.body_has_info_sections,
-                            ping_name: request.ping_name,
-                        };
-                        let upload_request = CapablePingUploadRequest {
-                            request: upload_request,
-                            capabilities: request.uploader_capabilities,
-                        };
-                        let result = inner.uploader.upload(upload_request);
+                        let (doc_id, upload_request) =
+                            CapablePingUploadRequest::new(&inner.server_endpoint, request);
+                        let result = uploader.upload(upload_request);
                         // Process the upload response.
                         match glean_core::glean_process_ping_upload_response(doc_id, result) {
                             UploadTaskAction::Next => (),
@@ -167,7 +295,43 @@ impl UploadManager {
                             break;
                         }
                     }
-                    PingUploadTask::Wait { time } => {
+                    PingUploadBatchTask::Upload { requests } => {
+                        log::trace!("Received upload task with {} requests", requests.len());
+                        let (doc_ids, upload_requests): (Vec<_>, Vec<_>) = requests
+                            .into_iter()
+                            .map(|request| {
+                                CapablePingUploadRequest::new(&inner.server_endpoint, request)
+                            })
+                            .unzip();
+                        let results = uploader.upload_batch(upload_requests);
+                        if results.len() != doc_ids.len() {
+                            log::warn!(
+                                "Uploader returned {} results for {} pings.",
+                                results.len(),
+                                doc_ids.len()
+                            );
+                        }
+                        // Pings without a result are retried later.
+                        let results =
+                            doc_ids
+                                .into_iter()
+                                .zip(results.into_iter().chain(std::iter::repeat_with(
+                                    UploadResult::recoverable_failure,
+                                )))
+                                .collect();
+                        // Process the upload responses.
+                        match glean_core::glean_process_ping_upload_batch_response(results) {
+                            UploadTaskAction::Next => (),
+                            UploadTaskAction::End => break,
+                        }
+
+                        let status = inner.thread_running.load(Ordering::SeqCst);
+                        // asked to shut down. let's do it.
+                        if status == State::ShuttingDown {
+                            break;
+                        }
+                    }
+                    PingUploadBatchTask::Wait { time } => {
                         log::trace!("Instructed to wait for {:?}ms", time);
                         let _ = inner.rx.recv_timeout(Duration::from_millis(time));
 
@@ -177,7 +341,7 @@ impl UploadManager {
                             break;
                         }
                     }
-                    PingUploadTask::Done { .. } => {
+                    PingUploadBatchTask::Done { .. } => {
                         log::trace!("Received PingUploadTask::Done. Exiting.");
                         // Nothing to do here, break out of the loop.
                         break;
@@ -221,6 +385,12 @@ impl UploadManager {
             .thread_running
             .store(State::ShuttingDown, Ordering::SeqCst);
 
+        #[cfg(feature = "async-uploader")]
+        if let Uploader::Async(uploader) = &self.inner.uploader {
+            uploader.shutdown();
+            return;
+        }
+
         // take the thread handle out.
         let mut handle = self.inner.handle.lock().unwrap();
         let thread = handle.take();
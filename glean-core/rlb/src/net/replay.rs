@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Uploading pings from an archive exported by another Glean instance.
+
+use std::fs;
+use std::path::Path;
+
+use glean_core::upload::{read_archive, write_archive};
+
+use super::{CapablePingUploadRequest, PingUploader, UploadResult};
+
+/// The outcome of replaying an archive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplaySummary {
+    /// The number of pings successfully uploaded.
+    pub uploaded: usize,
+    /// The number of pings that failed to upload and were dropped.
+    pub failed: usize,
+    /// The number of pings left in the archive to retry later.
+    pub remaining: usize,
+}
+
+/// Uploads all pings from an archive.
+///
+/// Uploaded pings, and those that can never be uploaded, are removed from the archive.
+/// Pings that failed to upload with a recoverable failure are kept,
+/// so that replaying the archive again retries only those.
+/// The archive is deleted once no pings are left.
+///
+/// Replaying stops early if the uploader is done or the server asks to retry later.
+///
+/// # Arguments
+///
+/// * `archive_path` - Path to the archive, as written by [`glean_core::upload::export_pending_pings`].
+/// * `server_endpoint` - The server to upload the pings to.
+/// * `uploader` - The uploader used to send the pings.
+pub fn replay_archive(
+    archive_path: &Path,
+    server_endpoint: &str,
+    uploader: &dyn PingUploader,
+) -> glean_core::Result<ReplaySummary> {
+    let mut summary = ReplaySummary::default();
+    let mut remaining = Vec::new();
+    let mut stopped = false;
+
+    for ping in read_archive(archive_path)? {
+        if stopped {
+            remaining.push(ping);
+            continue;
+        }
+
+        let request = match ping.to_request(crate::LANGUAGE_BINDING_NAME) {
+            Ok(request) => request,
+            Err(e) => {
+                log::warn!("Dropping archived ping {}. Error: {}", ping.document_id, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        let (document_id, upload_request) = CapablePingUploadRequest::new(server_endpoint, request);
+
+        match uploader.upload(upload_request) {
+            UploadResult::HttpStatus { code }
+            | UploadResult::HttpStatusWithRetryAfter { code, .. }
+                if (200..=299).contains(&code) =>
+            {
+                summary.uploaded += 1;
+            }
+            status @ (UploadResult::UnrecoverableFailure { .. }
+            | UploadResult::Incapable { .. }
+            | UploadResult::HttpStatus {
+                code: 400..=428 | 430..=499,
+            }
+            | UploadResult::HttpStatusWithRetryAfter {
+                code: 400..=428 | 430..=499,
+                ..
+            }) => {
+                log::warn!(
+                    "Dropping archived ping {} after unrecoverable upload failure {:?}",
+                    document_id,
+                    status
+                );
+                summary.failed += 1;
+            }
+            status
+            @ (UploadResult::HttpStatusWithRetryAfter { .. } | UploadResult::Done { .. }) => {
+                log::info!("Stopping replay after {:?}", status);
+                remaining.push(ping);
+                stopped = true;
+            }
+            status
+            @ (UploadResult::RecoverableFailure { .. } | UploadResult::HttpStatus { .. }) => {
+                log::warn!(
+                    "Keeping archived ping {} after recoverable upload failure {:?}",
+                    document_id,
+                    status
+                );
+                remaining.push(ping);
+            }
+        }
+    }
+
+    summary.remaining = remaining.len();
+    if remaining.is_empty() {
+        fs::remove_file(archive_path)?;
+    } else {
+        write_archive(archive_path, &remaining)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use glean_core::upload::ArchivedPing;
+    use std::sync::Mutex;
+
+    #[derive(Debug)]
+    struct FakeUploader {
+        results: Mutex<Vec<UploadResult>>,
+        urls: Mutex<Vec<String>>,
+    }
+
+    impl PingUploader for FakeUploader {
+        fn upload(&self, upload_request: CapablePingUploadRequest) -> UploadResult {
+            let request = upload_request.capable(|_| true).unwrap();
+            assert!(request.headers.iter().any(|(name, _)| name == "Date"));
+            self.urls.lock().unwrap().push(request.url);
+            self.results.lock().unwrap().remove(0)
+        }
+    }
+
+    fn ping(document_id: &str) -> ArchivedPing {
+        ArchivedPing {
+            document_id: document_id.into(),
+            upload_path: format!("/submit/app/test/1/{}", document_id),
+            json_body: "{}".into(),
+            headers: None,
+            body_has_info_sections: true,
+            ping_name: "test".into(),
+            uploader_capabilities: vec![],
+            deletion_request: false,
+            priority: Default::default(),
+        }
+    }
+
+    #[test]
+    fn keeps_pings_to_retry_in_the_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pings.archive");
+        write_archive(&archive, &[ping("a"), ping("b"), ping("c"), ping("d")]).unwrap();
+
+        let uploader = FakeUploader {
+            results: Mutex::new(vec![
+                UploadResult::http_status(200),
+                UploadResult::http_status(400),
+                UploadResult::http_status(500),
+                UploadResult::done(),
+            ]),
+            urls: Mutex::new(vec![]),
+        };
+        let summary = replay_archive(&archive, "https://example.com", &uploader).unwrap();
+        assert_eq!(
+            ReplaySummary {
+                uploaded: 1,
+                failed: 1,
+                remaining: 2
+            },
+            summary
+        );
+        assert_eq!(
+            "https://example.com/submit/app/test/1/a",
+            uploader.urls.lock().unwrap()[0]
+        );
+
+        let remaining: Vec<_> = read_archive(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|ping| ping.document_id)
+            .collect();
+        assert_eq!(vec!["c", "d"], remaining);
+
+        *uploader.results.lock().unwrap() = vec![
+            UploadResult::http_status(200),
+            UploadResult::http_status(200),
+        ];
+        let summary = replay_archive(&archive, "https://example.com", &uploader).unwrap();
+        assert_eq!(2, summary.uploaded);
+        assert_eq!(0, summary.remaining);
+        assert!(!archive.exists());
+    }
+}
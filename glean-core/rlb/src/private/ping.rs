@@ -7,6 +7,8 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+use glean_core::metrics::{PingCapacity, PingPriority, PingSchedule};
+use glean_core::UploadConstraints;
 use malloc_size_of::MallocSizeOf;
 
 type BoxedCallback = Box<dyn FnOnce(Option<&str>) + Send + 'static>;
@@ -93,6 +95,79 @@ impl PingType {
         self.inner.set_enabled(enabled)
     }
 
+    /// Sets the priority this ping is uploaded with.
+    ///
+    /// Pending pings of a higher priority are uploaded first,
+    /// and pings of the lowest priority are the first to be deleted
+    /// when the pending pings quota is hit.
+    /// Only affects pings submitted afterwards.
+    pub fn set_priority(&self, priority: PingPriority) {
+        self.inner.set_priority(priority)
+    }
+
+    /// Sets the priority this ping is uploaded with.
+    ///
+    /// See [`PingType::set_priority`].
+    pub fn with_priority(self, priority: PingPriority) -> Self {
+        self.set_priority(priority);
+        self
+    }
+
+    /// Sets the constraints on when this ping may be uploaded,
+    /// e.g. only within a window of local time or only on unmetered networks.
+    ///
+    /// Pings whose constraints aren't met stay pending while other pings are uploaded.
+    /// Applies to all pending pings of this type.
+    /// Constraints with window hours out of range are ignored.
+    pub fn set_upload_constraints(&self, constraints: Option<UploadConstraints>) {
+        self.inner.set_upload_constraints(constraints)
+    }
+
+    /// Sets the constraints on when this ping may be uploaded.
+    ///
+    /// See [`PingType::set_upload_constraints`].
+    pub fn with_upload_constraints(self, constraints: UploadConstraints) -> Self {
+        self.set_upload_constraints(Some(constraints));
+        self
+    }
+
+    /// Sets the thresholds at which this ping is submitted automatically,
+    /// with the reason `max_capacity`.
+    ///
+    /// Only events and metrics with a `ping` lifetime count towards them.
+    /// Use them to keep the ping below the maximum ping body size,
+    /// above which it is discarded instead of being uploaded.
+    pub fn set_capacity(&self, capacity: Option<PingCapacity>) {
+        self.inner.set_capacity(capacity)
+    }
+
+    /// Sets the thresholds at which this ping is submitted automatically.
+    ///
+    /// See [`PingType::set_capacity`].
+    pub fn with_capacity(self, capacity: PingCapacity) -> Self {
+        self.set_capacity(Some(capacity));
+        self
+    }
+
+    /// Sets the schedule on which this ping is submitted, replacing any previous schedule.
+    ///
+    /// The ping is submitted at the scheduled times while the application is running.
+    /// If a scheduled time passed while it wasn't running, it is submitted right away
+    /// with the reason `overdue`. Other submissions have the reason `today`, `tomorrow`
+    /// or `reschedule`.
+    pub fn set_schedule(&self, schedule: Option<PingSchedule>) {
+        self.inner.set_schedule(schedule)
+    }
+
+    /// Sets the schedule on which this ping is submitted.
+    ///
+    /// See [`PingType::set_schedule`].
+    /// A schedule can be parsed from a cron-like spec, e.g. `"0 4 * * *"` for every day at 4:00.
+    pub fn with_schedule(self, schedule: PingSchedule) -> Self {
+        self.set_schedule(Some(schedule));
+        self
+    }
+
     /// Submits the ping for eventual uploading.
     ///
     /// The ping content is assembled as soon as possible, but upload is not
@@ -140,6 +215,11 @@ impl PingType {
         self.inner.include_info_sections()
     }
 
+    /// The priority this ping is uploaded with.
+    pub fn priority(&self) -> PingPriority {
+        self.inner.priority()
+    }
+
     /// Whether the `enabled` field of this ping is set. Note that whether or
     /// not a ping is actually enabled is dependent upon the underlying glean
     /// instance settings, and `follows_collection_enabled`. In other words,
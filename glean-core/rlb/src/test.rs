@@ -77,6 +77,123 @@ fn send_a_ping() {
     assert!(upload_request.url.contains(PING_NAME));
 }
 
+#[test]
+fn send_pings_with_a_batch_uploader() {
+    let _lock = lock_test();
+
+    let (s, r) = crossbeam_channel::unbounded::<Vec<String>>();
+
+    // Define a fake uploader that reports back the ping names of each batch.
+    #[derive(Debug)]
+    pub struct FakeUploader {
+        sender: crossbeam_channel::Sender<Vec<String>>,
+    }
+    impl net::PingUploader for FakeUploader {
+        fn upload(&self, _upload_request: net::CapablePingUploadRequest) -> net::UploadResult {
+            panic!("Batch uploaders should only receive batches.");
+        }
+
+        fn max_batch_size(&self) -> usize {
+            10
+        }
+
+        fn upload_batch(
+            &self,
+            upload_requests: Vec<net::CapablePingUploadRequest>,
+        ) -> Vec<net::UploadResult> {
+            let names = upload_requests
+                .into_iter()
+                .map(|r| r.capable(|_| true).unwrap().ping_name)
+                .collect::<Vec<_>>();
+            let results = names
+                .iter()
+                .map(|_| net::UploadResult::http_status(200))
+                .collect();
+            self.sender.send(names).unwrap();
+            results
+        }
+    }
+
+    // Create a custom configuration to use a fake uploader.
+    let dir = tempfile::tempdir().unwrap();
+    let tmpname = dir.path().to_path_buf();
+
+    let cfg = ConfigurationBuilder::new(true, tmpname, GLOBAL_APPLICATION_ID)
+        .with_server_endpoint("invalid-test-host")
+        .with_uploader(FakeUploader { sender: s })
+        .build();
+
+    glean_core::glean_set_test_mode(true);
+    let _t = new_glean(Some(cfg), true);
+
+    // Define new pings and submit them.
+    let pings = ["batch-ping-1", "batch-ping-2", "batch-ping-3"];
+    for name in pings {
+        new_test_ping(name).submit(None);
+    }
+
+    // Wait for the pings to arrive, in one or more batches.
+    let mut received = vec![];
+    while received.len() < pings.len() {
+        received.extend(r.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+    received.sort();
+    assert_eq!(pings.to_vec(), received);
+}
+
+#[cfg(feature = "async-uploader")]
+#[test]
+fn send_a_ping_with_an_async_uploader() {
+    let _lock = lock_test();
+
+    let (s, r) = crossbeam_channel::bounded::<net::PingUploadRequest>(1);
+
+    // Define a fake async uploader that reports back the ping upload request.
+    #[derive(Debug)]
+    pub struct FakeUploader {
+        sender: crossbeam_channel::Sender<net::PingUploadRequest>,
+    }
+    impl net::AsyncPingUploader for FakeUploader {
+        fn upload(&self, upload_request: net::CapablePingUploadRequest) -> net::UploadFuture<'_> {
+            Box::pin(async move {
+                // Yield to the runtime to make sure the upload is actually driven asynchronously.
+                tokio::task::yield_now().await;
+                let upload_request = upload_request.capable(|_| true).unwrap();
+                self.sender.send(upload_request).unwrap();
+                net::UploadResult::http_status(200)
+            })
+        }
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_time()
+        .build()
+        .unwrap();
+
+    // Create a custom configuration to use a fake uploader.
+    let dir = tempfile::tempdir().unwrap();
+    let tmpname = dir.path().to_path_buf();
+
+    let cfg = ConfigurationBuilder::new(true, tmpname, GLOBAL_APPLICATION_ID)
+        .with_server_endpoint("invalid-test-host")
+        .with_async_uploader(FakeUploader { sender: s }, runtime.handle().clone())
+        .build();
+
+    glean_core::glean_set_test_mode(true);
+    let _t = new_glean(Some(cfg), true);
+
+    // Define a new ping and submit it.
+    const PING_NAME: &str = "test-ping";
+    let custom_ping = new_test_ping(PING_NAME);
+    custom_ping.submit(None);
+
+    // Wait for the ping to arrive.
+    let upload_request = r.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(upload_request.ping_name, PING_NAME);
+    assert!(upload_request.url.starts_with("invalid-test-host/submit/"));
+}
+
 #[test]
 fn send_a_ping_without_info_sections() {
     let _lock = lock_test();
@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Check that the parts of a ping split for exceeding the maximum body size
+//! validate against the ping schema.
+
+mod common;
+use crate::common::*;
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use jsonschema_valid::schemas::Draft;
+use serde_json::Value;
+
+use glean::net::{CapablePingUploadRequest, UploadResult};
+use glean::private::*;
+use glean::{CommonMetricData, ConfigurationBuilder};
+
+const SCHEMA_JSON: &str = include_str!("../../../glean.1.schema.json");
+
+const GLOBAL_APPLICATION_ID: &str = "org.mozilla.glean.test.app";
+
+/// A string of pseudo-random alphanumeric characters, which doesn't compress well.
+fn incompressible_text(len: usize, seed: u64) -> String {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            CHARS[(state >> 33) as usize % CHARS.len()] as char
+        })
+        .collect()
+}
+
+#[test]
+fn split_ping_parts_validate_against_schema() {
+    common::enable_test_logging();
+
+    let schema: Value = serde_json::from_str(SCHEMA_JSON).unwrap();
+
+    let (s, r) = crossbeam_channel::unbounded::<Vec<u8>>();
+
+    #[derive(Debug)]
+    pub struct ValidatingUploader {
+        sender: crossbeam_channel::Sender<Vec<u8>>,
+    }
+    impl glean::net::PingUploader for ValidatingUploader {
+        fn upload(&self, ping_request: CapablePingUploadRequest) -> UploadResult {
+            let ping_request = ping_request.capable(|_| true).unwrap();
+            // Other pings, e.g. the `health` ping, are sent on initialization.
+            if ping_request.url.contains("/test-ping/") {
+                self.sender.send(ping_request.body).unwrap();
+            }
+            UploadResult::http_status(200)
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let tmpname = dir.path().to_path_buf();
+
+    let cfg = ConfigurationBuilder::new(true, tmpname, GLOBAL_APPLICATION_ID)
+        .with_server_endpoint("invalid-test-host")
+        .with_uploader(ValidatingUploader { sender: s })
+        .build();
+    common::initialize(cfg);
+
+    const PING_NAME: &str = "test-ping";
+    let custom_ping = PingBuilder::new(PING_NAME).build();
+
+    // 10 texts of 200kB each exceed the maximum body size of 1MB, even compressed.
+    for i in 0..10 {
+        let text = TextMetric::new(CommonMetricData {
+            category: "test".into(),
+            name: format!("text{i}"),
+            send_in_pings: vec![PING_NAME.into()],
+            ..Default::default()
+        });
+        text.set(incompressible_text(200 * 1024, i));
+    }
+
+    custom_ping.submit(None);
+
+    let cfg = jsonschema_valid::Config::from_schema(&schema, Some(Draft::Draft6)).unwrap();
+    let mut parts = vec![];
+    loop {
+        let raw_body = r.recv().unwrap();
+        let mut s = String::new();
+        GzDecoder::new(&raw_body[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        let data: Value = serde_json::from_str(&s).unwrap();
+
+        if let Err(errors) = cfg.validate(&data) {
+            let errors: Vec<_> = errors.map(|e| e.to_string()).collect();
+            panic!(
+                "Part {} is invalid:\n{}",
+                parts.len() + 1,
+                errors.join("\n")
+            );
+        }
+
+        let count = data["metrics"]["quantity"]["glean.ping.split_parts"]
+            .as_u64()
+            .expect("the ping should have been split");
+        parts.push(data);
+        if parts.len() as u64 == count {
+            break;
+        }
+    }
+
+    assert!(parts.len() > 1);
+    let parent_id = &parts[0]["metrics"]["uuid"]["glean.ping.split_parent_id"];
+    for (index, part) in parts.iter().enumerate() {
+        assert_eq!(
+            parent_id,
+            &part["metrics"]["uuid"]["glean.ping.split_parent_id"]
+        );
+        assert_eq!(
+            index as u64 + 1,
+            part["metrics"]["quantity"]["glean.ping.split_part"]
+        );
+    }
+    let texts: usize = parts
+        .iter()
+        .map(|part| part["metrics"]["text"].as_object().map_or(0, |t| t.len()))
+        .sum();
+    assert_eq!(10, texts);
+}
@@ -0,0 +1,268 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Backup and restore of the data directory.
+//!
+//! A backup is a directory with the same layout as the data directory.
+//! The database is copied with SQLite's online backup, so a backup can be taken while Glean is running.
+//! Everything else, i.e. event files waiting to be imported, pending pings and `client_id.txt`,
+//! is copied as is.
+//!
+//! Encrypted data stays encrypted: a backup can only be restored with the same key.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::database::sqlite::validate_database;
+use crate::{Glean, Result};
+
+/// The directory of the database, relative to the data directory.
+const DATABASE_DIRECTORY: &str = "db";
+/// The database file.
+const DATABASE_FILE_NAME: &str = "glean.sqlite";
+/// Where a backup is staged while it is restored, relative to the data directory.
+const RESTORE_DIRECTORY: &str = "restore";
+/// The staged backup while it is moved into the data directory, relative to the data directory.
+const RESTORING_DIRECTORY: &str = "restoring";
+/// The current data while it is replaced by a backup, relative to the data directory.
+const REPLACED_DIRECTORY: &str = "replaced";
+/// Partially written files, relative to the data directory.
+const TEMP_DIRECTORY: &str = "tmp";
+
+/// Creates a backup of the data directory of a running Glean.
+///
+/// If Glean keeps its data in memory, the backup is a data directory with that data.
+/// An existing backup is never overwritten.
+/// Nothing is left behind if the backup fails.
+///
+/// # Arguments
+///
+/// * `glean` - The Glean instance to back up.
+/// * `data_path` - The data directory.
+/// * `backup_path` - The directory to create the backup in.
+///   It must not exist and must be outside the data directory.
+pub(crate) fn create(glean: &Glean, data_path: &Path, backup_path: &Path) -> Result<()> {
+    let Some(database) = glean.storage_opt() else {
+        return Err(crate::Error::not_initialized());
+    };
+
+    // A backup inside the data directory would be copied into itself.
+    if canonicalize(backup_path)?.starts_with(canonicalize(data_path)?) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The backup path is inside the data directory",
+        )
+        .into());
+    }
+
+    fs::create_dir(backup_path)?;
+    let result = (|| {
+        let db_dir = backup_path.join(DATABASE_DIRECTORY);
+        fs::create_dir(&db_dir)?;
+        database.backup(glean, &db_dir.join(DATABASE_FILE_NAME))?;
+
+        if glean.is_in_memory() {
+            // Events are kept in the database, only the pending pings are left to write out.
+            return glean
+                .upload_manager
+                .write_pending_pings_in_memory(backup_path)
+                .map_err(Into::into);
+        }
+
+        // Pings may be uploaded and deleted while copying.
+        copy_dir(
+            data_path,
+            backup_path,
+            &[
+                DATABASE_DIRECTORY,
+                RESTORE_DIRECTORY,
+                RESTORING_DIRECTORY,
+                REPLACED_DIRECTORY,
+                TEMP_DIRECTORY,
+            ],
+            true,
+        )
+    })();
+
+    if result.is_err() {
+        _ = fs::remove_dir_all(backup_path);
+    }
+    result
+}
+
+/// Restores a backup into a data directory.
+///
+/// Glean must not be running on the data directory.
+///
+/// The backup is copied into the data directory first and its database is upgraded and validated.
+/// If the backup is invalid, the current data is untouched.
+/// Only then the current data is moved aside and replaced, and only removed once that succeeded.
+/// If replacing fails or is interrupted, the current data is put back,
+/// here or by [`recover`] on the next restore or start of Glean.
+///
+/// # Arguments
+///
+/// * `backup_path` - The backup, as created by [`Glean::backup`].
+/// * `data_path` - The data directory to restore into. It is created if it doesn't exist.
+pub fn restore(backup_path: &Path, data_path: &Path) -> Result<()> {
+    recover(data_path)?;
+
+    let staging = data_path.join(RESTORE_DIRECTORY);
+    fs::create_dir_all(&staging)?;
+
+    let staged = copy_dir(backup_path, &staging, &[], false).and_then(|_| {
+        validate_database(&staging.join(DATABASE_DIRECTORY).join(DATABASE_FILE_NAME))
+    });
+    if let Err(e) = staged {
+        log::error!(
+            "Not restoring invalid backup {}: {}",
+            backup_path.display(),
+            e
+        );
+        _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    let replaced = data_path.join(REPLACED_DIRECTORY);
+    let restoring = data_path.join(RESTORING_DIRECTORY);
+    let swapped = (|| {
+        fs::create_dir(&replaced)?;
+        move_entries(data_path, &replaced)?;
+        // Marks that all of the current data was moved aside.
+        fs::rename(&staging, &restoring)?;
+        move_entries(&restoring, data_path)?;
+        // Marks that all of the backup was moved in.
+        fs::remove_dir(&restoring).map_err(Into::into)
+    })();
+    if let Err(e) = swapped {
+        log::error!("Failed to restore backup {}: {}", backup_path.display(), e);
+        if let Err(e) = recover(data_path) {
+            log::error!("Failed to put back the replaced data: {}", e);
+        }
+        return Err(e);
+    }
+
+    fs::remove_dir_all(&replaced)?;
+    Ok(())
+}
+
+/// Finishes or rolls back an interrupted restore of a backup.
+///
+/// Which step was interrupted is told by the directories that exist:
+///
+/// * Only the replaced data: the backup was completely moved in, the replaced data is removed.
+/// * The replaced data and the backup while it is moved in:
+///   the backup is moved out again, which leads to the next case.
+/// * The replaced data and the staged backup: the replaced data is moved back.
+/// * Only the staged backup: the current data was never touched, the staged backup is removed.
+///
+/// # Arguments
+///
+/// * `data_path` - The data directory.
+pub(crate) fn recover(data_path: &Path) -> Result<()> {
+    let staging = data_path.join(RESTORE_DIRECTORY);
+    let restoring = data_path.join(RESTORING_DIRECTORY);
+    let replaced = data_path.join(REPLACED_DIRECTORY);
+
+    if replaced.exists() {
+        if restoring.exists() {
+            log::info!("Rolling back an interrupted restore of a backup.");
+            move_entries(data_path, &restoring)?;
+            fs::rename(&restoring, &staging)?;
+        }
+        if staging.exists() {
+            move_entries(&replaced, data_path)?;
+        }
+        fs::remove_dir_all(&replaced)?;
+    }
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    Ok(())
+}
+
+/// Moves the entries of a directory into another one,
+/// except for the directories used while restoring a backup.
+///
+/// # Arguments
+///
+/// * `from` - The directory to move the entries of.
+/// * `to` - The existing directory to move the entries into.
+fn move_entries(from: &Path, to: &Path) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if [RESTORE_DIRECTORY, RESTORING_DIRECTORY, REPLACED_DIRECTORY]
+            .iter()
+            .any(|skipped| name == *skipped)
+        {
+            continue;
+        }
+        fs::rename(entry.path(), to.join(name))?;
+    }
+    Ok(())
+}
+
+/// Recursively copies the contents of a directory.
+///
+/// # Arguments
+///
+/// * `from` - The directory to copy.
+/// * `to` - The existing directory to copy into.
+/// * `skip` - Entries of `from` not to copy.
+/// * `skip_missing` - Whether to skip files that disappear while copying.
+fn copy_dir(from: &Path, to: &Path, skip: &[&str], skip_missing: bool) -> Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if skip.iter().any(|skipped| name == *skipped) {
+            continue;
+        }
+
+        let dest = to.join(&name);
+        let copied = if entry.file_type()?.is_dir() {
+            fs::create_dir(&dest)
+                .map_err(Into::into)
+                .and_then(|_| copy_dir(&entry.path(), &dest, &[], skip_missing))
+        } else {
+            fs::copy(entry.path(), &dest)
+                .map(|_| ())
+                .map_err(Into::into)
+        };
+
+        match copied {
+            Err(e) if skip_missing && is_not_found(&e) => {}
+            other => other?,
+        }
+    }
+    Ok(())
+}
+
+/// Canonicalizes a path that might not exist (yet).
+///
+/// The longest existing prefix is canonicalized, the rest is appended as is.
+fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut missing = vec![];
+    let mut existing = path.as_path();
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(canonical) => {
+                return Ok(missing.into_iter().rev().fold(canonical, |p, c| p.join(c)));
+            }
+            Err(e) => match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name);
+                    existing = parent;
+                }
+                _ => return Err(e),
+            },
+        }
+    }
+}
+
+fn is_not_found(err: &crate::Error) -> bool {
+    matches!(err.kind(), crate::ErrorKind::IoError(e) if e.kind() == io::ErrorKind::NotFound)
+}
@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -17,6 +17,7 @@ use uuid::Uuid;
 
 use crate::database::sqlite::{Database, MigrationResult};
 use crate::debug::DebugOptions;
+use crate::encryption::{self, Cipher};
 use crate::error::ClientIdFileError;
 use crate::event_database::EventDatabase;
 use crate::internal_metrics::{
@@ -24,17 +25,22 @@ use crate::internal_metrics::{
 };
 use crate::internal_pings::InternalPings;
 use crate::metrics::{
-    self, ExperimentMetric, Metric, MetricType, PingType, RecordedExperiment, RemoteSettingsConfig,
+    self, ExperimentMetric, Metric, MetricType, PingSchedule, PingType, RecordedExperiment,
+    RemoteSettingsConfig,
 };
 use crate::ping::PingMaker;
 use crate::session::{self, EventSessionContext, SessionManager, SessionMode, SessionState};
 use crate::storage::{StorageManager, INTERNAL_STORAGE};
-use crate::upload::{PingUploadManager, PingUploadTask, UploadResult, UploadTaskAction};
+use crate::upload::{
+    ContentEncoding, NetworkType, PingUploadBatchTask, PingUploadManager, PingUploadTask,
+    QuarantinedPing, UploadConstraints, UploadResult, UploadTaskAction,
+};
 use crate::util::{local_now_with_offset, sanitize_application_id, truncate_string_at_boundary};
 use crate::{
-    scheduler, system, AttributionMetrics, CommonMetricData, DistributionMetrics, ErrorKind,
-    InternalConfiguration, Lifetime, PingRateLimit, Result, DEFAULT_MAX_EVENTS,
-    GLEAN_SCHEMA_VERSION, GLEAN_VERSION, KNOWN_CLIENT_ID,
+    maintenance, ping_scheduler, scheduler, system, AttributionMetrics, CommonMetricData,
+    DistributionMetrics, ErrorKind, InternalConfiguration, Lifetime, MetricsPingSchedule,
+    PingRateLimit, Result, DEFAULT_MAX_EVENTS, GLEAN_SCHEMA_VERSION, GLEAN_VERSION,
+    KNOWN_CLIENT_ID,
 };
 
 const CLIENT_ID_PLAIN_FILENAME: &str = "client_id.txt";
@@ -134,6 +140,8 @@ where
 ///     trim_data_to_registered_pings: false,
 ///     log_level: None,
 ///     rate_limit: None,
+///     upload_backoff: None,
+///     max_quarantined_pings: None,
 ///     enable_event_timestamps: true,
 ///     experimentation_id: None,
 ///     enable_internal_pings: true,
@@ -146,6 +154,11 @@ where
 ///     session_sample_rate: 1.0,
 ///     session_inactivity_timeout_ms: 1_800_000,
 ///     events_ping_acceleration_factor: None,
+///     encryption_key_provider: None,
+///     max_database_size: None,
+///     in_memory: false,
+///     database_maintenance_interval_ms: None,
+///     metrics_ping_schedule: None,
 /// };
 /// let mut glean = Glean::new(cfg).unwrap();
 /// let ping = PingType::new("sample", true, false, true, true, true, vec![], vec![], true, vec![]);
@@ -196,6 +209,17 @@ pub struct Glean {
     #[ignore_malloc_size_of = "TODO: Expose session memory allocations (bug 2043355)"]
     pub(crate) session_manager: SessionManager,
     events_ping_acceleration_factor: Option<usize>,
+    metrics_ping_schedule: Option<MetricsPingSchedule>,
+    #[ignore_malloc_size_of = "holds no heap allocations of its own"]
+    cipher: Option<Arc<Cipher>>,
+    helper_process: bool,
+    in_memory: bool,
+    #[ignore_malloc_size_of = "external non-allocating type"]
+    database_maintenance_interval: Option<Duration>,
+    /// Whether pings are being submitted because they reached their capacity.
+    submitting_at_capacity: AtomicBool,
+    /// Whether pings were submitted because they reached their capacity, since last checked.
+    submitted_at_capacity: AtomicBool,
 }
 
 impl Glean {
@@ -211,11 +235,25 @@ impl Glean {
             return Err(ErrorKind::InvalidConfig.into());
         }
 
-        let data_path = Path::new(&cfg.data_path);
-        let event_data_store = EventDatabase::new(data_path)?;
+        let (cipher, event_data_store, mut upload_manager) = if cfg.in_memory {
+            // Nothing is stored at rest, so there's nothing to encrypt.
+            (
+                None,
+                EventDatabase::new_in_memory(),
+                PingUploadManager::new_in_memory(&cfg.language_binding_name),
+            )
+        } else {
+            let data_path = Path::new(&cfg.data_path);
+            // Without the key we can't read encrypted data, nor should we write any plaintext.
+            let cipher = encryption::load_cipher(cfg, data_path)?.map(Arc::new);
+            let event_data_store = EventDatabase::new(data_path, cipher.clone())?;
+            let mut upload_manager =
+                PingUploadManager::new(&cfg.data_path, &cfg.language_binding_name);
+            upload_manager.set_cipher(cipher.clone());
+            (cipher, event_data_store, upload_manager)
+        };
 
-        // Create an upload manager with rate limiting of 15 pings every 60 seconds.
-        let mut upload_manager = PingUploadManager::new(&cfg.data_path, &cfg.language_binding_name);
+        // Set up rate limiting of 15 pings every 60 seconds by default.
         let rate_limit = cfg.rate_limit.as_ref().unwrap_or(&PingRateLimit {
             seconds_per_interval: DEFAULT_SECONDS_PER_INTERVAL,
             pings_per_interval: DEFAULT_PINGS_PER_INTERVAL,
@@ -230,6 +268,12 @@ impl Glean {
         if let Some(n) = cfg.max_pending_pings_directory_size {
             upload_manager.set_max_pending_pings_directory_size(n);
         }
+        if let Some(backoff) = &cfg.upload_backoff {
+            upload_manager.set_backoff(backoff.clone());
+        }
+        if let Some(n) = cfg.max_quarantined_pings {
+            upload_manager.set_max_quarantined_pings(n);
+        }
 
         // We only scan the pending ping directories when calling this from a subprocess,
         // when calling this from ::new we need to scan the directories after dealing with the upload state.
@@ -279,6 +323,14 @@ impl Glean {
             events_ping_acceleration_factor: cfg
                 .events_ping_acceleration_factor
                 .map(|x| x as usize),
+            metrics_ping_schedule: cfg.metrics_ping_schedule.clone(),
+            cipher,
+            helper_process: false,
+            in_memory: cfg.in_memory,
+            // Subprocesses leave the database maintenance to the primary process.
+            database_maintenance_interval: None,
+            submitting_at_capacity: AtomicBool::new(false),
+            submitted_at_capacity: AtomicBool::new(false),
         };
 
         // Ensuring these pings are registered.
@@ -292,12 +344,65 @@ impl Glean {
         Ok(this)
     }
 
+    /// Creates and initializes a new Glean object for a helper process.
+    ///
+    /// A helper process records metrics and events into the database of a primary process,
+    /// created with [`Glean::new`] on the same data path, while both are running.
+    /// Only the primary process assembles and uploads pings:
+    /// a helper process never submits pings, changes the upload state or tracks sessions.
+    ///
+    /// Events recorded by a helper process use the primary process' `execution_counter`
+    /// of their stores and are timestamped with a clock shared by all processes since boot,
+    /// so they are ordered with the primary's events without marking a restart.
+    ///
+    /// # Arguments
+    ///
+    /// * `cfg` - The configuration of the primary process.
+    ///   Upload is disabled if it is disabled in the primary process,
+    ///   or if the primary process was never initialized.
+    ///   The data of an in-memory primary process can't be shared.
+    pub fn new_for_helper_process(cfg: &InternalConfiguration) -> Result<Self> {
+        if cfg.in_memory {
+            return Err(ErrorKind::InvalidConfig.into());
+        }
+        let mut glean = Self::new_for_subprocess(cfg, false)?;
+        glean.helper_process = true;
+        glean.data_store = Some(Database::open_shared(
+            Path::new(&cfg.data_path),
+            glean.cipher.clone(),
+            cfg.max_database_size,
+        )?);
+
+        // The primary process removes the client ID when upload is disabled.
+        // Without one it is disabled or not initialized yet, either way nothing should be recorded.
+        match glean
+            .core_metrics
+            .client_id
+            .get_value(&glean, Some("glean_client_info"))
+        {
+            Some(client_id) if client_id != *KNOWN_CLIENT_ID => {}
+            _ => {
+                log::info!("Upload is disabled in the primary process.");
+                glean.upload_enabled = false;
+            }
+        }
+
+        Ok(glean)
+    }
+
     /// Creates and initializes a new Glean object.
     ///
     /// This will create the necessary directories and files in
-    /// [`cfg.data_path`](InternalConfiguration::data_path). This will also initialize
-    /// the core metrics.
+    /// [`cfg.data_path`](InternalConfiguration::data_path),
+    /// unless all data is kept [in memory](InternalConfiguration::in_memory).
+    /// This will also initialize the core metrics.
     pub fn new(cfg: InternalConfiguration) -> Result<Self> {
+        if !cfg.in_memory {
+            // Before anything is read from the data directory.
+            if let Err(e) = crate::backup::recover(Path::new(&cfg.data_path)) {
+                log::error!("Failed to recover from an interrupted restore of a backup: {e}");
+            }
+        }
         let mut glean = Self::new_for_subprocess(&cfg, false)?;
 
         // Creating the data store creates the necessary path as well.
@@ -305,12 +410,29 @@ impl Glean {
         let data_path = Path::new(&cfg.data_path);
         let ping_lifetime_threshold = cfg.ping_lifetime_threshold as usize;
         let ping_lifetime_max_time = Duration::from_millis(cfg.ping_lifetime_max_time);
-        glean.data_store = Some(Database::new(
-            data_path,
-            cfg.delay_ping_lifetime_io,
-            ping_lifetime_threshold,
-            ping_lifetime_max_time,
-        )?);
+        glean.data_store = Some(if cfg.in_memory {
+            Database::new_in_memory(
+                cfg.delay_ping_lifetime_io,
+                ping_lifetime_threshold,
+                ping_lifetime_max_time,
+                cfg.max_database_size,
+            )?
+        } else {
+            Database::new(
+                data_path,
+                cfg.delay_ping_lifetime_io,
+                ping_lifetime_threshold,
+                ping_lifetime_max_time,
+                glean.cipher.clone(),
+                cfg.max_database_size,
+            )?
+        });
+
+        if let Err(e) = glean.encrypt_plaintext_data() {
+            log::error!(
+                "Failed to encrypt existing data. Will retry on the next start. Error: {e}"
+            );
+        }
 
         if let Some(state) = glean.data_store.as_mut().unwrap().migration_state.take() {
             glean
@@ -345,55 +467,60 @@ impl Glean {
         // We don't have the database yet when we first encounter the error,
         // so we store it and apply it later.
         // state (a)
-        let stored_client_id = match glean.client_id_from_file() {
-            Ok(id) if id == *KNOWN_CLIENT_ID => {
-                glean
-                    .health_metrics
-                    .file_read_error
-                    .get("c0ffee-in-file")
-                    .add_sync(&glean, 1);
-                None
-            }
-            Ok(id) => Some(id),
-            Err(ClientIdFileError::NotFound) => {
-                // That's ok, the file might just not exist yet.
-                glean
-                    .health_metrics
-                    .file_read_error
-                    .get("file-not-found")
-                    .add_sync(&glean, 1);
-                None
-            }
-            Err(ClientIdFileError::PermissionDenied) => {
-                // state (b)
-                // Uhm ... who removed our permission?
-                glean
-                    .health_metrics
-                    .file_read_error
-                    .get("permission-denied")
-                    .add_sync(&glean, 1);
-                None
-            }
-            Err(ClientIdFileError::ParseError(e)) => {
-                // state (b)
-                log::trace!("reading cliend_id.txt. Could not parse into UUID: {e}");
-                glean
-                    .health_metrics
-                    .file_read_error
-                    .get("parse")
-                    .add_sync(&glean, 1);
-                None
-            }
-            Err(ClientIdFileError::IoError(e)) => {
-                // state (b)
-                // We can't handle other IO errors (most couldn't occur on this operation anyway)
-                log::trace!("reading client_id.txt. Unexpected io error: {e}");
-                glean
-                    .health_metrics
-                    .file_read_error
-                    .get("io")
-                    .add_sync(&glean, 1);
-                None
+        let stored_client_id = if glean.in_memory {
+            // Nothing is stored on disk.
+            None
+        } else {
+            match glean.client_id_from_file() {
+                Ok(id) if id == *KNOWN_CLIENT_ID => {
+                    glean
+                        .health_metrics
+                        .file_read_error
+                        .get("c0ffee-in-file")
+                        .add_sync(&glean, 1);
+                    None
+                }
+                Ok(id) => Some(id),
+                Err(ClientIdFileError::NotFound) => {
+                    // That's ok, the file might just not exist yet.
+                    glean
+                        .health_metrics
+                        .file_read_error
+                        .get("file-not-found")
+                        .add_sync(&glean, 1);
+                    None
+                }
+                Err(ClientIdFileError::PermissionDenied) => {
+                    // state (b)
+                    // Uhm ... who removed our permission?
+                    glean
+                        .health_metrics
+                        .file_read_error
+                        .get("permission-denied")
+                        .add_sync(&glean, 1);
+                    None
+                }
+                Err(ClientIdFileError::ParseError(e)) => {
+                    // state (b)
+                    log::trace!("reading cliend_id.txt. Could not parse into UUID: {e}");
+                    glean
+                        .health_metrics
+                        .file_read_error
+                        .get("parse")
+                        .add_sync(&glean, 1);
+                    None
+                }
+                Err(ClientIdFileError::IoError(e)) => {
+                    // state (b)
+                    // We can't handle other IO errors (most couldn't occur on this operation anyway)
+                    log::trace!("reading client_id.txt. Unexpected io error: {e}");
+                    glean
+                        .health_metrics
+                        .file_read_error
+                        .get("io")
+                        .add_sync(&glean, 1);
+                    None
+                }
             }
         };
 
@@ -562,6 +689,10 @@ impl Glean {
         // If internal pings are disabled, we don't set up the MPS either,
         // it wouldn't send any data anyway.
         glean.schedule_metrics_pings = cfg.enable_internal_pings && cfg.use_core_mps;
+        glean.database_maintenance_interval = cfg
+            .database_maintenance_interval_ms
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis);
 
         // We only scan the pendings pings directories **after** dealing with the upload state.
         // If upload is disabled, we delete all pending pings files
@@ -592,6 +723,8 @@ impl Glean {
             trim_data_to_registered_pings: false,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: true,
             experimentation_id: None,
             enable_internal_pings,
@@ -604,6 +737,11 @@ impl Glean {
             session_sample_rate: 1.0,
             session_inactivity_timeout_ms: 1_800_000,
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval_ms: None,
+            metrics_ping_schedule: None,
         };
 
         let mut glean = Self::new(cfg).unwrap();
@@ -629,6 +767,9 @@ impl Glean {
     ///
     /// Use `store_client_id_with_reporting` to handle the error cases.
     fn store_client_id(&self, client_id: Uuid) -> Result<(), ClientIdFileError> {
+        if self.in_memory {
+            return Ok(());
+        }
         let mut fp = File::create(self.client_id_file_path())?;
 
         let mut buffer = Uuid::encode_buffer();
@@ -688,6 +829,9 @@ impl Glean {
     /// Remove the stored client ID from disk.
     /// Should only be called when the client ID is also removed from the database.
     fn remove_stored_client_id(&self) -> Result<(), ClientIdFileError> {
+        if self.in_memory {
+            return Ok(());
+        }
         match fs::remove_file(self.client_id_file_path()) {
             Ok(()) => Ok(()),
             Err(e) if e.kind() == io::ErrorKind::NotFound => {
@@ -753,6 +897,24 @@ impl Glean {
             let load_state = truncate_string_at_boundary(load_state, MAX_LENGTH_VALUE);
             self.database_metrics.load_error.set_sync(self, load_state)
         }
+
+        if let Some((rows, lost_rows)) = self
+            .data_store
+            .as_ref()
+            .and_then(|database| database.salvage())
+            .map(|salvage| (salvage.rows, salvage.lost_rows))
+        {
+            if rows > 0 {
+                self.database_metrics
+                    .salvaged_rows
+                    .add_sync(self, rows.try_into().unwrap_or(i32::MAX));
+            }
+            if lost_rows > 0 {
+                self.database_metrics
+                    .lost_rows
+                    .add_sync(self, lost_rows.try_into().unwrap_or(i32::MAX));
+            }
+        }
     }
 
     /// Signals that the environment is ready to submit pings.
@@ -805,6 +967,11 @@ impl Glean {
     pub fn set_upload_enabled(&mut self, flag: bool) -> bool {
         log::info!("Upload enabled: {:?}", flag);
 
+        if self.helper_process {
+            log::warn!("Upload can only be enabled or disabled in the primary process.");
+            return false;
+        }
+
         if self.upload_enabled != flag {
             if flag {
                 self.on_upload_enabled();
@@ -834,9 +1001,11 @@ impl Glean {
             }
             let ping_maker = PingMaker::new();
             let disabled_pings = &[ping.name()][..];
-            if let Err(err) = ping_maker.clear_pending_pings(self.get_data_path(), disabled_pings) {
+            if let Err(err) = ping_maker.clear_pending_pings(self, disabled_pings) {
                 log::warn!("Error clearing pending pings: {}", err);
             }
+            self.upload_manager
+                .purge_quarantined_pings(Some(disabled_pings));
         }
     }
 
@@ -930,9 +1099,10 @@ impl Glean {
             .filter(|&(_ping_name, ping)| ping.follows_collection_enabled())
             .map(|(ping_name, _ping)| &ping_name[..])
             .collect::<Vec<_>>();
-        if let Err(err) = ping_maker.clear_pending_pings(self.get_data_path(), &disabled_pings) {
+        if let Err(err) = ping_maker.clear_pending_pings(self, &disabled_pings) {
             log::warn!("Error clearing pending pings: {}", err);
         }
+        self.upload_manager.purge_quarantined_pings(None);
 
         if let Err(e) = self.remove_stored_client_id() {
             log::error!("Couldn't remove client ID on disk. This might lead to a resurrection of this client ID later. Error: {e}");
@@ -973,7 +1143,7 @@ impl Glean {
                 }
             }
         }
-        if let Err(err) = self.event_data_store.clear_all() {
+        if let Err(err) = self.event_data_store.clear_all(self) {
             log::warn!("Error clearing pending events: {}", err);
         }
 
@@ -993,6 +1163,30 @@ impl Glean {
         &self.data_path
     }
 
+    /// Gets the cipher to encrypt data at rest with, if encryption is enabled.
+    pub(crate) fn cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_deref()
+    }
+
+    /// Encrypts all data stored in plaintext, when encryption at rest was newly enabled.
+    ///
+    /// The data directory is only marked as encrypted once everything was encrypted,
+    /// so that an interrupted migration continues on the next start.
+    fn encrypt_plaintext_data(&self) -> Result<()> {
+        let Some(cipher) = self.cipher() else {
+            return Ok(());
+        };
+        if encryption::is_migrated(&self.data_path) {
+            return Ok(());
+        }
+
+        log::info!("Encrypting existing data");
+        self.storage().encrypt_plaintext_values()?;
+        self.event_data_store.encrypt_plaintext_files()?;
+        self.upload_manager.encrypt_plaintext_ping_files(cipher)?;
+        encryption::mark_migrated(cipher, &self.data_path)
+    }
+
     /// Gets a handle to the database.
     #[track_caller] // If this fails we're interested in the caller.
     pub fn storage(&self) -> &Database {
@@ -1040,6 +1234,40 @@ impl Glean {
         }
     }
 
+    /// Gets when to schedule the "metrics" ping.
+    ///
+    /// A schedule from the Server Knobs takes precedence over the configured one.
+    /// An hour out of range is replaced by the default hour,
+    /// a spread past midnight is shortened to end just before it.
+    pub fn get_metrics_ping_schedule(&self) -> MetricsPingSchedule {
+        let mut schedule = self
+            .remote_settings_config
+            .lock()
+            .unwrap()
+            .metrics_ping_schedule
+            .clone()
+            .or_else(|| self.metrics_ping_schedule.clone())
+            .unwrap_or_default();
+
+        if schedule.hour > 23 {
+            log::warn!(
+                "Invalid hour {} to schedule the 'metrics' ping at, using the default",
+                schedule.hour
+            );
+            schedule.hour = MetricsPingSchedule::default().hour;
+        }
+        let max_spread_seconds = scheduler::max_spread_seconds(schedule.hour);
+        if schedule.spread_seconds > max_spread_seconds {
+            log::warn!(
+                "Spread of {}s to schedule the 'metrics' ping over is too long, using {}s",
+                schedule.spread_seconds,
+                max_spread_seconds
+            );
+            schedule.spread_seconds = max_spread_seconds;
+        }
+        schedule
+    }
+
     /// Gets the next task for an uploader.
     ///
     /// This can be one of:
@@ -1058,6 +1286,22 @@ impl Glean {
         self.upload_manager.get_upload_task(self, self.log_pings())
     }
 
+    /// Gets the next task for an uploader uploading multiple pings at once.
+    ///
+    /// See [`get_upload_task`](Glean::get_upload_task).
+    ///
+    /// # Arguments
+    ///
+    /// * `max_pings` - The maximum number of pings to upload in one batch.
+    ///
+    /// # Returns
+    ///
+    /// A [`PingUploadBatchTask`] representing the next task.
+    pub fn get_upload_batch(&self, max_pings: usize) -> PingUploadBatchTask {
+        self.upload_manager
+            .get_upload_batch(self, max_pings, self.log_pings())
+    }
+
     /// Processes the response from an attempt to upload a ping.
     ///
     /// # Arguments
@@ -1073,6 +1317,19 @@ impl Glean {
             .process_ping_upload_response(self, uuid, status)
     }
 
+    /// Processes the responses from an attempt to upload a batch of pings.
+    ///
+    /// # Arguments
+    ///
+    /// * `results` - The upload result for each ping in the batch, by UUID.
+    pub fn process_ping_upload_batch_response(
+        &self,
+        results: HashMap<String, UploadResult>,
+    ) -> UploadTaskAction {
+        self.upload_manager
+            .process_ping_upload_batch_response(self, results)
+    }
+
     /// Takes a snapshot for the given store and optionally clear it.
     ///
     /// # Arguments
@@ -1129,6 +1386,113 @@ impl Glean {
         }
     }
 
+    /// Assembles the payload a ping would have if it was submitted now, without submitting it.
+    ///
+    /// The ping's data is not cleared and its sequence number and start time are not advanced,
+    /// so the next submitted ping contains everything shown here. See [`PingMaker::preview`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping to preview.
+    ///
+    /// # Returns
+    ///
+    /// The ping payload as pretty-printed JSON, or `None` if the ping is unknown,
+    /// disabled or wouldn't be sent because it has no data.
+    pub fn preview_ping(&self, ping_name: &str) -> Option<String> {
+        let Some(ping) = self.get_ping_by_name(ping_name) else {
+            log::error!("Attempted to preview unknown ping '{}'", ping_name);
+            return None;
+        };
+        if !ping.enabled(self) {
+            log::info!("The ping '{}' is disabled and would not be sent", ping_name);
+            return None;
+        }
+
+        PingMaker::new()
+            .preview(self, ping, None)
+            .map(|content| ::serde_json::to_string_pretty(&content).unwrap())
+    }
+
+    /// Submits the pings that reached any of their capacity thresholds,
+    /// with the reason `max_capacity`.
+    ///
+    /// See [`PingType::set_capacity`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_names` - The pings data was just recorded into.
+    ///
+    /// # Returns
+    ///
+    /// The names of the submitted pings.
+    pub(crate) fn submit_pings_at_capacity<'a>(
+        &self,
+        ping_names: &'a [impl AsRef<str>],
+    ) -> Vec<&'a str> {
+        // Only the primary process submits pings.
+        if self.helper_process {
+            return Vec::new();
+        }
+        // Collecting a ping might record into it, don't submit it again meanwhile.
+        if self
+            .submitting_at_capacity
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Vec::new();
+        }
+
+        let mut submitted = Vec::new();
+        for ping_name in ping_names {
+            let ping_name = ping_name.as_ref();
+            let Some(capacity) = self
+                .get_ping_by_name(ping_name)
+                .and_then(PingType::capacity)
+            else {
+                continue;
+            };
+            let Some(database) = self.storage_opt() else {
+                break;
+            };
+            // Measuring is expensive, only do it when the estimate reaches the capacity.
+            if database
+                .estimated_ping_usage(ping_name)
+                .is_some_and(|estimate| !capacity.is_reached(&estimate))
+            {
+                continue;
+            }
+            let usage = match database.ping_usage(ping_name) {
+                Ok(usage) => usage,
+                Err(e) => {
+                    log::warn!("Failed to measure the '{}' ping: {:?}", ping_name, e);
+                    continue;
+                }
+            };
+            if capacity.is_reached(&usage) {
+                log::info!(
+                    "The '{}' ping reached its capacity ({:?}), submitting it",
+                    ping_name,
+                    usage
+                );
+                if self.submit_ping_by_name(ping_name, Some("max_capacity")) {
+                    submitted.push(ping_name);
+                }
+            }
+        }
+
+        self.submitting_at_capacity.store(false, Ordering::SeqCst);
+        if !submitted.is_empty() {
+            self.submitted_at_capacity.store(true, Ordering::SeqCst);
+        }
+        submitted
+    }
+
+    /// Whether pings were submitted because they reached their capacity since last called.
+    pub(crate) fn take_submitted_at_capacity(&self) -> bool {
+        self.submitted_at_capacity.swap(false, Ordering::SeqCst)
+    }
+
     /// Gets a [`PingType`] by name.
     ///
     /// # Returns
@@ -1221,6 +1585,7 @@ impl Glean {
     ///
     /// * `cfg` - The stringified JSON representation of a `RemoteSettingsConfig` object
     pub fn apply_server_knobs_config(&self, cfg: RemoteSettingsConfig) {
+        let metrics_ping_schedule_changed;
         let config_value = {
             // Hold the lock while merging config and serializing, then release
             // before performing IO in set_sync.
@@ -1269,6 +1634,10 @@ impl Glean {
             remote_settings_config.events_ping_acceleration_factor =
                 cfg.events_ping_acceleration_factor;
 
+            metrics_ping_schedule_changed =
+                remote_settings_config.metrics_ping_schedule != cfg.metrics_ping_schedule;
+            remote_settings_config.metrics_ping_schedule = cfg.metrics_ping_schedule;
+
             // Store the Server Knobs configuration as an ObjectMetric
             // Since RemoteSettingsConfig only contains maps with string keys and primitives,
             // serialization via the derived Serialize impl cannot fail so it is safe to unwrap.
@@ -1281,6 +1650,11 @@ impl Glean {
 
         // Update remote_settings epoch
         self.remote_settings_epoch.fetch_add(1, Ordering::SeqCst);
+
+        // Scheduling again replaces the Metrics Ping Scheduler's task with one of the new schedule.
+        if metrics_ping_schedule_changed {
+            self.start_metrics_ping_scheduler();
+        }
     }
 
     /// Persists [`Lifetime::Ping`] data that might be in memory in case
@@ -1290,7 +1664,7 @@ impl Glean {
     /// If there is no data to persist, this function does nothing.
     pub fn persist_ping_lifetime_data(&self) -> Result<()> {
         if let Some(data) = self.data_store.as_ref() {
-            return data.persist_ping_lifetime_data();
+            return data.persist_ping_lifetime_data(self);
         }
 
         Ok(())
@@ -1319,6 +1693,16 @@ impl Glean {
         self.is_first_run
     }
 
+    /// Whether all data is kept in memory, see [`InternalConfiguration::in_memory`].
+    pub fn is_in_memory(&self) -> bool {
+        self.in_memory
+    }
+
+    /// Whether this is a helper process, see [`Glean::new_for_helper_process`].
+    pub fn is_helper_process(&self) -> bool {
+        self.helper_process
+    }
+
     /// Sets a debug view tag.
     ///
     /// This will return `false` in case `value` is not a valid tag.
@@ -1378,6 +1762,173 @@ impl Glean {
         self.debug.log_pings.set(value)
     }
 
+    /// Exports all pending pings into an archive, to upload them elsewhere.
+    ///
+    /// An existing archive is never overwritten.
+    ///
+    /// Exported pings are deleted, as if they were sent.
+    /// Pings currently being uploaded are not exported.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path to the archive file to write.
+    ///
+    /// # Returns
+    ///
+    /// The number of exported pings.
+    pub fn export_pending_pings(&self, archive_path: &Path) -> Result<usize> {
+        self.upload_manager
+            .export_pending_pings(archive_path)
+            .map(|exported| exported.len())
+    }
+
+    /// Creates a backup of the data directory, while Glean is running.
+    ///
+    /// The database is copied with SQLite's online backup,
+    /// event files, pending pings and `client_id.txt` as they are.
+    /// If all data is kept in memory, the backup is the only way to keep it.
+    /// Restore it with [`backup::restore`](crate::backup::restore) before initializing Glean.
+    ///
+    /// # Arguments
+    ///
+    /// * `backup_path` - The directory to create the backup in.
+    ///   It must not exist and must be outside the data directory.
+    pub fn backup(&self, backup_path: &Path) -> Result<()> {
+        crate::backup::create(self, &self.data_path, backup_path)
+    }
+
+    /// Lists the pings that permanently failed to upload and were kept for diagnosis.
+    ///
+    /// Pings are only kept if `max_quarantined_pings` is configured.
+    pub fn quarantined_pings(&self) -> Vec<QuarantinedPing> {
+        self.upload_manager.quarantined_pings()
+    }
+
+    /// Enqueues a quarantined ping for upload again.
+    ///
+    /// # Arguments
+    ///
+    /// * `document_id` - The UUID of the ping in question.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ping was found and enqueued.
+    pub fn reenqueue_quarantined_ping(&self, document_id: &str) -> bool {
+        self.upload_manager
+            .reenqueue_quarantined_ping(self, document_id)
+    }
+
+    /// Deletes all quarantined pings.
+    pub fn purge_quarantined_pings(&self) {
+        self.upload_manager.purge_quarantined_pings(None);
+    }
+
+    /// Sets the content encodings the uploader accepts, in order of preference.
+    ///
+    /// Ping payloads are encoded with the first supported encoding,
+    /// unless an encoding was set for the ping itself.
+    /// Unknown encodings are ignored. Without any, pings are gzip-encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `encodings` - The names of the accepted encodings, as used in the `Content-Encoding` header.
+    pub fn set_content_encodings(&self, encodings: &[String]) {
+        self.upload_manager.set_content_encodings(encodings);
+    }
+
+    /// Sets the content encoding for a single ping,
+    /// overriding the encodings the uploader accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `encoding` - The name of the encoding, as used in the `Content-Encoding` header.
+    ///   `None` to go back to the accepted encodings.
+    pub fn set_ping_content_encoding(&self, ping_name: &str, encoding: Option<&str>) {
+        let encoding = match encoding {
+            Some(name) => match ContentEncoding::from_name(name) {
+                Some(encoding) => Some(encoding),
+                None => {
+                    log::warn!(
+                        "Unsupported content encoding {:?} for ping {}, ignoring.",
+                        name,
+                        ping_name
+                    );
+                    return;
+                }
+            },
+            None => None,
+        };
+        self.upload_manager
+            .set_ping_content_encoding(ping_name, encoding);
+    }
+
+    /// Sets the constraints on when a ping may be uploaded.
+    ///
+    /// Constraints with a field out of range are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `constraints` - The constraints, `None` to upload the ping whenever possible.
+    pub fn set_ping_upload_constraints(
+        &self,
+        ping_name: &str,
+        constraints: Option<UploadConstraints>,
+    ) {
+        if let Some(Err(e)) = constraints.as_ref().map(UploadConstraints::validate) {
+            log::error!("Ignoring the upload constraints of the '{ping_name}' ping. {e}");
+            return;
+        }
+        self.upload_manager
+            .set_ping_upload_constraints(ping_name, constraints);
+    }
+
+    /// Sets the schedule on which a ping is submitted, replacing any previous schedule.
+    ///
+    /// If a scheduled time passed since the ping was last submitted by the scheduler,
+    /// it is submitted right away. Invalid schedules are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `schedule` - The schedule, `None` to stop scheduling the ping.
+    ///
+    /// # Returns
+    ///
+    /// Whether an overdue ping was submitted.
+    pub fn set_ping_schedule(&self, ping_name: &str, schedule: Option<PingSchedule>) -> bool {
+        if self.helper_process {
+            log::info!("Ignoring the schedule of the '{ping_name}' ping in a helper process.");
+            return false;
+        }
+        if self.get_ping_by_name(ping_name).is_none() {
+            log::error!("Attempted to schedule unknown ping '{}'", ping_name);
+            return false;
+        }
+        if let Some(Err(e)) = schedule.as_ref().map(PingSchedule::validate) {
+            log::error!("Ignoring the schedule of the '{ping_name}' ping. {e}");
+            return false;
+        }
+
+        ping_scheduler::schedule(self, ping_name, schedule)
+    }
+
+    /// Instructs the threads of all ping schedules to exit cleanly.
+    pub fn cancel_ping_schedules(&self) {
+        ping_scheduler::cancel();
+    }
+
+    /// Sets the kind of network the device is currently connected to,
+    /// as checked by the upload constraints of pings.
+    ///
+    /// # Returns
+    ///
+    /// Whether the network type changed.
+    pub fn set_network_type(&self, network: NetworkType) -> bool {
+        self.upload_manager.set_network_type(network)
+    }
+
     /// Return the value for the log pings debug option or `false` if it hasn't been set.
     ///
     /// The `log_pings` option may be set from an environment variable (`GLEAN_LOG_PINGS`)
@@ -1413,6 +1964,10 @@ impl Glean {
     /// `true`, then Glean knows it did not exit cleanly and can implement
     /// coping mechanisms (e.g. sending a `baseline` ping).
     pub fn set_dirty_flag(&self, new_value: bool) {
+        // The dirty flag is the primary process's.
+        if self.helper_process {
+            return;
+        }
         self.get_dirty_bit_metric().set_sync(self, new_value);
     }
 
@@ -1789,6 +2344,11 @@ impl Glean {
     /// This functions generates a baseline ping with reason `active`
     /// and then sets the dirty bit.
     pub fn handle_client_active(&mut self) {
+        if self.helper_process {
+            log::info!("Ignoring client activity in a helper process.");
+            return;
+        }
+
         match self.session_manager.mode {
             SessionMode::Auto => {
                 if !self.session_manager.is_active() {
@@ -1830,6 +2390,11 @@ impl Glean {
     /// This functions generates a baseline and an events ping with reason
     /// `inactive` and then clears the dirty bit.
     pub fn handle_client_inactive(&mut self) {
+        if self.helper_process {
+            log::info!("Ignoring client inactivity in a helper process.");
+            return;
+        }
+
         match self.session_manager.mode {
             SessionMode::Auto => {
                 // In AUTO mode, don't end the session immediately. Instead record
@@ -1862,6 +2427,8 @@ impl Glean {
         }
 
         self.set_dirty_flag(false);
+
+        self.run_database_maintenance();
     }
 
     /// **Test-only API (exported for FFI purposes).**
@@ -1875,7 +2442,7 @@ impl Glean {
             data.clear_all()
         }
         // We don't care about this failing, maybe the data does just not exist.
-        let _ = self.event_data_store.clear_all();
+        let _ = self.event_data_store.clear_all(self);
     }
 
     /// Instructs the Metrics Ping Scheduler's thread to exit cleanly.
@@ -1894,6 +2461,54 @@ impl Glean {
         }
     }
 
+    /// Instructs the database maintenance scheduler's thread to exit cleanly.
+    /// If Glean was configured without `database_maintenance_interval_ms`, this has no effect.
+    pub fn cancel_database_maintenance_scheduler(&self) {
+        if self.database_maintenance_interval.is_some() {
+            maintenance::cancel();
+        }
+    }
+
+    /// Instructs the database maintenance scheduler to run maintenance periodically.
+    /// If Glean was configured without `database_maintenance_interval_ms`, this has no effect.
+    pub fn start_database_maintenance_scheduler(&self) {
+        if let Some(interval) = self.database_maintenance_interval {
+            maintenance::schedule(interval);
+        }
+    }
+
+    /// Runs database maintenance: vacuums free pages, optimizes and truncates the write-ahead log.
+    ///
+    /// Records the time it took and the bytes reclaimed.
+    /// Helper processes leave the maintenance to the primary process.
+    pub fn run_database_maintenance(&self) {
+        if self.helper_process {
+            return;
+        }
+        let Some(database) = self.data_store.as_ref() else {
+            return;
+        };
+
+        match database.run_idle_maintenance() {
+            Ok(maintenance) => {
+                log::debug!("Ran database maintenance: {:?}", maintenance);
+                let duration_ns = maintenance
+                    .duration
+                    .as_nanos()
+                    .try_into()
+                    .unwrap_or(u64::MAX);
+                self.database_metrics
+                    .maintenance_duration
+                    .accumulate_raw_samples_nanos_sync(self, &[duration_ns]);
+                self.database_metrics.maintenance_reclaimed.accumulate_sync(
+                    self,
+                    maintenance.reclaimed_bytes.try_into().unwrap_or(i64::MAX),
+                );
+            }
+            Err(e) => log::info!("Can't run database maintenance: {:?}", e),
+        }
+    }
+
     /// Clears the core attribution data.
     /// Does not clear glean.attribution.ext.
     pub fn clear_attribution(&self) {
@@ -2,41 +2,76 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::collections::HashMap;
 use std::fmt::{self, Display};
 use std::fs;
 use std::num::NonZeroU64;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use malloc_size_of::MallocSizeOf;
 use rusqlite::params;
 use rusqlite::types::FromSqlError;
 use rusqlite::OptionalExtension;
 use rusqlite::Transaction;
+use rusqlite::TransactionBehavior;
 use rusqlite::{Error as SqlError, ErrorCode};
 
+use buffer::PingLifetimeBuffer;
 use connection::Connection;
+use quota::Quota;
+pub use salvage::Salvage;
 use schema::Schema;
 pub use schema::SchemaError;
 
-use crate::common_metric_data::CommonMetricDataInternal;
+use crate::common_metric_data::{CommonMetricDataInternal, MetricLabel};
 use crate::database::migration::{self, MigrationState};
+use crate::encryption::{self, Cipher};
+use crate::error_recording::{record_error_sqlite, ErrorType};
 use crate::metrics::dual_labeled_counter::RECORD_SEPARATOR;
-use crate::metrics::Metric;
+use crate::metrics::{Metric, MetricType};
+use crate::storage::INTERNAL_STORAGE;
+use crate::ErrorKind;
 use crate::Glean;
 use crate::Lifetime;
 use crate::Result;
 
 use super::ConnExt;
 
+mod buffer;
 mod connection;
+mod quota;
+mod salvage;
 mod schema;
 
 #[derive(Debug)]
 pub enum LoadState {
     Ok,
-    Err(OpenError),
+    /// The database couldn't be opened and was replaced by a new one,
+    /// with what could be salvaged from it.
+    Recovered(OpenError, Salvage),
+}
+
+/// The outcome of a database maintenance run.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Maintenance {
+    /// The time the maintenance took.
+    pub duration: Duration,
+    /// The bytes reclaimed from the database file and its write-ahead log.
+    pub reclaimed_bytes: u64,
+}
+
+/// The data stored for a ping that submitting it clears.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct PingUsage {
+    /// The number of events.
+    pub events: u64,
+    /// The number of labeled entries of metrics with a `ping` lifetime.
+    pub labeled_entries: u64,
+    /// The estimated size in bytes of the events and the metrics with a `ping` lifetime.
+    pub bytes: u64,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -63,6 +98,37 @@ pub struct Database {
 
     /// Set when a database migration attempt failed.
     pub(crate) migration_error: MigrationResult,
+
+    /// If the `delay_ping_lifetime_io` Glean config option is `true`,
+    /// metrics with 'ping' lifetime are kept in memory
+    /// and persisted to the database in bulk.
+    ///
+    /// Buffered data is lost if the process crashes before it is persisted.
+    ping_lifetime_data: Option<Mutex<PingLifetimeBuffer>>,
+
+    /// The number of ping-lifetime writes after which to persist the buffer. `0` disables it.
+    ping_lifetime_threshold: usize,
+
+    /// The time after which to persist the buffer on the next write. Zero disables it.
+    ping_lifetime_max_time: Duration,
+
+    /// The cipher to encrypt metric values with, if encryption at rest is enabled.
+    cipher: Option<Arc<Cipher>>,
+
+    /// The size quota of the `telemetry` table, if any.
+    quota: Option<Quota>,
+
+    /// Upper bounds of the usage of the pings whose usage was measured, grown with every write.
+    ///
+    /// Measuring the usage requires a scan of the ping's data, so it is only re-measured
+    /// when the estimate reaches the ping's capacity. Writes of helper processes aren't counted.
+    usage_estimates: Mutex<HashMap<String, PingUsage>>,
+
+    /// Whether this is the database of a primary process, opened by a helper process.
+    shared: bool,
+
+    /// The database file, `None` if the database only lives in memory.
+    path: Option<PathBuf>,
 }
 
 impl MallocSizeOf for Database {
@@ -73,6 +139,11 @@ impl MallocSizeOf for Database {
 }
 
 const DEFAULT_DATABASE_FILE_NAME: &str = "glean.sqlite";
+/// The name SQLite opens a private, in-memory database for.
+const IN_MEMORY_DATABASE_NAME: &str = ":memory:";
+/// Marks the files in the `events` directory whose events were imported, labeled by file name.
+/// It has no value, so it never shows up in snapshots.
+const IMPORTED_EVENTS_ID: &str = "events_imported";
 
 /// Calculate the database size from all the files in the directory.
 ///
@@ -123,7 +194,7 @@ impl Display for OpenError {
             SqlError(err) => write!(f, "Error executing SQL: {err}"),
             RecoveryError(err) => write!(
                 f,
-                "Failed to recover a corrupt database due to an error deleting the file: {err}"
+                "Failed to recover a corrupt database due to an error moving the file: {err}"
             ),
         }
     }
@@ -152,54 +223,125 @@ impl From<SchemaError> for OpenError {
 }
 
 pub fn sqlite_open(path: &Path) -> std::result::Result<(Connection, LoadState), OpenError> {
-    // TODO(bug 2049292): Make this more robust, use the correct errors and see how we can test all the branches
-    // properly.
     match Connection::new::<Schema>(path) {
         Err(e @ SchemaError::UnsupportedSchemaVersion(_)) => Err(e.into()),
-        Err(e @ SchemaError::Sqlite(SqlError::SqliteFailure(err, _))) => {
-            match err.code {
-                ErrorCode::PermissionDenied => Err(e.into()),
-                ErrorCode::NotADatabase => {
-                    log::debug!("sqlite failed: not a database. starting from scratch.");
-                    fs::remove_file(path).map_err(OpenError::RecoveryError)?;
-                    // Now try again, we only handle that error once.
-                    let conn = Connection::new::<Schema>(path)?;
-                    Ok((conn, LoadState::Err(OpenError::Corrupt)))
-                }
-                ErrorCode::CannotOpen => {
-                    log::debug!("sqlite failed: cannot open. starting from scratch.");
-                    fs::remove_file(path).map_err(OpenError::RecoveryError)?;
-                    // Now try again, we only handle that error once.
-                    let conn = Connection::new::<Schema>(path)?;
-                    Ok((conn, LoadState::Err(OpenError::Corrupt)))
-                }
-                _ => Err(e.into()),
+        Err(e @ SchemaError::Sqlite(SqlError::SqliteFailure(err, _))) => match err.code {
+            ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt | ErrorCode::CannotOpen => {
+                log::debug!("sqlite failed: {e}. salvaging into a new database.");
+                recover(path, OpenError::Corrupt)
             }
-        }
+            _ => {
+                salvage::mark_for_check(path);
+                Err(e.into())
+            }
+        },
         Err(err @ SchemaError::Sqlite(SqlError::SqlInputError { .. })) => {
-            log::debug!("sqlite failed: schema migration failed. starting from scratch.");
-            fs::remove_file(path).map_err(OpenError::RecoveryError)?;
-            // Now try again, we only handle that error once.
-            let conn = Connection::new::<Schema>(path)?;
-            Ok((conn, LoadState::Err(err.into())))
+            log::debug!("sqlite failed: schema migration failed. salvaging into a new database.");
+            recover(path, err.into())
         }
         other => {
             let conn = other?;
-            Ok((conn, LoadState::Ok))
+            let marker = salvage::check_marker_path(path);
+            if !marker.exists() {
+                return Ok((conn, LoadState::Ok));
+            }
+
+            log::debug!("Checking the database for corruption after an earlier error.");
+            let check = conn.read(salvage::quick_check);
+            if let Err(e) = fs::remove_file(&marker) {
+                log::warn!("Failed to remove {}: {e}", marker.display());
+            }
+            match check {
+                Err(e) if salvage::is_corrupt(&e) => {
+                    log::debug!("sqlite failed: {e}. salvaging into a new database.");
+                    drop(conn);
+                    recover(path, OpenError::Corrupt)
+                }
+                Err(e) => Err(e.into()),
+                Ok(()) => Ok((conn, LoadState::Ok)),
+            }
         }
     }
 }
 
+/// Recovers from a database that can't be opened.
+///
+/// The database is moved aside, a new one is created in its place
+/// and the readable rows of the old one are salvaged into it, see [`salvage`].
+///
+/// # Arguments
+///
+/// * `path` - The database file.
+/// * `error` - Why the database couldn't be opened.
+fn recover(
+    path: &Path,
+    error: OpenError,
+) -> std::result::Result<(Connection, LoadState), OpenError> {
+    let corrupt_path = salvage::corrupt_path(path);
+    salvage::move_aside(path, &corrupt_path).map_err(OpenError::RecoveryError)?;
+
+    // Now try again, we only handle that error once.
+    let conn = Connection::new::<Schema>(path)?;
+    let salvaged = salvage::salvage(&corrupt_path, &conn);
+    salvage::remove(&corrupt_path);
+    Ok((conn, LoadState::Recovered(error, salvaged)))
+}
+
+/// Checks that a database file can be used by this version of Glean.
+///
+/// Upgrades the database to the latest schema and validates it,
+/// see [`ConnectionOpener::validate`](connection::ConnectionOpener::validate),
+/// and checks it for corrupt pages.
+/// Databases of a newer schema version are rejected instead of being downgraded.
+///
+/// # Arguments
+///
+/// * `path` - The database file.
+pub fn validate_database(path: &Path) -> Result<()> {
+    use connection::ConnectionOpener;
+
+    let flags = rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY;
+    let version: u32 = rusqlite::Connection::open_with_flags(path, flags)?.query_row(
+        "PRAGMA user_version",
+        [],
+        |row| row.get(0),
+    )?;
+    // A version of 0 is an empty or foreign database.
+    if version == 0 || version > Schema::MAX_SCHEMA_VERSION {
+        return Err(OpenError::IncompatibleVersion(version).into());
+    }
+
+    let conn = Connection::new::<Schema>(path).map_err(OpenError::from)?;
+    conn.read(salvage::quick_check)?;
+    Ok(())
+}
+
 impl Database {
     /// Initializes the data store.
     ///
     /// This opens the underlying SQLite store and creates
     /// the underlying directory structure.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - The data directory.
+    /// * `delay_ping_lifetime_io` - Whether to buffer metrics with ping lifetime in memory.
+    ///   Buffered data is persisted on ping collection, on shutdown,
+    ///   through [`Database::persist_ping_lifetime_data`] and by the following thresholds.
+    /// * `ping_lifetime_threshold` - The number of buffered writes after which to persist the buffer.
+    ///   `0` disables it.
+    /// * `ping_lifetime_max_time` - The time since the buffer was last persisted
+    ///   after which the next write persists it. Zero disables it.
+    /// * `cipher` - The cipher to encrypt metric values with, if any.
+    /// * `max_size` - The size quota of stored metrics in bytes, if any.
+    ///   Metrics are evicted when it is exceeded, see [`quota`].
     pub fn new(
         data_path: &Path,
-        _delay_ping_lifetime_io: bool,
-        _ping_lifetime_threshold: usize,
-        _ping_lifetime_max_time: Duration,
+        delay_ping_lifetime_io: bool,
+        ping_lifetime_threshold: usize,
+        ping_lifetime_max_time: Duration,
+        cipher: Option<Arc<Cipher>>,
+        max_size: Option<u64>,
     ) -> Result<Self> {
         let path = data_path.join("db");
         log::debug!("Database path: {:?}", path.display());
@@ -215,6 +357,15 @@ impl Database {
             load_state,
             migration_state: None,
             migration_error: MigrationResult::Unknown,
+            ping_lifetime_data: delay_ping_lifetime_io
+                .then(|| Mutex::new(PingLifetimeBuffer::new())),
+            ping_lifetime_threshold,
+            ping_lifetime_max_time,
+            cipher,
+            quota: max_size.map(Quota::new),
+            usage_estimates: Mutex::new(HashMap::new()),
+            shared: false,
+            path: Some(store_path),
         };
 
         match migration::try_migrate(&path, &db) {
@@ -241,6 +392,86 @@ impl Database {
         Ok(db)
     }
 
+    /// Opens the data store of a primary process from a helper process.
+    ///
+    /// Unlike [`Database::new`] a database that can't be opened is not recovered,
+    /// and there is no migration and no maintenance: that's left to the primary process.
+    /// Metrics with ping lifetime are never buffered, so the primary process sees them right away.
+    ///
+    /// Concurrent writes of other processes are waited for, up to the `busy_timeout` set in `Schema::setup`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - The data directory of the primary process.
+    /// * `cipher` - The cipher to encrypt metric values with, if any.
+    /// * `max_size` - The size quota of stored metrics in bytes, if any.
+    pub fn open_shared(
+        data_path: &Path,
+        cipher: Option<Arc<Cipher>>,
+        max_size: Option<u64>,
+    ) -> Result<Self> {
+        let path = data_path.join("db");
+        log::debug!("Shared database path: {:?}", path.display());
+
+        fs::create_dir_all(&path)?;
+        let store_path = path.join(DEFAULT_DATABASE_FILE_NAME);
+        let conn = Connection::new::<Schema>(&store_path).map_err(OpenError::from)?;
+
+        Ok(Self {
+            conn,
+            file_size: None,
+            load_state: LoadState::Ok,
+            migration_state: None,
+            migration_error: MigrationResult::Unknown,
+            ping_lifetime_data: None,
+            ping_lifetime_threshold: 0,
+            ping_lifetime_max_time: Duration::ZERO,
+            cipher,
+            quota: max_size.map(Quota::new),
+            usage_estimates: Mutex::new(HashMap::new()),
+            shared: true,
+            path: Some(store_path),
+        })
+    }
+
+    /// Initializes a data store that only lives in memory.
+    ///
+    /// Like [`Database::new`], but nothing is read from or written to disk,
+    /// so there's nothing to recover or migrate. All data is dropped with the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay_ping_lifetime_io` - Whether to buffer metrics with ping lifetime, see [`Database::new`].
+    /// * `ping_lifetime_threshold` - The number of buffered writes after which to persist the buffer.
+    /// * `ping_lifetime_max_time` - The time after which the next write persists the buffer.
+    /// * `max_size` - The size quota of stored metrics in bytes, if any.
+    pub fn new_in_memory(
+        delay_ping_lifetime_io: bool,
+        ping_lifetime_threshold: usize,
+        ping_lifetime_max_time: Duration,
+        max_size: Option<u64>,
+    ) -> Result<Self> {
+        let conn = Connection::new::<Schema>(Path::new(IN_MEMORY_DATABASE_NAME))
+            .map_err(OpenError::from)?;
+
+        Ok(Self {
+            conn,
+            file_size: None,
+            load_state: LoadState::Ok,
+            migration_state: None,
+            migration_error: MigrationResult::Unknown,
+            ping_lifetime_data: delay_ping_lifetime_io
+                .then(|| Mutex::new(PingLifetimeBuffer::new())),
+            ping_lifetime_threshold,
+            ping_lifetime_max_time,
+            cipher: None,
+            quota: max_size.map(Quota::new),
+            usage_estimates: Mutex::new(HashMap::new()),
+            shared: false,
+            path: None,
+        })
+    }
+
     /// Get the initial database file size.
     pub fn file_size(&self) -> Option<NonZeroU64> {
         self.file_size
@@ -248,7 +479,7 @@ impl Database {
 
     /// Get the load state.
     pub fn load_state(&self) -> Option<String> {
-        if let LoadState::Err(e) = &self.load_state {
+        if let LoadState::Recovered(e, _) = &self.load_state {
             Some(match e {
                 OpenError::IncompatibleVersion(v) => format!("incompatible version: {v}"),
                 OpenError::Corrupt => "database file corrupt".to_string(),
@@ -260,6 +491,74 @@ impl Database {
         }
     }
 
+    /// Get what was salvaged, if the database was corrupt and recovered.
+    pub fn salvage(&self) -> Option<&Salvage> {
+        match &self.load_state {
+            LoadState::Recovered(_, salvage) => Some(salvage),
+            LoadState::Ok => None,
+        }
+    }
+
+    /// Encrypts all metric values and events stored in plaintext.
+    ///
+    /// Only does something when encryption at rest is enabled.
+    /// Afterwards the database file is rebuilt, so no plaintext is left in unused pages.
+    pub fn encrypt_plaintext_values(&self) -> Result<()> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(());
+        };
+
+        self.conn.write(|tx| {
+            let mut select = tx.prepare("SELECT rowid, value FROM telemetry")?;
+            let plaintext: Vec<(i64, Vec<u8>)> = select
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .filter_map(|row| row.ok())
+                .filter(|(_, value): &(i64, Vec<u8>)| !encryption::is_encrypted(value))
+                .collect();
+
+            let mut update = tx.prepare("UPDATE telemetry SET value = ?1 WHERE rowid = ?2")?;
+            for (rowid, value) in plaintext {
+                update.execute(params![cipher.encrypt(&value), rowid])?;
+            }
+
+            let mut select = tx.prepare("SELECT id, value FROM events")?;
+            let plaintext: Vec<(i64, Vec<u8>)> = select
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .filter_map(|row| row.ok())
+                .filter(|(_, value): &(i64, Vec<u8>)| !encryption::is_encrypted(value))
+                .collect();
+
+            let mut update = tx.prepare("UPDATE events SET value = ?1 WHERE id = ?2")?;
+            for (id, value) in plaintext {
+                update.execute(params![cipher.encrypt(&value), id])?;
+            }
+            Ok::<(), rusqlite::Error>(())
+        })?;
+
+        let conn = self.conn.lock();
+        self.run_maintenance_vacuum(&conn, true)?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+        Ok(())
+    }
+
+    /// Serializes a metric for storage, encrypting it if encryption at rest is enabled.
+    fn encode(&self, metric: &Metric) -> Vec<u8> {
+        let encoded = rmp_serde::to_vec(metric).expect("IMPOSSIBLE: Serializing metric failed");
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&encoded),
+            None => encoded,
+        }
+    }
+
+    /// Deserializes a stored metric, decrypting it if necessary.
+    fn decode(&self, blob: &[u8]) -> Option<Metric> {
+        match &self.cipher {
+            Some(cipher) => rmp_serde::from_slice(&cipher.decrypt(blob)?).ok(),
+            None => rmp_serde::from_slice(blob).ok(),
+        }
+    }
+
     /// Run periodic database maintenance.
     ///
     /// If `force=true` always run the full maintenance taks
@@ -306,6 +605,68 @@ impl Database {
         Ok(())
     }
 
+    /// Run database maintenance while the process is running,
+    /// on the maintenance schedule or when the client becomes inactive.
+    ///
+    /// Unlike [`Database::run_maintenance`] this checks the database for corrupt pages,
+    /// vacuums all free pages and truncates the write-ahead log, if no reader is in the way.
+    /// A corrupt database is recovered when it is opened next.
+    ///
+    /// # Returns
+    ///
+    /// The time it took and the number of bytes reclaimed.
+    pub fn run_idle_maintenance(&self) -> Result<Maintenance> {
+        let start = Instant::now();
+        let conn = self.conn.lock();
+        let conn = &*conn;
+
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count =
+            || -> Result<u64> { Ok(conn.query_row("PRAGMA page_count", [], |row| row.get(0))?) };
+        let pages_before = page_count()?;
+
+        if let Err(e) = salvage::quick_check(conn) {
+            if let Some(path) = self.path.as_ref().filter(|_| salvage::is_corrupt(&e)) {
+                salvage::mark_for_check(path);
+            }
+            return Err(e.into());
+        }
+
+        let auto_vacuum_setting: u32 =
+            conn.query_row_and_then("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        if auto_vacuum_setting == 2 {
+            // Frees one page per step.
+            let mut stmt = conn.prepare("PRAGMA incremental_vacuum")?;
+            let mut rows = stmt.query([])?;
+            while rows.next()?.is_some() {}
+        } else {
+            // Switches to auto_vacuum=incremental with a full vacuum.
+            self.run_maintenance_vacuum(conn, false)?;
+        }
+        let freed_pages = pages_before.saturating_sub(page_count()?);
+
+        self.run_maintenance_optimize(conn)?;
+
+        // Checkpointing returns whether it was blocked and the number of frames in the log,
+        // which is -1 if the database isn't in WAL mode.
+        // A truncating checkpoint reports an empty log, so count the frames before.
+        let checkpoint = |mode: &str| -> Result<(bool, i64)> {
+            Ok(
+                conn.query_row(&format!("PRAGMA wal_checkpoint({mode})"), [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?,
+            )
+        };
+        let (_, wal_frames) = checkpoint("PASSIVE")?;
+        let (busy, _) = checkpoint("TRUNCATE")?;
+        let truncated_frames = if busy { 0 } else { wal_frames.max(0) as u64 };
+
+        Ok(Maintenance {
+            duration: start.elapsed(),
+            reclaimed_bytes: (freed_pages + truncated_frames) * page_size,
+        })
+    }
+
     /// Iterates with the provided transaction function
     /// over the requested data from the given storage.
     ///
@@ -328,6 +689,112 @@ impl Database {
         storage_name: &str,
         mut transaction_fn: F,
     ) -> Result<()>
+    where
+        F: FnMut(&[u8], &[&str], &Metric),
+    {
+        let buffered = self.buffered(lifetime, storage_name);
+        let result = self.conn.read(|conn| {
+            self.iter_rows(conn, lifetime, storage_name, &buffered, &mut transaction_fn)
+        });
+        self.check_on_error(result)
+    }
+
+    /// Iterates over the metrics with ping lifetime of a store and clears them.
+    ///
+    /// Reading and clearing happen in a single transaction,
+    /// so metrics recorded concurrently by a helper process are either
+    /// passed to `transaction_fn` or kept for the next time.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_name` - The name of the store.
+    /// * `transaction_fn` - Called for each metric with its identifier, labels and value.
+    pub fn take_ping_lifetime_store<F>(&self, storage_name: &str, transaction_fn: F) -> Result<()>
+    where
+        F: FnMut(&[u8], &[&str], &Metric),
+    {
+        self.take_ping_data(storage_name, transaction_fn, false)
+            .map(|_| ())
+    }
+
+    /// Iterates over the metrics with ping lifetime of a store and clears them,
+    /// taking the store's events along with them.
+    ///
+    /// Metrics and events are read and cleared in a single transaction,
+    /// so a ping contains either both or neither of what is recorded concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_name` - The name of the store.
+    /// * `transaction_fn` - Called for each metric with its identifier, labels and value.
+    ///
+    /// # Returns
+    ///
+    /// The serialized events of the store, in the order they were recorded.
+    pub(crate) fn take_ping_lifetime_store_and_events<F>(
+        &self,
+        storage_name: &str,
+        transaction_fn: F,
+    ) -> Result<Vec<Vec<u8>>>
+    where
+        F: FnMut(&[u8], &[&str], &Metric),
+    {
+        self.take_ping_data(storage_name, transaction_fn, true)
+    }
+
+    fn take_ping_data<F>(
+        &self,
+        storage_name: &str,
+        mut transaction_fn: F,
+        with_events: bool,
+    ) -> Result<Vec<Vec<u8>>>
+    where
+        F: FnMut(&[u8], &[&str], &Metric),
+    {
+        let buffered = self.buffered(Lifetime::Ping, storage_name);
+        let result = self.conn.write(|tx| {
+            self.iter_rows(
+                tx,
+                Lifetime::Ping,
+                storage_name,
+                &buffered,
+                &mut transaction_fn,
+            )?;
+            tx.prepare_cached("DELETE FROM telemetry WHERE lifetime = 'ping' AND ping = ?1")?
+                .execute([storage_name])?;
+            if !with_events {
+                return Ok(Vec::new());
+            }
+            Ok::<_, crate::Error>(Self::select_events(tx, storage_name, true)?)
+        });
+        let events = self.check_on_error(result)?;
+        self.clear_buffered(|storage, _| storage == storage_name);
+        self.reset_usage(Some(storage_name));
+        Ok(self.decrypt_events(events))
+    }
+
+    /// The buffered values of a store, which replace the persisted ones.
+    fn buffered(&self, lifetime: Lifetime, storage_name: &str) -> Vec<(String, String, Metric)> {
+        match &self.ping_lifetime_data {
+            Some(buffer) if lifetime == Lifetime::Ping => buffer
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(storage, ..)| *storage == storage_name)
+                .map(|(_, id, labels, metric)| (id.to_string(), labels.to_string(), metric.clone()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn iter_rows<F>(
+        &self,
+        conn: &rusqlite::Connection,
+        lifetime: Lifetime,
+        storage_name: &str,
+        buffered: &[(String, String, Metric)],
+        transaction_fn: &mut F,
+    ) -> Result<()>
     where
         F: FnMut(&[u8], &[&str], &Metric),
     {
@@ -342,30 +809,38 @@ impl Database {
             AND ping = ?2
         "#;
 
-        self.conn.read(|conn| {
-            let mut stmt = conn.prepare_cached(iter_sql)?;
-            let rows = stmt.query_map(
-                params![lifetime.as_str().to_string(), storage_name],
-                |row| {
-                    let id: String = row.get(0)?;
-                    let blob: Vec<u8> = row.get(1)?;
-                    let labels: String = row.get(2)?;
-                    let blob: Metric =
-                        rmp_serde::from_slice(&blob).map_err(|_| FromSqlError::InvalidType)?;
-                    Ok((id, labels, blob))
-                },
-            )?;
-
-            for row in rows {
-                let Ok((metric_id, labels, metric)) = row else {
-                    continue;
-                };
-                let labels = labels.split(RECORD_SEPARATOR).collect::<Vec<_>>();
-                transaction_fn(metric_id.as_bytes(), &labels, &metric);
+        let mut stmt = conn.prepare_cached(iter_sql)?;
+        let rows = stmt.query_map(
+            params![lifetime.as_str().to_string(), storage_name],
+            |row| {
+                let id: String = row.get(0)?;
+                let blob: Vec<u8> = row.get(1)?;
+                let labels: String = row.get(2)?;
+                let blob: Metric = self.decode(&blob).ok_or(FromSqlError::InvalidType)?;
+                Ok((id, labels, blob))
+            },
+        )?;
+
+        for row in rows {
+            let Ok((metric_id, labels, metric)) = row else {
+                continue;
+            };
+            if buffered
+                .iter()
+                .any(|(id, l, _)| *id == metric_id && *l == labels)
+            {
+                continue;
             }
+            let labels = labels.split(RECORD_SEPARATOR).collect::<Vec<_>>();
+            transaction_fn(metric_id.as_bytes(), &labels, &metric);
+        }
 
-            Ok(())
-        })
+        for (metric_id, labels, metric) in buffered {
+            let labels = labels.split(RECORD_SEPARATOR).collect::<Vec<_>>();
+            transaction_fn(metric_id.as_bytes(), &labels, metric);
+        }
+
+        Ok(())
     }
 
     /// Get a single metric by name from storage
@@ -388,20 +863,41 @@ impl Database {
 
         let metric_identifier = &data.base_identifier();
 
-        self.conn
+        let result = self
+            .conn
             .read(|tx| {
                 let labels = data.check_labels(tx);
 
+                if let Some(buffer) = self.ping_lifetime_buffer(data) {
+                    let buffer = buffer.lock().unwrap();
+                    if let Some(metric) =
+                        buffer.get(storage_name, metric_identifier, labels.label())
+                    {
+                        return Ok(Some(metric.clone()));
+                    }
+                }
+
                 let mut stmt = tx.prepare_cached(get_metric_sql)?;
                 stmt.query_one([metric_identifier, storage_name, labels.label()], |row| {
                     let blob: Vec<u8> = row.get(0)?;
-                    let blob: Metric =
-                        rmp_serde::from_slice(&blob).map_err(|_| FromSqlError::InvalidType)?;
+                    let blob: Metric = self.decode(&blob).ok_or(FromSqlError::InvalidType)?;
                     Ok(blob)
                 })
                 .optional()
             })
-            .unwrap_or(None) // TODO(bug 2047617): Should we handle the error here properly?
+            .map_err(crate::Error::from);
+        self.check_on_error(result).unwrap_or(None) // TODO(bug 2047617): Should we handle the error here properly?
+    }
+
+    /// Passes `result` through, requesting to check the database for corruption
+    /// when it is opened next if it failed on a corrupt database file.
+    fn check_on_error<T>(&self, result: Result<T>) -> Result<T> {
+        if let (Err(e), Some(path)) = (&result, &self.path) {
+            if matches!(e.kind(), ErrorKind::SQLite(e) if salvage::is_corrupt(e)) {
+                salvage::mark_for_check(path);
+            }
+        }
+        result
     }
 
     /// Determines if the storage has the given metric.
@@ -423,6 +919,17 @@ impl Database {
         storage_name: &str,
         metric_identifier: &str,
     ) -> bool {
+        if let Some(buffer) = &self.ping_lifetime_data {
+            if lifetime == Lifetime::Ping
+                && buffer
+                    .lock()
+                    .unwrap()
+                    .contains_metric(storage_name, metric_identifier)
+            {
+                return true;
+            }
+        }
+
         let has_metric_sql = r#"
         SELECT id
         FROM telemetry
@@ -450,9 +957,19 @@ impl Database {
 
     /// Records a metric in the underlying storage system.
     pub fn record(&self, glean: &Glean, data: &CommonMetricDataInternal, value: &Metric) {
-        let name = data.base_identifier();
+        if let Some(buffer) = self.ping_lifetime_buffer(data) {
+            self.record_buffered(glean, data, buffer, |_| value.clone());
+            self.persist_ping_lifetime_data_if_due(glean);
+            glean.submit_pings_at_capacity(data.storage_names());
+            return;
+        }
 
+        let name = data.base_identifier();
         _ = self.conn.write(|tx| {
+            if !self.may_write(tx) {
+                return Ok(());
+            }
+
             let labels = data.check_labels(tx);
             labels.record_error(glean, tx, &name, data.storage_names());
 
@@ -475,11 +992,82 @@ impl Database {
                     }
                 }
             }
+            self.enforce_quota(glean, tx);
+
+            Ok::<(), rusqlite::Error>(())
+        });
+
+        self.persist_ping_lifetime_data_if_due(glean);
+        glean.submit_pings_at_capacity(data.storage_names());
+    }
+
+    /// Records a metric into the ping-lifetime buffer,
+    /// after applying the given transformation function to its current value.
+    ///
+    /// The database is only read, without taking its write lock:
+    /// buffered metrics have no dynamic labels to check against it,
+    /// and the quota is enforced when the buffer is persisted.
+    fn record_buffered<F>(
+        &self,
+        glean: &Glean,
+        data: &CommonMetricDataInternal,
+        buffer: &Mutex<PingLifetimeBuffer>,
+        mut transform: F,
+    ) where
+        F: FnMut(Option<Metric>) -> Metric,
+    {
+        let name = data.base_identifier();
+        _ = self.conn.read(|conn| {
+            if !self.may_write(conn) {
+                return Ok(());
+            }
+
+            // Static labels are never invalid, so there are no errors to record.
+            let labels = data.check_labels(conn);
+            for ping_name in data.storage_names() {
+                if glean.is_ping_enabled(ping_name) {
+                    if let Err(e) = self.record_per_lifetime_with(
+                        conn,
+                        Lifetime::Ping,
+                        ping_name,
+                        &name,
+                        labels.label(),
+                        Some(buffer),
+                        &mut transform,
+                    ) {
+                        log::error!(
+                            "Failed to record metric '{}' into {}: {:?}",
+                            data.base_identifier(),
+                            ping_name,
+                            e
+                        );
+                    }
+                }
+            }
 
             Ok::<(), rusqlite::Error>(())
         });
     }
 
+    /// The buffer to record a metric into instead of the database, if any.
+    ///
+    /// Only metrics with ping lifetime are buffered.
+    /// Metrics with dynamic labels are always written to the database,
+    /// as the number of labels in use is checked against it.
+    fn ping_lifetime_buffer(
+        &self,
+        data: &CommonMetricDataInternal,
+    ) -> Option<&Mutex<PingLifetimeBuffer>> {
+        let dynamic_label = matches!(
+            data.inner.label,
+            Some(ref label) if !matches!(label, MetricLabel::Static(_))
+        );
+        if data.inner.lifetime != Lifetime::Ping || dynamic_label {
+            return None;
+        }
+        self.ping_lifetime_data.as_ref()
+    }
+
     /// Records a metric in the underlying storage system, for a single lifetime.
     ///
     /// # Returns
@@ -511,7 +1099,7 @@ impl Database {
         "#;
 
         let mut stmt = tx.prepare_cached(insert_sql)?;
-        let encoded = rmp_serde::to_vec(&metric).expect("IMPOSSIBLE: Serializing metric failed");
+        let encoded = self.encode(metric);
         stmt.execute(params![
             key,
             storage_name,
@@ -519,6 +1107,7 @@ impl Database {
             labels,
             encoded
         ])?;
+        self.count_write(lifetime, storage_name, key, labels, &encoded);
 
         Ok(())
     }
@@ -529,9 +1118,22 @@ impl Database {
     where
         F: FnMut(Option<Metric>) -> Metric,
     {
-        _ = self
-            .conn
-            .write(|tx| self.record_with_transaction(glean, tx, data, transform));
+        if let Some(buffer) = self.ping_lifetime_buffer(data) {
+            self.record_buffered(glean, data, buffer, transform);
+            self.persist_ping_lifetime_data_if_due(glean);
+            glean.submit_pings_at_capacity(data.storage_names());
+            return;
+        }
+
+        _ = self.conn.write(|tx| {
+            if !self.may_write(tx) {
+                return Ok(());
+            }
+            self.record_with_transaction(glean, tx, data, transform)
+        });
+
+        self.persist_ping_lifetime_data_if_due(glean);
+        glean.submit_pings_at_capacity(data.storage_names());
     }
 
     pub fn record_with_transaction<F>(
@@ -545,6 +1147,7 @@ impl Database {
         F: FnMut(Option<Metric>) -> Metric,
     {
         let name = data.base_identifier();
+        let buffer = self.ping_lifetime_buffer(data);
 
         let labels = data.check_labels(tx);
         labels.record_error(glean, tx, &name, data.storage_names());
@@ -557,6 +1160,7 @@ impl Database {
                     ping_name,
                     &name,
                     labels.label(),
+                    buffer,
                     &mut transform,
                 ) {
                     log::error!(
@@ -568,6 +1172,7 @@ impl Database {
                 }
             }
         }
+        self.enforce_quota(glean, tx);
 
         Ok(())
     }
@@ -575,6 +1180,9 @@ impl Database {
     /// Records a metric in the underlying storage system,
     /// after applying the given transformation function, for a single lifetime.
     ///
+    /// With a `buffer`, the metric is recorded into it instead,
+    /// starting from the buffered value, if any.
+    ///
     /// # Returns
     ///
     /// If the storage is unavailable or the write fails, no data will be stored and an error will be returned.
@@ -584,18 +1192,36 @@ impl Database {
     /// # Panics
     ///
     /// This function will **not** panic on database errors.
+    #[allow(clippy::too_many_arguments)]
     fn record_per_lifetime_with<F>(
         &self,
-        tx: &mut Transaction,
+        conn: &rusqlite::Connection,
         lifetime: Lifetime,
         storage_name: &str,
         key: &str,
         labels: &str,
+        buffer: Option<&Mutex<PingLifetimeBuffer>>,
         mut transform: F,
     ) -> Result<()>
     where
         F: FnMut(Option<Metric>) -> Metric,
     {
+        let mut buffer = buffer.map(|buffer| buffer.lock().unwrap());
+        if let Some(metric) = buffer
+            .as_ref()
+            .and_then(|buffer| buffer.get(storage_name, key, labels))
+        {
+            let new_value = transform(Some(metric.clone()));
+            self.buffer_write(
+                buffer.as_mut().unwrap(),
+                storage_name,
+                key,
+                labels,
+                new_value,
+            );
+            return Ok(());
+        }
+
         // TODO(bug 2048194): Remove the `LIMIT 1` and error out when more than 1 row is returned.
         let value_sql = r#"
         SELECT value
@@ -609,7 +1235,7 @@ impl Database {
         "#;
 
         let new_value = {
-            let mut stmt = tx.prepare_cached(value_sql)?;
+            let mut stmt = conn.prepare_cached(value_sql)?;
             let mut rows = stmt.query(params![
                 key,
                 storage_name,
@@ -619,13 +1245,18 @@ impl Database {
 
             if let Ok(Some(row)) = rows.next() {
                 let blob: Vec<u8> = row.get(0)?;
-                let old_value = rmp_serde::from_slice(&blob).ok();
+                let old_value = self.decode(&blob);
                 transform(old_value)
             } else {
                 transform(None)
             }
         };
 
+        if let Some(buffer) = buffer.as_mut() {
+            self.buffer_write(buffer, storage_name, key, labels, new_value);
+            return Ok(());
+        }
+
         let insert_sql = r#"
                     INSERT INTO
                         telemetry (id, ping, lifetime, labels, value)
@@ -637,9 +1268,8 @@ impl Database {
                     "#;
 
         {
-            let mut stmt = tx.prepare_cached(insert_sql)?;
-            let encoded =
-                rmp_serde::to_vec(&new_value).expect("IMPOSSIBLE: Serializing metric failed");
+            let mut stmt = conn.prepare_cached(insert_sql)?;
+            let encoded = self.encode(&new_value);
             stmt.execute(params![
                 key,
                 storage_name,
@@ -647,11 +1277,191 @@ impl Database {
                 labels,
                 encoded
             ])?;
+            self.count_write(lifetime, storage_name, key, labels, &encoded);
         }
 
         Ok(())
     }
 
+    /// Records a metric into the ping-lifetime buffer, counting it towards the ping's usage.
+    fn buffer_write(
+        &self,
+        buffer: &mut PingLifetimeBuffer,
+        storage_name: &str,
+        key: &str,
+        labels: &str,
+        metric: Metric,
+    ) {
+        self.count_usage(storage_name, |usage| {
+            usage.labeled_entries += u64::from(!labels.is_empty());
+            usage.bytes += (key.len() + labels.len() + self.encode(&metric).len()) as u64;
+        });
+        buffer.insert(storage_name, key, labels, metric);
+    }
+
+    /// Whether metrics and events may be recorded within the given transaction.
+    ///
+    /// A helper process may only record while upload is enabled in the primary process,
+    /// which removes the client ID when upload is disabled.
+    /// This is checked within every write transaction and before recording into the buffer,
+    /// as upload might be disabled at any time after the helper process started.
+    fn may_write(&self, conn: &rusqlite::Connection) -> bool {
+        if !self.shared {
+            return true;
+        }
+
+        let client_id_sql = r#"
+        SELECT value
+        FROM telemetry
+        WHERE
+            id = 'client_id'
+            AND ping = 'glean_client_info'
+        LIMIT 1
+        "#;
+        let client_id = conn
+            .query_row(client_id_sql, [], |row| row.get::<_, Vec<u8>>(0))
+            .optional()
+            .ok()
+            .flatten()
+            .and_then(|blob| self.decode(&blob));
+
+        match client_id {
+            Some(Metric::Uuid(client_id)) => client_id != crate::KNOWN_CLIENT_ID.to_string(),
+            _ => {
+                log::info!("Upload is disabled in the primary process, not recording.");
+                false
+            }
+        }
+    }
+
+    /// Adds a write to the estimated size of the `telemetry` table, if it has a quota,
+    /// and to the estimated usage of its ping.
+    fn count_write(
+        &self,
+        lifetime: Lifetime,
+        storage_name: &str,
+        key: &str,
+        labels: &str,
+        encoded: &[u8],
+    ) {
+        if let Some(quota) = &self.quota {
+            quota.add(storage_name.len() + key.len() + labels.len() + encoded.len());
+        }
+        if lifetime == Lifetime::Ping {
+            self.count_usage(storage_name, |usage| {
+                usage.labeled_entries += u64::from(!labels.is_empty());
+                usage.bytes += (key.len() + labels.len() + encoded.len()) as u64;
+            });
+        }
+    }
+
+    /// Grows the estimated usage of a ping by a write, if its usage was measured before.
+    ///
+    /// Overwrites are counted as new data, so the estimate stays an upper bound.
+    fn count_usage(&self, storage_name: &str, f: impl FnOnce(&mut PingUsage)) {
+        if let Some(usage) = self.usage_estimates.lock().unwrap().get_mut(storage_name) {
+            f(usage);
+        }
+    }
+
+    /// Forgets the estimated usage of pings whose data was cleared,
+    /// so that it's measured again when needed.
+    fn reset_usage(&self, storage_name: Option<&str>) {
+        let mut estimates = self.usage_estimates.lock().unwrap();
+        match storage_name {
+            Some(storage_name) => _ = estimates.remove(storage_name),
+            None => estimates.clear(),
+        }
+    }
+
+    /// An upper bound of the data stored for a ping that submitting it clears,
+    /// if it was measured with [`Database::ping_usage`] before.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_name` - The name of the ping.
+    pub(crate) fn estimated_ping_usage(&self, storage_name: &str) -> Option<PingUsage> {
+        self.usage_estimates
+            .lock()
+            .unwrap()
+            .get(storage_name)
+            .copied()
+    }
+
+    /// Evicts metrics if the `telemetry` table exceeds its quota, see [`quota`].
+    ///
+    /// Records an `invalid_overflow` error for every evicted metric,
+    /// and the quota pressure in the database metrics.
+    ///
+    /// # Returns
+    ///
+    /// The evicted entries.
+    ///
+    /// # Panics
+    ///
+    /// This function will **not** panic on database errors.
+    fn enforce_quota(&self, glean: &Glean, tx: &mut Transaction) -> Vec<quota::Evicted> {
+        let Some(quota) = &self.quota else {
+            return Vec::new();
+        };
+        if !quota.start() {
+            return Vec::new();
+        }
+
+        let result = quota::measure(tx).and_then(|size| {
+            if size <= quota.max_size() {
+                return Ok((Vec::new(), size));
+            }
+            quota::evict(tx, size, quota.target_size())
+        });
+        let (evicted, size) = match result {
+            Ok((evicted, size)) if !evicted.is_empty() => {
+                log::warn!(
+                    "Database exceeded its quota of {} bytes. Evicted {} metrics.",
+                    quota.max_size(),
+                    evicted.len()
+                );
+                self.record_evictions(glean, tx, &evicted);
+                (evicted, size)
+            }
+            Ok((_, size)) => (Vec::new(), size),
+            Err(e) => {
+                log::error!("Failed to enforce the database quota: {e:?}");
+                (Vec::new(), 0)
+            }
+        };
+        // If nothing more can be evicted, wait for more writes before checking again.
+        quota.done(size.min(quota.target_size()));
+        evicted
+    }
+
+    /// Records errors against evicted metrics and counts the evictions.
+    fn record_evictions(&self, glean: &Glean, tx: &mut Transaction, evicted: &[quota::Evicted]) {
+        let mut per_metric: HashMap<&str, (Vec<String>, i32)> = HashMap::new();
+        for entry in evicted {
+            let (pings, count) = per_metric.entry(&entry.id).or_default();
+            if !pings.contains(&entry.ping) {
+                pings.push(entry.ping.clone());
+            }
+            *count += 1;
+        }
+        for (id, (pings, count)) in per_metric {
+            record_error_sqlite(glean, tx, id, &pings, ErrorType::InvalidOverflow, count);
+        }
+
+        let metrics = &glean.database_metrics;
+        let evicted_count = i32::try_from(evicted.len()).unwrap_or(i32::MAX);
+        for (metric, amount) in [
+            (&metrics.quota_exceeded, 1),
+            (&metrics.quota_evicted, evicted_count),
+        ] {
+            _ = self.record_with_transaction(glean, tx, metric.meta(), |old| match old {
+                Some(Metric::Counter(old)) => Metric::Counter(old.saturating_add(amount)),
+                _ => Metric::Counter(amount),
+            });
+        }
+    }
+
     /// Clears a storage (only Ping Lifetime).
     ///
     /// # Returns
@@ -666,6 +1476,8 @@ impl Database {
     ///
     /// This function will **not** panic on database errors.
     pub fn clear_ping_lifetime_storage(&self, storage_name: &str) -> Result<()> {
+        self.clear_buffered(|storage, _| storage == storage_name);
+
         let clear_sql = "DELETE FROM telemetry WHERE lifetime = 'ping' AND ping = ?1";
         self.conn.write(|tx| {
             let mut stmt = tx.prepare_cached(clear_sql)?;
@@ -675,6 +1487,10 @@ impl Database {
     }
 
     pub fn clear_lifetime_storage(&self, lifetime: Lifetime, storage_name: &str) -> Result<()> {
+        if lifetime == Lifetime::Ping {
+            self.clear_buffered(|storage, _| storage == storage_name);
+        }
+
         let clear_sql = "DELETE FROM telemetry WHERE lifetime = ?1 AND ping = ?2";
         self.conn.write(|tx| {
             let mut stmt = tx.prepare_cached(clear_sql)?;
@@ -707,6 +1523,10 @@ impl Database {
         storage_name: &str,
         metric_id: &str,
     ) -> Result<()> {
+        if lifetime == Lifetime::Ping {
+            self.clear_buffered(|storage, id| storage == storage_name && id == metric_id);
+        }
+
         let clear_sql = "DELETE FROM telemetry WHERE lifetime = ?1 AND ping = ?2 AND id = ?3";
         self.conn.write(|tx| {
             let mut stmt = tx.prepare_cached(clear_sql)?;
@@ -723,6 +1543,10 @@ impl Database {
     ///
     /// * This function will **not** panic on database errors.
     pub fn clear_lifetime(&self, lifetime: Lifetime) {
+        if lifetime == Lifetime::Ping {
+            self.clear_buffered(|_, _| true);
+        }
+
         let clear_sql = "DELETE FROM telemetry WHERE lifetime = ?1";
         _ = self.conn.write(|tx| {
             let mut stmt = tx.prepare_cached(clear_sql)?;
@@ -743,6 +1567,8 @@ impl Database {
     ///
     /// * This function will **not** panic on database errors.
     pub fn clear_all(&self) {
+        self.clear_buffered(|_, _| true);
+
         let lifetimes = &[
             Lifetime::User.as_str(),
             Lifetime::Ping.as_str(),
@@ -761,14 +1587,363 @@ impl Database {
         });
     }
 
+    /// Records serialized events into their stores, in a single transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - Pairs of `(store_name, event)`.
+    /// * `counted_store` - The store to count the events of after recording, if any.
+    ///
+    /// # Returns
+    ///
+    /// The number of events in `counted_store` after recording, if it was given.
+    pub(crate) fn record_events(
+        &self,
+        events: &[(&str, Vec<u8>)],
+        counted_store: Option<&str>,
+    ) -> Result<Option<usize>> {
+        let count_sql = "SELECT COUNT(*) FROM events WHERE ping = ?1";
+
+        let store_len = self.conn.write(|tx| {
+            if !self.may_write(tx) {
+                return Ok(counted_store.map(|_| 0));
+            }
+
+            self.insert_events(tx, events)?;
+
+            counted_store
+                .map(|store_name| {
+                    tx.prepare_cached(count_sql)?
+                        .query_row([store_name], |row| row.get::<_, i64>(0))
+                        .map(|n| n as usize)
+                })
+                .transpose()
+        })?;
+        Ok(store_len)
+    }
+
+    fn insert_events(&self, tx: &Transaction, events: &[(&str, Vec<u8>)]) -> rusqlite::Result<()> {
+        let mut insert = tx.prepare_cached("INSERT INTO events (ping, value) VALUES (?1, ?2)")?;
+        for (store_name, event) in events {
+            let value = match &self.cipher {
+                Some(cipher) => cipher.encrypt(event),
+                None => event.clone(),
+            };
+            insert.execute(params![store_name, value])?;
+            self.count_usage(store_name, |usage| {
+                usage.events += 1;
+                usage.bytes += value.len() as u64;
+            });
+        }
+        Ok(())
+    }
+
+    /// Imports the events of a file on disk, marking the file as imported.
+    ///
+    /// The events and the mark are written in a single transaction,
+    /// so a file that is still around after a crash isn't imported twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - The name of the file the events are imported from.
+    /// * `events` - The events, as pairs of the store name and the serialized event.
+    pub(crate) fn import_events(&self, file_name: &str, events: &[(&str, Vec<u8>)]) -> Result<()> {
+        self.conn.write(|tx| {
+            if self.may_write(tx) {
+                self.insert_events(tx, events)?;
+            }
+            tx.prepare_cached(
+                "INSERT INTO telemetry (id, ping, lifetime, labels, value) VALUES (?1, ?2, ?3, ?4, NULL) ON CONFLICT(id, ping, labels) DO NOTHING",
+            )?
+            .execute(params![
+                IMPORTED_EVENTS_ID,
+                INTERNAL_STORAGE,
+                Lifetime::User.as_str(),
+                file_name
+            ])?;
+            Ok(())
+        })
+    }
+
+    /// Whether the events of a file were already imported.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - The name of the file the events are imported from.
+    pub(crate) fn events_imported(&self, file_name: &str) -> Result<bool> {
+        self.conn.read(|conn| {
+            let imported = conn
+                .prepare_cached(
+                    "SELECT 1 FROM telemetry WHERE id = ?1 AND ping = ?2 AND labels = ?3",
+                )?
+                .query_row(
+                    params![IMPORTED_EVENTS_ID, INTERNAL_STORAGE, file_name],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            Ok(imported.is_some())
+        })
+    }
+
+    /// Removes the mark of a file whose events were imported, once the file is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - The name of the file the events were imported from.
+    pub(crate) fn clear_events_imported(&self, file_name: &str) -> Result<()> {
+        self.conn.write(|tx| {
+            tx.prepare_cached("DELETE FROM telemetry WHERE id = ?1 AND ping = ?2 AND labels = ?3")?
+                .execute(params![IMPORTED_EVENTS_ID, INTERNAL_STORAGE, file_name])?;
+            Ok(())
+        })
+    }
+
+    /// Measures the data stored for a ping that submitting it clears:
+    /// its events and metrics with a `ping` lifetime.
+    ///
+    /// Ping-lifetime metrics still buffered in memory are counted as well,
+    /// in place of the stored values they replace.
+    /// The measurement is where [`Database::estimated_ping_usage`] grows from.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_name` - The name of the ping.
+    pub(crate) fn ping_usage(&self, storage_name: &str) -> Result<PingUsage> {
+        let metrics_sql = r#"
+        SELECT
+            COUNT(NULLIF(labels, '')),
+            COALESCE(SUM(length(id) + length(labels) + length(value)), 0)
+        FROM telemetry
+        WHERE ping = ?1 AND lifetime = ?2
+        "#;
+        let events_sql =
+            "SELECT COUNT(*), COALESCE(SUM(length(value)), 0) FROM events WHERE ping = ?1";
+
+        self.conn.read(|conn| {
+            let (labeled_entries, metrics_bytes) = conn
+                .prepare_cached(metrics_sql)?
+                .query_row([storage_name, Lifetime::Ping.as_str()], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+                })?;
+            let (events, events_bytes) = conn
+                .prepare_cached(events_sql)?
+                .query_row([storage_name], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+                })?;
+
+            let mut usage = PingUsage {
+                events: events as u64,
+                labeled_entries: labeled_entries as u64,
+                bytes: (metrics_bytes + events_bytes) as u64,
+            };
+            // Lock order: the connection first, then the buffer, as when recording.
+            if let Some(buffer) = &self.ping_lifetime_data {
+                self.add_buffered_usage(conn, &buffer.lock().unwrap(), storage_name, &mut usage)?;
+            }
+            self.usage_estimates
+                .lock()
+                .unwrap()
+                .insert(storage_name.to_string(), usage);
+            Ok(usage)
+        })
+    }
+
+    /// Adds the ping-lifetime metrics buffered for a ping to its usage,
+    /// replacing the stored values they overwrite once persisted.
+    fn add_buffered_usage(
+        &self,
+        conn: &rusqlite::Connection,
+        buffer: &PingLifetimeBuffer,
+        storage_name: &str,
+        usage: &mut PingUsage,
+    ) -> rusqlite::Result<()> {
+        let stored_sql = r#"
+        SELECT length(id) + length(labels) + length(value)
+        FROM telemetry
+        WHERE ping = ?1 AND lifetime = ?2 AND id = ?3 AND labels = ?4
+        "#;
+        let mut stored = conn.prepare_cached(stored_sql)?;
+        let lifetime = Lifetime::Ping.as_str();
+        for (_, key, labels, metric) in buffer
+            .iter()
+            .filter(|(storage, ..)| *storage == storage_name)
+        {
+            let labeled = u64::from(!labels.is_empty());
+            if let Some(bytes) = stored
+                .query_row(params![storage_name, lifetime, key, labels], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .optional()?
+            {
+                usage.labeled_entries -= labeled;
+                usage.bytes = usage.bytes.saturating_sub(bytes as u64);
+            }
+            usage.labeled_entries += labeled;
+            usage.bytes += (key.len() + labels.len() + self.encode(metric).len()) as u64;
+        }
+        Ok(())
+    }
+
+    /// Gets the names of all stores with recorded events.
+    pub(crate) fn event_stores(&self) -> Result<Vec<String>> {
+        self.conn.read(|conn| {
+            let mut stmt = conn.prepare_cached("SELECT DISTINCT ping FROM events")?;
+            let stores = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(stores)
+        })
+    }
+
+    /// Gets the serialized events of a store, in the order they were recorded in.
+    ///
+    /// Events that cannot be decrypted are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `store_name` - The name of the store.
+    /// * `clear_store` - Whether to delete the events in the same transaction.
+    pub(crate) fn get_events(&self, store_name: &str, clear_store: bool) -> Result<Vec<Vec<u8>>> {
+        let result = self
+            .conn
+            .write(|tx| Self::select_events(tx, store_name, clear_store));
+        let values = self.check_on_error(result.map_err(crate::Error::from))?;
+        Ok(self.decrypt_events(values))
+    }
+
+    /// Selects the still encrypted events of a store, optionally deleting them.
+    fn select_events(
+        conn: &rusqlite::Connection,
+        store_name: &str,
+        clear_store: bool,
+    ) -> rusqlite::Result<Vec<Vec<u8>>> {
+        let values = conn
+            .prepare_cached("SELECT value FROM events WHERE ping = ?1 ORDER BY id")?
+            .query_map([store_name], |row| row.get::<_, Vec<u8>>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if clear_store {
+            conn.prepare_cached("DELETE FROM events WHERE ping = ?1")?
+                .execute([store_name])?;
+        }
+        Ok(values)
+    }
+
+    /// Decrypts selected events, dropping those that can't be decrypted.
+    fn decrypt_events(&self, values: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        values
+            .into_iter()
+            .filter_map(|value| match &self.cipher {
+                Some(cipher) => cipher.decrypt(&value).map(|value| value.into_owned()),
+                None => Some(value),
+            })
+            .collect()
+    }
+
+    /// Clears the events of a store, or of all stores if none is given.
+    pub(crate) fn clear_events(&self, store_name: Option<&str>) -> Result<()> {
+        self.reset_usage(store_name);
+        self.conn.write(|tx| {
+            match store_name {
+                Some(store_name) => {
+                    tx.execute("DELETE FROM events WHERE ping = ?1", [store_name])?
+                }
+                None => tx.execute("DELETE FROM events", [])?,
+            };
+            Ok(())
+        })
+    }
+
+    /// Drops buffered ping-lifetime metrics matching the predicate on `(storage_name, metric_id)`.
+    fn clear_buffered(&self, f: impl FnMut(&str, &str) -> bool) {
+        if let Some(buffer) = &self.ping_lifetime_data {
+            buffer.lock().unwrap().remove_where(f);
+        }
+    }
+
     /// Persists ping_lifetime_data to disk.
     ///
+    /// All buffered metrics are written in a single transaction,
+    /// which enforces the quota as well.
+    /// The buffer is only emptied once that transaction is committed,
+    /// so on errors the data stays buffered and is persisted on the next attempt.
+    ///
     /// Does nothing in case there is nothing to persist.
     ///
     /// # Panics
     ///
     /// * This function will **not** panic on database errors.
-    pub fn persist_ping_lifetime_data(&self) -> Result<()> {
+    pub fn persist_ping_lifetime_data(&self, glean: &Glean) -> Result<()> {
+        let Some(buffer) = &self.ping_lifetime_data else {
+            return Ok(());
+        };
+
+        // Lock order: the connection first, then the buffer, as when recording.
+        // Holding the connection keeps others from recording into the buffer meanwhile.
+        let mut conn = self.conn.lock();
+        if buffer.lock().unwrap().is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.write_buffer(&mut tx, buffer)?;
+
+        // Enforcing the quota records into the buffer, which is written again,
+        // but not what was evicted.
+        let evicted = self.enforce_quota(glean, &mut tx);
+        if !evicted.is_empty() {
+            buffer.lock().unwrap().remove_where(|storage_name, key| {
+                evicted
+                    .iter()
+                    .any(|entry| entry.ping == storage_name && entry.id == key)
+            });
+            self.write_buffer(&mut tx, buffer)?;
+        }
+
+        tx.commit()?;
+        buffer.lock().unwrap().persisted();
+
         Ok(())
     }
+
+    /// Writes all buffered ping-lifetime metrics within the given transaction.
+    fn write_buffer(&self, tx: &mut Transaction, buffer: &Mutex<PingLifetimeBuffer>) -> Result<()> {
+        let buffer = buffer.lock().unwrap();
+        for (storage_name, key, labels, metric) in buffer.iter() {
+            self.record_per_lifetime(tx, Lifetime::Ping, storage_name, key, labels, metric)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a consistent copy of the database to a new file, using SQLite's online backup.
+    ///
+    /// Buffered ping-lifetime data is persisted first.
+    /// Writes are blocked until the copy is done.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - The path of the copy.
+    pub fn backup(&self, glean: &Glean, dest: &Path) -> Result<()> {
+        self.persist_ping_lifetime_data(glean)?;
+        let conn = self.conn.lock();
+        conn.backup(rusqlite::MAIN_DB, dest, None)?;
+        Ok(())
+    }
+
+    /// Persists the ping-lifetime buffer if it reached its write threshold or maximum age.
+    ///
+    /// Errors are logged.
+    fn persist_ping_lifetime_data_if_due(&self, glean: &Glean) {
+        let Some(buffer) = &self.ping_lifetime_data else {
+            return;
+        };
+        let due = buffer
+            .lock()
+            .unwrap()
+            .is_due(self.ping_lifetime_threshold, self.ping_lifetime_max_time);
+        if due {
+            if let Err(e) = self.persist_ping_lifetime_data(glean) {
+                log::warn!("Could not persist ping lifetime data: {:?}", e);
+            }
+        }
+    }
 }
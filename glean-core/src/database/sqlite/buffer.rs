@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! In-memory buffer for metrics with ping lifetime.
+//!
+//! With `delay_ping_lifetime_io` set, ping-lifetime metrics are recorded into this buffer
+//! instead of writing each of them in its own transaction.
+//! The buffer is written to the database in a single transaction when it is persisted.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metric;
+
+/// Buffered metrics, keyed by `(storage_name, metric_id, labels)`.
+#[derive(Debug)]
+pub struct PingLifetimeBuffer {
+    data: BTreeMap<(String, String, String), Metric>,
+    /// The number of writes since the buffer was last persisted.
+    writes: usize,
+    /// When the buffer was last persisted.
+    last_persisted: Instant,
+}
+
+impl PingLifetimeBuffer {
+    pub fn new() -> Self {
+        Self {
+            data: BTreeMap::new(),
+            writes: 0,
+            last_persisted: Instant::now(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, storage_name: &str, metric_id: &str, labels: &str) -> Option<&Metric> {
+        self.data
+            .get(&(storage_name.into(), metric_id.into(), labels.into()))
+    }
+
+    pub fn insert(&mut self, storage_name: &str, metric_id: &str, labels: &str, metric: Metric) {
+        self.data.insert(
+            (storage_name.into(), metric_id.into(), labels.into()),
+            metric,
+        );
+        self.writes += 1;
+    }
+
+    /// Whether the buffer holds a metric with any labels.
+    pub fn contains_metric(&self, storage_name: &str, metric_id: &str) -> bool {
+        self.data
+            .keys()
+            .any(|(storage, id, _)| storage == storage_name && id == metric_id)
+    }
+
+    /// Iterates over all buffered metrics as `(storage_name, metric_id, labels, metric)`.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, &str, &Metric)> {
+        self.data
+            .iter()
+            .map(|((storage, id, labels), metric)| (&storage[..], &id[..], &labels[..], metric))
+    }
+
+    /// Removes buffered metrics matching the predicate on `(storage_name, metric_id)`.
+    pub fn remove_where(&mut self, mut f: impl FnMut(&str, &str) -> bool) {
+        self.data.retain(|(storage, id, _), _| !f(storage, id));
+    }
+
+    /// Whether the buffer should be persisted.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The number of writes after which to persist. `0` disables it.
+    /// * `max_time` - The time after which to persist. Zero disables it.
+    pub fn is_due(&self, threshold: usize, max_time: Duration) -> bool {
+        !self.is_empty()
+            && ((threshold > 0 && self.writes >= threshold)
+                || (!max_time.is_zero() && self.last_persisted.elapsed() >= max_time))
+    }
+
+    /// Empties the buffer after it was written to the database.
+    pub fn persisted(&mut self) {
+        self.data.clear();
+        self.writes = 0;
+        self.last_persisted = Instant::now();
+    }
+}
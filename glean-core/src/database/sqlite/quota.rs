@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Size quota for the `telemetry` table.
+//!
+//! The size of the table is the total size of its identifiers, store names, labels and values,
+//! which approximates the space it takes on disk.
+//! Measuring it requires a full scan, so it is only measured on the first write and
+//! when an upper-bound estimate, grown with every write, exceeds the quota.
+//!
+//! When the table exceeds the quota, entries are evicted until it takes up
+//! [`TARGET_PERCENT`] of the quota, in this order:
+//!
+//! 1. Metrics with ping lifetime before metrics with application lifetime.
+//! 2. Labeled entries before unlabeled ones, as unbounded dynamic labels are the usual cause of growth.
+//! 3. The largest entries first.
+//!
+//! Metrics with user lifetime, Glean's internal storage and Glean's own metrics,
+//! including recorded errors, are never evicted.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use rusqlite::Transaction;
+
+/// The size in percent of the quota to evict down to, to not evict on every write.
+pub const TARGET_PERCENT: u64 = 90;
+
+/// An evicted entry.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Evicted {
+    /// The metric identifier.
+    pub id: String,
+    /// The store it was evicted from.
+    pub ping: String,
+}
+
+/// The quota and the estimated size of the `telemetry` table.
+#[derive(Debug)]
+pub struct Quota {
+    max_size: u64,
+    /// An upper bound of the table size: overwrites are counted as new entries.
+    estimated_size: AtomicU64,
+    /// Set while the quota is enforced, as that records errors, which writes to the table again.
+    enforcing: AtomicBool,
+}
+
+impl Quota {
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            // Forces a measurement on the first write.
+            estimated_size: AtomicU64::new(u64::MAX),
+            enforcing: AtomicBool::new(false),
+        }
+    }
+
+    /// Adds a write of the given size to the estimate.
+    pub fn add(&self, size: usize) {
+        _ = self
+            .estimated_size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |estimate| {
+                Some(estimate.saturating_add(size as u64))
+            });
+    }
+
+    /// Starts enforcing the quota if the estimate exceeds it and it isn't already enforced.
+    ///
+    /// # Returns
+    ///
+    /// Whether to enforce it. Call [`Quota::done`] when done.
+    pub fn start(&self) -> bool {
+        self.estimated_size.load(Ordering::Relaxed) > self.max_size
+            && !self.enforcing.swap(true, Ordering::Relaxed)
+    }
+
+    /// Finishes enforcing the quota with the measured size of the table.
+    pub fn done(&self, size: u64) {
+        self.estimated_size.store(size, Ordering::Relaxed);
+        self.enforcing.store(false, Ordering::Relaxed);
+    }
+
+    /// The maximum size of the table in bytes.
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// The size to evict down to.
+    pub fn target_size(&self) -> u64 {
+        self.max_size / 100 * TARGET_PERCENT
+    }
+}
+
+/// Measures the size of the `telemetry` table.
+pub fn measure(tx: &Transaction) -> rusqlite::Result<u64> {
+    tx.query_row(
+        "SELECT COALESCE(SUM(length(id) + length(ping) + length(labels) + COALESCE(length(value), 0)), 0) FROM telemetry",
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|size| size as u64)
+}
+
+/// Evicts entries until the table is no larger than `target_size`.
+///
+/// # Arguments
+///
+/// * `tx` - The transaction to evict in.
+/// * `size` - The current size of the table.
+/// * `target_size` - The size to evict down to.
+///
+/// # Returns
+///
+/// The evicted entries and the size of the table afterwards,
+/// which is larger than `target_size` if there was nothing left to evict.
+pub fn evict(
+    tx: &Transaction,
+    mut size: u64,
+    target_size: u64,
+) -> rusqlite::Result<(Vec<Evicted>, u64)> {
+    let mut evicted = Vec::new();
+    let mut to_delete = Vec::new();
+    {
+        let mut stmt = tx.prepare(
+            "SELECT rowid, id, ping, length(id) + length(ping) + length(labels) + COALESCE(length(value), 0) AS size
+             FROM telemetry
+             WHERE lifetime IN ('ping', 'application')
+               AND ping NOT IN ('glean_internal_info', 'glean_client_info')
+               AND id NOT LIKE 'glean.%'
+             ORDER BY lifetime = 'ping' DESC, labels = '' ASC, size DESC",
+        )?;
+        let mut rows = stmt.query([])?;
+        while size > target_size {
+            let Some(row) = rows.next()? else {
+                break;
+            };
+            to_delete.push(row.get::<_, i64>(0)?);
+            evicted.push(Evicted {
+                id: row.get(1)?,
+                ping: row.get(2)?,
+            });
+            size = size.saturating_sub(row.get::<_, i64>(3)? as u64);
+        }
+    }
+
+    let mut stmt = tx.prepare_cached("DELETE FROM telemetry WHERE rowid = ?1")?;
+    for rowid in to_delete {
+        stmt.execute([rowid])?;
+    }
+    Ok((evicted, size))
+}
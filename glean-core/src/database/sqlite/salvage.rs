@@ -0,0 +1,254 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Salvaging the readable rows of a corrupt database.
+//!
+//! A database that can't be opened is moved aside, and every row that can still be read
+//! is copied into a fresh database, table by table in `rowid` order.
+//! When reading fails, e.g. on a corrupt page, the scan resumes after increasingly larger
+//! gaps of row ids, until it gets past the unreadable part or gives up.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::types::Value;
+use rusqlite::{ErrorCode, OpenFlags, Transaction};
+
+use super::connection::Connection;
+
+/// The tables to salvage, in the order they are salvaged.
+const TABLES: &[&str] = &["telemetry", "events", "migration"];
+
+/// How often in a row reading may fail before the rest of a table is given up.
+///
+/// The gap of row ids to skip doubles with every failure.
+const MAX_FAILURES: u32 = 32;
+
+/// What was salvaged from a corrupt database.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Salvage {
+    /// The number of rows copied into the new database.
+    pub rows: u64,
+    /// An estimate of the number of rows that couldn't be read.
+    ///
+    /// Derived from the row ids skipped over, so it includes rows deleted before.
+    /// It doesn't include tables or databases that couldn't be read at all.
+    pub lost_rows: u64,
+}
+
+/// The path of a corrupt database while it is salvaged.
+pub fn corrupt_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".corrupt")
+}
+
+/// The path of the marker requesting to check a database for corruption when it is opened next.
+pub fn check_marker_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".check")
+}
+
+/// Requests to check a database for corruption when it is opened next, see [`quick_check`].
+pub fn mark_for_check(path: &Path) {
+    if let Err(e) = fs::write(check_marker_path(path), []) {
+        log::warn!(
+            "Failed to mark {} for a corruption check: {e}",
+            path.display()
+        );
+    }
+}
+
+/// Whether an error is caused by a corrupt database file.
+pub fn is_corrupt(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
+/// Checks a database for corrupt pages.
+///
+/// Corrupt pages would otherwise only be noticed when reading them.
+/// This reads the whole database, so it only runs after errors and during maintenance.
+///
+/// # Returns
+///
+/// A `SQLITE_CORRUPT` error with the first problem found, if any.
+pub fn quick_check(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let check: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some(check),
+        ));
+    }
+    Ok(())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// Moves a database file and its write-ahead log aside to `to`.
+///
+/// The shared memory file is removed, SQLite recreates it from the log.
+pub fn move_aside(path: &Path, to: &Path) -> io::Result<()> {
+    // Left over from an interrupted recovery.
+    remove(to);
+
+    fs::rename(path, to)?;
+    match fs::rename(with_suffix(path, "-wal"), with_suffix(to, "-wal")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    match fs::remove_file(with_suffix(path, "-shm")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Removes a database file with its write-ahead log and shared memory file.
+pub fn remove(path: &Path) {
+    for path in [
+        path.to_path_buf(),
+        with_suffix(path, "-wal"),
+        with_suffix(path, "-shm"),
+    ] {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                log::warn!("Failed to remove {}: {e}", path.display());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Copies the readable rows of the database at `from` into `to`.
+///
+/// Tables or columns that don't exist in both databases are skipped.
+/// Never fails: whatever can't be read or written is lost.
+///
+/// # Arguments
+///
+/// * `from` - The corrupt database file.
+/// * `to` - The new database, with the current schema.
+///
+/// # Returns
+///
+/// What was salvaged.
+pub fn salvage(from: &Path, to: &Connection) -> Salvage {
+    let mut salvage = Salvage::default();
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let from = match rusqlite::Connection::open_with_flags(from, flags) {
+        Ok(from) => from,
+        Err(e) => {
+            log::info!("Can't salvage anything from the corrupt database: {e}");
+            return salvage;
+        }
+    };
+
+    let result = to.write(|tx| {
+        for table in TABLES {
+            if let Err(e) = salvage_table(&from, tx, table, &mut salvage) {
+                log::info!("Can't salvage the {table} table: {e}");
+            }
+        }
+        Ok::<(), rusqlite::Error>(())
+    });
+    match result {
+        Ok(()) => log::info!("Salvaged a corrupt database: {salvage:?}"),
+        Err(e) => {
+            log::warn!("Failed to write the salvaged rows: {e}");
+            salvage = Salvage::default();
+        }
+    }
+    salvage
+}
+
+/// The columns of a table, empty if it doesn't exist.
+fn columns(conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let columns = stmt.query_map([table], |row| row.get(0))?;
+    columns.collect()
+}
+
+fn salvage_table(
+    from: &rusqlite::Connection,
+    tx: &Transaction,
+    table: &str,
+    salvage: &mut Salvage,
+) -> rusqlite::Result<()> {
+    let current = columns(tx, table)?;
+    let columns: Vec<_> = columns(from, table)?
+        .into_iter()
+        .filter(|column| current.contains(column))
+        .collect();
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let names = columns.join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let mut select = from.prepare(&format!(
+        "SELECT rowid, {names} FROM {table} WHERE rowid > ?1 ORDER BY rowid"
+    ))?;
+    let mut insert = tx.prepare(&format!(
+        "INSERT OR IGNORE INTO {table} ({names}) VALUES ({placeholders})"
+    ))?;
+
+    // The row id of the last row read.
+    let mut last_read = 0i64;
+    let mut failures = 0;
+    loop {
+        // Skip 1, 3, 7, ... row ids after the last row read.
+        let after = last_read.saturating_add((1i64 << failures) - 1);
+        let mut rows = select.query([after])?;
+        let error = loop {
+            let row = match rows.next() {
+                Ok(Some(row)) => row,
+                Ok(None) => break None,
+                Err(e) => break Some(e),
+            };
+
+            let rowid: i64 = row.get(0)?;
+            if failures > 0 {
+                salvage.lost_rows += rowid.abs_diff(last_read) - 1;
+                failures = 0;
+            }
+            last_read = rowid;
+
+            let values = (1..=columns.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            match insert.execute(rusqlite::params_from_iter(values)) {
+                Ok(1) => salvage.rows += 1,
+                Ok(_) => salvage.lost_rows += 1,
+                Err(e) => {
+                    log::debug!("Failed to salvage a row of {table}: {e}");
+                    salvage.lost_rows += 1;
+                }
+            }
+        };
+
+        match error {
+            None => {
+                if failures > 0 {
+                    // Whatever was unreadable at the end of the table.
+                    salvage.lost_rows += 1;
+                }
+                return Ok(());
+            }
+            Some(e) => {
+                log::debug!("Failed to read {table} after row {after}: {e}");
+                failures += 1;
+                if failures > MAX_FAILURES {
+                    salvage.lost_rows += 1;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -16,7 +16,7 @@ use super::connection::ConnectionOpener;
 pub struct Schema;
 
 impl ConnectionOpener for Schema {
-    const MAX_SCHEMA_VERSION: u32 = 2;
+    const MAX_SCHEMA_VERSION: u32 = 3;
 
     type Error = SchemaError;
 
@@ -64,6 +64,7 @@ impl ConnectionOpener for Schema {
              CREATE TABLE migration(id INTEGER PRIMARY KEY, state TEXT NOT NULL);
             ",
         )?;
+        create_events_table(tx)?;
         Ok(())
     }
 
@@ -90,6 +91,13 @@ impl ConnectionOpener for Schema {
                 }
                 Ok(())
             }
+            3 => {
+                log::info!("Upgrading user_version to 3");
+                // Events recorded before are still in files in the `events` directory.
+                // They are imported when pending events are loaded on startup.
+                create_events_table(tx)?;
+                Ok(())
+            }
             to_version => Err(SchemaError::UnsupportedSchemaVersion(to_version)),
         }
     }
@@ -97,10 +105,29 @@ impl ConnectionOpener for Schema {
     fn validate(tx: &mut Transaction<'_>) -> Result<(), Self::Error> {
         // A query selecting every field, it doesn't need to return anything.
         tx.execute_batch("SELECT id, ping, lifetime, labels, value FROM telemetry WHERE 1 = 0")?;
+        tx.execute_batch("SELECT id, ping, value FROM events WHERE 1 = 0")?;
         Ok(())
     }
 }
 
+/// Creates the table holding recorded events.
+///
+/// Events of a ping are kept in the order they were recorded in, by `id`.
+/// The table might already exist if a newer version downgraded the schema.
+fn create_events_table(tx: &mut Transaction<'_>) -> Result<(), SchemaError> {
+    tx.execute_batch(
+        "
+         CREATE TABLE IF NOT EXISTS events(
+           id INTEGER PRIMARY KEY AUTOINCREMENT,
+           ping TEXT NOT NULL,
+           value BLOB NOT NULL
+         );
+         CREATE INDEX IF NOT EXISTS events_ping ON events(ping);
+        ",
+    )?;
+    Ok(())
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum SchemaError {
     #[error("unsupported schema version: {0}")]
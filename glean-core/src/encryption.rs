@@ -0,0 +1,412 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encryption of Glean's data at rest.
+//!
+//! When the embedder configures an [`EncryptionKeyProvider`],
+//! metric values in the database, recorded events and pending pings
+//! are encrypted with AES-256-GCM before they are written to disk.
+//! This requires the `encryption` feature.
+//!
+//! Encrypted data starts with a marker that can't start a plaintext value,
+//! so data written before encryption was enabled can still be read.
+//! On the first initialization with a key all existing plaintext data is encrypted.
+//!
+//! A check value, encrypted with the key, is stored in the data directory.
+//! Once it exists Glean refuses to initialize without the key,
+//! or with a different key, instead of discarding data it can't read.
+//!
+//! Metric identifiers, ping names and labels in the database,
+//! as well as the names of event stores and ping files, are not encrypted.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::InternalConfiguration;
+
+/// The file in the data directory holding the encrypted check value.
+const CHECK_FILENAME: &str = "encryption_check";
+
+/// The value encrypted into the check file.
+const CHECK_VALUE: &[u8] = b"glean-encryption-check";
+
+/// The first byte of encrypted data.
+///
+/// `0xC1` is neither valid in MessagePack nor in UTF-8,
+/// so it can't start a plaintext database value or ping file.
+const MARKER: u8 = 0xC1;
+
+/// The length of the key expected from an [`EncryptionKeyProvider`], in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// Supplies the key to encrypt Glean's data with.
+///
+/// Implemented by the embedder, e.g. backed by the platform's key store.
+#[uniffi::trait_interface]
+pub trait EncryptionKeyProvider: Send + Sync {
+    /// Returns the 256-bit key, or `None` if it is currently unavailable.
+    ///
+    /// This is called once during initialization.
+    fn get_key(&self) -> Option<Vec<u8>>;
+}
+
+impl fmt::Debug for dyn EncryptionKeyProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKeyProvider")
+    }
+}
+
+/// The ways encryption at rest can fail to set up.
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncryptionError {
+    /// The data directory is encrypted, but no key is available.
+    KeyUnavailable,
+    /// The key isn't [`KEY_LEN`] bytes long.
+    InvalidKey,
+    /// The data directory is encrypted with a different key.
+    KeyMismatch,
+    /// This build doesn't support encryption.
+    Unsupported,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::KeyUnavailable => {
+                write!(f, "The data is encrypted, but no key is available")
+            }
+            EncryptionError::InvalidKey => write!(f, "The key must be {} bytes long", KEY_LEN),
+            EncryptionError::KeyMismatch => {
+                write!(f, "The data is encrypted with a different key")
+            }
+            EncryptionError::Unsupported => {
+                write!(f, "Glean was built without the `encryption` feature")
+            }
+        }
+    }
+}
+
+impl From<EncryptionError> for Error {
+    fn from(error: EncryptionError) -> Error {
+        ErrorKind::Encryption(error).into()
+    }
+}
+
+/// Whether data was encrypted by a [`Cipher`].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.first() == Some(&MARKER)
+}
+
+/// Whether a line of text was encrypted by [`Cipher::encrypt_line`].
+///
+/// Plaintext lines hold JSON objects.
+pub fn is_encrypted_line(line: &str) -> bool {
+    !line.is_empty() && !line.starts_with('{')
+}
+
+#[cfg(feature = "encryption")]
+mod imp {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use super::{EncryptionError, KEY_LEN, MARKER};
+
+    /// The version of the encrypted data format, following the marker.
+    const FORMAT_VERSION: u8 = 1;
+
+    /// The length of the header preceding the ciphertext.
+    const HEADER_LEN: usize = 2 + NONCE_LEN;
+
+    /// Encrypts and decrypts data with AES-256-GCM.
+    pub struct Cipher {
+        key: LessSafeKey,
+        rng: SystemRandom,
+    }
+
+    impl Cipher {
+        pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+            if key.len() != KEY_LEN {
+                return Err(EncryptionError::InvalidKey);
+            }
+            let key =
+                UnboundKey::new(&AES_256_GCM, key).map_err(|_| EncryptionError::InvalidKey)?;
+            Ok(Self {
+                key: LessSafeKey::new(key),
+                rng: SystemRandom::new(),
+            })
+        }
+
+        pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+            let mut nonce = [0; NONCE_LEN];
+            self.rng
+                .fill(&mut nonce)
+                .expect("IMPOSSIBLE: The system random number generator failed");
+
+            let mut data = Vec::with_capacity(HEADER_LEN + plaintext.len() + AES_256_GCM.tag_len());
+            data.extend_from_slice(&[MARKER, FORMAT_VERSION]);
+            data.extend_from_slice(&nonce);
+            let mut ciphertext = plaintext.to_vec();
+            self.key
+                .seal_in_place_append_tag(
+                    Nonce::assume_unique_for_key(nonce),
+                    Aad::empty(),
+                    &mut ciphertext,
+                )
+                .expect("IMPOSSIBLE: Encrypting failed");
+            data.extend_from_slice(&ciphertext);
+            data
+        }
+
+        pub fn decrypt_encrypted(&self, data: &[u8]) -> Option<Vec<u8>> {
+            if data.len() < HEADER_LEN || data[1] != FORMAT_VERSION {
+                return None;
+            }
+            let nonce = Nonce::try_assume_unique_for_key(&data[2..HEADER_LEN]).ok()?;
+            let mut plaintext = data[HEADER_LEN..].to_vec();
+            let len = self
+                .key
+                .open_in_place(nonce, Aad::empty(), &mut plaintext)
+                .ok()?
+                .len();
+            plaintext.truncate(len);
+            Some(plaintext)
+        }
+
+        pub fn encrypt_line(&self, line: &str) -> String {
+            STANDARD.encode(self.encrypt(line.as_bytes()))
+        }
+
+        pub fn decrypt_encrypted_line(&self, line: &str) -> Option<String> {
+            let data = STANDARD.decode(line).ok()?;
+            String::from_utf8(self.decrypt_encrypted(&data)?).ok()
+        }
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+mod imp {
+    use super::EncryptionError;
+
+    /// Encrypts and decrypts data.
+    ///
+    /// Without the `encryption` feature this can't be constructed.
+    pub enum Cipher {}
+
+    impl Cipher {
+        pub fn new(_key: &[u8]) -> Result<Self, EncryptionError> {
+            Err(EncryptionError::Unsupported)
+        }
+
+        pub fn encrypt(&self, _plaintext: &[u8]) -> Vec<u8> {
+            match *self {}
+        }
+
+        pub fn decrypt_encrypted(&self, _data: &[u8]) -> Option<Vec<u8>> {
+            match *self {}
+        }
+
+        pub fn encrypt_line(&self, _line: &str) -> String {
+            match *self {}
+        }
+
+        pub fn decrypt_encrypted_line(&self, _line: &str) -> Option<String> {
+            match *self {}
+        }
+    }
+}
+
+/// Encrypts and decrypts Glean's data at rest.
+pub struct Cipher(imp::Cipher);
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cipher")
+    }
+}
+
+impl Cipher {
+    /// Creates a cipher from a [`KEY_LEN`]-byte key.
+    pub fn new(key: &[u8]) -> Result<Self, EncryptionError> {
+        imp::Cipher::new(key).map(Cipher)
+    }
+
+    /// Encrypts data.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        self.0.encrypt(plaintext)
+    }
+
+    /// Decrypts data.
+    ///
+    /// Data that isn't encrypted is passed through unchanged.
+    ///
+    /// # Returns
+    ///
+    /// The plaintext, or `None` if the data can't be decrypted with this key.
+    pub fn decrypt<'a>(&self, data: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if !is_encrypted(data) {
+            return Some(Cow::Borrowed(data));
+        }
+        self.0.decrypt_encrypted(data).map(Cow::Owned)
+    }
+
+    /// Encrypts a line of text, keeping it a single line.
+    pub fn encrypt_line(&self, line: &str) -> String {
+        self.0.encrypt_line(line)
+    }
+
+    /// Decrypts a line of text encrypted by [`Cipher::encrypt_line`].
+    ///
+    /// Lines that aren't encrypted are passed through unchanged.
+    pub fn decrypt_line<'a>(&self, line: &'a str) -> Option<Cow<'a, str>> {
+        if !is_encrypted_line(line) {
+            return Some(Cow::Borrowed(line));
+        }
+        self.0.decrypt_encrypted_line(line).map(Cow::Owned)
+    }
+}
+
+/// Sets up encryption at rest for a data directory.
+///
+/// # Arguments
+///
+/// * `cfg` - The configuration, holding the key provider, if any.
+/// * `data_path` - The data directory.
+///
+/// # Returns
+///
+/// The cipher to use, or `None` if encryption isn't configured.
+/// Fails if the data directory is encrypted, but the key is unavailable or doesn't match.
+pub fn load_cipher(cfg: &InternalConfiguration, data_path: &Path) -> Result<Option<Cipher>> {
+    load_cipher_with(cfg.encryption_key_provider.as_deref(), data_path)
+}
+
+/// Sets up encryption at rest for a data directory, with the key from the given provider.
+///
+/// See [`load_cipher`].
+pub fn load_cipher_with(
+    provider: Option<&dyn EncryptionKeyProvider>,
+    data_path: &Path,
+) -> Result<Option<Cipher>> {
+    let check = match fs::read(data_path.join(CHECK_FILENAME)) {
+        Ok(check) => Some(check),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some(provider) = provider else {
+        return match check {
+            Some(_) => Err(EncryptionError::KeyUnavailable.into()),
+            None => Ok(None),
+        };
+    };
+
+    let key = provider.get_key().ok_or(EncryptionError::KeyUnavailable)?;
+    let cipher = Cipher::new(&key)?;
+
+    if let Some(check) = check {
+        if !is_encrypted(&check) || cipher.decrypt(&check).as_deref() != Some(CHECK_VALUE) {
+            return Err(EncryptionError::KeyMismatch.into());
+        }
+    }
+
+    Ok(Some(cipher))
+}
+
+/// Whether the data directory was fully encrypted before.
+pub fn is_migrated(data_path: &Path) -> bool {
+    data_path.join(CHECK_FILENAME).exists()
+}
+
+/// Marks the data directory as fully encrypted with the cipher's key.
+///
+/// Must only be called once all plaintext data was encrypted.
+pub fn mark_migrated(cipher: &Cipher, data_path: &Path) -> Result<()> {
+    let tmp_path = data_path.join(format!("{CHECK_FILENAME}.tmp"));
+    fs::write(&tmp_path, cipher.encrypt(CHECK_VALUE))?;
+    fs::rename(&tmp_path, data_path.join(CHECK_FILENAME))?;
+    Ok(())
+}
+
+/// Encrypts a file in place, if it isn't encrypted yet.
+///
+/// The file is replaced atomically.
+pub fn encrypt_file(cipher: &Cipher, path: &Path) -> std::io::Result<()> {
+    let data = fs::read(path)?;
+    if is_encrypted(&data) {
+        return Ok(());
+    }
+    write_atomically(path, &cipher.encrypt(&data))
+}
+
+/// Encrypts the lines of a text file in place, if they aren't encrypted yet.
+///
+/// The file is replaced atomically.
+pub fn encrypt_lines_file(cipher: &Cipher, path: &Path) -> std::io::Result<()> {
+    let data = fs::read_to_string(path)?;
+    if data.lines().all(is_encrypted_line) {
+        return Ok(());
+    }
+    let mut encrypted = String::with_capacity(data.len() * 2);
+    for line in data.lines().filter(|line| !line.is_empty()) {
+        if is_encrypted_line(line) {
+            encrypted.push_str(line);
+        } else {
+            encrypted.push_str(&cipher.encrypt_line(line));
+        }
+        encrypted.push('\n');
+    }
+    write_atomically(path, encrypted.as_bytes())
+}
+
+fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn data_roundtrips_and_plaintext_passes_through() {
+        let cipher = Cipher::new(&[7; KEY_LEN]).unwrap();
+
+        let encrypted = cipher.encrypt(b"hello");
+        assert!(is_encrypted(&encrypted));
+        assert_ne!(
+            cipher.encrypt(b"hello"),
+            encrypted,
+            "nonces must not repeat"
+        );
+        assert_eq!(b"hello", &*cipher.decrypt(&encrypted).unwrap());
+        assert_eq!(b"plain", &*cipher.decrypt(b"plain").unwrap());
+
+        let line = cipher.encrypt_line("{\"a\":1}");
+        assert!(is_encrypted_line(&line));
+        assert!(!line.contains('\n'));
+        assert_eq!("{\"a\":1}", cipher.decrypt_line(&line).unwrap());
+        assert_eq!("{\"b\":2}", cipher.decrypt_line("{\"b\":2}").unwrap());
+    }
+
+    #[test]
+    fn other_keys_cant_decrypt() {
+        let cipher = Cipher::new(&[7; KEY_LEN]).unwrap();
+        let other = Cipher::new(&[8; KEY_LEN]).unwrap();
+
+        assert!(other.decrypt(&cipher.encrypt(b"hello")).is_none());
+        assert!(other.decrypt_line(&cipher.encrypt_line("{}")).is_none());
+        assert_eq!(
+            EncryptionError::InvalidKey,
+            Cipher::new(&[7; 16]).unwrap_err()
+        );
+    }
+}
@@ -10,6 +10,7 @@ use std::result;
 use rkv::StoreError;
 
 use crate::database::sqlite::{OpenError, SchemaError};
+use crate::encryption::EncryptionError;
 
 /// A specialized [`Result`] type for this crate's operations.
 ///
@@ -73,6 +74,9 @@ pub enum ErrorKind {
 
     /// Schema error
     Schema(SchemaError),
+
+    /// Encryption at rest could not be set up
+    Encryption(EncryptionError),
 }
 
 /// A specialized [`Error`] type for this crate's operations.
@@ -131,6 +135,7 @@ impl Display for Error {
             UuidError(e) => write!(f, "Failed to parse UUID: {}", e),
             SQLite(e) => write!(f, "SQLite error: {}", e),
             Schema(e) => write!(f, "Schema error: {}", e),
+            Encryption(e) => write!(f, "Encryption error: {}", e),
         }
     }
 }
@@ -21,6 +21,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
 
 use crate::common_metric_data::CommonMetricDataInternal;
+use crate::encryption::{self, Cipher};
 use crate::error_recording::{record_error, ErrorType};
 use crate::metrics::{DatetimeMetric, TimeUnit};
 use crate::session::{EventSessionContext, SessionMetadata};
@@ -68,9 +69,9 @@ pub struct RecordedEvent {
 #[derive(
     Debug, Clone, Deserialize, Serialize, PartialEq, Eq, malloc_size_of_derive::MallocSizeOf,
 )]
-struct StoredEvent {
+pub(crate) struct StoredEvent {
     #[serde(flatten)]
-    event: RecordedEvent,
+    pub event: RecordedEvent,
 
     /// The monotonically-increasing execution counter.
     ///
@@ -81,46 +82,53 @@ struct StoredEvent {
     pub execution_counter: Option<i32>,
 }
 
-/// This struct handles the in-memory and on-disk storage logic for events.
+/// This struct handles the storage logic for events.
 ///
-/// So that the data survives shutting down of the application, events are stored
-/// in an append-only file on disk, in addition to the store in memory. Each line
-/// of this file records a single event in JSON, exactly as it will be sent in the
-/// ping. There is one file per store.
+/// Events are stored in the `events` table of the database, in the order they
+/// were recorded in, one row per event and store. Each row holds the event in
+/// JSON, exactly as it will be sent in the ping.
+/// Recording an event into all its stores happens in a single transaction,
+/// as does snapshotting and clearing a store when collecting a ping.
 ///
-/// When restarting the application, these on-disk files are checked, and if any are
-/// found, they are loaded, and a `glean.restarted` event is added before any
-/// further events are collected. This is because the timestamps for these events
-/// may have come from a previous boot of the device, and therefore will not be
-/// compatible with any newly-collected events.
+/// Without a database, e.g. in a subprocess, events are appended to one file per
+/// store in the `events` directory instead, one event per line.
+/// These files, and files left over by earlier versions, are imported into the
+/// database on startup.
+///
+/// When restarting the application, stores that already contain events get a
+/// `glean.restarted` event added before any further events are collected.
+/// This is because the timestamps for these events may have come from a previous
+/// boot of the device, and therefore will not be compatible with any newly-collected events.
 ///
 /// Normalizing all these timestamps happens on serialization for submission (see
 /// `serialize_as_json`) where the client time between restarts is calculated using
 /// data stored in the `glean.startup.date` extra of the `glean.restarted` event, plus
-/// the `execution_counter` stored in events on disk.
+/// the `execution_counter` stored in events.
 ///
 /// Neither `execution_counter` nor `glean.startup.date` is submitted in pings.
 /// The `glean.restarted` event is, though.
 /// (See [bug 1716725](https://bugzilla.mozilla.org/show_bug.cgi?id=1716725).)
+///
+/// With encryption at rest, each event is encrypted on its own.
 #[derive(Debug)]
 pub struct EventDatabase {
     /// Path to directory of on-disk event files
     pub path: PathBuf,
-    /// The in-memory list of events
-    event_stores: RwLock<HashMap<String, Vec<StoredEvent>>>,
     event_store_files: RwLock<HashMap<String, Arc<File>>>,
     /// A lock to be held when doing operations on the filesystem
     file_lock: Mutex<()>,
     /// How many "events" pings have been submitted,
     /// as estimated from how often the "events" store is snapshotted and cleared.
     events_pings_submitted: atomic::AtomicUsize,
+    /// The cipher to encrypt events on disk with, if any.
+    cipher: Option<Arc<Cipher>>,
+    /// Whether events are only kept in the (in-memory) database, never on disk.
+    in_memory: bool,
 }
 
 impl MallocSizeOf for EventDatabase {
     fn size_of(&self, ops: &mut malloc_size_of::MallocSizeOfOps) -> usize {
         let mut n = 0;
-        n += self.event_stores.read().unwrap().size_of(ops);
-
         let map = self.event_store_files.read().unwrap();
         for store_name in map.keys() {
             n += store_name.size_of(ops);
@@ -138,27 +146,64 @@ impl EventDatabase {
     ///
     /// * `data_path` - The directory to store events in. A new directory
     /// * `events` - will be created inside of this directory.
-    pub fn new(data_path: &Path) -> Result<Self> {
+    /// * `cipher` - The cipher to encrypt events on disk with, if any.
+    pub fn new(data_path: &Path, cipher: Option<Arc<Cipher>>) -> Result<Self> {
         let path = data_path.join("events");
         create_dir_all(&path)?;
 
         Ok(Self {
             path,
-            event_stores: RwLock::new(HashMap::new()),
             event_store_files: RwLock::new(HashMap::new()),
             file_lock: Mutex::new(()),
             events_pings_submitted: atomic::AtomicUsize::new(0),
+            cipher,
+            in_memory: false,
         })
     }
 
+    /// Creates a new event database that never touches the disk.
+    ///
+    /// Events are only recorded into the database, which is kept in memory as well.
+    /// Without a database they are dropped.
+    pub fn new_in_memory() -> Self {
+        Self {
+            path: PathBuf::new(),
+            event_store_files: RwLock::new(HashMap::new()),
+            file_lock: Mutex::new(()),
+            events_pings_submitted: atomic::AtomicUsize::new(0),
+            cipher: None,
+            in_memory: true,
+        }
+    }
+
+    /// Encrypts all events stored on disk in plaintext.
+    ///
+    /// Only does something when encryption at rest is enabled.
+    pub fn encrypt_plaintext_files(&self) -> Result<()> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(());
+        };
+
+        let _lock = self.file_lock.lock().unwrap(); // safe unwrap, only error case is poisoning
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                encryption::encrypt_lines_file(cipher, &entry.path())?;
+            }
+        }
+        // Appending to a replaced file would write to the old one.
+        self.event_store_files.write().unwrap().clear(); // safe unwrap, only error case is poisoning
+
+        Ok(())
+    }
+
     /// Initializes events storage after Glean is fully initialized and ready to send pings.
     ///
     /// This must be called once on application startup, e.g. from
     /// [Glean.initialize], but after we are ready to send pings, since this
     /// could potentially collect and send the "events" ping.
     ///
-    /// If there are any events queued on disk, it loads them into memory so
-    /// that the memory and disk representations are in sync.
+    /// If there are any events queued on disk, it imports them into the database.
     ///
     /// If event records for the "events" ping are present, they are assembled into
     /// an "events" ping which is submitted immediately with reason "startup".
@@ -181,113 +226,145 @@ impl EventDatabase {
         glean: &Glean,
         trim_data_to_registered_pings: bool,
     ) -> bool {
-        match self.load_events_from_disk(glean, trim_data_to_registered_pings) {
-            Ok(_) => {
-                let stores_with_events: Vec<String> = {
-                    self.event_stores
-                        .read()
-                        .unwrap()
-                        .keys()
-                        .map(|x| x.to_owned())
-                        .collect() // safe unwrap, only error case is poisoning
-                };
-                // We do not want to be holding the event stores lock when
-                // submitting a ping or recording new events.
-                let has_events_events = stores_with_events.contains(&"events".to_owned());
-                let glean_restarted_stores = if has_events_events {
-                    stores_with_events
-                        .into_iter()
-                        .filter(|store| store != "events")
-                        .collect()
-                } else {
-                    stores_with_events
-                };
-                if !glean_restarted_stores.is_empty() {
-                    for store_name in glean_restarted_stores.iter() {
-                        CounterMetric::new(CommonMetricData {
-                            name: "execution_counter".into(),
-                            category: store_name.into(),
-                            send_in_pings: vec![INTERNAL_STORAGE.into()],
-                            lifetime: Lifetime::Ping,
-                            ..Default::default()
-                        })
-                        .add_sync(glean, 1);
-                    }
-                    let glean_restarted = CommonMetricData {
-                        name: "restarted".into(),
-                        category: "glean".into(),
-                        send_in_pings: glean_restarted_stores,
-                        lifetime: Lifetime::Ping,
-                        ..Default::default()
-                    };
-                    let startup = get_iso_time_string(glean.start_time(), TimeUnit::Minute);
-                    let mut extra: HashMap<String, String> =
-                        [("glean.startup.date".into(), startup)].into();
-                    if glean.with_timestamps() {
-                        let now = Utc::now();
-                        let precise_timestamp = now.timestamp_millis() as u64;
-                        extra.insert("glean_timestamp".to_string(), precise_timestamp.to_string());
-                    }
-                    self.record(
-                        glean,
-                        &glean_restarted.into(),
-                        crate::get_timestamp_ms(),
-                        Some(extra),
-                        EventSessionContext::OutOfSession,
-                    );
-                }
-                if has_events_events && glean.submit_ping_by_name("events", Some("startup")) {
-                    self.events_pings_submitted
-                        .fetch_sub(1, atomic::Ordering::Relaxed);
-                    true
-                } else {
-                    false
-                }
-            }
+        let Some(database) = glean.storage_opt() else {
+            log::warn!("No database to load pending events from.");
+            return false;
+        };
+
+        if let Err(err) = self.import_events_from_disk(glean, trim_data_to_registered_pings) {
+            log::warn!("Error importing events from disk: {}", err);
+        }
+
+        let stores_with_events = match database.event_stores() {
+            Ok(stores) => stores,
             Err(err) => {
-                log::warn!("Error loading events from disk: {}", err);
-                false
+                log::warn!("Error loading pending events: {}", err);
+                return false;
+            }
+        };
+
+        let has_events_events = stores_with_events.contains(&"events".to_owned());
+        let glean_restarted_stores = if has_events_events {
+            stores_with_events
+                .into_iter()
+                .filter(|store| store != "events")
+                .collect()
+        } else {
+            stores_with_events
+        };
+        if !glean_restarted_stores.is_empty() {
+            for store_name in glean_restarted_stores.iter() {
+                CounterMetric::new(CommonMetricData {
+                    name: "execution_counter".into(),
+                    category: store_name.into(),
+                    send_in_pings: vec![INTERNAL_STORAGE.into()],
+                    lifetime: Lifetime::Ping,
+                    ..Default::default()
+                })
+                .add_sync(glean, 1);
+            }
+            let glean_restarted = CommonMetricData {
+                name: "restarted".into(),
+                category: "glean".into(),
+                send_in_pings: glean_restarted_stores,
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            };
+            let startup = get_iso_time_string(glean.start_time(), TimeUnit::Minute);
+            let mut extra: HashMap<String, String> =
+                [("glean.startup.date".into(), startup)].into();
+            if glean.with_timestamps() {
+                let now = Utc::now();
+                let precise_timestamp = now.timestamp_millis() as u64;
+                extra.insert("glean_timestamp".to_string(), precise_timestamp.to_string());
             }
+            self.record(
+                glean,
+                &glean_restarted.into(),
+                crate::get_timestamp_ms(),
+                Some(extra),
+                EventSessionContext::OutOfSession,
+            );
+        }
+        if has_events_events && glean.submit_ping_by_name("events", Some("startup")) {
+            self.events_pings_submitted
+                .fetch_sub(1, atomic::Ordering::Relaxed);
+            true
+        } else {
+            false
         }
     }
 
-    fn load_events_from_disk(
+    /// Imports the events in files on disk into the database and removes the files.
+    ///
+    /// A file is marked as imported together with its events,
+    /// so one that is left behind, e.g. after a crash, is only removed.
+    /// Lines that can't be read, e.g. because they were truncated, are skipped.
+    /// When trimming, events of pings that aren't registered are removed
+    /// from the files and the database.
+    fn import_events_from_disk(
         &self,
         glean: &Glean,
         trim_data_to_registered_pings: bool,
     ) -> Result<()> {
-        // NOTE: The order of locks here is important.
-        // In other code parts we might acquire the `file_lock` when we already have acquired
-        // a lock on `event_stores`.
-        // This is a potential lock-order-inversion.
-        let mut db = self.event_stores.write().unwrap(); // safe unwrap, only error case is poisoning
+        if self.in_memory {
+            return Ok(());
+        }
+        let database = glean.storage();
         let _lock = self.file_lock.lock().unwrap(); // safe unwrap, only error case is poisoning
 
         for entry in fs::read_dir(&self.path)? {
             let entry = entry?;
-            if entry.file_type()?.is_file() {
-                let store_name = entry.file_name().into_string()?;
-                log::info!("Loading events for {}", store_name);
-                if trim_data_to_registered_pings && glean.get_ping_by_name(&store_name).is_none() {
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let store_name = entry.file_name().into_string()?;
+            let trim =
+                trim_data_to_registered_pings && glean.get_ping_by_name(&store_name).is_none();
+            if trim {
+                log::warn!("Trimming {}'s events", store_name);
+            } else if database.events_imported(&store_name)? {
+                log::info!("Events for {} were already imported", store_name);
+            } else {
+                log::info!("Importing events for {}", store_name);
+                let file = BufReader::new(File::open(entry.path())?);
+                let events: Vec<(&str, Vec<u8>)> = file
+                    .split(b'\n')
+                    // Stop on I/O errors, but only skip lines that aren't valid UTF-8.
+                    .map_while(Result::ok)
+                    .filter_map(|line| String::from_utf8(line).ok())
+                    .filter_map(|line| match &self.cipher {
+                        Some(cipher) => cipher
+                            .decrypt_line(&line)
+                            .and_then(|line| serde_json::from_str::<StoredEvent>(&line).ok()),
+                        None => serde_json::from_str::<StoredEvent>(&line).ok(),
+                    })
+                    .map(|event| (store_name.as_str(), serde_json::to_vec(&event).unwrap())) // safe unwrap, event can always be serialized
+                    .collect();
+                database.import_events(&store_name, &events)?;
+            }
+
+            self.event_store_files.write().unwrap().remove(&store_name); // safe unwrap, only error case is poisoning
+            let removed = match fs::remove_file(entry.path()) {
+                Ok(()) => true,
+                // silently drop this error, the file was already non-existing
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => true,
+                Err(err) => {
+                    log::warn!("Error removing events file '{}': {}", store_name, err);
+                    false
+                }
+            };
+            if removed {
+                database.clear_events_imported(&store_name)?;
+            }
+        }
+
+        if trim_data_to_registered_pings {
+            for store_name in database.event_stores()? {
+                if glean.get_ping_by_name(&store_name).is_none() {
                     log::warn!("Trimming {}'s events", store_name);
-                    if let Err(err) = fs::remove_file(entry.path()) {
-                        match err.kind() {
-                            std::io::ErrorKind::NotFound => {
-                                // silently drop this error, the file was already non-existing
-                            }
-                            _ => log::warn!("Error trimming events file '{}': {}", store_name, err),
-                        }
-                    }
-                    continue;
+                    database.clear_events(Some(&store_name))?;
                 }
-                let file = BufReader::new(File::open(entry.path())?);
-                db.insert(
-                    store_name,
-                    file.lines()
-                        .map_while(Result::ok)
-                        .filter_map(|line| serde_json::from_str::<StoredEvent>(&line).ok())
-                        .collect(),
-                );
             }
         }
         Ok(())
@@ -330,24 +407,23 @@ impl EventDatabase {
             EventSessionContext::InSession(session_meta) => Some(session_meta),
         };
 
-        let mut submit_max_capacity_event_ping = false;
-        {
-            let mut db = self.event_stores.write().unwrap(); // safe unwrap, only error case is poisoning
-            for store_name in meta.inner.send_in_pings.iter() {
-                if !glean.is_ping_enabled(store_name) {
-                    continue;
-                }
-
-                let store = db.entry(store_name.to_string()).or_default();
-                let execution_counter = CounterMetric::new(CommonMetricData {
-                    name: "execution_counter".into(),
-                    category: store_name.into(),
-                    send_in_pings: vec![INTERNAL_STORAGE.into()],
-                    lifetime: Lifetime::Ping,
-                    ..Default::default()
-                })
-                .get_value(glean, INTERNAL_STORAGE);
-                // Create StoredEvent object, and its JSON form for serialization on disk.
+        let events: Vec<(&str, String)> = meta
+            .inner
+            .send_in_pings
+            .iter()
+            .filter(|store_name| glean.is_ping_enabled(store_name))
+            .map(|store_name| {
+                // Without a database there's no execution counter to read.
+                let execution_counter = glean.storage_opt().and_then(|_| {
+                    CounterMetric::new(CommonMetricData {
+                        name: "execution_counter".into(),
+                        category: store_name.into(),
+                        send_in_pings: vec![INTERNAL_STORAGE.into()],
+                        lifetime: Lifetime::Ping,
+                        ..Default::default()
+                    })
+                    .get_value(glean, INTERNAL_STORAGE)
+                });
                 let event = StoredEvent {
                     event: RecordedEvent {
                         timestamp,
@@ -359,26 +435,58 @@ impl EventDatabase {
                     execution_counter,
                 };
                 let event_json = serde_json::to_string(&event).unwrap(); // safe unwrap, event can always be serialized
-                store.push(event);
-                self.write_event_to_disk(store_name, &event_json);
-                if store_name == "events" {
-                    if store.len() == glean.get_max_events() {
-                        submit_max_capacity_event_ping = true;
-                    }
-                    let factor = glean.get_events_ping_acceleration_factor();
-                    let events_pings_submitted =
-                        self.events_pings_submitted.load(atomic::Ordering::Relaxed);
-                    if factor > events_pings_submitted {
-                        // The early "events" ping acceleration formula is y = ax^2.
-                        let a = glean.get_max_events() / factor.saturating_mul(factor);
-                        let x = events_pings_submitted + 1; // We want the y for the next ping.
-                        let y = a.saturating_mul(x.saturating_mul(x));
-                        // It is possible to apply an acceleration factor at runtime that would
-                        // decrease y below store.len(). Submit a ping on the next event in that case.
-                        if store.len() >= y {
-                            submit_max_capacity_event_ping = true;
-                        }
-                    }
+                (store_name.as_str(), event_json)
+            })
+            .collect();
+
+        let Some(database) = glean.storage_opt() else {
+            // Events are imported into the database on the next start.
+            for (store_name, event_json) in &events {
+                self.write_event_to_disk(store_name, event_json);
+            }
+            return false;
+        };
+
+        let events: Vec<(&str, Vec<u8>)> = events
+            .into_iter()
+            .map(|(store_name, event_json)| (store_name, event_json.into_bytes()))
+            .collect();
+        // Only the length of the "events" store is needed, for the checks below.
+        let counted_store = events
+            .iter()
+            .any(|(store_name, _)| *store_name == "events")
+            .then_some("events");
+        let events_store_len = match database.record_events(&events, counted_store) {
+            Ok(store_len) => store_len,
+            Err(err) => {
+                log::warn!("Error recording event: {}", err);
+                return false;
+            }
+        };
+
+        let store_names: Vec<&str> = events.iter().map(|(store_name, _)| *store_name).collect();
+        let submitted_at_capacity = glean.submit_pings_at_capacity(&store_names);
+
+        let mut submit_max_capacity_event_ping = false;
+        // Its length is stale if it was just submitted for reaching its capacity.
+        if let Some(store_len) =
+            events_store_len.filter(|_| !submitted_at_capacity.contains(&"events"))
+        {
+            if store_len == glean.get_max_events() {
+                submit_max_capacity_event_ping = true;
+            }
+            let factor = glean.get_events_ping_acceleration_factor();
+            let events_pings_submitted =
+                self.events_pings_submitted.load(atomic::Ordering::Relaxed);
+            if factor > events_pings_submitted {
+                // The early "events" ping acceleration formula is y = ax^2.
+                let a = glean.get_max_events() / factor.saturating_mul(factor);
+                let x = events_pings_submitted + 1; // We want the y for the next ping.
+                let y = a.saturating_mul(x.saturating_mul(x));
+                // It is possible to apply an acceleration factor at runtime that would
+                // decrease y below store_len. Submit a ping on the next event in that case.
+                if store_len >= y {
+                    submit_max_capacity_event_ping = true;
                 }
             }
         }
@@ -386,7 +494,7 @@ impl EventDatabase {
             glean.submit_ping_by_name("events", Some("max_capacity"));
             true
         } else {
-            false
+            !submitted_at_capacity.is_empty()
         }
     }
 
@@ -416,8 +524,17 @@ impl EventDatabase {
     /// * `store_name` - The name of the store.
     /// * `event_json` - The event content, as a single-line JSON-encoded string.
     fn write_event_to_disk(&self, store_name: &str, event_json: &str) {
+        if self.in_memory {
+            log::warn!("No database to record events in, dropping an event for '{store_name}'.");
+            return;
+        }
         let _lock = self.file_lock.lock().unwrap(); // safe unwrap, only error case is poisoning
 
+        let event_json = match &self.cipher {
+            Some(cipher) => std::borrow::Cow::Owned(cipher.encrypt_line(event_json)),
+            None => std::borrow::Cow::Borrowed(event_json),
+        };
+
         let write_res = (|| {
             let mut file = self.get_event_store(store_name)?;
             file.write_all(event_json.as_bytes())?;
@@ -451,7 +568,7 @@ impl EventDatabase {
     ///
     /// In the event that something goes awry, this will record an invalid_state on
     /// glean.restarted if it is due to internal inconsistencies, or invalid_value
-    /// on client clock weirdness.
+    /// on client clock weirdness, if `record_errors` is set.
     ///
     /// # Arguments
     ///
@@ -459,12 +576,15 @@ impl EventDatabase {
     /// * `store_name` - The name of the store we're normalizing.
     /// * `store` - The store we're to normalize.
     /// * `glean_start_time` - Used if the glean.startup.date or ping_info.start_time aren't available. Passed as a parameter to ease unit-testing.
+    /// * `record_errors` - Whether to record errors and clamped timestamps.
+    ///   Not set when previewing a ping, which must not change any data.
     fn normalize_store(
         &self,
         glean: &Glean,
         store_name: &str,
         store: &mut Vec<StoredEvent>,
         glean_start_time: DateTime<FixedOffset>,
+        record_errors: bool,
     ) {
         let is_glean_restarted =
             |event: &RecordedEvent| event.category == "glean" && event.name == "restarted";
@@ -475,6 +595,17 @@ impl EventDatabase {
             lifetime: Lifetime::Ping,
             ..Default::default()
         };
+        let report_error = |error: ErrorType, message: String| {
+            if record_errors {
+                record_error(
+                    glean,
+                    &glean_restarted_meta(store_name).into(),
+                    error,
+                    message,
+                    None,
+                );
+            }
+        };
         // Step 1
         store.sort_by(|a, b| {
             a.execution_counter
@@ -533,12 +664,9 @@ impl EventDatabase {
                         extra.remove("glean.startup.date").and_then(|date_str| {
                             DateTime::parse_from_str(&date_str, TimeUnit::Minute.format_pattern())
                                 .map_err(|_| {
-                                    record_error(
-                                        glean,
-                                        &glean_restarted_meta(store_name).into(),
+                                    report_error(
                                         ErrorType::InvalidState,
                                         format!("Unparseable glean.startup.date '{}'", date_str),
-                                        None,
                                     );
                                 })
                                 .ok()
@@ -573,13 +701,7 @@ impl EventDatabase {
                 inter_group_offset =
                     u64::try_from(time_from_ping_start_to_glean_restarted).unwrap_or(0);
                 if inter_group_offset < highest_ts {
-                    record_error(
-                        glean,
-                        &glean_restarted_meta(store_name).into(),
-                        ErrorType::InvalidValue,
-                        format!("Time between restart and ping start {} indicates client clock weirdness.", time_from_ping_start_to_glean_restarted),
-                        None,
-                    );
+                    report_error(ErrorType::InvalidValue, format!("Time between restart and ping start {} indicates client clock weirdness.", time_from_ping_start_to_glean_restarted));
                     // The client's clock went backwards enough that this event group's
                     // glean.restarted looks like it happened _before_ the final event of the previous group.
                     // Or, it went ahead enough to overflow u64.
@@ -592,15 +714,12 @@ impl EventDatabase {
             }
             event.event.timestamp = event.event.timestamp - intra_group_offset + inter_group_offset;
             if execution_counter != cur_ec {
-                record_error(
-                    glean,
-                    &glean_restarted_meta(store_name).into(),
+                report_error(
                     ErrorType::InvalidState,
                     format!(
                         "Inconsistent execution counter {} (expected {})",
                         execution_counter, cur_ec
                     ),
-                    None,
                 );
                 // Let's fix cur_ec up and hope this isn't a sign something big is broken.
                 cur_ec = execution_counter;
@@ -609,10 +728,12 @@ impl EventDatabase {
             // event timestamp is a `u64`, but BigQuery uses `i64` (signed!) everywhere. Let's clamp the value to make
             // sure we stay within bounds.
             if event.event.timestamp > i64::MAX as u64 {
-                glean
-                    .additional_metrics
-                    .event_timestamp_clamped
-                    .add_sync(glean, 1);
+                if record_errors {
+                    glean
+                        .additional_metrics
+                        .event_timestamp_clamped
+                        .add_sync(glean, 1);
+                }
                 log::warn!(
                     "Calculated event timestamp was too high. Got: {}, max: {}",
                     event.event.timestamp,
@@ -624,15 +745,12 @@ impl EventDatabase {
             if highest_ts > event.event.timestamp {
                 // Even though we sorted everything, something in the
                 // execution_counter or glean.startup.date math went awry.
-                record_error(
-                    glean,
-                    &glean_restarted_meta(store_name).into(),
+                report_error(
                     ErrorType::InvalidState,
                     format!(
                         "Inconsistent previous highest timestamp {} (expected <= {})",
                         highest_ts, event.event.timestamp
                     ),
-                    None,
                 );
                 // Let the highest_ts regress to event.timestamp to hope this minimizes weirdness.
             }
@@ -647,6 +765,7 @@ impl EventDatabase {
     /// * `glean` - the Glean instance.
     /// * `store_name` - The name of the desired store.
     /// * `clear_store` - Whether to clear the store after snapshotting.
+    ///   Errors in the stored events are only recorded then, not when previewing a ping.
     ///
     /// # Returns
     ///
@@ -657,64 +776,102 @@ impl EventDatabase {
         store_name: &str,
         clear_store: bool,
     ) -> Option<JsonValue> {
-        let result = {
-            let mut db = self.event_stores.write().unwrap(); // safe unwrap, only error case is poisoning
-            db.get_mut(&store_name.to_string()).and_then(|store| {
-                if !store.is_empty() {
-                    // Normalization happens in-place, so if we're not clearing,
-                    // operate on a clone.
-                    let mut clone;
-                    let store = if clear_store {
-                        store
-                    } else {
-                        clone = store.clone();
-                        &mut clone
-                    };
-                    // We may need to normalize event timestamps across multiple restarts.
-                    self.normalize_store(glean, store_name, store, glean.start_time());
-                    Some(json!(store))
-                } else {
-                    log::warn!("Unexpectly got empty event store for '{}'", store_name);
-                    None
-                }
-            })
-        };
+        let store = self.get_stored_events(glean, store_name, clear_store);
+        self.snapshot_events_as_json(glean, store_name, store, clear_store)
+    }
 
-        if clear_store {
-            self.event_stores
-                .write()
-                .unwrap() // safe unwrap, only error case is poisoning
-                .remove(&store_name.to_string());
-            self.event_store_files
-                .write()
-                .unwrap() // safe unwrap, only error case is poisoning
-                .remove(&store_name.to_string());
-
-            let _lock = self.file_lock.lock().unwrap(); // safe unwrap, only error case is poisoning
-            if let Err(err) = fs::remove_file(self.path.join(store_name)) {
-                match err.kind() {
-                    std::io::ErrorKind::NotFound => {
-                        // silently drop this error, the file was already non-existing
-                    }
-                    _ => log::warn!("Error removing events queue file '{}': {}", store_name, err),
-                }
-            }
-        }
+    /// Gets a snapshot of events already taken from the database as a JsonValue.
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - the Glean instance.
+    /// * `store_name` - The name of the store the events were taken from.
+    /// * `events` - The serialized events of the store.
+    /// * `cleared_store` - Whether the store was cleared when taking the events.
+    ///
+    /// # Returns
+    ///
+    /// A array of events, JSON encoded, if any. Otherwise `None`.
+    pub(crate) fn snapshot_taken_as_json(
+        &self,
+        glean: &Glean,
+        store_name: &str,
+        events: &[Vec<u8>],
+        cleared_store: bool,
+    ) -> Option<JsonValue> {
+        let store = Self::deserialize_events(events);
+        self.snapshot_events_as_json(glean, store_name, store, cleared_store)
+    }
 
+    fn snapshot_events_as_json(
+        &self,
+        glean: &Glean,
+        store_name: &str,
+        mut store: Vec<StoredEvent>,
+        clear_store: bool,
+    ) -> Option<JsonValue> {
         if clear_store && store_name == "events" {
             self.events_pings_submitted
                 .fetch_add(1, atomic::Ordering::Relaxed);
         }
 
-        result
+        if store.is_empty() {
+            return None;
+        }
+
+        // We may need to normalize event timestamps across multiple restarts.
+        self.normalize_store(
+            glean,
+            store_name,
+            &mut store,
+            glean.start_time(),
+            clear_store,
+        );
+        Some(json!(store))
+    }
+
+    /// Gets the events of a store from the database, optionally clearing it.
+    ///
+    /// Errors are logged and events that can't be read are skipped.
+    fn get_stored_events(
+        &self,
+        glean: &Glean,
+        store_name: &str,
+        clear_store: bool,
+    ) -> Vec<StoredEvent> {
+        let Some(database) = glean.storage_opt() else {
+            return Vec::new();
+        };
+
+        match database.get_events(store_name, clear_store) {
+            Ok(events) => Self::deserialize_events(&events),
+            Err(err) => {
+                log::warn!("Error reading events of store '{}': {}", store_name, err);
+                Vec::new()
+            }
+        }
     }
 
-    /// Clears all stored events, both in memory and on-disk.
-    pub fn clear_all(&self) -> Result<()> {
+    /// Deserializes events, skipping those that can't be read.
+    fn deserialize_events(events: &[Vec<u8>]) -> Vec<StoredEvent> {
+        events
+            .iter()
+            .filter_map(|event| serde_json::from_slice(event).ok())
+            .collect()
+    }
+
+    /// Clears all stored events, both in the database and on-disk.
+    pub fn clear_all(&self, glean: &Glean) -> Result<()> {
+        if let Some(database) = glean.storage_opt() {
+            database.clear_events(None)?;
+        }
+
         // safe unwrap, only error case is poisoning
-        self.event_stores.write().unwrap().clear();
         self.event_store_files.write().unwrap().clear();
 
+        if self.in_memory {
+            return Ok(());
+        }
         // safe unwrap, only error case is poisoning
         let _lock = self.file_lock.lock().unwrap();
         std::fs::remove_dir_all(&self.path)?;
@@ -731,17 +888,14 @@ impl EventDatabase {
     /// This doesn't clear the stored value.
     pub fn test_get_value<'a>(
         &'a self,
+        glean: &Glean,
         meta: &'a CommonMetricDataInternal,
         store_name: &str,
     ) -> Option<Vec<RecordedEvent>> {
         let value: Vec<RecordedEvent> = self
-            .event_stores
-            .read()
-            .unwrap() // safe unwrap, only error case is poisoning
-            .get(&store_name.to_string())
+            .get_stored_events(glean, store_name, false)
             .into_iter()
-            .flatten()
-            .map(|stored_event| stored_event.event.clone())
+            .map(|stored_event| stored_event.event)
             .filter(|event| event.name == meta.inner.name && event.category == meta.inner.category)
             .collect();
         if !value.is_empty() {
@@ -765,7 +919,7 @@ mod test {
         let (glean, t) = new_glean(None);
 
         {
-            let db = EventDatabase::new(t.path()).unwrap();
+            let db = EventDatabase::new(t.path(), None).unwrap();
             db.write_event_to_disk("events", "{\"timestamp\": 500");
             db.write_event_to_disk("events", "{\"timestamp\"");
             db.write_event_to_disk(
@@ -775,13 +929,53 @@ mod test {
         }
 
         {
-            let db = EventDatabase::new(t.path()).unwrap();
-            db.load_events_from_disk(&glean, false).unwrap();
-            let events = &db.event_stores.read().unwrap()["events"];
+            let db = EventDatabase::new(t.path(), None).unwrap();
+            db.import_events_from_disk(&glean, false).unwrap();
+            let events = db.get_stored_events(&glean, "events", false);
             assert_eq!(1, events.len());
+            assert!(!db.path.join("events").exists());
         }
     }
 
+    #[test]
+    fn events_on_disk_after_invalid_utf8_are_imported() {
+        let (glean, t) = new_glean(None);
+
+        let db = EventDatabase::new(t.path(), None).unwrap();
+        let mut data = b"{\"timestamp\": 500, \"category\": \"ui\", \"name\": \"\xff\"}\n".to_vec();
+        data.extend_from_slice(
+            b"{\"timestamp\": 501, \"category\": \"ui\", \"name\": \"click\"}\n",
+        );
+        fs::write(db.path.join("events"), data).unwrap();
+
+        db.import_events_from_disk(&glean, false).unwrap();
+        let events = db.get_stored_events(&glean, "events", false);
+        assert_eq!(1, events.len());
+        assert_eq!(501, events[0].event.timestamp);
+    }
+
+    #[test]
+    fn events_on_disk_are_imported_once() {
+        let (glean, t) = new_glean(None);
+
+        let db = EventDatabase::new(t.path(), None).unwrap();
+        db.write_event_to_disk(
+            "events",
+            "{\"timestamp\": 501, \"category\": \"ui\", \"name\": \"click\"}",
+        );
+        let contents = fs::read(db.path.join("events")).unwrap();
+        db.import_events_from_disk(&glean, false).unwrap();
+        assert_eq!(1, db.get_stored_events(&glean, "events", false).len());
+
+        // A file left behind after its events were imported, e.g. after a crash, is only removed.
+        glean.storage().import_events("events", &[]).unwrap();
+        fs::write(db.path.join("events"), contents).unwrap();
+        db.import_events_from_disk(&glean, false).unwrap();
+        assert_eq!(1, db.get_stored_events(&glean, "events", false).len());
+        assert!(!db.path.join("events").exists());
+        assert!(!glean.storage().events_imported("events").unwrap());
+    }
+
     #[test]
     fn stable_serialization() {
         let event_empty = RecordedEvent {
@@ -879,7 +1073,7 @@ mod test {
     #[test]
     fn doesnt_record_when_upload_is_disabled() {
         let (mut glean, dir) = new_glean(None);
-        let db = EventDatabase::new(dir.path()).unwrap();
+        let db = EventDatabase::new(dir.path(), None).unwrap();
 
         let test_storage = "store1";
         let test_category = "category";
@@ -904,18 +1098,20 @@ mod test {
             EventSessionContext::OutOfSession,
         );
         {
-            let event_stores = db.event_stores.read().unwrap();
+            let events = db.get_stored_events(&glean, test_storage, false);
             assert_eq!(
-                &StoredEvent {
+                StoredEvent {
                     event: event_data,
                     execution_counter: None
                 },
-                &event_stores.get(test_storage).unwrap()[0]
+                events[0]
             );
-            assert_eq!(event_stores.get(test_storage).unwrap().len(), 1);
+            assert_eq!(events.len(), 1);
         }
 
         glean.set_upload_enabled(false);
+        // Disabling upload cleared the store.
+        assert!(db.get_stored_events(&glean, test_storage, false).is_empty());
 
         // Now that upload is disabled, let's check nothing is recorded.
         db.record(
@@ -925,10 +1121,7 @@ mod test {
             None,
             EventSessionContext::OutOfSession,
         );
-        {
-            let event_stores = db.event_stores.read().unwrap();
-            assert_eq!(event_stores.get(test_storage).unwrap().len(), 1);
-        }
+        assert!(db.get_stored_events(&glean, test_storage, false).is_empty());
     }
 
     #[test]
@@ -950,15 +1143,23 @@ mod test {
         let mut store = vec![glean_restarted.clone()];
         let glean_start_time = glean.start_time();
 
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert!(store.is_empty());
 
         let mut store = vec![glean_restarted.clone(), glean_restarted.clone()];
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert!(store.is_empty());
 
         let mut store = vec![
@@ -966,9 +1167,13 @@ mod test {
             glean_restarted.clone(),
             glean_restarted,
         ];
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert!(store.is_empty());
     }
 
@@ -1005,9 +1210,13 @@ mod test {
         ];
         let glean_start_time = glean.start_time();
 
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert_eq!(1, store.len());
         assert_eq!(
             StoredEvent {
@@ -1070,9 +1279,13 @@ mod test {
             glean_restarted,
         ];
 
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean.start_time());
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean.start_time(),
+            true,
+        );
         assert_eq!(3, store.len());
         for (timestamp, event) in timestamps.iter().zip(store.iter()) {
             assert_eq!(
@@ -1175,6 +1388,7 @@ mod test {
             store_name,
             &mut store,
             glean_start_time.unwrap(),
+            true,
         );
         assert_eq!(5, store.len()); // 4 "real" events plus 1 `glean.restarted`
 
@@ -1301,6 +1515,7 @@ mod test {
             store_name,
             &mut store,
             glean_start_time.unwrap(),
+            true,
         );
         assert_eq!(5, store.len()); // 4 "real" events plus 1 `glean.restarted`
 
@@ -1403,9 +1618,13 @@ mod test {
         ];
         let glean_start_time = glean.start_time();
 
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
 
         assert_eq!(1, store.len());
         assert_eq!(
@@ -1477,9 +1696,13 @@ mod test {
             .collect();
 
         let glean_start_time = glean.start_time();
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert_eq!(4, store.len());
 
         assert_eq!(0, store[0].event.timestamp);
@@ -1517,9 +1740,13 @@ mod test {
             .collect();
 
         let glean_start_time = glean.start_time();
-        glean
-            .event_storage()
-            .normalize_store(&glean, store_name, &mut store, glean_start_time);
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            true,
+        );
         assert_eq!(2, store.len());
 
         assert_eq!(0, store[0].event.timestamp);
@@ -1531,4 +1758,65 @@ mod test {
             .get_value(&glean, "health");
         assert_eq!(Some(1), error_count);
     }
+
+    #[test]
+    fn normalize_store_without_recording_errors() {
+        let (glean, _dir) = new_glean(None);
+
+        let mut cfg = RemoteSettingsConfig::default();
+        cfg.metrics_enabled
+            .insert("glean.error.event_timestamp_clamped".to_string(), true);
+        glean.apply_server_knobs_config(cfg);
+
+        let store_name = "store-name";
+        let event = RecordedEvent {
+            category: "category".into(),
+            name: "name".into(),
+            ..Default::default()
+        };
+
+        // An inconsistent execution counter and a timestamp to clamp.
+        let mut store = vec![
+            StoredEvent {
+                event: event.clone(),
+                execution_counter: Some(1),
+            },
+            StoredEvent {
+                event: RecordedEvent {
+                    timestamp: (i64::MAX as u64) + 1,
+                    ..event
+                },
+                execution_counter: Some(2),
+            },
+        ];
+
+        let glean_start_time = glean.start_time();
+        glean.event_storage().normalize_store(
+            &glean,
+            store_name,
+            &mut store,
+            glean_start_time,
+            false,
+        );
+        assert_eq!((i64::MAX as u64), store[1].event.timestamp);
+
+        assert!(test_get_num_recorded_errors(
+            &glean,
+            &CommonMetricData {
+                name: "restarted".into(),
+                category: "glean".into(),
+                send_in_pings: vec![store_name.into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            }
+            .into(),
+            ErrorType::InvalidState
+        )
+        .is_err());
+        let error_count = glean
+            .additional_metrics
+            .event_timestamp_clamped
+            .get_value(&glean, "health");
+        assert_eq!(None, error_count);
+    }
 }
@@ -0,0 +1,385 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Read-only inspection of a Glean data directory.
+//!
+//! An [`Inspector`] opens the data of a data directory for reading only.
+//! It doesn't take ownership of it: no schema upgrades, migrations or maintenance run,
+//! nothing is written and nothing is deleted, so it is safe to use on the data
+//! directory of a running application, e.g. from debugging tools like `glean-inspect`.
+//!
+//! Values are returned as they are stored. Ping-lifetime data buffered in memory by
+//! a running application (see `delay_ping_lifetime_io`) is not visible.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, OpenFlags, OptionalExtension};
+use serde_json::Value as JsonValue;
+
+use crate::encryption::{self, Cipher, EncryptionKeyProvider};
+use crate::event_database::StoredEvent;
+use crate::metrics::dual_labeled_counter::RECORD_SEPARATOR;
+use crate::metrics::{Metric, RecordedEvent, RecordedExperiment};
+use crate::session;
+use crate::storage::INTERNAL_STORAGE;
+use crate::upload::read_ping_file;
+use crate::{Lifetime, Result, DELETION_REQUEST_PINGS_DIRECTORY, PENDING_PINGS_DIRECTORY};
+
+/// A metric as stored in the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredMetric {
+    /// The lifetime of the metric.
+    pub lifetime: Lifetime,
+    /// The store (usually a ping name) the metric is stored in.
+    pub store: String,
+    /// The metric identifier, `category.name`.
+    pub id: String,
+    /// The labels of a labeled metric, empty otherwise.
+    /// Dual-labeled counters have two.
+    pub labels: Vec<String>,
+    /// The decoded value.
+    pub value: Metric,
+}
+
+/// An event waiting to be sent in a ping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredEventRecord {
+    /// The store (usually a ping name) the event is stored in.
+    pub store: String,
+    /// The event, with the timestamp it was recorded with.
+    pub event: RecordedEvent,
+    /// The execution counter of the run the event was recorded in, if any.
+    pub execution_counter: Option<i32>,
+    /// Whether the event is still in a file on disk,
+    /// to be imported into the database on the next start.
+    pub pending_import: bool,
+}
+
+/// A ping waiting to be uploaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingPing {
+    /// The document ID of the ping.
+    pub document_id: String,
+    /// Whether this is a `deletion-request` ping.
+    pub deletion_request: bool,
+    /// The path the ping is uploaded to.
+    pub path: String,
+    /// The ping payload.
+    pub body: JsonValue,
+    /// The ping metadata, e.g. additional headers, if any.
+    pub metadata: Option<JsonValue>,
+}
+
+/// The persisted state of the current session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionState {
+    /// The sequence number of the latest session.
+    pub seq: Option<i64>,
+    /// The ID of the current session, if any.
+    pub id: Option<String>,
+    /// When the current session started, as an RFC 3339 string.
+    pub start_time: Option<String>,
+    /// Since when the current session is inactive, as an RFC 3339 string.
+    pub inactive_since: Option<String>,
+    /// The sequence number of the latest event in the current session.
+    pub event_seq: Option<i64>,
+}
+
+/// Read-only access to a Glean data directory.
+#[derive(Debug)]
+pub struct Inspector {
+    data_path: PathBuf,
+    conn: rusqlite::Connection,
+    schema_version: u32,
+    cipher: Option<Cipher>,
+}
+
+impl Inspector {
+    /// Opens a data directory for inspection.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - The data directory, as passed to Glean on initialization.
+    /// * `key_provider` - Provides the key to decrypt the data with,
+    ///   if it is encrypted at rest.
+    ///
+    /// # Returns
+    ///
+    /// An error if there is no database in the data directory, it can't be opened,
+    /// or the data is encrypted and the key is missing or doesn't match.
+    pub fn open(
+        data_path: impl AsRef<Path>,
+        key_provider: Option<&dyn EncryptionKeyProvider>,
+    ) -> Result<Self> {
+        let data_path = data_path.as_ref().to_path_buf();
+        let cipher = encryption::load_cipher_with(key_provider, &data_path)?;
+
+        let flags = OpenFlags::SQLITE_OPEN_NO_MUTEX
+            | OpenFlags::SQLITE_OPEN_EXRESCODE
+            | OpenFlags::SQLITE_OPEN_READ_ONLY;
+        let db_path = data_path.join("db").join("glean.sqlite");
+        let conn = rusqlite::Connection::open_with_flags(db_path, flags)?;
+        let schema_version = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        Ok(Self {
+            data_path,
+            conn,
+            schema_version,
+            cipher,
+        })
+    }
+
+    /// The schema version of the database.
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    /// Lists all stores that have metrics or events.
+    pub fn stores(&self) -> Result<Vec<String>> {
+        let mut stores = BTreeSet::new();
+        let mut stmt = self.conn.prepare("SELECT DISTINCT ping FROM telemetry")?;
+        for store in stmt.query_map([], |row| row.get(0))? {
+            stores.insert(store?);
+        }
+        for event in self.events(None)? {
+            stores.insert(event.store);
+        }
+        Ok(stores.into_iter().collect())
+    }
+
+    /// Lists the stored metrics, ordered by lifetime, store and identifier.
+    ///
+    /// Values that can't be decoded are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Only list metrics of this store, if given.
+    pub fn metrics(&self, store: Option<&str>) -> Result<Vec<StoredMetric>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT lifetime, ping, id, labels, value FROM telemetry
+             WHERE ?1 IS NULL OR ping = ?1
+             ORDER BY lifetime, ping, id, labels",
+        )?;
+        let rows = stmt.query_map(params![store], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Vec<u8>>(4)?,
+            ))
+        })?;
+
+        let mut metrics = Vec::new();
+        for row in rows {
+            let (lifetime, store, id, labels, value) = row?;
+            let Some(lifetime) = parse_lifetime(&lifetime) else {
+                continue;
+            };
+            let Some(value) = self.decode(&value) else {
+                log::warn!("Can't decode the value of {id} in {store}");
+                continue;
+            };
+            let labels = labels
+                .split(RECORD_SEPARATOR)
+                .filter(|label| !label.is_empty())
+                .map(str::to_string)
+                .collect();
+            metrics.push(StoredMetric {
+                lifetime,
+                store,
+                id,
+                labels,
+                value,
+            });
+        }
+        Ok(metrics)
+    }
+
+    /// Gets a single unlabeled metric.
+    fn metric(&self, lifetime: Lifetime, store: &str, id: &str) -> Result<Option<Metric>> {
+        let value: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "SELECT value FROM telemetry WHERE lifetime = ?1 AND ping = ?2 AND id = ?3 AND labels = ''",
+                params![lifetime.as_str(), store, id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.and_then(|value| self.decode(&value)))
+    }
+
+    /// Lists the events waiting to be sent, in the order they were recorded in per store.
+    ///
+    /// This includes events that were recorded without a database and
+    /// are imported on the next start.
+    /// Events that can't be decoded are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Only list events of this store, if given.
+    pub fn events(&self, store: Option<&str>) -> Result<Vec<StoredEventRecord>> {
+        let mut events = Vec::new();
+
+        // Before schema 3 all events were in files.
+        if self.schema_version >= 3 {
+            let mut stmt = self.conn.prepare(
+                "SELECT ping, value FROM events WHERE ?1 IS NULL OR ping = ?1 ORDER BY ping, id",
+            )?;
+            let rows = stmt.query_map(params![store], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            for row in rows {
+                let (store, value) = row?;
+                let value = match &self.cipher {
+                    Some(cipher) => cipher.decrypt(&value).map(|value| value.into_owned()),
+                    None => Some(value),
+                };
+                let Some(event) =
+                    value.and_then(|v| serde_json::from_slice::<StoredEvent>(&v).ok())
+                else {
+                    continue;
+                };
+                events.push(StoredEventRecord {
+                    store,
+                    event: event.event,
+                    execution_counter: event.execution_counter,
+                    pending_import: false,
+                });
+            }
+        }
+
+        let events_dir = self.data_path.join("events");
+        let mut files: Vec<_> = match fs::read_dir(&events_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| store.is_none_or(|store| store == name))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort();
+        for file_store in files {
+            let file = BufReader::new(File::open(events_dir.join(&file_store))?);
+            for line in file.lines().map_while(std::io::Result::ok) {
+                let event = match &self.cipher {
+                    Some(cipher) => cipher
+                        .decrypt_line(&line)
+                        .and_then(|line| serde_json::from_str::<StoredEvent>(&line).ok()),
+                    None => serde_json::from_str::<StoredEvent>(&line).ok(),
+                };
+                let Some(event) = event else {
+                    continue;
+                };
+                events.push(StoredEventRecord {
+                    store: file_store.clone(),
+                    event: event.event,
+                    execution_counter: event.execution_counter,
+                    pending_import: true,
+                });
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Lists the pings waiting to be uploaded, `deletion-request` pings first.
+    ///
+    /// Files that can't be read as pings are skipped.
+    pub fn pending_pings(&self) -> Result<Vec<PendingPing>> {
+        let mut pings = Vec::new();
+        for (dir, deletion_request) in [
+            (DELETION_REQUEST_PINGS_DIRECTORY, true),
+            (PENDING_PINGS_DIRECTORY, false),
+        ] {
+            let Ok(entries) = fs::read_dir(self.data_path.join(dir)) else {
+                continue;
+            };
+            let mut entries: Vec<_> = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+                .collect();
+            entries.sort_by_key(|entry| entry.file_name());
+
+            for entry in entries {
+                let Ok(document_id) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let Ok(Some(contents)) = read_ping_file(&entry.path(), self.cipher.as_ref()) else {
+                    continue;
+                };
+                let Ok(body) = serde_json::from_str(&contents.body) else {
+                    continue;
+                };
+                pings.push(PendingPing {
+                    document_id,
+                    deletion_request,
+                    path: contents.path,
+                    body,
+                    metadata: contents
+                        .metadata
+                        .and_then(|metadata| serde_json::from_str(&metadata).ok()),
+                });
+            }
+        }
+        Ok(pings)
+    }
+
+    /// Gets the persisted state of the current session.
+    pub fn session(&self) -> Result<SessionState> {
+        let string = |id| -> Result<Option<String>> {
+            Ok(match self.metric(Lifetime::User, INTERNAL_STORAGE, id)? {
+                Some(Metric::String(s)) if !s.is_empty() => Some(s),
+                _ => None,
+            })
+        };
+        let quantity = |id| -> Result<Option<i64>> {
+            Ok(match self.metric(Lifetime::User, INTERNAL_STORAGE, id)? {
+                Some(Metric::Quantity(n)) => Some(n),
+                _ => None,
+            })
+        };
+
+        Ok(SessionState {
+            seq: quantity(session::SESSION_SEQ_METRIC_NAME)?,
+            id: string(session::SESSION_ID_METRIC_NAME)?,
+            start_time: string(session::SESSION_START_TIME_METRIC_NAME)?,
+            inactive_since: string(session::SESSION_INACTIVE_SINCE_METRIC_NAME)?,
+            event_seq: quantity(session::SESSION_EVENT_SEQ_METRIC_NAME)?,
+        })
+    }
+
+    /// Lists the active experiments by their ID.
+    pub fn experiments(&self) -> Result<Vec<(String, RecordedExperiment)>> {
+        Ok(self
+            .metrics(Some(INTERNAL_STORAGE))?
+            .into_iter()
+            .filter(|metric| metric.lifetime == Lifetime::Application)
+            .filter_map(|metric| match metric.value {
+                Metric::Experiment(experiment) => {
+                    let (id, _) = metric.id.split_once("#experiment")?;
+                    Some((id.to_string(), experiment))
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn decode(&self, blob: &[u8]) -> Option<Metric> {
+        match &self.cipher {
+            Some(cipher) => rmp_serde::from_slice(&cipher.decrypt(blob)?).ok(),
+            None => rmp_serde::from_slice(blob).ok(),
+        }
+    }
+}
+
+fn parse_lifetime(lifetime: &str) -> Option<Lifetime> {
+    [Lifetime::Ping, Lifetime::Application, Lifetime::User]
+        .into_iter()
+        .find(|l| l.as_str() == lifetime)
+}
@@ -379,6 +379,24 @@ pub struct DatabaseMetrics {
 
     /// Number of times a migration was attempted and failed
     pub migration_error: CounterMetric,
+
+    /// Number of times the stored metrics exceeded the database quota.
+    pub quota_exceeded: CounterMetric,
+
+    /// Number of metric entries evicted to stay within the database quota.
+    pub quota_evicted: CounterMetric,
+
+    /// Number of rows salvaged from a corrupt database.
+    pub salvaged_rows: CounterMetric,
+
+    /// Estimated number of rows lost from a corrupt database.
+    pub lost_rows: CounterMetric,
+
+    /// The time it takes to run database maintenance while running.
+    pub maintenance_duration: TimingDistributionMetric,
+
+    /// The bytes reclaimed by one database maintenance run.
+    pub maintenance_reclaimed: MemoryDistributionMetric,
 }
 
 impl DatabaseMetrics {
@@ -457,6 +475,60 @@ impl DatabaseMetrics {
                 lifetime: Lifetime::Ping,
                 ..Default::default()
             }),
+
+            quota_exceeded: CounterMetric::new(CommonMetricData {
+                name: "quota_exceeded".into(),
+                category: "glean.database".into(),
+                send_in_pings: vec!["metrics".into(), "health".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            }),
+
+            quota_evicted: CounterMetric::new(CommonMetricData {
+                name: "quota_evicted".into(),
+                category: "glean.database".into(),
+                send_in_pings: vec!["metrics".into(), "health".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            }),
+
+            salvaged_rows: CounterMetric::new(CommonMetricData {
+                name: "salvaged_rows".into(),
+                category: "glean.database".into(),
+                send_in_pings: vec!["metrics".into(), "health".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            }),
+
+            lost_rows: CounterMetric::new(CommonMetricData {
+                name: "lost_rows".into(),
+                category: "glean.database".into(),
+                send_in_pings: vec!["metrics".into(), "health".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            }),
+
+            maintenance_duration: TimingDistributionMetric::new(
+                CommonMetricData {
+                    name: "maintenance_duration".into(),
+                    category: "glean.database".into(),
+                    send_in_pings: vec!["metrics".into(), "health".into()],
+                    lifetime: Lifetime::Ping,
+                    ..Default::default()
+                },
+                TimeUnit::Millisecond,
+            ),
+
+            maintenance_reclaimed: MemoryDistributionMetric::new(
+                CommonMetricData {
+                    name: "maintenance_reclaimed".into(),
+                    category: "glean.database".into(),
+                    send_in_pings: vec!["metrics".into(), "health".into()],
+                    lifetime: Lifetime::Ping,
+                    ..Default::default()
+                },
+                MemoryUnit::Byte,
+            ),
         }
     }
 }
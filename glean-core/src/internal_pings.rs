@@ -4,7 +4,7 @@
 
 use malloc_size_of_derive::MallocSizeOf;
 
-use crate::metrics::PingType;
+use crate::metrics::{PingPriority, PingType};
 
 /// Glean-provided pings, all enabled by default.
 ///
@@ -23,7 +23,7 @@ pub struct InternalPings {
 
 impl InternalPings {
     pub fn new(enabled: bool) -> InternalPings {
-        InternalPings {
+        let pings = InternalPings {
             baseline: PingType::new(
                 "baseline",
                 true,
@@ -98,6 +98,13 @@ impl InternalPings {
                 true,
                 vec![],
             ),
-        }
+        };
+
+        // Let small, important pings jump ahead of any backlog of `events` pings.
+        pings.baseline.set_priority(PingPriority::Critical);
+        pings.deletion_request.set_priority(PingPriority::Critical);
+        pings.events.set_priority(PingPriority::Bulk);
+
+        pings
     }
 }
@@ -29,10 +29,12 @@ use crossbeam_channel::unbounded;
 use log::LevelFilter;
 use malloc_size_of_derive::MallocSizeOf;
 use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use metrics::RemoteSettingsConfig;
 
+pub mod backup;
 mod common_metric_data;
 mod core;
 mod core_metrics;
@@ -43,19 +45,24 @@ mod debug;
 pub mod dispatcher;
 #[cfg(not(feature = "benchmark"))]
 mod dispatcher;
+mod encryption;
 mod error;
 mod error_recording;
 mod event_database;
 mod glean_metrics;
 mod histogram;
+pub mod inspect;
 mod internal_metrics;
 mod internal_pings;
+mod maintenance;
 pub mod metrics;
 pub mod ping;
+mod ping_scheduler;
 mod scheduler;
 pub(crate) mod session;
 pub mod storage;
 mod system;
+mod task_scheduler;
 #[doc(hidden)]
 pub mod thread;
 pub mod traits;
@@ -69,6 +76,7 @@ pub use crate::common_metric_data::{CommonMetricData, Lifetime, MetricLabel};
 pub use crate::core::Glean;
 pub use crate::core_metrics::{AttributionMetrics, ClientInfoMetrics, DistributionMetrics};
 use crate::dispatcher::is_test_mode;
+pub use crate::encryption::{EncryptionError, EncryptionKeyProvider};
 pub use crate::error::{Error, ErrorKind, Result};
 pub use crate::error_recording::{test_get_num_recorded_errors, ErrorType};
 pub use crate::histogram::HistogramType;
@@ -82,13 +90,16 @@ pub use crate::metrics::{
     BooleanMetric, CounterMetric, CustomDistributionMetric, Datetime, DatetimeMetric,
     DenominatorMetric, DistributionData, DualLabeledCounterMetric, EventMetric,
     LocalCustomDistribution, LocalMemoryDistribution, LocalTimingDistribution,
-    MemoryDistributionMetric, MemoryUnit, NumeratorMetric, ObjectMetric, PingType, QuantityMetric,
-    Rate, RateMetric, RecordedEvent, RecordedExperiment, StringListMetric, StringMetric,
-    TestGetValue, TextMetric, TimeUnit, TimerId, TimespanMetric, TimingDistributionMetric,
-    UrlMetric, UuidMetric,
+    MemoryDistributionMetric, MemoryUnit, NumeratorMetric, ObjectMetric, PingCapacity,
+    PingPriority, PingSchedule, PingType, QuantityMetric, Rate, RateMetric, RecordedEvent,
+    RecordedExperiment, StringListMetric, StringMetric, TestGetValue, TextMetric, TimeUnit,
+    TimerId, TimespanMetric, TimingDistributionMetric, UrlMetric, UuidMetric,
 };
 pub use crate::session::{SessionManager, SessionMetadata, SessionMode};
-pub use crate::upload::{PingRequest, PingUploadTask, UploadResult, UploadTaskAction};
+pub use crate::upload::{
+    ContentEncoding, NetworkType, PingRequest, PingUploadBatchTask, PingUploadTask,
+    QuarantinedPing, UploadConstraints, UploadResult, UploadTaskAction, UploadWindow,
+};
 
 const GLEAN_VERSION: &str = env!("CARGO_PKG_VERSION");
 const GLEAN_SCHEMA_VERSION: u32 = 1;
@@ -114,6 +125,11 @@ static PRE_INIT_SOURCE_TAGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
 static PRE_INIT_PING_REGISTRATION: Mutex<Vec<metrics::PingType>> = Mutex::new(Vec::new());
 static PRE_INIT_PING_ENABLED: Mutex<Vec<(metrics::PingType, bool)>> = Mutex::new(Vec::new());
 
+/// Keep track of upload constraints set before Glean is initialized,
+/// so that they apply before any pending ping is uploaded.
+static PRE_INIT_PING_UPLOAD_CONSTRAINTS: Mutex<Vec<(String, Option<UploadConstraints>)>> =
+    Mutex::new(Vec::new());
+
 /// Keep track of attribution and distribution supplied before Glean is initialized.
 static PRE_INIT_ATTRIBUTION: Mutex<Option<AttributionMetrics>> = Mutex::new(None);
 static PRE_INIT_DISTRIBUTION: Mutex<Option<DistributionMetrics>> = Mutex::new(None);
@@ -154,6 +170,12 @@ pub struct InternalConfiguration {
     pub log_level: Option<LevelFilter>,
     /// The rate at which pings may be uploaded before they are throttled.
     pub rate_limit: Option<PingRateLimit>,
+    /// The backoff schedule for retrying pings after a recoverable upload failure,
+    /// e.g. [`UploadBackoff::default`]. Pings are retried without delay when not set.
+    pub upload_backoff: Option<UploadBackoff>,
+    /// The number of pings that permanently failed to upload to keep for diagnosis.
+    /// Such pings are deleted when not set.
+    pub max_quarantined_pings: Option<u32>,
     /// Whether to add a wallclock timestamp to all events.
     pub enable_event_timestamps: bool,
     /// An experimentation identifier derived by the application to be sent with all pings, it should
@@ -184,6 +206,24 @@ pub struct InternalConfiguration {
     pub session_inactivity_timeout_ms: u64,
     /// The number of "events" pings to accelerate each session, plus one.
     pub events_ping_acceleration_factor: Option<u32>,
+    /// Supplies the key to encrypt all data at rest with. Requires the `encryption` feature.
+    /// Once data was encrypted, Glean fails to initialize without the key.
+    #[ignore_malloc_size_of = "external trait object"]
+    pub encryption_key_provider: Option<Arc<dyn EncryptionKeyProvider>>,
+    /// Maximum size in bytes of the stored metrics. When exceeded, metrics are evicted,
+    /// ping-lifetime metrics with many labels first. Unbounded when not set.
+    pub max_database_size: Option<u64>,
+    /// Whether to keep all data in memory only: the database, events and pending pings.
+    /// Nothing is read from or written to `data_path`, and everything is dropped on shutdown,
+    /// unless exported with [`glean_backup`] or [`glean_export_pending_pings`].
+    pub in_memory: bool,
+    /// The interval in milliseconds at which to run database maintenance while running:
+    /// vacuuming free pages, optimizing and truncating the write-ahead log.
+    /// Maintenance also runs when the client becomes inactive. Never periodically when not set.
+    pub database_maintenance_interval_ms: Option<u64>,
+    /// When to schedule the "metrics" ping if `use_core_mps` is `true`.
+    /// Daily at 04:00 local time when not set.
+    pub metrics_ping_schedule: Option<MetricsPingSchedule>,
 }
 
 /// How to specify the rate at which pings may be uploaded before they are throttled.
@@ -195,15 +235,89 @@ pub struct PingRateLimit {
     pub pings_per_interval: u32,
 }
 
+/// How to back off from uploading a ping again after a recoverable upload failure.
+///
+/// After the n-th failed attempt a ping is not handed out for upload for
+/// `min(base_delay_ms * multiplier^(n-1), max_delay_ms)` milliseconds,
+/// randomly spread by up to `jitter` times that delay in either direction.
+/// A `base_delay_ms` of `0` disables backing off.
+///
+/// The default waits 1 minute after the first failure, doubling with every further failure
+/// up to 6 hours, spread by 20%.
+#[derive(Debug, Clone, MallocSizeOf)]
+pub struct UploadBackoff {
+    /// The delay in milliseconds after the first failed attempt.
+    pub base_delay_ms: u64,
+    /// The factor the delay grows by with every further failed attempt.
+    pub multiplier: f64,
+    /// The maximum delay in milliseconds.
+    pub max_delay_ms: u64,
+    /// The fraction of the delay to randomly spread retries by. Clamped to `[0.0, 1.0]`.
+    pub jitter: f64,
+}
+
+/// When the Metrics Ping Scheduler schedules the "metrics" ping.
+///
+/// The ping is scheduled daily at `hour:00`, delayed by a random amount of up to
+/// `spread_seconds` every day, so that not all clients submit it at the same time.
+/// It can be overridden through [`RemoteSettingsConfig::metrics_ping_schedule`].
+#[derive(Debug, Clone, PartialEq, Eq, MallocSizeOf, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsPingSchedule {
+    /// The hour of the day, from 0 to 23. Default: `4`.
+    pub hour: u32,
+    /// The fixed offset from UTC in seconds of the timezone `hour` is in,
+    /// e.g. `0` for UTC. The device's local timezone when not set.
+    pub utc_offset_seconds: Option<i32>,
+    /// The length in seconds of the window after `hour` to randomly spread submissions over,
+    /// ending before midnight. Default: `0`.
+    pub spread_seconds: u32,
+}
+
+impl Default for MetricsPingSchedule {
+    fn default() -> Self {
+        Self {
+            hour: scheduler::DEFAULT_SCHEDULED_HOUR,
+            utc_offset_seconds: None,
+            spread_seconds: 0,
+        }
+    }
+}
+
 /// Launches a new task on the global dispatch queue with a reference to the Glean singleton.
 fn launch_with_glean(callback: impl FnOnce(&Glean) + Send + 'static) {
-    dispatcher::launch(|| core::with_glean(callback));
+    dispatcher::launch(|| {
+        let submitted = core::with_glean(|glean| {
+            callback(glean);
+            glean.take_submitted_at_capacity()
+        });
+        trigger_upload_if(submitted);
+    });
 }
 
 /// Launches a new task on the global dispatch queue with a mutable reference to the
 /// Glean singleton.
 fn launch_with_glean_mut(callback: impl FnOnce(&mut Glean) + Send + 'static) {
-    dispatcher::launch(|| core::with_glean_mut(callback));
+    dispatcher::launch(|| {
+        let submitted = core::with_glean_mut(|glean| {
+            callback(glean);
+            glean.take_submitted_at_capacity()
+        });
+        trigger_upload_if(submitted);
+    });
+}
+
+/// Triggers upload if pings were submitted by recording data,
+/// because they reached their capacity.
+fn trigger_upload_if(submitted: bool) {
+    if !submitted {
+        return;
+    }
+    if let Some(state) = maybe_global_state() {
+        if let Err(e) = state.lock().unwrap().callbacks.trigger_upload() {
+            log::error!("Triggering upload failed. Error: {}", e);
+        }
+    }
 }
 
 /// Block on the dispatcher emptying.
@@ -405,6 +519,62 @@ pub fn glean_initialize_for_subprocess(cfg: InternalConfiguration) -> bool {
     true
 }
 
+/// Creates and initializes a new Glean object for a helper process.
+///
+/// The helper process records into the database of the primary process on the same data path.
+/// Only the primary process assembles and uploads pings.
+/// See [`Glean::new_for_helper_process`] for details.
+///
+/// Initializes synchronously. Returns whether initialization succeeded.
+pub fn glean_initialize_for_helper_process(cfg: InternalConfiguration) -> bool {
+    if was_initialize_called() {
+        log::error!("Glean should not be initialized multiple times");
+        return false;
+    }
+
+    if let Some(level) = cfg.log_level {
+        log::set_max_level(level)
+    }
+
+    let glean = match Glean::new_for_helper_process(&cfg) {
+        Ok(glean) => glean,
+        Err(err) => {
+            log::error!("Failed to initialize Glean for helper process: {}", err);
+            return false;
+        }
+    };
+    if core::setup_glean(glean).is_err() {
+        return false;
+    }
+
+    core::with_glean_mut(|glean| {
+        // Perform registration of pings that were attempted to be
+        // registered before init.
+        let pings = PRE_INIT_PING_REGISTRATION.lock().unwrap();
+        for ping in pings.iter() {
+            glean.register_ping_type(ping);
+        }
+        let pings = PRE_INIT_PING_ENABLED.lock().unwrap();
+        for (ping, enabled) in pings.iter() {
+            glean.set_ping_enabled(ping, *enabled);
+        }
+    });
+
+    match dispatcher::flush_init() {
+        Ok(task_count) if task_count > 0 => {
+            core::with_glean(|glean| {
+                glean_metrics::error::preinit_tasks_overflow.add_sync(glean, task_count as i32);
+            });
+        }
+        Ok(_) => {}
+        Err(err) => log::error!("Unable to flush the preinit queue: {}", err),
+    }
+
+    INITIALIZE_CALLED.store(true, Ordering::SeqCst);
+    log::info!("Glean initialized for helper process");
+    true
+}
+
 fn initialize_inner(
     cfg: InternalConfiguration,
     client_info: ClientInfoMetrics,
@@ -427,7 +597,7 @@ fn initialize_inner(
         let data_path_str = cfg.data_path.clone();
         let data_path = Path::new(&data_path_str);
         let internal_pings_enabled = cfg.enable_internal_pings;
-        let dir_info = if !is_test_mode() && internal_pings_enabled {
+        let dir_info = if !is_test_mode() && internal_pings_enabled && !cfg.in_memory {
             collect_directory_info(Path::new(&data_path))
         } else {
             None
@@ -504,6 +674,10 @@ fn initialize_inner(
             for (ping, enabled) in pings.iter() {
                 glean.set_ping_enabled(ping, *enabled);
             }
+            let constraints = PRE_INIT_PING_UPLOAD_CONSTRAINTS.lock().unwrap();
+            for (ping_name, constraints) in constraints.iter() {
+                glean.set_ping_upload_constraints(ping_name, constraints.clone());
+            }
 
             // The attribution and distribution might have been cleared or set before initialize,
             // clear if necessary, and then take the cached values and set them.
@@ -550,6 +724,7 @@ fn initialize_inner(
         core::with_glean(|glean| {
             // Start the MPS if its handled within Rust.
             glean.start_metrics_ping_scheduler();
+            glean.start_database_maintenance_scheduler();
         });
 
         // The metrics ping scheduler might _synchronously_ submit a ping
@@ -750,6 +925,8 @@ pub fn shutdown() {
     // Case 3: "After init completed"
     crate::launch_with_glean_mut(|glean| {
         glean.cancel_metrics_ping_scheduler();
+        glean.cancel_ping_schedules();
+        glean.cancel_database_maintenance_scheduler();
         glean.set_dirty_flag(false);
     });
 
@@ -786,7 +963,11 @@ pub fn shutdown() {
         log::error!("Can't shutdown dispatcher thread: {:?}", e);
     }
 
-    uploader_shutdown();
+    // Helper processes neither upload pings nor maintain the database.
+    let helper_process = core::with_glean(|glean| glean.is_helper_process());
+    if !helper_process {
+        uploader_shutdown();
+    }
 
     // Be sure to call this _after_ draining the dispatcher
     core::with_glean_mut(|glean| {
@@ -794,7 +975,7 @@ pub fn shutdown() {
             log::info!("Can't persist ping lifetime data: {:?}", e);
         }
 
-        if let Some(database) = &glean.data_store {
+        if let Some(database) = glean.data_store.as_ref().filter(|_| !helper_process) {
             if let Err(e) = database.run_maintenance(false) {
                 log::info!("Can't run database maintenance on shutdown: {:?}", e);
             }
@@ -1172,6 +1353,213 @@ pub fn glean_set_log_pings(value: bool) {
     }
 }
 
+/// Exports all pending pings into an archive, to upload them elsewhere.
+///
+/// An existing archive is never overwritten.
+///
+/// Exported pings are deleted, as if they were sent.
+/// Pings currently being uploaded are not exported.
+///
+/// # Arguments
+///
+/// * `archive_path` - Path to the archive file to write.
+///
+/// # Returns
+///
+/// The number of exported pings, or `None` if the archive couldn't be written.
+pub fn glean_export_pending_pings(archive_path: String) -> Option<u32> {
+    block_on_dispatcher();
+    core::with_glean(
+        |glean| match glean.export_pending_pings(Path::new(&archive_path)) {
+            Ok(count) => Some(count.try_into().unwrap_or(u32::MAX)),
+            Err(e) => {
+                log::error!(
+                    "Failed to export pending pings to {}. Error: {}",
+                    archive_path,
+                    e
+                );
+                None
+            }
+        },
+    )
+}
+
+/// Creates a backup of the data directory.
+///
+/// See [`Glean::backup`] for details.
+///
+/// # Arguments
+///
+/// * `backup_path` - The directory to create the backup in.
+///   It must not exist and must be outside the data directory.
+///
+/// # Returns
+///
+/// Whether the backup was created.
+pub fn glean_backup(backup_path: String) -> bool {
+    block_on_dispatcher();
+    core::with_glean(|glean| match glean.backup(Path::new(&backup_path)) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!(
+                "Failed to back up the data to {}. Error: {}",
+                backup_path,
+                e
+            );
+            false
+        }
+    })
+}
+
+/// Restores a backup into a data directory, replacing its data.
+///
+/// Must be called before Glean is initialized.
+/// The current data is only replaced if the backup is valid.
+///
+/// # Arguments
+///
+/// * `backup_path` - The backup, as created by [`glean_backup`].
+/// * `data_path` - The data directory to restore into.
+///
+/// # Returns
+///
+/// Whether the backup was restored.
+pub fn glean_restore_backup(backup_path: String, data_path: String) -> bool {
+    if was_initialize_called() {
+        log::error!("Can't restore a backup after Glean was initialized.");
+        return false;
+    }
+
+    match backup::restore(Path::new(&backup_path), Path::new(&data_path)) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Failed to restore the backup {}. Error: {}", backup_path, e);
+            false
+        }
+    }
+}
+
+/// Lists the pings that permanently failed to upload and were kept for diagnosis.
+///
+/// Pings are only kept if `max_quarantined_pings` is configured.
+pub fn glean_get_quarantined_pings() -> Vec<QuarantinedPing> {
+    block_on_dispatcher();
+    core::with_glean(|glean| glean.quarantined_pings())
+}
+
+/// Enqueues a quarantined ping for upload again.
+///
+/// # Arguments
+///
+/// * `document_id` - The UUID of the ping in question.
+pub fn glean_reenqueue_quarantined_ping(document_id: String) {
+    crate::launch_with_glean(move |glean| {
+        if glean.reenqueue_quarantined_ping(&document_id) {
+            let state = global_state().lock().unwrap();
+            if let Err(e) = state.callbacks.trigger_upload() {
+                log::error!("Triggering upload failed. Error: {}", e);
+            }
+        }
+    });
+}
+
+/// Deletes all quarantined pings.
+pub fn glean_purge_quarantined_pings() {
+    crate::launch_with_glean(|glean| glean.purge_quarantined_pings());
+}
+
+/// Sets the content encodings the uploader accepts, in order of preference.
+///
+/// Ping payloads are encoded with the first supported encoding,
+/// unless an encoding was set for the ping itself.
+/// Unknown encodings are ignored. Without any, pings are gzip-encoded.
+///
+/// # Arguments
+///
+/// * `encodings` - The names of the accepted encodings, as used in the `Content-Encoding` header.
+pub fn glean_set_content_encodings(encodings: Vec<String>) {
+    crate::launch_with_glean(move |glean| glean.set_content_encodings(&encodings));
+}
+
+/// Sets the content encoding for a single ping,
+/// overriding the encodings the uploader accepts.
+///
+/// # Arguments
+///
+/// * `ping_name` - The name of the ping.
+/// * `encoding` - The name of the encoding, as used in the `Content-Encoding` header.
+///   `None` to go back to the accepted encodings.
+pub fn glean_set_ping_content_encoding(ping_name: String, encoding: Option<String>) {
+    crate::launch_with_glean(move |glean| {
+        glean.set_ping_content_encoding(&ping_name, encoding.as_deref())
+    });
+}
+
+/// Sets the constraints on when a ping may be uploaded.
+///
+/// Constraints set before initialization apply before any pending ping is uploaded.
+///
+/// # Arguments
+///
+/// * `ping_name` - The name of the ping.
+/// * `constraints` - The constraints, `None` to upload the ping whenever possible.
+pub fn glean_set_ping_upload_constraints(
+    ping_name: String,
+    constraints: Option<UploadConstraints>,
+) {
+    if was_initialize_called() && core::global_glean().is_some() {
+        crate::launch_with_glean(move |glean| {
+            glean.set_ping_upload_constraints(&ping_name, constraints)
+        });
+    } else {
+        let mut lock = PRE_INIT_PING_UPLOAD_CONSTRAINTS.lock().unwrap();
+        lock.push((ping_name, constraints));
+    }
+}
+
+/// Sets the schedule on which a ping is submitted, replacing any previous schedule.
+///
+/// If a scheduled time passed since the ping was last submitted by the scheduler,
+/// it is submitted right away with the reason `overdue`.
+///
+/// # Arguments
+///
+/// * `ping_name` - The name of the ping.
+/// * `schedule` - The schedule, `None` to stop scheduling the ping.
+pub fn glean_set_ping_schedule(ping_name: String, schedule: Option<PingSchedule>) {
+    // Need to separate access to the Glean object from access to global state.
+    // `trigger_upload` itself might lock the Glean object and we need to avoid that deadlock.
+    crate::dispatcher::launch(move || {
+        let submitted = core::with_glean(|glean| glean.set_ping_schedule(&ping_name, schedule));
+        if submitted {
+            let state = global_state().lock().unwrap();
+            if let Err(e) = state.callbacks.trigger_upload() {
+                log::error!("Triggering upload failed. Error: {}", e);
+            }
+        }
+    })
+}
+
+/// Reports the kind of network the device is currently connected to.
+///
+/// Pings constrained to unmetered networks are only uploaded
+/// while an unmetered network is reported.
+/// Uploading is triggered whenever the network type changes.
+///
+/// # Arguments
+///
+/// * `network` - The current network type.
+pub fn glean_set_network_type(network: NetworkType) {
+    crate::launch_with_glean(move |glean| {
+        if glean.set_network_type(network) {
+            let state = global_state().lock().unwrap();
+            if let Err(e) = state.callbacks.trigger_upload() {
+                log::error!("Triggering upload failed. Error: {}", e);
+            }
+        }
+    });
+}
+
 /// Gets the current log pings value.
 ///
 /// # Returns
@@ -1291,6 +1679,27 @@ pub fn glean_submit_ping_by_name_sync(ping_name: String, reason: Option<String>)
         .unwrap_or(false)
 }
 
+/// Assembles the payload a ping would have if it was submitted now, without submitting it.
+///
+/// Waits for previously queued tasks to finish first, so their data is included.
+/// See [`Glean::preview_ping`] for details.
+///
+/// # Arguments
+///
+/// * `ping_name` - The name of the ping to preview.
+///
+/// # Returns
+///
+/// The ping payload as pretty-printed JSON, or `None` if there is nothing to send.
+pub fn glean_preview_ping(ping_name: String) -> Option<String> {
+    if !was_initialize_called() {
+        return None;
+    }
+
+    block_on_dispatcher();
+    core::with_opt_glean(|glean| glean.preview_ping(&ping_name)).flatten()
+}
+
 /// EXPERIMENTAL: Register a listener object to recieve notifications of event recordings.
 ///
 /// # Arguments
@@ -1335,7 +1744,7 @@ pub fn glean_test_destroy_glean(clear_stores: bool, data_path: Option<String>) {
             // We need to flush the ping lifetime data before a full shutdown.
             glean
                 .storage_opt()
-                .map(|storage| storage.persist_ping_lifetime_data())
+                .map(|storage| storage.persist_ping_lifetime_data(glean))
                 .is_some()
         })
         .unwrap_or(false);
@@ -1345,6 +1754,8 @@ pub fn glean_test_destroy_glean(clear_stores: bool, data_path: Option<String>) {
 
         if core::global_glean().is_some() {
             core::with_glean_mut(|glean| {
+                glean.cancel_ping_schedules();
+                glean.cancel_database_maintenance_scheduler();
                 if clear_stores {
                     glean.test_clear_all_stores()
                 }
@@ -1373,6 +1784,19 @@ pub fn glean_process_ping_upload_response(uuid: String, result: UploadResult) ->
     core::with_glean(|glean| glean.process_ping_upload_response(&uuid, result))
 }
 
+/// Gets the next task for an uploader uploading up to `max_pings` pings at once.
+pub fn glean_get_upload_batch(max_pings: u32) -> PingUploadBatchTask {
+    core::with_opt_glean(|glean| glean.get_upload_batch(max_pings as usize))
+        .unwrap_or_else(PingUploadBatchTask::done)
+}
+
+/// Processes the responses from an attempt to upload a batch of pings.
+pub fn glean_process_ping_upload_batch_response(
+    results: HashMap<String, UploadResult>,
+) -> UploadTaskAction {
+    core::with_glean(|glean| glean.process_ping_upload_batch_response(results))
+}
+
 /// **TEST-ONLY Method**
 ///
 /// Set the dirty flag
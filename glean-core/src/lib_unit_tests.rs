@@ -226,6 +226,8 @@ fn experimentation_id_is_set_correctly() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: Some(experimentation_id.to_string()),
         enable_internal_pings: true,
@@ -238,6 +240,11 @@ fn experimentation_id_is_set_correctly() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     })
     .unwrap();
 
@@ -1118,6 +1125,76 @@ fn test_remote_settings_epoch() {
     assert_eq!(1u8, current_epoch, "Current epoch must match");
 }
 
+#[test]
+fn server_knobs_override_the_metrics_ping_schedule() {
+    let (glean, _t) = new_glean(None);
+    assert_eq!(
+        MetricsPingSchedule::default(),
+        glean.get_metrics_ping_schedule()
+    );
+
+    let remote_settings_config = json!(
+        {
+            "metrics_ping_schedule": {
+                "hour": 2,
+                "utc_offset_seconds": 0
+            }
+        }
+    )
+    .to_string();
+    glean
+        .apply_server_knobs_config(RemoteSettingsConfig::try_from(remote_settings_config).unwrap());
+    assert_eq!(
+        MetricsPingSchedule {
+            hour: 2,
+            utc_offset_seconds: Some(0),
+            spread_seconds: 0,
+        },
+        glean.get_metrics_ping_schedule()
+    );
+
+    // An invalid hour falls back to the default hour.
+    let remote_settings_config = json!({ "metrics_ping_schedule": { "hour": 24 } }).to_string();
+    glean
+        .apply_server_knobs_config(RemoteSettingsConfig::try_from(remote_settings_config).unwrap());
+    assert_eq!(
+        MetricsPingSchedule::default(),
+        glean.get_metrics_ping_schedule()
+    );
+
+    // A spread past midnight is shortened.
+    let remote_settings_config =
+        json!({ "metrics_ping_schedule": { "spread_seconds": 24 * 60 * 60 } }).to_string();
+    glean
+        .apply_server_knobs_config(RemoteSettingsConfig::try_from(remote_settings_config).unwrap());
+    assert_eq!(
+        MetricsPingSchedule {
+            spread_seconds: 20 * 60 * 60 - 1,
+            ..Default::default()
+        },
+        glean.get_metrics_ping_schedule()
+    );
+    let remote_settings_config =
+        json!({ "metrics_ping_schedule": { "hour": 23, "spread_seconds": 7200 } }).to_string();
+    glean
+        .apply_server_knobs_config(RemoteSettingsConfig::try_from(remote_settings_config).unwrap());
+    assert_eq!(
+        MetricsPingSchedule {
+            hour: 23,
+            spread_seconds: 3599,
+            ..Default::default()
+        },
+        glean.get_metrics_ping_schedule()
+    );
+
+    // Without an override, the default schedule is used again.
+    glean.apply_server_knobs_config(RemoteSettingsConfig::try_from("{}".to_string()).unwrap());
+    assert_eq!(
+        MetricsPingSchedule::default(),
+        glean.get_metrics_ping_schedule()
+    );
+}
+
 #[test]
 fn test_remote_settings_epoch_updates_in_metric() {
     let (glean, _t) = new_glean(None);
@@ -1542,3 +1619,62 @@ fn crash_recovery_no_session_is_noop() {
         "crash recovery without a persisted session must not emit any event"
     );
 }
+
+#[test]
+fn ping_usage_is_estimated_between_measurements() {
+    let (mut glean, _t) = new_glean(None);
+
+    let ping = PingType::new_internal(
+        "capacity",
+        true,
+        false,
+        true,
+        true,
+        true,
+        vec![],
+        vec!["max_capacity".into()],
+        true,
+        vec![],
+    );
+    glean.register_ping_type(&ping);
+    ping.set_capacity(Some(PingCapacity {
+        max_labels: Some(3),
+        ..Default::default()
+    }));
+
+    let labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "labeled_metric".into(),
+                category: "telemetry".into(),
+                send_in_pings: vec!["capacity".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            },
+        },
+        None,
+    );
+    let database = glean.storage();
+    assert_eq!(None, database.estimated_ping_usage("capacity"));
+
+    // The first write is measured, later ones only grow the estimate.
+    labeled.get("a").add_sync(&glean, 1);
+    let measured = database.estimated_ping_usage("capacity").unwrap();
+    assert_eq!(1, measured.labeled_entries);
+
+    labeled.get("b").add_sync(&glean, 1);
+    let estimate = database.estimated_ping_usage("capacity").unwrap();
+    assert_eq!(2, estimate.labeled_entries);
+    assert!(estimate.bytes > measured.bytes);
+    let usage = database.ping_usage("capacity").unwrap();
+    assert_eq!(2, usage.labeled_entries);
+    assert!(estimate.bytes >= usage.bytes);
+
+    // Reaching the capacity measures and submits the ping, which clears the estimate.
+    labeled.get("c").add_sync(&glean, 1);
+    assert_eq!(None, database.estimated_ping_usage("capacity"));
+    assert_eq!(
+        database::sqlite::PingUsage::default(),
+        database.ping_usage("capacity").unwrap()
+    );
+}
@@ -0,0 +1,94 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Database Maintenance Scheduler
+//!
+//! Runs database maintenance at a fixed interval while the process is running,
+//! so long-running processes keep their database and write-ahead log compact.
+//! Maintenance also runs when the client becomes inactive, independent of this scheduler.
+//! Initialization and shutdown only run a partial maintenance, see `Database::run_maintenance`.
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// Clippy thinks an AtomicBool would be preferred, but Condvar requires a full Mutex.
+// See https://github.com/rust-lang/rust-clippy/issues/1516
+#[allow(clippy::mutex_atomic)]
+static TASK_CONDVAR: Lazy<Arc<(Mutex<bool>, Condvar)>> =
+    Lazy::new(|| Arc::new((Mutex::new(false), Condvar::new())));
+
+/// Starts running database maintenance every `interval`, on another thread.
+///
+/// The first maintenance runs `interval` from now.
+/// Each run is queued on the dispatcher and holds up the tasks queued after it.
+/// That's noticeable when there are many free pages to vacuum, or on the full `VACUUM`
+/// that switches a database to incremental vacuuming.
+pub fn schedule(interval: Duration) {
+    let (cancelled_lock, _condvar) = &**TASK_CONDVAR;
+    if *cancelled_lock.lock().unwrap() {
+        log::debug!("Told to schedule, but already cancelled. Are we in a test?");
+    }
+    *cancelled_lock.lock().unwrap() = false; // Uncancel the thread.
+
+    start_scheduler(interval);
+}
+
+/// Tells the scheduler task to exit quickly and cleanly.
+pub fn cancel() {
+    let (cancelled_lock, condvar) = &**TASK_CONDVAR; // One `*` for Lazy, the second for Arc
+    *cancelled_lock.lock().unwrap() = true; // Cancel the scheduler thread.
+    condvar.notify_all(); // Notify any/all listening schedulers to check whether they were cancelled.
+}
+
+fn start_scheduler(interval: Duration) -> JoinHandle<()> {
+    let pair = Arc::clone(&TASK_CONDVAR);
+    crate::thread::spawn("glean.maintenance", move || {
+        let (cancelled_lock, condvar) = &*pair;
+        loop {
+            log::debug!("Scheduling database maintenance after {:?}", interval);
+            match condvar.wait_timeout_while(
+                cancelled_lock.lock().unwrap(),
+                interval,
+                |cancelled| !*cancelled,
+            ) {
+                Err(err) => {
+                    log::warn!(
+                        "Condvar wait failure. Maintenance scheduler exiting. {}",
+                        err
+                    );
+                    break;
+                }
+                Ok((cancelled, _)) if *cancelled => {
+                    log::info!("Database maintenance scheduler cancelled. Exiting.");
+                    break;
+                }
+                // Timed out, time for maintenance.
+                Ok(_) => {}
+            }
+
+            // We might be cancelled by now, e.g. during shutdown.
+            // Maintenance doesn't hurt then, and the next loop exits.
+            crate::launch_with_glean(|glean| glean.run_database_maintenance());
+        }
+    })
+    .expect("Unable to spawn thread for the database maintenance scheduler.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cancelled_scheduler_exits() {
+        let (cancelled_lock, _condvar) = &**TASK_CONDVAR;
+        *cancelled_lock.lock().unwrap() = false;
+
+        // Long enough to never run maintenance, which would need a global Glean.
+        let handle = start_scheduler(Duration::from_secs(24 * 60 * 60));
+        cancel();
+        handle.join().unwrap();
+    }
+}
@@ -190,7 +190,7 @@ impl EventMetric {
 
         let events = glean
             .event_storage()
-            .test_get_value(&self.meta, queried_ping_name);
+            .test_get_value(glean, &self.meta, queried_ping_name);
 
         events.map(|mut evts| {
             for ev in &mut evts {
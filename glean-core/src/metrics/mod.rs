@@ -27,6 +27,9 @@ mod memory_unit;
 mod numerator;
 mod object;
 mod ping;
+mod ping_capacity;
+mod ping_priority;
+mod ping_schedule;
 mod quantity;
 mod rate;
 mod recorded_experiment;
@@ -65,6 +68,9 @@ pub use self::memory_unit::MemoryUnit;
 pub use self::numerator::NumeratorMetric;
 pub use self::object::ObjectMetric;
 pub use self::ping::PingType;
+pub use self::ping_capacity::PingCapacity;
+pub use self::ping_priority::PingPriority;
+pub use self::ping_schedule::{InvalidScheduleError, PingSchedule};
 pub use self::quantity::QuantityMetric;
 pub use self::rate::{Rate, RateMetric};
 pub use self::string::StringMetric;
@@ -3,11 +3,12 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::fmt;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
 
-use crate::ping::PingMaker;
-use crate::upload::PingPayload;
+use crate::metrics::{PingCapacity, PingPriority, PingSchedule};
+use crate::ping::{Ping, PingMaker};
+use crate::upload::{PingPayload, UploadConstraints};
 use crate::Glean;
 
 use malloc_size_of_derive::MallocSizeOf;
@@ -45,6 +46,13 @@ struct InnerPing {
 
     /// Ordered list of uploader capabilities required to upload this ping.
     uploader_capabilities: Vec<String>,
+
+    /// The upload priority of this ping, stored as `PingPriority as u8`.
+    priority: AtomicU8,
+
+    /// The thresholds at which this ping is submitted automatically.
+    #[ignore_malloc_size_of = "holds no heap allocations of its own"]
+    capacity: Mutex<Option<PingCapacity>>,
 }
 
 impl fmt::Debug for PingType {
@@ -63,6 +71,8 @@ impl fmt::Debug for PingType {
                 &self.0.follows_collection_enabled.load(Ordering::Relaxed),
             )
             .field("uploader_capabilities", &self.0.uploader_capabilities)
+            .field("priority", &self.priority())
+            .field("capacity", &self.capacity())
             .finish()
     }
 }
@@ -145,6 +155,8 @@ impl PingType {
             reason_codes,
             follows_collection_enabled: AtomicBool::new(follows_collection_enabled),
             uploader_capabilities,
+            priority: AtomicU8::new(PingPriority::default() as u8),
+            capacity: Mutex::new(None),
         }));
 
         // Register this ping.
@@ -246,6 +258,66 @@ impl PingType {
         &self.0.uploader_capabilities
     }
 
+    /// The priority this ping is uploaded with.
+    pub fn priority(&self) -> PingPriority {
+        PingPriority::from_u8(self.0.priority.load(Ordering::Relaxed))
+    }
+
+    /// Sets the priority this ping is uploaded with.
+    ///
+    /// Only affects pings submitted afterwards.
+    pub fn set_priority(&self, priority: PingPriority) {
+        self.0.priority.store(priority as u8, Ordering::Relaxed);
+    }
+
+    /// The thresholds at which this ping is submitted automatically, if any.
+    pub fn capacity(&self) -> Option<PingCapacity> {
+        self.0.capacity.lock().unwrap().clone()
+    }
+
+    /// Sets the thresholds at which this ping is submitted automatically,
+    /// with the reason `max_capacity`.
+    ///
+    /// Checked whenever data is recorded into this ping.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The thresholds, `None` to never submit this ping automatically.
+    pub fn set_capacity(&self, capacity: Option<PingCapacity>) {
+        let capacity = capacity.filter(PingCapacity::is_set);
+        if capacity.is_some() && !self.0.reason_codes.iter().any(|r| r == "max_capacity") {
+            log::warn!(
+                "Ping '{}' has no 'max_capacity' reason, it is submitted at capacity without a reason",
+                self.0.name
+            );
+        }
+        *self.0.capacity.lock().unwrap() = capacity;
+    }
+
+    /// Sets the constraints on when this ping may be uploaded.
+    ///
+    /// Applies to all pending pings of this type.
+    /// Constraints with window hours out of range are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `constraints` - The constraints, `None` to upload the ping whenever possible.
+    pub fn set_upload_constraints(&self, constraints: Option<UploadConstraints>) {
+        crate::glean_set_ping_upload_constraints(self.0.name.clone(), constraints)
+    }
+
+    /// Sets the schedule on which this ping is submitted, replacing any previous schedule.
+    ///
+    /// If a scheduled time passed since the ping was last submitted by the scheduler,
+    /// e.g. while the application wasn't running, it is submitted right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The schedule, `None` to stop scheduling this ping.
+    pub fn set_schedule(&self, schedule: Option<PingSchedule>) {
+        crate::glean_set_ping_schedule(self.0.name.clone(), schedule)
+    }
+
     /// Submits the ping for eventual uploading.
     ///
     /// The ping content is assembled as soon as possible, but upload is not
@@ -302,6 +374,14 @@ impl PingType {
             None => None,
         };
 
+        if glean.is_helper_process() {
+            log::info!(
+                "The ping '{}' is submitted by the primary process, not by a helper process",
+                self.0.name
+            );
+            return false;
+        }
+
         if !self.enabled(glean) {
             log::info!(
                 "The ping '{}' is disabled and will be discarded and not submitted",
@@ -353,38 +433,31 @@ impl PingType {
                         .add_sync(glean, 1);
                 }
 
-                if let Err(e) = ping_maker.store_ping(glean.get_data_path(), &ping) {
-                    log::warn!(
-                        "IO error while writing ping to file: {}. Enqueuing upload of what we have in memory.",
-                        e
-                    );
-                    glean.additional_metrics.io_errors.add_sync(glean, 1);
-                    // `serde_json::to_string` only fails if serialization of the content
-                    // fails or it contains maps with non-string keys.
-                    // However `ping.content` is already a `JsonValue`,
-                    // so both scenarios should be impossible.
-                    let content =
-                        ::serde_json::to_string(&ping.content).expect("ping serialization failed");
-                    // TODO: Shouldn't we consolidate on a single collected Ping representation?
-                    let ping = PingPayload {
-                        document_id: ping.doc_id.to_string(),
-                        upload_path: ping.url_path.to_string(),
-                        json_body: content,
-                        headers: Some(ping.headers),
-                        body_has_info_sections: self.0.include_info_sections,
-                        ping_name: self.0.name.to_string(),
-                        uploader_capabilities: self.0.uploader_capabilities.clone(),
-                    };
-
-                    glean.upload_manager.enqueue_ping(glean, ping);
-                    return true;
+                match ping_maker.split(glean, &ping) {
+                    None => self.store_and_enqueue(glean, &ping_maker, ping),
+                    Some(parts) => {
+                        for content in parts {
+                            let doc_id = Uuid::new_v4().to_string();
+                            let url_path = glean.make_path(ping.name, &doc_id);
+                            let part = Ping {
+                                doc_id: &doc_id,
+                                name: ping.name,
+                                url_path: &url_path,
+                                content,
+                                headers: ping.headers.clone(),
+                                includes_info_sections: ping.includes_info_sections,
+                                schedules_pings: ping.schedules_pings.clone(),
+                                uploader_capabilities: ping.uploader_capabilities.clone(),
+                                priority: ping.priority,
+                            };
+                            self.store_and_enqueue(glean, &ping_maker, part);
+                        }
+                    }
                 }
 
-                glean.upload_manager.enqueue_ping_from_file(glean, &doc_id);
-
                 log::info!(
                     "The ping '{}' was submitted and will be sent as soon as possible",
-                    ping.name
+                    self.0.name
                 );
 
                 true
@@ -395,6 +468,43 @@ impl PingType {
         submitted
     }
 
+    /// Stores a collected ping on disk and queues it for upload.
+    ///
+    /// If storing fails, the ping is queued for upload from memory instead.
+    fn store_and_enqueue(&self, glean: &Glean, ping_maker: &PingMaker, ping: Ping) {
+        if let Err(e) = ping_maker.store_ping(glean, &ping) {
+            log::warn!(
+                "IO error while writing ping to file: {}. Enqueuing upload of what we have in memory.",
+                e
+            );
+            glean.additional_metrics.io_errors.add_sync(glean, 1);
+            // `serde_json::to_string` only fails if serialization of the content
+            // fails or it contains maps with non-string keys.
+            // However `ping.content` is already a `JsonValue`,
+            // so both scenarios should be impossible.
+            let content =
+                ::serde_json::to_string(&ping.content).expect("ping serialization failed");
+            // TODO: Shouldn't we consolidate on a single collected Ping representation?
+            let ping = PingPayload {
+                document_id: ping.doc_id.to_string(),
+                upload_path: ping.url_path.to_string(),
+                json_body: content,
+                headers: Some(ping.headers),
+                body_has_info_sections: self.0.include_info_sections,
+                ping_name: self.0.name.to_string(),
+                uploader_capabilities: self.0.uploader_capabilities.clone(),
+                priority: self.priority(),
+            };
+
+            glean.upload_manager.enqueue_ping(glean, ping);
+            return;
+        }
+
+        glean
+            .upload_manager
+            .enqueue_ping_from_file(glean, ping.doc_id);
+    }
+
     fn handle_ping_schedule(&self, glean: &Glean, ping: &InnerPing, reason: Option<&str>) {
         if ping.schedules_pings.is_empty() {
             let ping_schedule = glean
@@ -0,0 +1,82 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use malloc_size_of_derive::MallocSizeOf;
+
+use crate::database::sqlite::PingUsage;
+
+/// Thresholds at which a ping is submitted automatically, with the reason `max_capacity`.
+///
+/// Only data that submitting the ping clears counts towards them:
+/// events and metrics with a `ping` lifetime.
+/// Use them to keep large pings below the maximum ping body size,
+/// above which they would be discarded instead of being uploaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, MallocSizeOf)]
+pub struct PingCapacity {
+    /// The number of events.
+    pub max_events: Option<u32>,
+    /// The number of labeled entries, across all labeled metrics.
+    pub max_labels: Option<u32>,
+    /// The estimated size of the ping's payload in bytes, before compression.
+    pub max_payload_bytes: Option<u64>,
+}
+
+impl PingCapacity {
+    /// Whether any threshold is set.
+    pub(crate) fn is_set(&self) -> bool {
+        self.max_events.is_some() || self.max_labels.is_some() || self.max_payload_bytes.is_some()
+    }
+
+    /// Whether the data stored for a ping reaches any of the thresholds.
+    pub(crate) fn is_reached(&self, usage: &PingUsage) -> bool {
+        self.max_events
+            .is_some_and(|max| usage.events >= u64::from(max))
+            || self
+                .max_labels
+                .is_some_and(|max| usage.labeled_entries >= u64::from(max))
+            || self.max_payload_bytes.is_some_and(|max| usage.bytes >= max)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn any_threshold_is_enough() {
+        let usage = PingUsage {
+            events: 10,
+            labeled_entries: 3,
+            bytes: 2048,
+        };
+
+        assert!(!PingCapacity::default().is_set());
+        assert!(!PingCapacity::default().is_reached(&usage));
+
+        let capacity = PingCapacity {
+            max_events: Some(11),
+            max_labels: Some(4),
+            max_payload_bytes: Some(4096),
+        };
+        assert!(capacity.is_set());
+        assert!(!capacity.is_reached(&usage));
+
+        for capacity in [
+            PingCapacity {
+                max_events: Some(10),
+                ..capacity.clone()
+            },
+            PingCapacity {
+                max_labels: Some(3),
+                ..capacity.clone()
+            },
+            PingCapacity {
+                max_payload_bytes: Some(1024),
+                ..capacity.clone()
+            },
+        ] {
+            assert!(capacity.is_reached(&usage), "{capacity:?}");
+        }
+    }
+}
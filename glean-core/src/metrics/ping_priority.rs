@@ -0,0 +1,53 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use malloc_size_of_derive::MallocSizeOf;
+use serde::{Deserialize, Serialize};
+
+/// How urgently a ping should be uploaded.
+///
+/// Pings of a higher priority are uploaded before pending pings of a lower priority.
+/// When the pending pings directory exceeds its quota,
+/// pings of the lowest priority are deleted first.
+///
+/// Priorities are ordered from most to least urgent.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Serialize,
+    MallocSizeOf,
+)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum PingPriority {
+    /// Small, important pings that should jump ahead of any backlog,
+    /// e.g. `baseline` or `deletion-request`.
+    Critical,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// Large or frequent pings that can wait, e.g. `events`.
+    /// These are the first to be deleted when the pending pings quota is hit.
+    Bulk,
+}
+
+impl PingPriority {
+    /// Converts a priority stored as `u8` back,
+    /// falling back to [`PingPriority::Normal`] on unknown values.
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            0 => PingPriority::Critical,
+            2 => PingPriority::Bulk,
+            _ => PingPriority::Normal,
+        }
+    }
+}
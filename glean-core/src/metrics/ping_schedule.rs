@@ -0,0 +1,359 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveDateTime, TimeDelta, TimeZone};
+use malloc_size_of_derive::MallocSizeOf;
+
+/// The longest interval of an interval schedule, a year.
+const MAX_INTERVAL_SECONDS: u64 = 365 * 24 * 60 * 60;
+
+/// When a ping is submitted by the ping scheduler.
+///
+/// A scheduled ping is submitted at the scheduled time while the application is running.
+/// If that time passed while it wasn't running, the ping is submitted as soon as
+/// the schedule is set again, with the reason `overdue`.
+#[derive(Clone, Debug, PartialEq, Eq, MallocSizeOf)]
+pub enum PingSchedule {
+    /// A fixed interval after the ping was last submitted by the scheduler.
+    Interval {
+        /// The interval, in seconds. Must not be zero and at most a year.
+        seconds: u64,
+    },
+    /// A time of local time, like an entry of a crontab.
+    Calendar {
+        /// The minute of the hour, from 0 to 59.
+        minute: u32,
+        /// The hour of the day, from 0 to 23, or every hour if not set.
+        hour: Option<u32>,
+        /// The day of the week, from 0 (Sunday) to 6 (Saturday), or every day if not set.
+        weekday: Option<u32>,
+    },
+}
+
+/// Why a ping schedule is invalid.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidScheduleError(String);
+
+impl fmt::Display for InvalidScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid ping schedule: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidScheduleError {}
+
+impl PingSchedule {
+    /// A schedule to submit a ping every day at `hour:minute` local time.
+    pub fn daily(hour: u32, minute: u32) -> Self {
+        PingSchedule::Calendar {
+            minute,
+            hour: Some(hour),
+            weekday: None,
+        }
+    }
+
+    /// Checks that all fields are in range.
+    pub fn validate(&self) -> Result<(), InvalidScheduleError> {
+        let invalid = |msg: &str| Err(InvalidScheduleError(msg.into()));
+        match *self {
+            PingSchedule::Interval { seconds: 0 } => invalid("the interval must not be zero"),
+            PingSchedule::Interval { seconds } if seconds > MAX_INTERVAL_SECONDS => {
+                invalid("the interval must be at most a year")
+            }
+            PingSchedule::Interval { .. } => Ok(()),
+            PingSchedule::Calendar { minute, .. } if minute > 59 => {
+                invalid("the minute must be between 0 and 59")
+            }
+            PingSchedule::Calendar {
+                hour: Some(hour), ..
+            } if hour > 23 => invalid("the hour must be between 0 and 23"),
+            PingSchedule::Calendar {
+                weekday: Some(weekday),
+                ..
+            } if weekday > 6 => invalid("the weekday must be between 0 and 6"),
+            PingSchedule::Calendar { .. } => Ok(()),
+        }
+    }
+
+    /// The first scheduled time after `now`.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    /// * `last_sent` - When the ping was last submitted by the scheduler, if ever.
+    ///   Interval schedules count from there, or from `now` if it was never submitted.
+    pub fn next_after(
+        &self,
+        now: DateTime<FixedOffset>,
+        last_sent: Option<DateTime<FixedOffset>>,
+    ) -> DateTime<FixedOffset> {
+        match self {
+            PingSchedule::Interval { seconds } => {
+                let from = last_sent.filter(|last| *last <= now).unwrap_or(now);
+                // Valid intervals always fit, even if the time doesn't for an invalid one.
+                i64::try_from(*seconds)
+                    .ok()
+                    .and_then(TimeDelta::try_seconds)
+                    .and_then(|interval| from.checked_add_signed(interval))
+                    .unwrap_or(now)
+            }
+            PingSchedule::Calendar { .. } => self
+                .calendar_times(now, true)
+                .find(|time| *time > now)
+                .unwrap_or(now),
+        }
+    }
+
+    /// Whether a scheduled time passed since the ping was last submitted by the scheduler.
+    ///
+    /// A ping that was never submitted is overdue.
+    pub fn is_overdue(
+        &self,
+        now: DateTime<FixedOffset>,
+        last_sent: Option<DateTime<FixedOffset>>,
+    ) -> bool {
+        let Some(last_sent) = last_sent else {
+            return true;
+        };
+        match self {
+            PingSchedule::Interval { .. } => self.next_after(last_sent, Some(last_sent)) <= now,
+            PingSchedule::Calendar { .. } => self
+                .calendar_times(now, false)
+                .find(|time| *time <= now)
+                .is_some_and(|latest| latest > last_sent),
+        }
+    }
+
+    /// The scheduled times of a calendar schedule within a week of `now`, in local time.
+    ///
+    /// Ascending from the start of today if `forward`, else descending from the end of today.
+    fn calendar_times(
+        &self,
+        now: DateTime<FixedOffset>,
+        forward: bool,
+    ) -> impl Iterator<Item = DateTime<FixedOffset>> + '_ {
+        let PingSchedule::Calendar {
+            minute,
+            hour,
+            weekday,
+        } = *self
+        else {
+            unreachable!("only calendar schedules have scheduled times");
+        };
+        let today = now.date_naive();
+        let offset = now.timezone();
+
+        let hours: Vec<u32> = match hour {
+            Some(hour) => vec![hour],
+            None if forward => (0..24).collect(),
+            None => (0..24).rev().collect(),
+        };
+        (0..=7)
+            .filter_map(move |day| {
+                if forward {
+                    today.checked_add_days(Days::new(day))
+                } else {
+                    today.checked_sub_days(Days::new(day))
+                }
+            })
+            .filter(move |date| {
+                weekday.is_none_or(|weekday| date.weekday().num_days_from_sunday() == weekday)
+            })
+            .flat_map(move |date| {
+                hours
+                    .clone()
+                    .into_iter()
+                    .filter_map(move |hour| date.and_hms_opt(hour, minute, 0))
+            })
+            .filter_map(move |time: NaiveDateTime| offset.from_local_datetime(&time).single())
+    }
+}
+
+impl FromStr for PingSchedule {
+    type Err = InvalidScheduleError;
+
+    /// Parses a cron-like schedule: `minute hour day-of-month month day-of-week`.
+    ///
+    /// The hour and day of the week can be `*` for every hour or day,
+    /// the day of the month and month must be `*`.
+    /// `@hourly`, `@daily` and `@weekly` are accepted as well,
+    /// as is `@every <seconds>` for an interval.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidScheduleError(spec.into());
+        let schedule = match spec.trim() {
+            "@hourly" => PingSchedule::Calendar {
+                minute: 0,
+                hour: None,
+                weekday: None,
+            },
+            "@daily" => PingSchedule::daily(0, 0),
+            "@weekly" => PingSchedule::Calendar {
+                minute: 0,
+                hour: Some(0),
+                weekday: Some(0),
+            },
+            spec => {
+                if let Some(seconds) = spec.strip_prefix("@every ") {
+                    PingSchedule::Interval {
+                        seconds: seconds.trim().parse().map_err(|_| invalid())?,
+                    }
+                } else {
+                    let field = |field: &str| -> Result<Option<u32>, InvalidScheduleError> {
+                        match field {
+                            "*" => Ok(None),
+                            field => field.parse().map(Some).map_err(|_| invalid()),
+                        }
+                    };
+                    let fields: Vec<&str> = spec.split_whitespace().collect();
+                    let [minute, hour, "*", "*", weekday] = fields[..] else {
+                        return Err(invalid());
+                    };
+                    PingSchedule::Calendar {
+                        minute: field(minute)?.ok_or_else(invalid)?,
+                        hour: field(hour)?,
+                        // Both 0 and 7 are Sunday.
+                        weekday: field(weekday)?
+                            .map(|weekday| if weekday == 7 { 0 } else { weekday }),
+                    }
+                }
+            }
+        };
+        schedule.validate()?;
+        Ok(schedule)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date).unwrap()
+    }
+
+    #[test]
+    fn cron_like_schedules_are_parsed() {
+        assert_eq!(Ok(PingSchedule::daily(4, 30)), "30 4 * * *".parse());
+        assert_eq!(
+            Ok(PingSchedule::Calendar {
+                minute: 15,
+                hour: None,
+                weekday: None
+            }),
+            "15 * * * *".parse()
+        );
+        assert_eq!(
+            Ok(PingSchedule::Calendar {
+                minute: 0,
+                hour: Some(9),
+                weekday: Some(0)
+            }),
+            "0 9 * * 7".parse()
+        );
+        assert_eq!(
+            Ok(PingSchedule::Interval { seconds: 90 }),
+            "@every 90".parse()
+        );
+        assert_eq!(Ok(PingSchedule::daily(0, 0)), "@daily".parse());
+
+        for invalid in [
+            "",
+            "* * * * *",
+            "0 24 * * *",
+            "0 4 1 * *",
+            "@every 0",
+            "@every 31536001",
+            "@every 10000000000000000",
+            "@every 100000000000000000000",
+            "60 1 * * *",
+        ] {
+            assert!(invalid.parse::<PingSchedule>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn calendar_schedules_find_the_next_time() {
+        let daily = PingSchedule::daily(4, 0);
+        let now = at("2021-04-21T03:00:00+02:00");
+        assert_eq!(at("2021-04-21T04:00:00+02:00"), daily.next_after(now, None));
+        let now = at("2021-04-21T04:00:00+02:00");
+        assert_eq!(at("2021-04-22T04:00:00+02:00"), daily.next_after(now, None));
+
+        let hourly: PingSchedule = "30 * * * *".parse().unwrap();
+        let now = at("2021-04-21T23:45:00+00:00");
+        assert_eq!(
+            at("2021-04-22T00:30:00+00:00"),
+            hourly.next_after(now, None)
+        );
+
+        // 2021-04-21 is a Wednesday.
+        let weekly: PingSchedule = "0 9 * * 1".parse().unwrap();
+        let now = at("2021-04-21T12:00:00+00:00");
+        assert_eq!(
+            at("2021-04-26T09:00:00+00:00"),
+            weekly.next_after(now, None)
+        );
+    }
+
+    #[test]
+    fn interval_schedules_count_from_the_last_submission() {
+        let hourly = PingSchedule::Interval { seconds: 3600 };
+        let now = at("2021-04-21T12:00:00+00:00");
+        let last_sent = at("2021-04-21T11:30:00+00:00");
+        assert_eq!(
+            at("2021-04-21T12:30:00+00:00"),
+            hourly.next_after(now, Some(last_sent))
+        );
+        assert_eq!(
+            at("2021-04-21T13:00:00+00:00"),
+            hourly.next_after(now, None)
+        );
+
+        assert!(!hourly.is_overdue(now, Some(last_sent)));
+        assert!(hourly.is_overdue(now, Some(at("2021-04-21T11:00:00+00:00"))));
+        assert!(hourly.is_overdue(now, None));
+
+        let yearly = PingSchedule::Interval {
+            seconds: MAX_INTERVAL_SECONDS,
+        };
+        assert_eq!(Ok(()), yearly.validate());
+        assert_eq!(
+            at("2022-04-21T12:00:00+00:00"),
+            yearly.next_after(now, None)
+        );
+    }
+
+    #[test]
+    fn too_long_intervals_are_invalid_but_do_not_panic() {
+        let now = at("2021-04-21T12:00:00+00:00");
+        let schedule = PingSchedule::Interval {
+            seconds: MAX_INTERVAL_SECONDS + 1,
+        };
+        assert!(schedule.validate().is_err());
+
+        // Too long to be added to a time at all.
+        for seconds in [10_000_000_000_000_000, u64::MAX] {
+            let schedule = PingSchedule::Interval { seconds };
+            assert!(schedule.validate().is_err());
+            assert_eq!(now, schedule.next_after(now, None));
+        }
+    }
+
+    #[test]
+    fn calendar_schedules_are_overdue_if_a_time_was_missed() {
+        let daily = PingSchedule::daily(4, 0);
+        let now = at("2021-04-21T12:00:00+00:00");
+        assert!(!daily.is_overdue(now, Some(at("2021-04-21T04:00:00+00:00"))));
+        assert!(daily.is_overdue(now, Some(at("2021-04-21T03:59:00+00:00"))));
+        assert!(daily.is_overdue(now, Some(at("2021-04-20T12:00:00+00:00"))));
+
+        // Before today's time, yesterday's counts.
+        let now = at("2021-04-21T03:00:00+00:00");
+        assert!(!daily.is_overdue(now, Some(at("2021-04-20T05:00:00+00:00"))));
+        assert!(daily.is_overdue(now, Some(at("2021-04-20T03:00:00+00:00"))));
+    }
+}
@@ -7,6 +7,8 @@ use std::collections::HashMap;
 use malloc_size_of_derive::MallocSizeOf;
 use serde::{Deserialize, Serialize};
 
+use crate::MetricsPingSchedule;
+
 /// Represents a list of metrics and an associated boolean property
 /// indicating if the metric is enabled from the remote-settings
 /// configuration store.
@@ -51,6 +53,12 @@ pub struct RemoteSettingsConfig {
     /// It overrides the value configured at initialization time.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub events_ping_acceleration_factor: Option<usize>,
+
+    /// When to schedule the "metrics" ping.
+    /// It overrides the value configured at initialization time,
+    /// fields missing from it take their default value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics_ping_schedule: Option<MetricsPingSchedule>,
 }
 
 impl RemoteSettingsConfig {
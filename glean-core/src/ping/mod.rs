@@ -5,16 +5,18 @@
 //! Ping collection, assembly & submission.
 
 use std::fs::{self, create_dir_all, File};
-use std::io::{BufRead, BufReader, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use log::info;
 use serde_json::{json, Value as JsonValue};
 
 use crate::common_metric_data::{CommonMetricData, Lifetime};
-use crate::metrics::{CounterMetric, DatetimeMetric, Metric, MetricType, PingType, TimeUnit};
+use crate::metrics::{
+    CounterMetric, DatetimeMetric, Metric, MetricType, PingPriority, PingType, TimeUnit,
+};
 use crate::storage::{StorageManager, INTERNAL_STORAGE};
-use crate::upload::{HeaderMap, PingMetadata};
+use crate::upload::{read_ping_file, HeaderMap, PingFileContents, PingMetadata};
 use crate::util::{get_iso_time_string, local_now_with_offset};
 use crate::{Glean, Result, DELETION_REQUEST_PINGS_DIRECTORY, PENDING_PINGS_DIRECTORY};
 
@@ -36,6 +38,8 @@ pub struct Ping<'a> {
     pub schedules_pings: Vec<String>,
     /// Capabilities the uploader must have in order to uplaoad this ping.
     pub uploader_capabilities: Vec<String>,
+    /// The priority to upload this ping with.
+    pub priority: PingPriority,
 }
 
 /// Collect a ping's data, assemble it into its full payload and store it on disk.
@@ -54,6 +58,106 @@ fn merge(a: &mut JsonValue, b: &JsonValue) {
     }
 }
 
+/// Adds the metrics linking a part of a split ping back to the ping it was split from.
+///
+/// These are added the most manual way, like `glean.ping.uploader_capabilities`,
+/// as they only exist in the payload and are never stored.
+fn link_part(content: &mut JsonValue, parent_id: &str, part: usize, parts: usize) {
+    let metrics = &mut content["metrics"];
+    metrics["uuid"]["glean.ping.split_parent_id"] = json!(parent_id);
+    metrics["quantity"]["glean.ping.split_part"] = json!(part);
+    metrics["quantity"]["glean.ping.split_parts"] = json!(parts);
+}
+
+/// Splits a ping payload in halves until every part fits, collecting the parts in order.
+///
+/// Parts that cannot be split any further are kept as they are.
+fn split_to_fit(
+    content: JsonValue,
+    fits: &impl Fn(&JsonValue) -> bool,
+    parts: &mut Vec<JsonValue>,
+) {
+    if fits(&content) {
+        parts.push(content);
+        return;
+    }
+
+    match split_in_two(&content) {
+        Some((first, second)) => {
+            split_to_fit(first, fits, parts);
+            split_to_fit(second, fits, parts);
+        }
+        None => parts.push(content),
+    }
+}
+
+/// Splits a ping payload in two halves that both keep the `*_info` sections.
+///
+/// With more than one event, the events are split and the metrics stay with the first half.
+/// Otherwise the metrics are split, counting every label of a labeled metric separately.
+/// Returns `None` if there is nothing left to split.
+fn split_in_two(content: &JsonValue) -> Option<(JsonValue, JsonValue)> {
+    let without_data = || -> JsonValue {
+        let mut content = content.clone();
+        if let Some(content) = content.as_object_mut() {
+            content.remove("metrics");
+            content.remove("events");
+        }
+        content
+    };
+
+    if let Some(events) = content["events"].as_array().filter(|e| e.len() > 1) {
+        let (first_events, second_events) = events.split_at(events.len() / 2);
+        let mut first = content.clone();
+        first["events"] = json!(first_events);
+        let mut second = without_data();
+        second["events"] = json!(second_events);
+        return Some((first, second));
+    }
+
+    let mut entries = Vec::new();
+    for (metric_type, metrics) in content["metrics"].as_object()? {
+        for (name, value) in metrics.as_object()? {
+            match value
+                .as_object()
+                .filter(|_| metric_type.contains("labeled_"))
+            {
+                Some(labels) => entries.extend(
+                    labels
+                        .iter()
+                        .map(|(label, value)| (metric_type, name, Some(label), value)),
+                ),
+                None => entries.push((metric_type, name, None, value)),
+            }
+        }
+    }
+    if entries.len() < 2 {
+        return None;
+    }
+
+    let metrics_from = |entries: &[(&String, &String, Option<&String>, &JsonValue)]| {
+        let mut metrics = json!({});
+        for &(metric_type, name, label, value) in entries {
+            let metric = &mut metrics[metric_type][name];
+            match label {
+                Some(label) => metric[label] = value.clone(),
+                None => *metric = value.clone(),
+            }
+        }
+        metrics
+    };
+
+    let (first_entries, second_entries) = entries.split_at(entries.len() / 2);
+    let mut first = without_data();
+    first["metrics"] = metrics_from(first_entries);
+    if let Some(events) = content.get("events") {
+        first["events"] = events.clone();
+    }
+    let mut second = without_data();
+    second["metrics"] = metrics_from(second_entries);
+    Some((first, second))
+}
+
 impl Default for PingMaker {
     fn default() -> Self {
         Self::new()
@@ -66,8 +170,8 @@ impl PingMaker {
         Self
     }
 
-    /// Gets, and then increments, the sequence number for a given ping.
-    fn get_ping_seq(&self, glean: &Glean, storage_name: &str) -> usize {
+    /// Gets, and then increments unless previewing, the sequence number for a given ping.
+    fn get_ping_seq(&self, glean: &Glean, storage_name: &str, advance: bool) -> usize {
         // Don't attempt to increase sequence number for disabled ping
         if !glean.is_ping_enabled(storage_name) {
             return 0;
@@ -89,17 +193,20 @@ impl PingMaker {
         };
 
         // Increase to next sequence id
-        seq.add_sync(glean, 1);
+        if advance {
+            seq.add_sync(glean, 1);
+        }
 
         current_seq as usize
     }
 
-    /// Gets the formatted start and end times for this ping and update for the next ping.
+    /// Gets the formatted start and end times for this ping and, unless previewing, update for the next ping.
     fn get_start_end_times(
         &self,
         glean: &Glean,
         storage_name: &str,
         time_unit: TimeUnit,
+        advance: bool,
     ) -> (String, String) {
         let start_time = DatetimeMetric::new(
             CommonMetricData {
@@ -120,7 +227,9 @@ impl PingMaker {
         let end_time_data = local_now_with_offset();
 
         // Update the start time with the current time.
-        start_time.set_sync_chrono(glean, end_time_data);
+        if advance {
+            start_time.set_sync_chrono(glean, end_time_data);
+        }
 
         // Format the times.
         let start_time_data = get_iso_time_string(start_time_data, time_unit);
@@ -134,10 +243,12 @@ impl PingMaker {
         storage_name: &str,
         reason: Option<&str>,
         precision: TimeUnit,
+        advance: bool,
     ) -> JsonValue {
-        let (start_time, end_time) = self.get_start_end_times(glean, storage_name, precision);
+        let (start_time, end_time) =
+            self.get_start_end_times(glean, storage_name, precision, advance);
         let mut map = json!({
-            "seq": self.get_ping_seq(glean, storage_name),
+            "seq": self.get_ping_seq(glean, storage_name, advance),
             "start_time": start_time,
             "end_time": end_time,
         });
@@ -277,13 +388,70 @@ impl PingMaker {
         url_path: &'a str,
     ) -> Option<Ping<'a>> {
         info!("Collecting {}", ping.name());
+        let content = self.assemble(glean, ping, reason, true)?;
+
+        Some(Ping {
+            content,
+            name: ping.name(),
+            doc_id,
+            url_path,
+            headers: self.get_headers(glean),
+            includes_info_sections: ping.include_info_sections(),
+            schedules_pings: ping.schedules_pings().to_vec(),
+            uploader_capabilities: ping.uploader_capabilities().to_vec(),
+            priority: ping.priority(),
+        })
+    }
+
+    /// Assembles the payload the given ping would have if it was submitted now,
+    /// without changing any stored data.
+    ///
+    /// Neither the ping's data is cleared nor its sequence number and start time are advanced,
+    /// so the next submitted ping contains everything shown here.
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - the [`Glean`] instance to collect data from.
+    /// * `ping` - the ping to preview.
+    /// * `reason` - an optional reason code to include in the ping.
+    ///
+    /// # Returns
+    ///
+    /// The full ping payload, including the `*_info` sections.
+    /// If there is no data stored for the ping and it wouldn't be sent empty, `None` is returned.
+    pub fn preview(
+        &self,
+        glean: &Glean,
+        ping: &PingType,
+        reason: Option<&str>,
+    ) -> Option<JsonValue> {
+        info!("Previewing {}", ping.name());
+        self.assemble(glean, ping, reason, false)
+    }
+
+    /// Assembles a ping's payload from storage, clearing its data if `advance` is set.
+    fn assemble(
+        &self,
+        glean: &Glean,
+        ping: &PingType,
+        reason: Option<&str>,
+        advance: bool,
+    ) -> Option<JsonValue> {
         let database = glean.storage();
 
-        let mut metrics_data = StorageManager.snapshot_as_json(database, ping.name(), true);
+        // Collecting a ping is a natural checkpoint for buffered ping-lifetime data.
+        if let Err(e) = database.persist_ping_lifetime_data(glean) {
+            log::warn!("Could not persist ping lifetime data: {:?}", e);
+        }
 
-        let events_data = glean
-            .event_storage()
-            .snapshot_as_json(glean, ping.name(), true);
+        // Metrics and events are taken together, so that concurrent recordings,
+        // e.g. from helper processes, don't end up in this ping only partially.
+        let (mut metrics_data, events) =
+            StorageManager.snapshot_as_json_with_events(database, ping.name(), advance);
+        let events_data =
+            glean
+                .event_storage()
+                .snapshot_taken_as_json(glean, ping.name(), &events, advance);
 
         // We're adding the metric `glean.ping.uploader_capabilities` the most manual way here.
         // This avoids creating a `StringListMetric` and further indirection.
@@ -361,7 +529,7 @@ impl PingMaker {
         };
 
         let mut json = if ping.include_info_sections() {
-            let ping_info = self.get_ping_info(glean, ping.name(), reason, precision);
+            let ping_info = self.get_ping_info(glean, ping.name(), reason, precision, advance);
             let client_info = self.get_client_info(glean, ping.include_client_id());
 
             json!({
@@ -380,16 +548,66 @@ impl PingMaker {
             json_obj.insert("events".to_string(), events_data);
         }
 
-        Some(Ping {
-            content: json,
-            name: ping.name(),
-            doc_id,
-            url_path,
-            headers: self.get_headers(glean),
-            includes_info_sections: ping.include_info_sections(),
-            schedules_pings: ping.schedules_pings().to_vec(),
-            uploader_capabilities: ping.uploader_capabilities().to_vec(),
-        })
+        Some(json)
+    }
+
+    /// Splits a ping's payload into several payloads that each fit into the maximum ping body size.
+    ///
+    /// The `events` are split first, then the metrics,
+    /// counting every label of a labeled metric separately.
+    /// Each part repeats the `*_info` sections and links back to the ping it was split from
+    /// through the `glean.ping.split_parent_id`, `glean.ping.split_part` (starting at 1)
+    /// and `glean.ping.split_parts` metrics, which the ping schema already allows.
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - the [`Glean`] instance whose upload limits apply.
+    /// * `ping` - the collected ping.
+    ///
+    /// # Returns
+    ///
+    /// The payloads of the parts, in order.
+    /// `None` if the ping fits already, cannot be split,
+    /// or doesn't include the `*_info` sections and thus isn't a regular Glean ping.
+    pub fn split(&self, glean: &Glean, ping: &Ping) -> Option<Vec<JsonValue>> {
+        let fits = |content: &JsonValue| {
+            // `content` is already a `JsonValue`, so serializing it cannot fail.
+            let body = ::serde_json::to_string(content).expect("ping serialization failed");
+            !glean
+                .upload_manager
+                .exceeds_max_ping_body_size(ping.name, &body)
+        };
+        // Parts need to fit with the linkage metrics added, which are never larger than this.
+        let part_fits = |content: &JsonValue| {
+            let mut content = content.clone();
+            link_part(&mut content, ping.doc_id, usize::MAX, usize::MAX);
+            fits(&content)
+        };
+
+        if !ping.includes_info_sections || fits(&ping.content) {
+            return None;
+        }
+
+        let mut parts = Vec::new();
+        split_to_fit(ping.content.clone(), &part_fits, &mut parts);
+        if parts.len() < 2 {
+            log::warn!(
+                "The ping '{}' exceeds the maximum body size and cannot be split",
+                ping.name
+            );
+            return None;
+        }
+
+        let count = parts.len();
+        for (index, part) in parts.iter_mut().enumerate() {
+            link_part(part, ping.doc_id, index + 1, count);
+        }
+
+        info!(
+            "The ping '{}' exceeds the maximum body size and was split into {} parts",
+            ping.name, count
+        );
+        Some(parts)
     }
 
     /// Gets the path to a directory for ping storage.
@@ -397,16 +615,20 @@ impl PingMaker {
     /// The directory will be created inside the `data_path`.
     /// The `pings` directory (and its parents) is created if it does not exist.
     fn get_pings_dir(&self, data_path: &Path, ping_type: Option<&str>) -> std::io::Result<PathBuf> {
-        // Use a special directory for deletion-request pings
-        let pings_dir = match ping_type {
-            Some("deletion-request") => data_path.join(DELETION_REQUEST_PINGS_DIRECTORY),
-            _ => data_path.join(PENDING_PINGS_DIRECTORY),
-        };
-
+        let pings_dir = data_path.join(Self::pings_dir_name(ping_type));
         create_dir_all(&pings_dir)?;
         Ok(pings_dir)
     }
 
+    /// Gets the name of the directory for ping storage.
+    fn pings_dir_name(ping_type: Option<&str>) -> &'static str {
+        // Use a special directory for deletion-request pings
+        match ping_type {
+            Some("deletion-request") => DELETION_REQUEST_PINGS_DIRECTORY,
+            _ => PENDING_PINGS_DIRECTORY,
+        }
+    }
+
     /// Gets path to a directory for temporary storage.
     ///
     /// The directory will be created inside the `data_path`.
@@ -418,7 +640,22 @@ impl PingMaker {
     }
 
     /// Stores a ping to disk in the pings directory.
-    pub fn store_ping(&self, data_path: &Path, ping: &Ping) -> std::io::Result<()> {
+    ///
+    /// The ping file is encrypted if encryption at rest is enabled.
+    /// If Glean keeps its data in memory, the ping file is kept in memory as well.
+    pub fn store_ping(&self, glean: &Glean, ping: &Ping) -> std::io::Result<()> {
+        let contents = self.ping_file_contents(glean, ping)?;
+        if glean.is_in_memory() {
+            log::debug!("Storing ping '{}' in memory", ping.doc_id);
+            glean.upload_manager.store_ping_in_memory(
+                Self::pings_dir_name(Some(ping.name)),
+                ping.doc_id,
+                contents,
+            );
+            return Ok(());
+        }
+
+        let data_path = glean.get_data_path();
         let pings_dir = self.get_pings_dir(data_path, Some(ping.name))?;
         let temp_dir = self.get_tmp_dir(data_path)?;
 
@@ -435,21 +672,7 @@ impl PingMaker {
 
         {
             let mut file = File::create(&temp_ping_path)?;
-            file.write_all(ping.url_path.as_bytes())?;
-            file.write_all(b"\n")?;
-            file.write_all(::serde_json::to_string(&ping.content)?.as_bytes())?;
-            file.write_all(b"\n")?;
-            let metadata = PingMetadata {
-                // We don't actually need to clone the headers except to match PingMetadata's ownership.
-                // But since we're going to write a file to disk in a sec,
-                // and HeaderMaps tend to have only like two things in them, tops,
-                // the cost is bearable.
-                headers: Some(ping.headers.clone()),
-                body_has_info_sections: Some(ping.includes_info_sections),
-                ping_name: Some(ping.name.to_string()),
-                uploader_capabilities: Some(ping.uploader_capabilities.clone()),
-            };
-            file.write_all(::serde_json::to_string(&metadata)?.as_bytes())?;
+            file.write_all(&contents)?;
         }
 
         if let Err(e) = std::fs::rename(&temp_ping_path, &ping_path) {
@@ -464,12 +687,43 @@ impl PingMaker {
         Ok(())
     }
 
+    /// Serializes a ping into the contents of its ping file, encrypted if necessary.
+    fn ping_file_contents(&self, glean: &Glean, ping: &Ping) -> std::io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        contents.write_all(ping.url_path.as_bytes())?;
+        contents.write_all(b"\n")?;
+        contents.write_all(::serde_json::to_string(&ping.content)?.as_bytes())?;
+        contents.write_all(b"\n")?;
+        let metadata = PingMetadata {
+            // We don't actually need to clone the headers except to match PingMetadata's ownership.
+            // But since we're going to write a file to disk in a sec,
+            // and HeaderMaps tend to have only like two things in them, tops,
+            // the cost is bearable.
+            headers: Some(ping.headers.clone()),
+            body_has_info_sections: Some(ping.includes_info_sections),
+            ping_name: Some(ping.name.to_string()),
+            uploader_capabilities: Some(ping.uploader_capabilities.clone()),
+            // Pings of the default priority are stored as they were before priorities existed.
+            priority: (ping.priority != PingPriority::default()).then_some(ping.priority),
+        };
+        contents.write_all(::serde_json::to_string(&metadata)?.as_bytes())?;
+        if let Some(cipher) = glean.cipher() {
+            contents = cipher.encrypt(&contents);
+        }
+        Ok(contents)
+    }
+
     /// Clears any pending pings in the queue.
-    pub fn clear_pending_pings(&self, data_path: &Path, ping_names: &[&str]) -> Result<()> {
-        let pings_dir = self.get_pings_dir(data_path, None)?;
+    pub fn clear_pending_pings(&self, glean: &Glean, ping_names: &[&str]) -> Result<()> {
+        if glean.is_in_memory() {
+            glean
+                .upload_manager
+                .delete_pending_pings_in_memory(ping_names);
+            return Ok(());
+        }
+
+        let pings_dir = self.get_pings_dir(glean.get_data_path(), None)?;
 
-        // TODO(bug 1932909): Refactor this into its own function
-        // and share it with `upload::directory`.
         let entries = pings_dir.read_dir()?;
         for entry in entries.filter_map(|entry| entry.ok()) {
             if let Ok(file_type) = entry.file_type() {
@@ -480,16 +734,8 @@ impl PingMaker {
                 continue;
             }
 
-            let file = match File::open(entry.path()) {
-                Ok(file) => file,
-                Err(_) => {
-                    continue;
-                }
-            };
-
-            let mut lines = BufReader::new(file).lines();
-            if let (Some(Ok(path)), Some(Ok(_body)), Ok(metadata)) =
-                (lines.next(), lines.next(), lines.next().transpose())
+            if let Ok(Some(PingFileContents { path, metadata, .. })) =
+                read_ping_file(&entry.path(), glean.cipher())
             {
                 let PingMetadata { ping_name, .. } = metadata
                     .and_then(|m| crate::upload::process_metadata(&path, &m))
@@ -521,16 +767,51 @@ mod test {
         let (mut glean, _t) = new_glean(None);
         let ping_maker = PingMaker::new();
 
-        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1"));
-        assert_eq!(1, ping_maker.get_ping_seq(&glean, "store1"));
+        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1", true));
+        assert_eq!(1, ping_maker.get_ping_seq(&glean, "store1", true));
 
         glean.set_upload_enabled(false);
-        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1"));
-        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1"));
+        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1", true));
+        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1", true));
 
         glean.set_upload_enabled(true);
-        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1"));
-        assert_eq!(1, ping_maker.get_ping_seq(&glean, "store1"));
+        assert_eq!(0, ping_maker.get_ping_seq(&glean, "store1", true));
+        assert_eq!(1, ping_maker.get_ping_seq(&glean, "store1", true));
+    }
+
+    #[test]
+    fn splitting_a_payload_splits_labeled_metrics_by_label() {
+        let content = json!({
+            "ping_info": { "seq": 0 },
+            "metrics": {
+                "counter": { "test.counter": 1 },
+                "labeled_counter": { "test.labeled": { "a": 1, "b": 2, "c": 3 } }
+            },
+            "events": [{ "timestamp": 0 }]
+        });
+
+        let (first, second) = split_in_two(&content).unwrap();
+        assert_eq!(
+            json!({
+                "ping_info": { "seq": 0 },
+                "metrics": {
+                    "counter": { "test.counter": 1 },
+                    "labeled_counter": { "test.labeled": { "a": 1 } }
+                },
+                "events": [{ "timestamp": 0 }]
+            }),
+            first
+        );
+        assert_eq!(
+            json!({
+                "ping_info": { "seq": 0 },
+                "metrics": { "labeled_counter": { "test.labeled": { "b": 2, "c": 3 } } }
+            }),
+            second
+        );
+
+        let single = json!({ "ping_info": {}, "metrics": { "counter": { "test.counter": 1 } } });
+        assert!(split_in_two(&single).is_none());
     }
 
     #[test]
@@ -553,18 +834,23 @@ mod test {
             event_threshold: Some(41),
             session_sample_rate: None,
             events_ping_acceleration_factor: Some(5),
+            metrics_ping_schedule: Some(crate::MetricsPingSchedule {
+                hour: 2,
+                ..Default::default()
+            }),
         };
         glean.apply_server_knobs_config(config);
 
         // Verify complete config structure appears in ping_info
         let ping_maker = PingMaker::new();
-        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute);
+        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute, true);
 
         let server_knobs = &ping_info["server_knobs_config"];
         assert_eq!(server_knobs["metrics_enabled"]["test.counter"], true);
         assert_eq!(server_knobs["pings_enabled"]["custom"], false);
         assert_eq!(server_knobs["event_threshold"], 41);
         assert_eq!(server_knobs["events_ping_acceleration_factor"], 5);
+        assert_eq!(server_knobs["metrics_ping_schedule"]["hour"], 2);
     }
 
     #[test]
@@ -572,7 +858,7 @@ mod test {
         let (glean, _t) = new_glean(None);
 
         let ping_maker = PingMaker::new();
-        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute);
+        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute, true);
 
         assert!(ping_info.get("server_knobs_config").is_none());
     }
@@ -595,8 +881,8 @@ mod test {
 
         // Verify config appears in multiple different pings
         let ping_maker = PingMaker::new();
-        let ping_info1 = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute);
-        let ping_info2 = ping_maker.get_ping_info(&glean, "store2", None, TimeUnit::Minute);
+        let ping_info1 = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute, true);
+        let ping_info2 = ping_maker.get_ping_info(&glean, "store2", None, TimeUnit::Minute, true);
 
         assert_eq!(
             ping_info1["server_knobs_config"]["metrics_enabled"]["test.counter"],
@@ -626,7 +912,7 @@ mod test {
         glean.apply_server_knobs_config(config);
 
         let ping_maker = PingMaker::new();
-        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute);
+        let ping_info = ping_maker.get_ping_info(&glean, "store1", None, TimeUnit::Minute, true);
 
         let server_knobs = &ping_info["server_knobs_config"];
         // metrics_enabled should be present
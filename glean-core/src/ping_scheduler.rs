@@ -0,0 +1,285 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Ping Scheduler
+//!
+//! Submits pings on the schedule set with [`PingType::set_schedule`](crate::metrics::PingType::set_schedule).
+//! Every scheduled ping waits for its next scheduled time on a thread of its own,
+//! see `task_scheduler.rs`, and a ping that became overdue while the application
+//! wasn't running is submitted right away when its schedule is set.
+//!
+//! Scheduled pings are submitted with one of these reasons:
+//!
+//! * `overdue`: A scheduled time passed since the ping was last submitted by the scheduler.
+//! * `today`: The first scheduled time after setting the schedule, on the same day.
+//! * `tomorrow`: The first scheduled time after setting the schedule, on a later day.
+//! * `reschedule`: Any further scheduled time.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset};
+use once_cell::sync::Lazy;
+
+use crate::metrics::{DatetimeMetric, PingSchedule, TimeUnit};
+use crate::storage::INTERNAL_STORAGE;
+use crate::task_scheduler::{
+    GleanScheduledPingSubmitter, ScheduledPingSubmitter, ScheduledTask, TaskScheduler, When,
+};
+use crate::util::local_now_with_offset;
+use crate::{CommonMetricData, Glean, Lifetime};
+
+static TASKS: Lazy<TaskScheduler> = Lazy::new(|| TaskScheduler::new("glean.ping_scheduler"));
+
+/// Sets the schedule of a ping, replacing any previous schedule.
+///
+/// If the ping is overdue, it is submitted immediately.
+///
+/// # Arguments
+///
+/// * `glean` - The Glean instance.
+/// * `ping_name` - The name of the ping.
+/// * `schedule` - The schedule, `None` to stop scheduling the ping.
+///
+/// # Returns
+///
+/// Whether an overdue ping was submitted.
+pub fn schedule(glean: &Glean, ping_name: &str, schedule: Option<PingSchedule>) -> bool {
+    let Some(schedule) = schedule else {
+        TASKS.stop(ping_name);
+        return false;
+    };
+
+    let submitter = GleanScheduledPingSubmitter {
+        ping_name: ping_name.into(),
+        last_sent_time: get_last_sent_time_metric(ping_name),
+    };
+    let now = local_now_with_offset();
+
+    let (submitted, due, when) = first_submission(glean, &submitter, ping_name, &schedule, now);
+    let task = PingTask {
+        submitter,
+        schedule,
+        now,
+        due,
+        when,
+    };
+    TASKS.start(ping_name, task);
+    submitted
+}
+
+/// Tells all scheduler tasks to exit quickly and cleanly.
+///
+/// Pings need to be scheduled again afterwards.
+pub fn cancel() {
+    TASKS.cancel();
+}
+
+/// Submits a ping if it is overdue, and decides when it is due next.
+///
+/// # Returns
+///
+/// Whether an overdue ping was submitted,
+/// and when and why the ping is submitted next.
+fn first_submission(
+    glean: &Glean,
+    submitter: &impl ScheduledPingSubmitter,
+    ping_name: &str,
+    schedule: &PingSchedule,
+    now: DateTime<FixedOffset>,
+) -> (bool, DateTime<FixedOffset>, When) {
+    let last_sent = get_last_sent_time_metric(ping_name).get_value(glean, INTERNAL_STORAGE);
+    if let Some(last_sent) = last_sent {
+        log::info!("The '{}' ping was last sent on {}", ping_name, last_sent);
+    }
+
+    if schedule.is_overdue(now, last_sent) {
+        log::info!(
+            "Sending the overdue '{}' ping immediately, {}",
+            ping_name,
+            now
+        );
+        let submitted = submitter.submit_scheduled_ping(glean, Some("overdue"), now);
+        (
+            submitted,
+            schedule.next_after(now, Some(now)),
+            When::Reschedule,
+        )
+    } else {
+        let due = schedule.next_after(now, last_sent);
+        let when = if due.date_naive() == now.date_naive() {
+            When::Today
+        } else {
+            When::Tomorrow
+        };
+        log::info!("The '{}' ping is scheduled for {}", ping_name, due);
+        (false, due, when)
+    }
+}
+
+/// The submissions of a scheduled ping.
+struct PingTask {
+    submitter: GleanScheduledPingSubmitter,
+    schedule: PingSchedule,
+    now: DateTime<FixedOffset>,
+    due: DateTime<FixedOffset>,
+    when: When,
+}
+
+impl ScheduledTask for PingTask {
+    fn until_due(&mut self) -> Duration {
+        // If the due time passed already, submit immediately.
+        let dur = (self.due - self.now).to_std().unwrap_or_default();
+        log::info!(
+            "Scheduling the '{}' ping for {} after {:?}, reason {}",
+            self.submitter.ping_name,
+            self.now,
+            dur,
+            self.when.reason()
+        );
+        dur
+    }
+
+    fn run(&mut self, glean: &Mutex<Glean>) {
+        // Don't submit twice for the same scheduled time if the clock lags behind.
+        self.now = local_now_with_offset().max(self.due);
+        log::info!(
+            "Time to submit the '{}' ping, {}",
+            self.submitter.ping_name,
+            self.when.reason()
+        );
+        let submitted = self.submitter.submit_scheduled_ping(
+            &glean.lock().unwrap(),
+            Some(self.when.reason()),
+            self.now,
+        );
+        if submitted {
+            if let Some(state) = crate::maybe_global_state() {
+                if let Err(e) = state.lock().unwrap().callbacks.trigger_upload() {
+                    log::error!("Triggering upload failed. Error: {}", e);
+                }
+            }
+        }
+
+        self.due = self.schedule.next_after(self.now, Some(self.now));
+        self.when = When::Reschedule;
+    }
+}
+fn get_last_sent_time_metric(ping_name: &str) -> DatetimeMetric {
+    DatetimeMetric::new(
+        CommonMetricData {
+            name: format!("{ping_name}_last_sent_time"),
+            category: "ping_scheduler".into(),
+            send_in_pings: vec![INTERNAL_STORAGE.into()],
+            lifetime: Lifetime::User,
+            ..Default::default()
+        },
+        TimeUnit::Second,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tests::new_glean;
+    use std::cell::Cell;
+
+    struct ValidatingSubmitter<F: Fn(&str)> {
+        submit_validator: F,
+        validator_run_count: Cell<u32>,
+    }
+    impl<F: Fn(&str)> ScheduledPingSubmitter for ValidatingSubmitter<F> {
+        fn submit_scheduled_ping(
+            &self,
+            _glean: &Glean,
+            reason: Option<&str>,
+            _now: DateTime<FixedOffset>,
+        ) -> bool {
+            (self.submit_validator)(reason.unwrap());
+            self.validator_run_count
+                .set(self.validator_run_count.get() + 1);
+            true
+        }
+    }
+
+    fn new_submitter<F: Fn(&str)>(submit_validator: F) -> ValidatingSubmitter<F> {
+        ValidatingSubmitter {
+            submit_validator,
+            validator_run_count: Cell::new(0),
+        }
+    }
+
+    fn at(date: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(date).unwrap()
+    }
+
+    #[test]
+    fn never_sent_ping_is_submitted_and_rescheduled() {
+        let (glean, _t) = new_glean(None);
+        let now = at("2021-04-21T12:00:00+00:00");
+
+        let submitter = new_submitter(|reason| assert_eq!("overdue", reason));
+        let hourly = PingSchedule::Interval { seconds: 3600 };
+        assert_eq!(
+            (true, at("2021-04-21T13:00:00+00:00"), When::Reschedule),
+            first_submission(&glean, &submitter, "never_sent", &hourly, now)
+        );
+        assert_eq!(1, submitter.validator_run_count.get());
+    }
+
+    #[test]
+    fn ping_is_scheduled_for_today() {
+        let (glean, _t) = new_glean(None);
+        get_last_sent_time_metric("today").set_sync_chrono(&glean, at("2021-04-20T05:00:00+02:00"));
+        let now = at("2021-04-21T03:00:00+02:00");
+
+        let submitter = new_submitter(|_| panic!("Not overdue, shouldn't submit a ping!"));
+        let daily = PingSchedule::daily(4, 0);
+        assert_eq!(
+            (false, at("2021-04-21T04:00:00+02:00"), When::Today),
+            first_submission(&glean, &submitter, "today", &daily, now)
+        );
+    }
+
+    #[test]
+    fn ping_sent_today_is_scheduled_for_tomorrow() {
+        let (glean, _t) = new_glean(None);
+        get_last_sent_time_metric("tomorrow")
+            .set_sync_chrono(&glean, at("2021-04-21T04:00:00+02:00"));
+        let now = at("2021-04-21T12:00:00+02:00");
+
+        let submitter = new_submitter(|_| panic!("Already sent today, shouldn't submit a ping!"));
+        let daily = PingSchedule::daily(4, 0);
+        assert_eq!(
+            (false, at("2021-04-22T04:00:00+02:00"), When::Tomorrow),
+            first_submission(&glean, &submitter, "tomorrow", &daily, now)
+        );
+    }
+
+    #[test]
+    fn submitted_pings_are_due_at_their_next_scheduled_time() {
+        let (glean, _t) = new_glean(None);
+        let mut task = PingTask {
+            submitter: GleanScheduledPingSubmitter {
+                ping_name: "store1".into(),
+                last_sent_time: get_last_sent_time_metric("store1"),
+            },
+            schedule: PingSchedule::Interval { seconds: 3600 },
+            now: at("2021-04-21T12:00:00+00:00"),
+            due: at("2021-04-21T11:00:00+00:00"),
+            when: When::Today,
+        };
+        // Overdue tasks run right away.
+        assert_eq!(Duration::ZERO, task.until_due());
+
+        let glean = Mutex::new(glean);
+        task.run(&glean);
+        let glean = glean.into_inner().unwrap();
+        assert!(get_last_sent_time_metric("store1")
+            .get_value(&glean, INTERNAL_STORAGE)
+            .is_some());
+        assert_eq!(When::Reschedule, task.when);
+        assert_eq!(chrono::TimeDelta::hours(1), task.due - task.now);
+    }
+}
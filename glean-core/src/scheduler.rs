@@ -7,33 +7,44 @@
 //! The Metrics Ping Scheduler (MPS) is responsible for scheduling "metrics" pings.
 //! It implements the spec described in
 //! [the docs](https://mozilla.github.io/glean/book/user/pings/metrics.html#scheduling)
+//!
+//! When the ping is scheduled is configurable through [`MetricsPingSchedule`].
 
 use crate::metrics::{DatetimeMetric, StringMetric, TimeUnit};
+use crate::session::uuid_to_sample_value;
 use crate::storage::INTERNAL_STORAGE;
+use crate::task_scheduler::{
+    GleanScheduledPingSubmitter, ScheduledPingSubmitter, ScheduledTask, TaskScheduler, When,
+};
 use crate::util::local_now_with_offset;
-use crate::{CommonMetricData, Glean, Lifetime};
+use crate::{CommonMetricData, Glean, Lifetime, MetricsPingSchedule};
 use chrono::prelude::*;
-use chrono::Days;
 use once_cell::sync::Lazy;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::Mutex;
 use std::thread::JoinHandle;
-
-const SCHEDULED_HOUR: u32 = 4;
-
-// Clippy thinks an AtomicBool would be preferred, but Condvar requires a full Mutex.
-// See https://github.com/rust-lang/rust-clippy/issues/1516
-#[allow(clippy::mutex_atomic)]
-static TASK_CONDVAR: Lazy<Arc<(Mutex<bool>, Condvar)>> =
-    Lazy::new(|| Arc::new((Mutex::new(false), Condvar::new())));
-
-/// Describes the interface for a submitter of "metrics" pings.
-/// Used to decouple the implementation so we can test it.
-trait MetricsPingSubmitter {
-    /// Submits a metrics ping, updating the last sent time to `now`
-    /// (which might not be _right now_ due to processing delays (or in tests))
-    fn submit_metrics_ping(&self, glean: &Glean, reason: Option<&str>, now: DateTime<FixedOffset>);
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The name of the ping the Metrics Ping Scheduler submits.
+const METRICS_PING: &str = "metrics";
+
+/// The hour of the day the "metrics" ping is scheduled at by default.
+pub(crate) const DEFAULT_SCHEDULED_HOUR: u32 = 4;
+
+/// The longest window to spread the "metrics" ping's submissions at `hour` over.
+///
+/// Submissions must stay on the day they are due: the next one is scheduled
+/// for the day after the actual submission, so crossing midnight would skip a day.
+///
+/// # Arguments
+///
+/// * `hour` - The hour of the day the "metrics" ping is scheduled at, at most 23.
+pub(crate) fn max_spread_seconds(hour: u32) -> u32 {
+    24 * 60 * 60 - 1 - hour.min(23) * 60 * 60
 }
 
+static TASKS: Lazy<TaskScheduler> = Lazy::new(|| TaskScheduler::new("glean.mps"));
+
 /// Describes the interface for a scheduler of "metrics" pings.
 /// Used to decouple the implementation so we can test it.
 trait MetricsPingScheduler {
@@ -42,65 +53,86 @@ trait MetricsPingScheduler {
     /// may not be _right now_ due to processing delays (or in tests).
     fn start_scheduler(
         &self,
-        submitter: impl MetricsPingSubmitter + Send + 'static,
+        submitter: impl ScheduledPingSubmitter + Send + 'static,
         now: DateTime<FixedOffset>,
         when: When,
     );
 }
 
-/// Uses Glean to submit "metrics" pings directly.
-struct GleanMetricsPingSubmitter {}
-impl MetricsPingSubmitter for GleanMetricsPingSubmitter {
-    fn submit_metrics_ping(&self, glean: &Glean, reason: Option<&str>, now: DateTime<FixedOffset>) {
-        glean.submit_ping_by_name("metrics", reason);
-        // Always update the collection date, irrespective of the ping being sent.
-        get_last_sent_time_metric().set_sync_chrono(glean, now);
-    }
-}
-
 /// Schedule "metrics" pings directly using the default behaviour.
-struct GleanMetricsPingScheduler {}
+struct GleanMetricsPingScheduler {
+    schedule: MetricsPingSchedule,
+}
 impl MetricsPingScheduler for GleanMetricsPingScheduler {
     fn start_scheduler(
         &self,
-        submitter: impl MetricsPingSubmitter + Send + 'static,
+        submitter: impl ScheduledPingSubmitter + Send + 'static,
         now: DateTime<FixedOffset>,
         when: When,
     ) {
-        start_scheduler(submitter, now, when);
+        start_scheduler(submitter, now, when, self.schedule.clone());
     }
 }
 
 /// Performs startup checks to decide when to schedule the next "metrics" ping collection.
 /// **Must** be called before draining the preinit queue.
 /// (We're at the Language Bindings' mercy for that)
+///
+/// Replaces the scheduler task of a previous call, so this also applies
+/// a changed "metrics" ping schedule. Like on startup, an overdue "metrics" ping
+/// is submitted immediately then.
 pub fn schedule(glean: &Glean) {
-    let now = local_now_with_offset();
+    let schedule = glean.get_metrics_ping_schedule();
+    let now = now_in(&schedule);
 
-    let (cancelled_lock, _condvar) = &**TASK_CONDVAR;
-    if *cancelled_lock.lock().unwrap() {
-        log::debug!("Told to schedule, but already cancelled. Are we in a test?");
-    }
-    *cancelled_lock.lock().unwrap() = false; // Uncancel the thread.
+    let submitter = GleanScheduledPingSubmitter {
+        ping_name: METRICS_PING.into(),
+        last_sent_time: get_last_sent_time_metric(),
+    };
+    let hour = schedule.hour;
+    let scheduler = GleanMetricsPingScheduler { schedule };
+
+    schedule_internal(glean, submitter, scheduler, now, hour)
+}
 
-    let submitter = GleanMetricsPingSubmitter {};
-    let scheduler = GleanMetricsPingScheduler {};
+/// The current time in the timezone of the schedule.
+fn now_in(schedule: &MetricsPingSchedule) -> DateTime<FixedOffset> {
+    let Some(offset_seconds) = schedule.utc_offset_seconds else {
+        return local_now_with_offset();
+    };
+    match FixedOffset::east_opt(offset_seconds) {
+        Some(offset) => Utc::now().with_timezone(&offset),
+        None => {
+            log::warn!(
+                "Invalid UTC offset of {} seconds to schedule the 'metrics' ping in, using local time",
+                offset_seconds
+            );
+            local_now_with_offset()
+        }
+    }
+}
 
-    schedule_internal(glean, submitter, scheduler, now)
+/// How long to delay a scheduled submission by to spread submissions over the schedule's window.
+///
+/// # Arguments
+///
+/// * `schedule` - The schedule in use.
+/// * `sample` - A random value in `[0, 1)` used to spread the submission.
+fn spread_delay(schedule: &MetricsPingSchedule, sample: f64) -> Duration {
+    Duration::from_secs_f64(f64::from(schedule.spread_seconds) * sample.clamp(0.0, 1.0))
 }
 
 /// Tells the scheduler task to exit quickly and cleanly.
 pub fn cancel() {
-    let (cancelled_lock, condvar) = &**TASK_CONDVAR; // One `*` for Lazy, the second for Arc
-    *cancelled_lock.lock().unwrap() = true; // Cancel the scheduler thread.
-    condvar.notify_all(); // Notify any/all listening schedulers to check whether they were cancelled.
+    TASKS.cancel();
 }
 
 fn schedule_internal(
     glean: &Glean,
-    submitter: impl MetricsPingSubmitter + Send + 'static,
+    submitter: impl ScheduledPingSubmitter + Send + 'static,
     scheduler: impl MetricsPingScheduler,
     now: DateTime<FixedOffset>,
+    hour: u32,
 ) {
     let last_sent_build_metric = get_last_sent_build_metric();
     if let Some(last_sent_build) = last_sent_build_metric.get_value(glean, Some(INTERNAL_STORAGE)) {
@@ -111,7 +143,7 @@ fn schedule_internal(
         if last_sent_build != glean.app_build {
             last_sent_build_metric.set_sync(glean, &glean.app_build);
             log::info!("App build changed. Sending 'metrics' ping");
-            submitter.submit_metrics_ping(glean, Some("upgrade"), now);
+            submitter.submit_scheduled_ping(glean, Some("upgrade"), now);
             scheduler.start_scheduler(submitter, now, When::Reschedule);
             return;
         }
@@ -135,11 +167,11 @@ fn schedule_internal(
     //    some time to the due time; schedule for submitting the current calendar day.
 
     let already_sent_today = last_sent_time.is_some_and(|d| d.date_naive() == now.date_naive());
-    // Today's 04:00 in local time
+    // Today's scheduled hour in local time
     let cutoff_time = now
         .naive_local()
         .date()
-        .and_hms_opt(SCHEDULED_HOUR, 0, 0)
+        .and_hms_opt(hour, 0, 0)
         .unwrap()
         .and_local_timezone(now.timezone())
         .unwrap();
@@ -151,7 +183,7 @@ fn schedule_internal(
     } else if now > cutoff_time {
         // Case #2
         log::info!("Sending the 'metrics' ping immediately, {}", now);
-        submitter.submit_metrics_ping(glean, Some("overdue"), now);
+        submitter.submit_scheduled_ping(glean, Some("overdue"), now);
         scheduler.start_scheduler(submitter, now, When::Reschedule);
     } else {
         // Case #3
@@ -160,108 +192,52 @@ fn schedule_internal(
     }
 }
 
-/// "metrics" ping scheduling deadlines.
-#[derive(Debug, PartialEq)]
-enum When {
-    Today,
-    Tomorrow,
-    Reschedule,
+/// The "metrics" ping submissions of a schedule.
+struct MetricsPingTask<S> {
+    submitter: S,
+    schedule: MetricsPingSchedule,
+    now: DateTime<FixedOffset>,
+    when: When,
 }
 
-impl When {
-    /// Returns the duration from now until our deadline.
-    /// Note that std::time::Duration doesn't do negative time spans, so if
-    /// our deadline has passed, this will return zero.
-    fn until(&self, now: DateTime<FixedOffset>) -> std::time::Duration {
-        let now_local = now.naive_local();
-
-        let fire_date = match self {
-            Self::Today => now_local.date().and_hms_opt(SCHEDULED_HOUR, 0, 0).unwrap(),
-            // Doesn't actually save us from being an hour off on DST because
-            // chrono doesn't know when DST changes. : (
-            Self::Tomorrow | Self::Reschedule => {
-                let next_day = now_local.checked_add_days(Days::new(1)).unwrap();
-                let next_day_date = next_day.date();
-                next_day_date.and_hms_opt(SCHEDULED_HOUR, 0, 0).unwrap()
-            }
-        };
-
-        (fire_date - now_local).to_std().unwrap_or_else(|_| {
-            // If we're somehow out of range schedule 24 hours into the future.
-            // We do NOT want to schedule a ping submission immediately.
-            std::time::Duration::from_secs(24 * 60 * 60)
-        })
+impl<S: ScheduledPingSubmitter + Send + 'static> ScheduledTask for MetricsPingTask<S> {
+    fn until_due(&mut self) -> Duration {
+        let dur = self.when.until(self.now, self.schedule.hour)
+            + spread_delay(&self.schedule, uuid_to_sample_value(&Uuid::new_v4()));
+        log::info!(
+            "Scheduling for {} after {:?}, reason {:?}",
+            self.now,
+            dur,
+            self.when
+        );
+        dur
     }
 
-    /// The "metrics" ping reason corresponding to our deadline.
-    fn reason(&self) -> &'static str {
-        match self {
-            Self::Today => "today",
-            Self::Tomorrow => "tomorrow",
-            Self::Reschedule => "reschedule",
-        }
+    fn run(&mut self, glean: &Mutex<Glean>) {
+        log::info!("Time to submit our metrics ping, {:?}", self.when);
+        self.submitter.submit_scheduled_ping(
+            &glean.lock().unwrap(),
+            Some(self.when.reason()),
+            self.now,
+        );
+        self.when = When::Reschedule;
+        self.now = now_in(&self.schedule);
     }
 }
 
 fn start_scheduler(
-    submitter: impl MetricsPingSubmitter + Send + 'static,
+    submitter: impl ScheduledPingSubmitter + Send + 'static,
     now: DateTime<FixedOffset>,
     when: When,
+    schedule: MetricsPingSchedule,
 ) -> JoinHandle<()> {
-    let pair = Arc::clone(&TASK_CONDVAR);
-    crate::thread::spawn("glean.mps", move || {
-        let (cancelled_lock, condvar) = &*pair;
-        let mut when = when;
-        let mut now = now;
-        loop {
-            let dur = when.until(now);
-            log::info!("Scheduling for {} after {:?}, reason {:?}", now, dur, when);
-            let mut timed_out = false;
-            {
-                match condvar.wait_timeout_while(cancelled_lock.lock().unwrap(), dur, |cancelled| {
-                    !*cancelled
-                }) {
-                    Err(err) => {
-                        log::warn!("Condvar wait failure. MPS exiting. {}", err);
-                        break;
-                    }
-                    Ok((cancelled, wait_result)) => {
-                        if *cancelled {
-                            log::info!("Metrics Ping Scheduler cancelled. Exiting.");
-                            break;
-                        } else if wait_result.timed_out() {
-                            // Can't get the global glean while holding cancelled's lock.
-                            timed_out = true;
-                        } else {
-                            // This should be impossible. `cancelled_lock` is acquired, and
-                            // `!*cancelled` is checked by the condvar before it is allowed
-                            // to return from `wait_timeout_while` (I checked).
-                            // So `Ok(_)` implies `*cancelled || wait_result.timed_out`.
-                            log::warn!("Spurious wakeup of the MPS condvar should be impossible.");
-                        }
-                    }
-                }
-            }
-            // Safety:
-            // We are okay dropping the condvar's cancelled lock here because it only guards
-            // whether we're cancelled, and we've established that we weren't when we timed out.
-            // We might _now_ be cancelled at any time, in which case when we loop back over
-            // we'll immediately exit. But first we need to submit our "metrics" ping.
-            if timed_out {
-                log::info!("Time to submit our metrics ping, {:?}", when);
-                let glean = crate::core::global_glean()
-                    .expect(
-                        "Global Glean not present when trying to send scheduled 'metrics' ping?!",
-                    )
-                    .lock()
-                    .unwrap();
-                submitter.submit_metrics_ping(&glean, Some(when.reason()), now);
-                when = When::Reschedule;
-            }
-            now = local_now_with_offset();
-        }
-    })
-    .expect("Unable to spawn Metrics Ping Scheduler thread.")
+    let task = MetricsPingTask {
+        submitter,
+        schedule,
+        now,
+        when,
+    };
+    TASKS.start(METRICS_PING, task)
 }
 
 fn get_last_sent_time_metric() -> DatetimeMetric {
@@ -292,6 +268,7 @@ mod test {
     use super::*;
     use crate::tests::new_glean;
     use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
 
     use chrono::Duration;
 
@@ -303,21 +280,22 @@ mod test {
         schedule_validator: F,
         validator_run_count: Arc<AtomicU32>,
     }
-    impl<F: Fn(DateTime<FixedOffset>, Option<&str>)> MetricsPingSubmitter for ValidatingSubmitter<F> {
-        fn submit_metrics_ping(
+    impl<F: Fn(DateTime<FixedOffset>, Option<&str>)> ScheduledPingSubmitter for ValidatingSubmitter<F> {
+        fn submit_scheduled_ping(
             &self,
             _glean: &Glean,
             reason: Option<&str>,
             now: DateTime<FixedOffset>,
-        ) {
+        ) -> bool {
             (self.submit_validator)(now, reason);
             self.validator_run_count.fetch_add(1, Ordering::Relaxed);
+            true
         }
     }
     impl<F: Fn(DateTime<FixedOffset>, When)> MetricsPingScheduler for ValidatingScheduler<F> {
         fn start_scheduler(
             &self,
-            _submitter: impl MetricsPingSubmitter + Send + 'static,
+            _submitter: impl ScheduledPingSubmitter + Send + 'static,
             now: DateTime<FixedOffset>,
             when: When,
         ) {
@@ -363,7 +341,7 @@ mod test {
 
         let fake_now = FixedOffset::east_opt(0)
             .unwrap()
-            .with_ymd_and_hms(2022, 11, 15, SCHEDULED_HOUR, 0, 1)
+            .with_ymd_and_hms(2022, 11, 15, DEFAULT_SCHEDULED_HOUR, 0, 1)
             .unwrap();
 
         let (submitter, submitter_count, scheduler, scheduler_count) = new_proxies(
@@ -371,7 +349,13 @@ mod test {
             |_, when| assert_eq!(when, When::Reschedule),
         );
 
-        schedule_internal(&glean, submitter, scheduler, fake_now);
+        schedule_internal(
+            &glean,
+            submitter,
+            scheduler,
+            fake_now,
+            DEFAULT_SCHEDULED_HOUR,
+        );
         assert_eq!(1, submitter_count.swap(0, Ordering::Relaxed));
         assert_eq!(1, scheduler_count.swap(0, Ordering::Relaxed));
 
@@ -395,7 +379,13 @@ mod test {
             |_, when| assert_eq!(when, When::Reschedule),
         );
 
-        schedule_internal(&glean, submitter, scheduler, local_now_with_offset());
+        schedule_internal(
+            &glean,
+            submitter,
+            scheduler,
+            local_now_with_offset(),
+            DEFAULT_SCHEDULED_HOUR,
+        );
         assert_eq!(1, submitter_count.swap(0, Ordering::Relaxed));
         assert_eq!(1, scheduler_count.swap(0, Ordering::Relaxed));
     }
@@ -416,7 +406,13 @@ mod test {
             |_, reason| panic!("Case #1 shouldn't submit a ping! reason: {:?}", reason),
             |_, when| assert_eq!(when, When::Tomorrow),
         );
-        schedule_internal(&glean, submitter, scheduler, fake_now);
+        schedule_internal(
+            &glean,
+            submitter,
+            scheduler,
+            fake_now,
+            DEFAULT_SCHEDULED_HOUR,
+        );
         assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
         assert_eq!(1, scheduler_count.swap(0, Ordering::Relaxed));
     }
@@ -430,7 +426,7 @@ mod test {
 
         let fake_yesterday = FixedOffset::east_opt(0)
             .unwrap()
-            .with_ymd_and_hms(2021, 4, 29, SCHEDULED_HOUR, 0, 1)
+            .with_ymd_and_hms(2021, 4, 29, DEFAULT_SCHEDULED_HOUR, 0, 1)
             .unwrap();
         get_last_sent_time_metric().set_sync_chrono(&glean, fake_yesterday);
         let fake_now = fake_yesterday + Duration::days(1);
@@ -439,7 +435,13 @@ mod test {
             |_, reason| assert_eq!(reason, Some("overdue")),
             |_, when| assert_eq!(when, When::Reschedule),
         );
-        schedule_internal(&glean, submitter, scheduler, fake_now);
+        schedule_internal(
+            &glean,
+            submitter,
+            scheduler,
+            fake_now,
+            DEFAULT_SCHEDULED_HOUR,
+        );
         assert_eq!(1, submitter_count.swap(0, Ordering::Relaxed));
         assert_eq!(1, scheduler_count.swap(0, Ordering::Relaxed));
     }
@@ -453,7 +455,7 @@ mod test {
 
         let fake_yesterday = FixedOffset::east_opt(0)
             .unwrap()
-            .with_ymd_and_hms(2021, 4, 29, SCHEDULED_HOUR - 1, 0, 1)
+            .with_ymd_and_hms(2021, 4, 29, DEFAULT_SCHEDULED_HOUR - 1, 0, 1)
             .unwrap();
         get_last_sent_time_metric().set_sync_chrono(&glean, fake_yesterday);
         let fake_now = fake_yesterday + Duration::days(1);
@@ -462,7 +464,13 @@ mod test {
             |_, reason| panic!("Case #3 shouldn't submit a ping! reason: {:?}", reason),
             |_, when| assert_eq!(when, When::Today),
         );
-        schedule_internal(&glean, submitter, scheduler, fake_now);
+        schedule_internal(
+            &glean,
+            submitter,
+            scheduler,
+            fake_now,
+            DEFAULT_SCHEDULED_HOUR,
+        );
         assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
         assert_eq!(1, scheduler_count.swap(0, Ordering::Relaxed));
     }
@@ -474,16 +482,22 @@ mod test {
             .unwrap()
             .with_ymd_and_hms(2021, 4, 30, 15, 2, 10)
             .unwrap();
-        // `now` is after `SCHEDULED_HOUR` but we should never schedule immediately:
-        assert_ne!(std::time::Duration::from_secs(0), When::Today.until(now));
-        // If we bring it back before `SCHEDULED_HOUR` it should give us the duration:
+        // `now` is after `DEFAULT_SCHEDULED_HOUR` but we should never schedule immediately:
+        assert_ne!(
+            std::time::Duration::from_secs(0),
+            When::Today.until(now, DEFAULT_SCHEDULED_HOUR)
+        );
+        // If we bring it back before `DEFAULT_SCHEDULED_HOUR` it should give us the duration:
         let earlier = now
             .date_naive()
-            .and_hms_opt(SCHEDULED_HOUR - 1, 0, 0)
+            .and_hms_opt(DEFAULT_SCHEDULED_HOUR - 1, 0, 0)
             .unwrap();
         assert_eq!(
             std::time::Duration::from_secs(3600),
-            When::Today.until(Utc.from_utc_datetime(&earlier).into())
+            When::Today.until(
+                Utc.from_utc_datetime(&earlier).into(),
+                DEFAULT_SCHEDULED_HOUR
+            )
         );
 
         // `Tomorrow` and `Reschedule` should differ only in their `reason()`
@@ -491,13 +505,16 @@ mod test {
         // (when the timezone doesn't change between them)).
         assert_eq!(
             std::time::Duration::from_secs(46670),
-            When::Tomorrow.until(now)
+            When::Tomorrow.until(now, DEFAULT_SCHEDULED_HOUR)
         );
         assert_eq!(
             std::time::Duration::from_secs(46670),
-            When::Reschedule.until(now)
+            When::Reschedule.until(now, DEFAULT_SCHEDULED_HOUR)
+        );
+        assert_eq!(
+            When::Tomorrow.until(now, DEFAULT_SCHEDULED_HOUR),
+            When::Reschedule.until(now, DEFAULT_SCHEDULED_HOUR)
         );
-        assert_eq!(When::Tomorrow.until(now), When::Reschedule.until(now));
         assert_ne!(When::Tomorrow.reason(), When::Reschedule.reason());
     }
 
@@ -508,7 +525,7 @@ mod test {
             .with_ymd_and_hms(2025, 7, 27, 22, 27, 59)
             .unwrap();
 
-        let next_schedule = When::Reschedule.until(now);
+        let next_schedule = When::Reschedule.until(now, DEFAULT_SCHEDULED_HOUR);
 
         // 22:27:59 -> (next day) 04:00 is 5h 32min 1s = 19921 seconds
         let expected_duration = std::time::Duration::from_secs(19921);
@@ -522,13 +539,97 @@ mod test {
             .with_ymd_and_hms(2025, 7, 27, 3, 30, 0)
             .unwrap();
 
-        let next_schedule = When::Today.until(now);
+        let next_schedule = When::Today.until(now, DEFAULT_SCHEDULED_HOUR);
 
         // 03:30:00 -> 04:00 is 30min
         let expected_duration = std::time::Duration::from_secs(30 * 60);
         assert_eq!(expected_duration, next_schedule);
     }
 
+    #[test]
+    fn when_uses_the_scheduled_hour() {
+        let now = FixedOffset::east_opt(0)
+            .unwrap()
+            .with_ymd_and_hms(2021, 4, 30, 15, 0, 0)
+            .unwrap();
+
+        assert_eq!(
+            std::time::Duration::from_secs(3600),
+            When::Today.until(now, 16)
+        );
+        // 15:00 -> (next day) 00:00 is 9h.
+        assert_eq!(
+            std::time::Duration::from_secs(9 * 3600),
+            When::Tomorrow.until(now, 0)
+        );
+    }
+
+    #[test]
+    fn schedules_can_use_a_fixed_timezone() {
+        let schedule = MetricsPingSchedule {
+            utc_offset_seconds: Some(-3600 * 5),
+            ..Default::default()
+        };
+        assert_eq!(
+            FixedOffset::west_opt(3600 * 5).unwrap(),
+            now_in(&schedule).timezone()
+        );
+
+        // Offsets of a day or more are invalid, we fall back to local time.
+        let schedule = MetricsPingSchedule {
+            utc_offset_seconds: Some(86_400),
+            ..Default::default()
+        };
+        assert_eq!(
+            local_now_with_offset().timezone(),
+            now_in(&schedule).timezone()
+        );
+    }
+
+    #[test]
+    fn submissions_are_spread_over_the_window() {
+        let schedule = MetricsPingSchedule {
+            spread_seconds: 3600,
+            ..Default::default()
+        };
+        assert_eq!(std::time::Duration::ZERO, spread_delay(&schedule, 0.0));
+        assert_eq!(
+            std::time::Duration::from_secs(1800),
+            spread_delay(&schedule, 0.5)
+        );
+        assert!(spread_delay(&schedule, 0.9999) < std::time::Duration::from_secs(3600));
+
+        assert_eq!(
+            std::time::Duration::ZERO,
+            spread_delay(&MetricsPingSchedule::default(), 0.5)
+        );
+    }
+
+    #[test]
+    fn spread_submissions_stay_on_the_day_they_are_due() {
+        let now = FixedOffset::east_opt(0)
+            .unwrap()
+            .with_ymd_and_hms(2021, 4, 30, 22, 0, 0)
+            .unwrap();
+        let schedule = MetricsPingSchedule {
+            hour: 23,
+            spread_seconds: max_spread_seconds(23),
+            ..Default::default()
+        };
+        assert_eq!(3599, schedule.spread_seconds);
+
+        // The latest submission is still before midnight,
+        // so the next one is scheduled for the next day.
+        let latest = now + When::Today.until(now, schedule.hour) + spread_delay(&schedule, 0.9999);
+        assert_eq!(now.date_naive(), latest.date_naive());
+        assert_eq!(
+            std::time::Duration::from_secs(24 * 3600) - spread_delay(&schedule, 0.9999),
+            When::Reschedule.until(latest, schedule.hour)
+        );
+
+        assert_eq!(24 * 60 * 60 - 1, max_spread_seconds(0));
+    }
+
     // Scheduler tests mutate global state and thus must not be run in parallel.
     // Otherwise one test could cancel the other.
     // This Mutex aims to solve that.
@@ -537,17 +638,13 @@ mod test {
     // The scheduler has been designed to be cancellable. Can we cancel it?
     #[test]
     fn cancellable_tasks_can_be_cancelled() {
-        // First and foremost, all scheduler tests must ensure they start uncancelled.
-        // Perils of having shared state.
         let _test_lock = SCHEDULER_TEST_MUTEX.lock().unwrap();
-        let (cancelled_lock, _condvar) = &**TASK_CONDVAR; // One `*` for Lazy, the second for Arc
-        *cancelled_lock.lock().unwrap() = false;
 
         // Pick a time at least two hours from the next scheduled submission.
         // (So that this test will time out if cancellation fails).
         let now = FixedOffset::east_opt(0)
             .unwrap()
-            .with_ymd_and_hms(2021, 4, 30, SCHEDULED_HOUR - 2, 0, 0)
+            .with_ymd_and_hms(2021, 4, 30, DEFAULT_SCHEDULED_HOUR - 2, 0, 0)
             .unwrap();
 
         let proxy_factory = || {
@@ -564,37 +661,80 @@ mod test {
 
         // Test Today.
         let (submitter, submitter_count, _, _) = proxy_factory();
-        let handle = start_scheduler(submitter, now, When::Today);
+        let handle = start_scheduler(submitter, now, When::Today, MetricsPingSchedule::default());
         super::cancel();
         handle.join().unwrap(); // Should complete immediately.
         assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
 
         // Test Tomorrow.
         let (submitter, submitter_count, _, _) = proxy_factory();
-        *cancelled_lock.lock().unwrap() = false; // Uncancel.
-        let handle = start_scheduler(submitter, now, When::Tomorrow);
+        let handle = start_scheduler(
+            submitter,
+            now,
+            When::Tomorrow,
+            MetricsPingSchedule::default(),
+        );
         super::cancel();
         handle.join().unwrap(); // Should complete immediately.
         assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
 
         // Test Reschedule.
         let (submitter, submitter_count, _, _) = proxy_factory();
-        *cancelled_lock.lock().unwrap() = false; // Uncancel.
-        let handle = start_scheduler(submitter, now, When::Reschedule);
+        let handle = start_scheduler(
+            submitter,
+            now,
+            When::Reschedule,
+            MetricsPingSchedule::default(),
+        );
         super::cancel();
         handle.join().unwrap(); // Should complete immediately.
         assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
     }
 
+    // Rescheduling replaces the scheduler task.
+    #[test]
+    fn superseded_tasks_exit() {
+        let _test_lock = SCHEDULER_TEST_MUTEX.lock().unwrap();
+
+        let now = FixedOffset::east_opt(0)
+            .unwrap()
+            .with_ymd_and_hms(2021, 4, 30, DEFAULT_SCHEDULED_HOUR - 2, 0, 0)
+            .unwrap();
+        let (submitter, submitter_count, _, _) = new_proxies(
+            |_, reason| {
+                panic!(
+                    "Shouldn't submit when testing scheduler. reason: {:?}",
+                    reason
+                )
+            },
+            |_, _| panic!("Not even using the scheduler this time."),
+        );
+
+        let (replacement, ..) = new_proxies(
+            |_, reason| panic!("Shouldn't submit either. reason: {:?}", reason),
+            |_, _| panic!("Not even using the scheduler this time."),
+        );
+
+        let handle = start_scheduler(submitter, now, When::Today, MetricsPingSchedule::default());
+        // What scheduling again does.
+        let replacement = start_scheduler(
+            replacement,
+            now,
+            When::Today,
+            MetricsPingSchedule::default(),
+        );
+        handle.join().unwrap(); // Should complete immediately.
+        assert_eq!(0, submitter_count.swap(0, Ordering::Relaxed));
+
+        super::cancel();
+        replacement.join().unwrap();
+    }
+
     // We're not keen to wait like the scheduler is, but we can test a quick schedule.
     #[test]
     fn immediate_task_runs_immediately() {
         let _ = env_logger::builder().try_init();
-        // First and foremost, all scheduler tests must ensure they start uncancelled.
-        // Perils of having shared state.
         let _test_lock = SCHEDULER_TEST_MUTEX.lock().unwrap();
-        let (cancelled_lock, _condvar) = &**TASK_CONDVAR; // One `*` for Lazy, the second for Arc
-        *cancelled_lock.lock().unwrap() = false;
 
         // We're actually going to submit a ping from the scheduler, which requires a global glean.
         let (glean, _t) = new_glean(None);
@@ -604,7 +744,7 @@ mod test {
         );
         assert!(crate::core::setup_glean(glean).is_ok());
 
-        // We're choosing the exact `SCHEDULED_HOUR` to give us a duration of 0.
+        // We're choosing the exact `DEFAULT_SCHEDULED_HOUR` to give us a duration of 0.
         let now = FixedOffset::east_opt(0)
             .unwrap()
             .with_ymd_and_hms(2021, 4, 21, 4, 0, 0)
@@ -614,13 +754,12 @@ mod test {
             move |_, reason| {
                 assert_eq!(reason, Some("today"));
                 // After submitting the ping we expect, let's cancel this scheduler so the thread exits.
-                // (But do it on another thread because the condvar loop is currently holding `cancelled`'s mutex)
-                std::thread::spawn(super::cancel);
+                super::cancel();
             },
             |_, _| panic!("Not using the scheduler this time."),
         );
 
-        let handle = start_scheduler(submitter, now, When::Today);
+        let handle = start_scheduler(submitter, now, When::Today, MetricsPingSchedule::default());
         handle.join().unwrap();
         assert_eq!(1, submitter_count.swap(0, Ordering::Relaxed));
     }
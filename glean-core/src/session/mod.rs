@@ -21,11 +21,11 @@ use crate::storage::INTERNAL_STORAGE;
 use crate::{CommonMetricData, Glean, Lifetime};
 
 // Storage key names for session persistence.
-const SESSION_SEQ_METRIC_NAME: &str = "session#seq";
-const SESSION_ID_METRIC_NAME: &str = "session#id";
-const SESSION_INACTIVE_SINCE_METRIC_NAME: &str = "session#inactive_since";
-const SESSION_START_TIME_METRIC_NAME: &str = "session#start_time";
-const SESSION_EVENT_SEQ_METRIC_NAME: &str = "session#event_seq";
+pub(crate) const SESSION_SEQ_METRIC_NAME: &str = "session#seq";
+pub(crate) const SESSION_ID_METRIC_NAME: &str = "session#id";
+pub(crate) const SESSION_INACTIVE_SINCE_METRIC_NAME: &str = "session#inactive_since";
+pub(crate) const SESSION_START_TIME_METRIC_NAME: &str = "session#start_time";
+pub(crate) const SESSION_EVENT_SEQ_METRIC_NAME: &str = "session#event_seq";
 
 /// How sessions are managed by Glean.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default, MallocSizeOf)]
@@ -116,6 +116,42 @@ impl StorageManager {
         store_name: &str,
         clear_store: bool,
     ) -> Option<JsonValue> {
+        self.snapshot_with_events(storage, store_name, clear_store, false)
+            .0
+    }
+
+    /// Snapshots the given store together with its events and optionally clear both.
+    ///
+    /// When clearing, the metrics with ping lifetime and the events are taken in a single
+    /// transaction, so data recorded concurrently ends up entirely in this snapshot or the next.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - the database to read from.
+    /// * `store_name` - the store to snapshot.
+    /// * `clear_store` - whether to clear the data after snapshotting.
+    ///
+    /// # Returns
+    ///
+    /// A JSON representation of the stored metrics, or `None` if there are none,
+    /// and the serialized events of the store.
+    pub(crate) fn snapshot_as_json_with_events(
+        &self,
+        storage: &Database,
+        store_name: &str,
+        clear_store: bool,
+    ) -> (Option<JsonValue>, Vec<Vec<u8>>) {
+        self.snapshot_with_events(storage, store_name, clear_store, true)
+    }
+
+    fn snapshot_with_events(
+        &self,
+        storage: &Database,
+        store_name: &str,
+        clear_store: bool,
+        with_events: bool,
+    ) -> (Option<JsonValue>, Vec<Vec<u8>>) {
+        let mut events = Vec::new();
         let mut snapshot: HashMap<String, HashMap<String, JsonValue>> = HashMap::new();
 
         let mut snapshotter = |metric_id: &[u8], labels: &[&str], metric: &Metric| {
@@ -139,9 +175,22 @@ impl StorageManager {
             }
         };
 
-        if let Err(e) = storage.iter_store(Lifetime::Ping, store_name, &mut snapshotter) {
+        let ping_lifetime = match (clear_store, with_events) {
+            (true, true) => storage
+                .take_ping_lifetime_store_and_events(store_name, &mut snapshotter)
+                .map(|taken| events = taken),
+            (true, false) => storage.take_ping_lifetime_store(store_name, &mut snapshotter),
+            (false, _) => storage.iter_store(Lifetime::Ping, store_name, &mut snapshotter),
+        };
+        if let Err(e) = ping_lifetime {
             log::debug!("could not snapshot ping lifetime store: {e:?}");
         }
+        if with_events && !clear_store {
+            match storage.get_events(store_name, false) {
+                Ok(stored) => events = stored,
+                Err(e) => log::warn!("Error reading events of store '{}': {}", store_name, e),
+            }
+        }
         if let Err(e) = storage.iter_store(Lifetime::Application, store_name, &mut snapshotter) {
             log::debug!("could not snapshot application lifetime store: {e:?}");
         }
@@ -157,10 +206,6 @@ impl StorageManager {
         }
 
         if clear_store {
-            if let Err(e) = storage.clear_ping_lifetime_storage(store_name) {
-                log::warn!("Failed to clear lifetime storage: {:?}", e);
-            }
-
             if let Err(e) = storage.run_maintenance(false) {
                 log::warn!(
                     "Failed to run database maintenance after ping submission: {:?}",
@@ -170,9 +215,9 @@ impl StorageManager {
         }
 
         if snapshot.is_empty() {
-            None
+            (None, events)
         } else {
-            Some(json!(snapshot))
+            (Some(json!(snapshot)), events)
         }
     }
 
@@ -0,0 +1,255 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! # Task Scheduler
+//!
+//! Runs tasks on threads of their own whenever they are due, until they are replaced or cancelled.
+//! The Metrics Ping Scheduler (see `scheduler.rs`) and the Ping Scheduler (see `ping_scheduler.rs`)
+//! only decide when their pings are due, and submit them through a [`ScheduledPingSubmitter`].
+
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, FixedOffset};
+
+use crate::metrics::DatetimeMetric;
+use crate::Glean;
+
+/// A task that runs on a thread of its own whenever it is due.
+pub(crate) trait ScheduledTask: Send + 'static {
+    /// How long to wait until the task is due next.
+    /// Zero if it is due already.
+    fn until_due(&mut self) -> Duration;
+
+    /// Runs the task, now that it is due.
+    fn run(&mut self, glean: &Mutex<Glean>);
+}
+
+/// Describes the interface for a submitter of scheduled pings.
+/// Used to decouple the implementation so we can test it.
+pub(crate) trait ScheduledPingSubmitter {
+    /// Submits a scheduled ping, updating its last sent time to `now`
+    /// (which might not be _right now_ due to processing delays (or in tests)).
+    ///
+    /// Returns whether the ping was submitted.
+    fn submit_scheduled_ping(
+        &self,
+        glean: &Glean,
+        reason: Option<&str>,
+        now: DateTime<FixedOffset>,
+    ) -> bool;
+}
+
+/// Uses Glean to submit scheduled pings directly.
+pub(crate) struct GleanScheduledPingSubmitter {
+    /// The name of the ping to submit.
+    pub(crate) ping_name: String,
+    /// When the ping was last submitted by the scheduler.
+    pub(crate) last_sent_time: DatetimeMetric,
+}
+
+impl ScheduledPingSubmitter for GleanScheduledPingSubmitter {
+    fn submit_scheduled_ping(
+        &self,
+        glean: &Glean,
+        reason: Option<&str>,
+        now: DateTime<FixedOffset>,
+    ) -> bool {
+        let submitted = glean.submit_ping_by_name(&self.ping_name, reason);
+        // Always update the last sent time, irrespective of the ping being sent.
+        self.last_sent_time.set_sync_chrono(glean, now);
+        submitted
+    }
+}
+
+/// Scheduled ping deadlines.
+#[derive(Debug, PartialEq)]
+pub(crate) enum When {
+    Today,
+    Tomorrow,
+    Reschedule,
+}
+
+impl When {
+    /// Returns the duration from now until our deadline at `hour`.
+    /// Note that std::time::Duration doesn't do negative time spans, so if
+    /// our deadline has passed, this will return zero.
+    pub(crate) fn until(&self, now: DateTime<FixedOffset>, hour: u32) -> Duration {
+        let now_local = now.naive_local();
+
+        let fire_date = match self {
+            Self::Today => now_local.date().and_hms_opt(hour, 0, 0).unwrap(),
+            // Doesn't actually save us from being an hour off on DST because
+            // chrono doesn't know when DST changes. : (
+            Self::Tomorrow | Self::Reschedule => {
+                let next_day = now_local.checked_add_days(Days::new(1)).unwrap();
+                let next_day_date = next_day.date();
+                next_day_date.and_hms_opt(hour, 0, 0).unwrap()
+            }
+        };
+
+        (fire_date - now_local).to_std().unwrap_or_else(|_| {
+            // If we're somehow out of range schedule 24 hours into the future.
+            // We do NOT want to schedule a ping submission immediately.
+            Duration::from_secs(24 * 60 * 60)
+        })
+    }
+
+    /// The ping reason corresponding to our deadline.
+    pub(crate) fn reason(&self) -> &'static str {
+        match self {
+            Self::Today => "today",
+            Self::Tomorrow => "tomorrow",
+            Self::Reschedule => "reschedule",
+        }
+    }
+}
+
+/// The tasks in effect, by name.
+///
+/// Every task has a generation of its own.
+/// A task's thread exits when the task of its name isn't of its generation anymore.
+#[derive(Debug, Default)]
+struct Generations {
+    next: u64,
+    current: HashMap<String, u64>,
+}
+
+/// Runs tasks on threads of their own, at most one per name.
+#[derive(Debug)]
+pub(crate) struct TaskScheduler {
+    thread_name: &'static str,
+    generations: Mutex<Generations>,
+    condvar: Condvar,
+}
+
+impl TaskScheduler {
+    /// Creates a scheduler running its tasks on threads named `thread_name`.
+    pub(crate) fn new(thread_name: &'static str) -> Self {
+        Self {
+            thread_name,
+            generations: Mutex::new(Generations::default()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Starts running a task whenever it is due, on another thread.
+    ///
+    /// The task replaces the previous task of the same name, whose thread exits.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the task.
+    /// * `task` - The task.
+    ///
+    /// # Returns
+    ///
+    /// The handle of the task's thread.
+    pub(crate) fn start(&'static self, name: &str, mut task: impl ScheduledTask) -> JoinHandle<()> {
+        let name = name.to_string();
+        let generation = {
+            let mut generations = self.generations.lock().unwrap();
+            let generation = generations.next;
+            generations.next += 1;
+            generations.current.insert(name.clone(), generation);
+            generation
+        };
+        // Notify the thread of the previous task that it was replaced.
+        self.condvar.notify_all();
+
+        crate::thread::spawn(self.thread_name, move || {
+            let is_current =
+                |generations: &Generations| generations.current.get(&name) == Some(&generation);
+            loop {
+                let dur = task.until_due();
+                match self.condvar.wait_timeout_while(
+                    self.generations.lock().unwrap(),
+                    dur,
+                    |generations| is_current(generations),
+                ) {
+                    Err(err) => {
+                        log::warn!(
+                            "Condvar wait failure. Task '{}' of {} exiting. {}",
+                            name,
+                            self.thread_name,
+                            err
+                        );
+                        break;
+                    }
+                    Ok((generations, _)) if !is_current(&generations) => {
+                        log::info!(
+                            "Task '{}' of {} cancelled or replaced. Exiting.",
+                            name,
+                            self.thread_name
+                        );
+                        break;
+                    }
+                    // Timed out. Can't get the global Glean while holding the generations' lock.
+                    Ok(_) => {}
+                }
+
+                // We might be cancelled by now, in which case the next loop exits.
+                let Some(glean) = crate::core::global_glean() else {
+                    log::warn!(
+                        "Global Glean not present when running task '{}' of {}.",
+                        name,
+                        self.thread_name
+                    );
+                    break;
+                };
+                task.run(glean);
+            }
+        })
+        .expect("Unable to spawn a task scheduler thread.")
+    }
+
+    /// Tells the thread of the task of that name, if any, to exit quickly and cleanly.
+    pub(crate) fn stop(&self, name: &str) {
+        self.generations.lock().unwrap().current.remove(name);
+        self.condvar.notify_all();
+    }
+
+    /// Tells the threads of all tasks to exit quickly and cleanly.
+    pub(crate) fn cancel(&self) {
+        self.generations.lock().unwrap().current.clear();
+        self.condvar.notify_all(); // Notify any/all listening tasks to check whether they were cancelled.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use once_cell::sync::Lazy;
+
+    static TASKS: Lazy<TaskScheduler> = Lazy::new(|| TaskScheduler::new("glean.test_tasks"));
+
+    /// A task that is never due in a test.
+    struct NeverDue;
+    impl ScheduledTask for NeverDue {
+        fn until_due(&mut self) -> Duration {
+            Duration::from_secs(24 * 60 * 60)
+        }
+
+        fn run(&mut self, _glean: &Mutex<Glean>) {
+            panic!("Not expected to run.");
+        }
+    }
+
+    #[test]
+    fn replaced_stopped_and_cancelled_tasks_exit() {
+        // Starting a task of the same name ends the previous one.
+        let replaced = TASKS.start("replaced", NeverDue);
+        let replacement = TASKS.start("replaced", NeverDue);
+        replaced.join().unwrap();
+
+        let stopped = TASKS.start("stopped", NeverDue);
+        TASKS.stop("stopped");
+        stopped.join().unwrap();
+
+        TASKS.cancel();
+        replacement.join().unwrap();
+    }
+}
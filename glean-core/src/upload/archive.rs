@@ -0,0 +1,350 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Portable archives of pending pings.
+//!
+//! Pings are exported from the pings directories into a single archive file,
+//! which can be moved elsewhere to upload the pings from there,
+//! e.g. off an air-gapped machine.
+//!
+//! An archive is a gzip-compressed file of newline-delimited JSON:
+//! a header line with the format version followed by one line per ping.
+//! Pings encrypted at rest are decrypted into the archive.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use super::directory::{PingDirectoryManager, PingPayload};
+use super::policy::Policy;
+use super::request::{create_date_header_value, HeaderMap, PingRequest};
+use crate::encryption::{self, EncryptionKeyProvider};
+use crate::error::{ErrorKind, Result};
+use crate::metrics::PingPriority;
+
+/// The version of the archive format written.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// The first line of an archive.
+#[derive(Debug, Deserialize, Serialize)]
+struct ArchiveHeader {
+    version: u32,
+}
+
+/// A ping in an archive.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArchivedPing {
+    /// The ping's document id.
+    pub document_id: String,
+    /// The path to upload the ping to.
+    pub upload_path: String,
+    /// The ping body as JSON-encoded string.
+    pub json_body: String,
+    /// HTTP headers to include in the upload request.
+    pub headers: Option<HeaderMap>,
+    /// Whether the ping body contains {client|ping}_info
+    pub body_has_info_sections: bool,
+    /// The ping's name.
+    pub ping_name: String,
+    /// The capabilities this ping must be uploaded under.
+    pub uploader_capabilities: Vec<String>,
+    /// Whether this is a ping from the deletion-request pings directory.
+    pub deletion_request: bool,
+    /// The priority to upload this ping with.
+    #[serde(default)]
+    pub priority: PingPriority,
+}
+
+impl ArchivedPing {
+    fn new(ping: PingPayload, deletion_request: bool) -> Self {
+        let PingPayload {
+            document_id,
+            upload_path,
+            json_body,
+            headers,
+            body_has_info_sections,
+            ping_name,
+            uploader_capabilities,
+            priority,
+        } = ping;
+        Self {
+            document_id,
+            upload_path,
+            json_body,
+            headers,
+            body_has_info_sections,
+            ping_name,
+            uploader_capabilities,
+            deletion_request,
+            priority,
+        }
+    }
+
+    /// Builds a request to upload this ping now.
+    ///
+    /// # Arguments
+    ///
+    /// * `language_binding_name` - The name of the language binding uploading the ping.
+    pub fn to_request(&self, language_binding_name: &str) -> Result<PingRequest> {
+        let mut request = PingRequest::builder(
+            language_binding_name,
+            Policy::default().max_ping_body_size(),
+        )
+        .document_id(&self.document_id)
+        .path(&self.upload_path)
+        .body(&self.json_body)
+        .body_has_info_sections(self.body_has_info_sections)
+        .ping_name(&self.ping_name)
+        .uploader_capabilities(self.uploader_capabilities.clone())
+        .priority(self.priority);
+        if let Some(headers) = &self.headers {
+            request = request.headers(headers.clone());
+        }
+
+        let mut request = request.build()?;
+        request
+            .headers
+            .insert("Date".to_string(), create_date_header_value(Utc::now()));
+        Ok(request)
+    }
+}
+
+/// Reads all pings from an archive.
+///
+/// # Arguments
+///
+/// * `archive_path` - Path to the archive file.
+pub fn read_archive(archive_path: &Path) -> Result<Vec<ArchivedPing>> {
+    let file = File::open(archive_path)?;
+    let mut lines = BufReader::new(GzDecoder::new(file)).lines();
+
+    let header: ArchiveHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => {
+            return Err(ErrorKind::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "empty ping archive",
+            ))
+            .into())
+        }
+    };
+    if header.version != ARCHIVE_VERSION {
+        return Err(ErrorKind::IoError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported ping archive version {}", header.version),
+        ))
+        .into());
+    }
+
+    let mut pings = Vec::new();
+    for line in lines {
+        pings.push(serde_json::from_str(&line?)?);
+    }
+    Ok(pings)
+}
+
+/// Writes pings to an archive, replacing any existing file.
+///
+/// The archive is written to a temporary file first,
+/// so that an existing archive is left intact on errors.
+///
+/// # Arguments
+///
+/// * `archive_path` - Path to the archive file.
+/// * `pings` - The pings to write.
+pub fn write_archive(archive_path: &Path, pings: &[ArchivedPing]) -> Result<()> {
+    let mut temp_path = archive_path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let write = || -> Result<()> {
+        let file = File::create(&temp_path)?;
+        let mut writer = BufWriter::new(GzEncoder::new(file, Compression::default()));
+        serde_json::to_writer(
+            &mut writer,
+            &ArchiveHeader {
+                version: ARCHIVE_VERSION,
+            },
+        )?;
+        writer.write_all(b"\n")?;
+        for ping in pings {
+            serde_json::to_writer(&mut writer, ping)?;
+            writer.write_all(b"\n")?;
+        }
+        let encoder = writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        encoder.finish()?.sync_all()?;
+        Ok(())
+    };
+
+    if let Err(e) = write() {
+        _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, archive_path)?;
+    Ok(())
+}
+
+/// Exports pings from the pings directories into an archive
+/// and deletes the exported ping files.
+///
+/// An existing archive is never overwritten, as its pings would be lost.
+///
+/// # Arguments
+///
+/// * `data_path` - Path to the data directory.
+/// * `archive_path` - Path to the archive file to write.
+/// * `key_provider` - Supplies the key the data directory is encrypted with, if it is.
+///
+/// # Returns
+///
+/// The document ids of the exported pings.
+/// Fails without exporting anything if the data directory is encrypted,
+/// but the key is unavailable or doesn't match.
+pub fn export_pending_pings(
+    data_path: &Path,
+    archive_path: &Path,
+    key_provider: Option<&dyn EncryptionKeyProvider>,
+) -> Result<Vec<String>> {
+    let cipher = encryption::load_cipher_with(key_provider, data_path)?;
+    let mut directory_manager = PingDirectoryManager::new(data_path);
+    directory_manager.set_cipher(cipher.map(Arc::new));
+    export_pings(&directory_manager, archive_path, |_| true)
+}
+
+/// Exports the pings matching the predicate into an archive
+/// and deletes the exported ping files.
+///
+/// Nothing is deleted if the archive can't be written.
+pub(super) fn export_pings(
+    directory_manager: &PingDirectoryManager,
+    archive_path: &Path,
+    mut include: impl FnMut(&str) -> bool,
+) -> Result<Vec<String>> {
+    if archive_path.exists() {
+        return Err(ErrorKind::IoError(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("ping archive {} already exists", archive_path.display()),
+        ))
+        .into());
+    }
+
+    let pings = directory_manager.process_dirs();
+    let pings: Vec<_> = pings
+        .deletion_request_pings
+        .into_iter()
+        .map(|(_, ping)| ArchivedPing::new(ping, true))
+        .chain(
+            pings
+                .pending_pings
+                .into_iter()
+                .map(|(_, ping)| ArchivedPing::new(ping, false)),
+        )
+        .filter(|ping| include(&ping.document_id))
+        .collect();
+
+    write_archive(archive_path, &pings)?;
+
+    let document_ids: Vec<_> = pings.into_iter().map(|ping| ping.document_id).collect();
+    for document_id in &document_ids {
+        directory_manager.delete_file(document_id);
+    }
+    log::info!("Exported {} pings.", document_ids.len());
+    Ok(document_ids)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DELETION_REQUEST_PINGS_DIRECTORY, PENDING_PINGS_DIRECTORY};
+    use uuid::Uuid;
+
+    fn write_ping(dir: &Path, directory: &str, metadata: &str) -> String {
+        let document_id = Uuid::new_v4().to_string();
+        let pings_dir = dir.join(directory);
+        fs::create_dir_all(&pings_dir).unwrap();
+        fs::write(
+            pings_dir.join(&document_id),
+            format!(
+                "/submit/app/test/1/{}\n{{\"ping_info\":{{}}}}\n{}\n",
+                document_id, metadata
+            ),
+        )
+        .unwrap();
+        document_id
+    }
+
+    #[test]
+    fn exported_pings_roundtrip_through_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pings.archive");
+
+        let pending = write_ping(
+            dir.path(),
+            PENDING_PINGS_DIRECTORY,
+            r#"{"headers":{"X-Debug-ID":"test"},"ping_name":"test","uploader_capabilities":["ohttp"]}"#,
+        );
+        let deletion = write_ping(
+            dir.path(),
+            DELETION_REQUEST_PINGS_DIRECTORY,
+            r#"{"ping_name":"deletion-request"}"#,
+        );
+
+        let exported = export_pending_pings(dir.path(), &archive, None).unwrap();
+        assert_eq!(vec![deletion.clone(), pending.clone()], exported);
+        assert!(!dir
+            .path()
+            .join(PENDING_PINGS_DIRECTORY)
+            .join(&pending)
+            .exists());
+        assert!(!dir
+            .path()
+            .join(DELETION_REQUEST_PINGS_DIRECTORY)
+            .join(&deletion)
+            .exists());
+
+        let pings = read_archive(&archive).unwrap();
+        assert_eq!(2, pings.len());
+        assert!(pings[0].deletion_request);
+        assert_eq!("deletion-request", pings[0].ping_name);
+        assert_eq!(pending, pings[1].document_id);
+        assert_eq!(vec!["ohttp".to_string()], pings[1].uploader_capabilities);
+
+        let request = pings[1].to_request("Rust").unwrap();
+        assert_eq!(pending, request.document_id);
+        assert_eq!(pings[1].upload_path, request.path);
+        assert_eq!("test", request.headers["X-Debug-ID"]);
+        assert!(request.headers.contains_key("Date"));
+        assert_eq!(
+            r#"{"ping_info":{}}"#,
+            request.pretty_body().unwrap().replace(['\n', ' '], "")
+        );
+
+        // Existing archives are not overwritten.
+        write_ping(dir.path(), PENDING_PINGS_DIRECTORY, "{}");
+        assert!(export_pending_pings(dir.path(), &archive, None).is_err());
+        assert_eq!(2, read_archive(&archive).unwrap().len());
+    }
+
+    #[test]
+    fn rejects_invalid_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("pings.archive");
+
+        assert!(read_archive(&archive).is_err());
+
+        fs::write(&archive, b"not an archive").unwrap();
+        assert!(read_archive(&archive).is_err());
+
+        let mut encoder = GzEncoder::new(File::create(&archive).unwrap(), Compression::default());
+        encoder.write_all(b"{\"version\":99}\n").unwrap();
+        encoder.finish().unwrap();
+        assert!(read_archive(&archive).is_err());
+    }
+}
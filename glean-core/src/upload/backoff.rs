@@ -0,0 +1,305 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Backing off from uploading pings that failed to upload.
+//!
+//! Keeps track of the failed upload attempts of each pending ping
+//! and of the time it may be uploaded again.
+//! This state is persisted in the data directory,
+//! so that restarting the application doesn't reset it.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use malloc_size_of_derive::MallocSizeOf;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::uuid_to_sample_value;
+use crate::UploadBackoff;
+
+/// The name of the file, in the data directory, the backoff state is persisted in.
+const BACKOFF_FILENAME: &str = "upload_backoff.json";
+
+/// The backoff state of a single ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, MallocSizeOf)]
+struct BackoffEntry {
+    /// The number of failed upload attempts.
+    attempts: u32,
+    /// The time the ping may be uploaded again, in milliseconds since the UNIX epoch.
+    retry_at: i64,
+}
+
+/// The backoff state of all pending pings that failed to upload.
+#[derive(Debug, MallocSizeOf)]
+pub struct PingBackoff {
+    /// Path to the data directory the state is persisted in, if any.
+    #[ignore_malloc_size_of = "not worth measuring"]
+    data_path: Option<PathBuf>,
+    /// The backoff state of each ping, by document id.
+    entries: HashMap<String, BackoffEntry>,
+}
+
+impl PingBackoff {
+    /// Loads the persisted backoff state.
+    ///
+    /// A missing or invalid state file results in an empty state.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - Path to the data directory.
+    pub fn load(data_path: &Path) -> Self {
+        let entries = match fs::read_to_string(data_path.join(BACKOFF_FILENAME)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("Invalid upload backoff state, ignoring it. Error: {}", e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                log::warn!("Failed to read the upload backoff state. Error: {}", e);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            data_path: Some(data_path.to_path_buf()),
+            entries,
+        }
+    }
+
+    /// Creates an empty state that is never persisted.
+    pub fn in_memory() -> Self {
+        Self {
+            data_path: None,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The time until a ping may be uploaded again.
+    ///
+    /// # Arguments
+    ///
+    /// * `document_id` - The UUID of the ping in question.
+    /// * `policy` - The backoff schedule in use.
+    ///
+    /// # Returns
+    ///
+    /// The remaining time in milliseconds,
+    /// or `None` if the ping may be uploaded right away.
+    pub fn remaining(&self, document_id: &str, policy: &UploadBackoff) -> Option<u64> {
+        let entry = self.entries.get(document_id)?;
+        let remaining = entry.retry_at - Utc::now().timestamp_millis();
+        if remaining <= 0 {
+            return None;
+        }
+
+        // A retry time further out than the maximum delay means the wall clock was changed
+        // (or the schedule was shortened) since the failure was recorded.
+        // Don't hold back the ping for longer than we'd ever intend to.
+        let remaining = remaining as u64;
+        if remaining > policy.max_delay_ms {
+            return None;
+        }
+
+        Some(remaining)
+    }
+
+    /// Records a failed upload attempt of a ping and persists the new state.
+    ///
+    /// # Arguments
+    ///
+    /// * `document_id` - The UUID of the ping in question.
+    /// * `policy` - The backoff schedule in use.
+    ///
+    /// # Returns
+    ///
+    /// The time in milliseconds until the ping may be uploaded again.
+    pub fn record_failure(&mut self, document_id: &str, policy: &UploadBackoff) -> u64 {
+        let entry = self
+            .entries
+            .entry(document_id.to_string())
+            .or_insert(BackoffEntry {
+                attempts: 0,
+                retry_at: 0,
+            });
+        entry.attempts = entry.attempts.saturating_add(1);
+
+        let delay = backoff_delay(
+            policy,
+            entry.attempts,
+            uuid_to_sample_value(&Uuid::new_v4()),
+        );
+        entry.retry_at = Utc::now()
+            .timestamp_millis()
+            .saturating_add(delay.try_into().unwrap_or(i64::MAX));
+
+        self.persist();
+        delay
+    }
+
+    /// Forgets about a ping, e.g. because it was uploaded or deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `document_id` - The UUID of the ping in question.
+    pub fn remove(&mut self, document_id: &str) {
+        if self.entries.remove(document_id).is_some() {
+            self.persist();
+        }
+    }
+
+    /// Forgets about all pings not matching the predicate.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - Returns whether to keep the state of the ping with the given document id.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let count = self.entries.len();
+        self.entries.retain(|document_id, _| keep(document_id));
+        if self.entries.len() != count {
+            self.persist();
+        }
+    }
+
+    /// Persists the state, logging any error.
+    fn persist(&self) {
+        let Some(data_path) = &self.data_path else {
+            return;
+        };
+        let path = data_path.join(BACKOFF_FILENAME);
+        if self.entries.is_empty() {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    log::warn!("Failed to remove the upload backoff state. Error: {}", e);
+                }
+                _ => (),
+            }
+            return;
+        }
+
+        if let Err(e) = self.write(data_path, &path) {
+            log::warn!("Failed to persist the upload backoff state. Error: {}", e);
+        }
+    }
+
+    fn write(&self, data_path: &Path, path: &Path) -> io::Result<()> {
+        // Write to a temporary location and then move when done,
+        // for transactional writes.
+        let temp_dir = data_path.join("tmp");
+        fs::create_dir_all(&temp_dir)?;
+        let temp_path = temp_dir.join(BACKOFF_FILENAME);
+
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(serde_json::to_string(&self.entries)?.as_bytes())?;
+        }
+
+        fs::rename(&temp_path, path)
+    }
+}
+
+/// Calculates how long to wait after a failed upload attempt.
+///
+/// # Arguments
+///
+/// * `policy` - The backoff schedule in use.
+/// * `attempts` - The number of failed attempts so far, including the latest one.
+/// * `sample` - A random value in `[0, 1)` used to spread the delay.
+///
+/// # Returns
+///
+/// The delay in milliseconds.
+fn backoff_delay(policy: &UploadBackoff, attempts: u32, sample: f64) -> u64 {
+    let max_delay = policy.max_delay_ms as f64;
+    let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+    let delay =
+        (policy.base_delay_ms as f64 * policy.multiplier.max(1.0).powi(exponent)).min(max_delay);
+
+    let jitter = if policy.jitter.is_nan() {
+        0.0
+    } else {
+        policy.jitter.clamp(0.0, 1.0)
+    };
+    let factor = 1.0 - jitter + 2.0 * jitter * sample;
+
+    (delay * factor).min(max_delay) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy(jitter: f64) -> UploadBackoff {
+        UploadBackoff {
+            base_delay_ms: 1000,
+            multiplier: 2.0,
+            max_delay_ms: 10_000,
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_the_maximum() {
+        let policy = policy(0.0);
+
+        let delays: Vec<_> = (1..=6)
+            .map(|attempts| backoff_delay(&policy, attempts, 0.5))
+            .collect();
+        assert_eq!(vec![1000, 2000, 4000, 8000, 10_000, 10_000], delays);
+
+        assert_eq!(10_000, backoff_delay(&policy, u32::MAX, 0.5));
+    }
+
+    #[test]
+    fn jitter_spreads_the_delay_in_both_directions() {
+        let policy = policy(0.5);
+
+        assert_eq!(500, backoff_delay(&policy, 1, 0.0));
+        assert_eq!(1000, backoff_delay(&policy, 1, 0.5));
+        assert_eq!(1499, backoff_delay(&policy, 1, 0.999));
+
+        // Jitter never exceeds the maximum delay.
+        assert_eq!(10_000, backoff_delay(&policy, 5, 0.999));
+    }
+
+    #[test]
+    fn state_is_persisted_until_all_pings_are_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = policy(0.0);
+
+        let mut backoff = PingBackoff::load(dir.path());
+        assert_eq!(1000, backoff.record_failure("a", &policy));
+        assert_eq!(2000, backoff.record_failure("a", &policy));
+        backoff.record_failure("b", &policy);
+
+        let mut backoff = PingBackoff::load(dir.path());
+        assert_eq!(2, backoff.entries["a"].attempts);
+        assert!(backoff.remaining("a", &policy).unwrap() <= 2000);
+        assert!(backoff.remaining("c", &policy).is_none());
+
+        backoff.retain(|document_id| document_id == "b");
+        backoff.remove("b");
+        assert!(!dir.path().join(BACKOFF_FILENAME).exists());
+        assert!(PingBackoff::load(dir.path()).entries.is_empty());
+    }
+
+    #[test]
+    fn retry_times_beyond_the_maximum_delay_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = policy(0.0);
+
+        let mut backoff = PingBackoff::load(dir.path());
+        backoff.entries.insert(
+            "a".into(),
+            BackoffEntry {
+                attempts: 1,
+                retry_at: Utc::now().timestamp_millis() + 24 * 60 * 60 * 1000,
+            },
+        );
+        assert!(backoff.remaining("a", &policy).is_none());
+    }
+}
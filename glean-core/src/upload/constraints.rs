@@ -0,0 +1,211 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Constraints on when pings may be uploaded.
+//!
+//! Pings can be restricted to upload only within a window of the local day,
+//! or only while the embedder reports an unmetered network.
+//! Pings whose constraints aren't met stay in the queue
+//! while other pings are uploaded.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::Timelike;
+use malloc_size_of_derive::MallocSizeOf;
+
+use crate::util::local_now_with_offset;
+
+/// The kind of network the device is connected to, as reported by the embedder.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, MallocSizeOf)]
+pub enum NetworkType {
+    /// The network state was not reported.
+    #[default]
+    Unknown,
+    /// A network that may charge by the amount of data, e.g. cellular.
+    Metered,
+    /// A network that doesn't charge by the amount of data, e.g. Wi-Fi.
+    Unmetered,
+}
+
+/// A daily window of local time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, MallocSizeOf)]
+pub struct UploadWindow {
+    /// The hour of the day the window opens, from `0` to `23`.
+    pub start_hour: u32,
+    /// The hour of the day the window closes, from `0` to `23`.
+    ///
+    /// If this is before `start_hour`, the window spans midnight.
+    /// If it is equal to `start_hour`, the window is always open.
+    pub end_hour: u32,
+}
+
+/// Upload constraints with a field out of range.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidConstraintsError(String);
+
+impl fmt::Display for InvalidConstraintsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid upload constraints: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidConstraintsError {}
+
+impl UploadWindow {
+    /// Whether the given hour of the day is within the window.
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour < self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+/// Constraints a ping has to meet in order to be uploaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, MallocSizeOf)]
+pub struct UploadConstraints {
+    /// Only upload within this window of local time.
+    pub window: Option<UploadWindow>,
+    /// Only upload while the embedder reports an unmetered network.
+    pub unmetered_only: bool,
+}
+
+impl UploadConstraints {
+    /// Checks that all fields are in range.
+    pub fn validate(&self) -> Result<(), InvalidConstraintsError> {
+        match self.window {
+            Some(window) if window.start_hour > 23 || window.end_hour > 23 => Err(
+                InvalidConstraintsError("the window hours must be between 0 and 23".into()),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the constraints are met.
+    ///
+    /// # Arguments
+    ///
+    /// * `hour` - The current hour of the local day.
+    /// * `network` - The current network type.
+    pub fn is_met(&self, hour: u32, network: NetworkType) -> bool {
+        self.window.is_none_or(|window| window.contains(hour))
+            && (!self.unmetered_only || network == NetworkType::Unmetered)
+    }
+}
+
+/// The upload constraints of all pings and the state they are checked against.
+#[derive(Debug, Default, MallocSizeOf)]
+pub struct UploadConstraintState {
+    /// The constraints by ping name.
+    per_ping: HashMap<String, UploadConstraints>,
+    /// The network type last reported by the embedder.
+    network: NetworkType,
+}
+
+impl UploadConstraintState {
+    /// Sets the upload constraints of a ping.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `constraints` - The constraints, `None` to upload the ping unconstrained.
+    pub fn set_for_ping(&mut self, ping_name: &str, constraints: Option<UploadConstraints>) {
+        match constraints {
+            Some(constraints) => self.per_ping.insert(ping_name.to_string(), constraints),
+            None => self.per_ping.remove(ping_name),
+        };
+    }
+
+    /// Sets the current network type.
+    ///
+    /// # Returns
+    ///
+    /// Whether the network type changed.
+    pub fn set_network(&mut self, network: NetworkType) -> bool {
+        std::mem::replace(&mut self.network, network) != network
+    }
+
+    /// Returns a check for whether a ping's constraints are currently met.
+    ///
+    /// The current time is taken once, so that all pings are checked against the same hour.
+    pub fn checker(&self) -> impl Fn(&str) -> bool + '_ {
+        let hour = local_now_with_offset().hour();
+        move |ping_name| {
+            self.per_ping
+                .get(ping_name)
+                .is_none_or(|constraints| constraints.is_met(hour, self.network))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn windows_can_span_midnight() {
+        let night = UploadWindow {
+            start_hour: 22,
+            end_hour: 5,
+        };
+        assert!(night.contains(23));
+        assert!(night.contains(0));
+        assert!(night.contains(4));
+        assert!(!night.contains(5));
+        assert!(!night.contains(12));
+
+        let early = UploadWindow {
+            start_hour: 1,
+            end_hour: 5,
+        };
+        assert!(early.contains(1));
+        assert!(!early.contains(5));
+        assert!(!early.contains(0));
+
+        let always = UploadWindow {
+            start_hour: 3,
+            end_hour: 3,
+        };
+        assert!((0..24).all(|hour| always.contains(hour)));
+    }
+
+    #[test]
+    fn window_hours_must_be_in_range() {
+        let constraints = |start_hour, end_hour| UploadConstraints {
+            window: Some(UploadWindow {
+                start_hour,
+                end_hour,
+            }),
+            unmetered_only: false,
+        };
+        assert_eq!(Ok(()), constraints(0, 23).validate());
+        assert!(constraints(24, 5).validate().is_err());
+        assert!(constraints(22, 24).validate().is_err());
+        assert_eq!(Ok(()), UploadConstraints::default().validate());
+    }
+
+    #[test]
+    fn unmetered_only_requires_a_reported_unmetered_network() {
+        let constraints = UploadConstraints {
+            window: None,
+            unmetered_only: true,
+        };
+        assert!(!constraints.is_met(12, NetworkType::Unknown));
+        assert!(!constraints.is_met(12, NetworkType::Metered));
+        assert!(constraints.is_met(12, NetworkType::Unmetered));
+
+        let constraints = UploadConstraints {
+            window: Some(UploadWindow {
+                start_hour: 1,
+                end_hour: 5,
+            }),
+            unmetered_only: true,
+        };
+        assert!(!constraints.is_met(12, NetworkType::Unmetered));
+        assert!(constraints.is_met(2, NetworkType::Unmetered));
+        assert!(UploadConstraints::default().is_met(12, NetworkType::Unknown));
+    }
+}
@@ -5,9 +5,10 @@
 //! Pings directory processing utilities.
 
 use std::cmp::Ordering;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 use malloc_size_of::MallocSizeOf;
 use malloc_size_of_derive::MallocSizeOf;
@@ -15,6 +16,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::request::HeaderMap;
+use crate::encryption::{self, Cipher};
+use crate::metrics::PingPriority;
 use crate::{DELETION_REQUEST_PINGS_DIRECTORY, PENDING_PINGS_DIRECTORY};
 
 /// A representation of the data extracted from a ping file,
@@ -34,6 +37,8 @@ pub struct PingPayload {
     pub ping_name: String,
     /// The capabilities this ping must be uploaded under.
     pub uploader_capabilities: Vec<String>,
+    /// The priority to upload this ping with.
+    pub priority: PingPriority,
 }
 
 /// A struct to hold the result of scanning all pings directories.
@@ -114,6 +119,9 @@ pub struct PingMetadata {
     pub ping_name: Option<String>,
     /// The capabilities this ping must be uploaded under.
     pub uploader_capabilities: Option<Vec<String>>,
+    /// The priority to upload this ping with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<PingPriority>,
 }
 
 /// Processes a ping's metadata.
@@ -131,13 +139,93 @@ pub fn process_metadata(path: &str, metadata: &str) -> Option<PingMetadata> {
     None
 }
 
+/// The lines of a ping file.
+pub(crate) struct PingFileContents {
+    /// The path to upload the ping to.
+    pub path: String,
+    /// The ping body as JSON-encoded string.
+    pub body: String,
+    /// The ping metadata, if any.
+    pub metadata: Option<String>,
+}
+
+/// Reads a ping file, decrypting it if necessary.
+///
+/// The way the ping file is structured:
+/// first line should always have the path,
+/// second line should have the body with the ping contents in JSON format
+/// and third line might contain ping metadata e.g. additional headers.
+///
+/// # Arguments
+///
+/// * `path` - The path of the ping file.
+/// * `cipher` - The cipher to decrypt the file with, if encryption at rest is enabled.
+///
+/// # Returns
+///
+/// An error if the file can't be read, which includes encrypted files without a cipher,
+/// or `None` if it isn't formatted as expected.
+pub(crate) fn read_ping_file(
+    path: &Path,
+    cipher: Option<&Cipher>,
+) -> io::Result<Option<PingFileContents>> {
+    parse_ping_file(fs::read(path)?, cipher)
+}
+
+/// Parses the contents of a ping file, see [`read_ping_file`].
+fn parse_ping_file(data: Vec<u8>, cipher: Option<&Cipher>) -> io::Result<Option<PingFileContents>> {
+    let data = match cipher {
+        Some(cipher) => match cipher.decrypt(&data) {
+            Some(data) => data.into_owned(),
+            None => return Ok(None),
+        },
+        None if encryption::is_encrypted(&data) => {
+            return Err(io::Error::other("the ping file is encrypted"))
+        }
+        None => data,
+    };
+    let Ok(text) = String::from_utf8(data) else {
+        return Ok(None);
+    };
+
+    let mut lines = text.lines();
+    match (lines.next(), lines.next()) {
+        (Some(path), Some(body)) => Ok(Some(PingFileContents {
+            path: path.to_string(),
+            body: body.to_string(),
+            metadata: lines.next().map(str::to_string),
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// A ping file kept in memory instead of a pings directory.
+#[derive(Debug)]
+struct MemoryFile {
+    /// The name of the pings directory the file would be stored in.
+    directory: &'static str,
+    /// The document id of the ping, its file name.
+    document_id: String,
+    /// The file contents.
+    contents: Vec<u8>,
+}
+
 /// Manages the pings directories.
+///
+/// An in-memory manager, see [`PingDirectoryManager::new_in_memory`],
+/// keeps the ping files in memory instead, in the order they were stored.
 #[derive(Debug, Clone, MallocSizeOf)]
 pub struct PingDirectoryManager {
     /// Path to the pending pings directory.
     pending_pings_dir: PathBuf,
     /// Path to the deletion-request pings directory.
     deletion_request_pings_dir: PathBuf,
+    /// The cipher ping files are encrypted with, if any.
+    #[ignore_malloc_size_of = "holds no heap allocations of its own"]
+    cipher: Option<Arc<Cipher>>,
+    /// The ping files, if they are kept in memory.
+    #[ignore_malloc_size_of = "the pings are measured in the upload queue"]
+    memory_files: Option<Arc<RwLock<Vec<MemoryFile>>>>,
 }
 
 impl PingDirectoryManager {
@@ -151,7 +239,106 @@ impl PingDirectoryManager {
         Self {
             pending_pings_dir: data_path.join(PENDING_PINGS_DIRECTORY),
             deletion_request_pings_dir: data_path.join(DELETION_REQUEST_PINGS_DIRECTORY),
+            cipher: None,
+            memory_files: None,
+        }
+    }
+
+    /// Creates a new directory manager that keeps the ping files in memory.
+    ///
+    /// Nothing is read from or written to disk, the files are dropped with the last clone of it.
+    pub fn new_in_memory() -> Self {
+        Self {
+            pending_pings_dir: PathBuf::from(PENDING_PINGS_DIRECTORY),
+            deletion_request_pings_dir: PathBuf::from(DELETION_REQUEST_PINGS_DIRECTORY),
+            cipher: None,
+            memory_files: Some(Arc::default()),
+        }
+    }
+
+    /// Whether the ping files are kept in memory.
+    pub fn is_in_memory(&self) -> bool {
+        self.memory_files.is_some()
+    }
+
+    /// Stores a ping file in memory.
+    ///
+    /// Does nothing if the ping files are stored on disk.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The name of the pings directory the file belongs in.
+    /// * `document_id` - The document id of the ping.
+    /// * `contents` - The file contents.
+    pub(crate) fn store_in_memory(
+        &self,
+        directory: &'static str,
+        document_id: &str,
+        contents: Vec<u8>,
+    ) {
+        let Some(files) = &self.memory_files else {
+            log::warn!(
+                "Not storing ping {} in memory, pings are stored on disk.",
+                document_id
+            );
+            return;
+        };
+        let mut files = files.write().unwrap(); // safe unwrap, only error case is poisoning
+        files.retain(|file| file.document_id != document_id);
+        files.push(MemoryFile {
+            directory,
+            document_id: document_id.to_string(),
+            contents,
+        });
+    }
+
+    /// Writes the ping files kept in memory into the pings directories of a data directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - The data directory to write the files to.
+    pub(crate) fn write_memory_files(&self, data_path: &Path) -> io::Result<()> {
+        let Some(files) = &self.memory_files else {
+            return Ok(());
+        };
+        let files = files.read().unwrap(); // safe unwrap, only error case is poisoning
+        for file in files.iter() {
+            let dir = data_path.join(file.directory);
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(&file.document_id), &file.contents)?;
         }
+        Ok(())
+    }
+
+    /// Sets the cipher ping files are encrypted with.
+    ///
+    /// Without a cipher, encrypted ping files are left alone.
+    pub(crate) fn set_cipher(&mut self, cipher: Option<Arc<Cipher>>) {
+        self.cipher = cipher;
+    }
+
+    /// Encrypts all ping files stored in plaintext.
+    ///
+    /// Only does something when a cipher is set.
+    pub(crate) fn encrypt_plaintext_files(&self) -> io::Result<()> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(());
+        };
+
+        for dir in [&self.pending_pings_dir, &self.deletion_request_pings_dir] {
+            let entries = match dir.read_dir() {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if get_file_name_as_str(&path).is_some_and(|name| Uuid::parse_str(name).is_ok()) {
+                    encryption::encrypt_file(cipher, &path)?;
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Attempts to delete a ping file.
@@ -168,6 +355,17 @@ impl PingDirectoryManager {
     ///
     /// Won't panic if unable to delete the file.
     pub fn delete_file(&self, uuid: &str) -> bool {
+        if let Some(files) = &self.memory_files {
+            let mut files = files.write().unwrap(); // safe unwrap, only error case is poisoning
+            let Some(index) = files.iter().position(|file| file.document_id == uuid) else {
+                log::warn!("Cannot find ping file to delete {}", uuid);
+                return false;
+            };
+            files.remove(index);
+            log::info!("Ping {} was deleted from memory", uuid);
+            return true;
+        }
+
         let path = match self.get_file_path(uuid) {
             Some(path) => path,
             None => {
@@ -195,36 +393,26 @@ impl PingDirectoryManager {
     ///
     /// * `document_id` - The UUID of the ping file to be processed
     pub fn process_file(&self, document_id: &str) -> Option<PingPayload> {
-        let path = match self.get_file_path(document_id) {
-            Some(path) => path,
-            None => {
-                log::warn!("Cannot find ping file to process {}", document_id);
-                return None;
-            }
-        };
-        let file = match File::open(&path) {
-            Ok(file) => file,
+        let contents = match self.read_file(document_id)? {
+            Ok(contents) => contents,
             Err(e) => {
-                log::warn!("Error reading ping file {}. {}", path.display(), e);
+                log::warn!("Error reading ping file {}. {}", document_id, e);
                 return None;
             }
         };
 
-        log::info!("Processing ping at: {}", path.display());
-
-        // The way the ping file is structured:
-        // first line should always have the path,
-        // second line should have the body with the ping contents in JSON format
-        // and third line might contain ping metadata e.g. additional headers.
-        let mut lines = BufReader::new(file).lines();
-        if let (Some(Ok(path)), Some(Ok(body)), Ok(metadata)) =
-            (lines.next(), lines.next(), lines.next().transpose())
+        if let Some(PingFileContents {
+            path,
+            body,
+            metadata,
+        }) = contents
         {
             let PingMetadata {
                 headers,
                 body_has_info_sections,
                 ping_name,
                 uploader_capabilities,
+                priority,
             } = metadata
                 .and_then(|m| process_metadata(&path, &m))
                 .unwrap_or_default();
@@ -238,6 +426,7 @@ impl PingDirectoryManager {
                 body_has_info_sections: body_has_info_sections.unwrap_or(true),
                 ping_name,
                 uploader_capabilities: uploader_capabilities.unwrap_or_default(),
+                priority: priority.unwrap_or_default(),
             });
         } else {
             log::warn!(
@@ -249,8 +438,35 @@ impl PingDirectoryManager {
         None
     }
 
+    /// Reads a ping file, from disk or memory.
+    ///
+    /// Returns `None` if there is no ping file for the document id.
+    fn read_file(&self, document_id: &str) -> Option<io::Result<Option<PingFileContents>>> {
+        let cipher = self.cipher.as_deref();
+        if let Some(files) = &self.memory_files {
+            let files = files.read().unwrap(); // safe unwrap, only error case is poisoning
+            let Some(file) = files.iter().find(|file| file.document_id == document_id) else {
+                log::warn!("Cannot find ping file to process {}", document_id);
+                return None;
+            };
+            log::info!("Processing ping {} in memory", document_id);
+            return Some(parse_ping_file(file.contents.clone(), cipher));
+        }
+
+        let Some(path) = self.get_file_path(document_id) else {
+            log::warn!("Cannot find ping file to process {}", document_id);
+            return None;
+        };
+        log::info!("Processing ping at: {}", path.display());
+        Some(read_ping_file(&path, cipher))
+    }
+
     /// Processes both ping directories.
     pub fn process_dirs(&self) -> PingPayloadsByDirectory {
+        if let Some(files) = &self.memory_files {
+            return self.process_memory_files(files);
+        }
+
         PingPayloadsByDirectory {
             pending_pings: self.process_dir(&self.pending_pings_dir),
             deletion_request_pings: self.process_dir(&self.deletion_request_pings_dir),
@@ -330,11 +546,44 @@ impl PingDirectoryManager {
             .collect()
     }
 
+    /// Processes the ping files kept in memory, in the order they were stored.
+    fn process_memory_files(&self, files: &RwLock<Vec<MemoryFile>>) -> PingPayloadsByDirectory {
+        // Invalid files are deleted while processing, so don't hold on to the lock.
+        let files: Vec<_> = files
+            .read()
+            .unwrap() // safe unwrap, only error case is poisoning
+            .iter()
+            .map(|file| {
+                (
+                    file.directory,
+                    file.document_id.clone(),
+                    file.contents.len() as u64,
+                )
+            })
+            .collect();
+
+        let mut pings = PingPayloadsByDirectory::default();
+        for (directory, document_id, size) in files {
+            let Some(ping) = self.process_file(&document_id) else {
+                continue;
+            };
+            if directory == DELETION_REQUEST_PINGS_DIRECTORY {
+                pings.deletion_request_pings.push((size, ping));
+            } else {
+                pings.pending_pings.push((size, ping));
+            }
+        }
+        pings
+    }
+
     /// Gets the path for a ping file based on its document_id.
     ///
     /// Will look for files in each ping directory until something is found.
-    /// If nothing is found, returns `None`.
-    fn get_file_path(&self, document_id: &str) -> Option<PathBuf> {
+    /// If nothing is found, or the ping files are kept in memory, returns `None`.
+    pub fn get_file_path(&self, document_id: &str) -> Option<PathBuf> {
+        if self.is_in_memory() {
+            return None;
+        }
         for dir in [&self.pending_pings_dir, &self.deletion_request_pings_dir].iter() {
             let path = dir.join(document_id);
             if path.exists() {
@@ -347,6 +596,8 @@ impl PingDirectoryManager {
 
 #[cfg(test)]
 mod test {
+    use std::fs::File;
+
     use super::*;
     use crate::metrics::PingType;
     use crate::tests::new_glean;
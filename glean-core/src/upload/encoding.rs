@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Content encodings for ping payloads.
+//!
+//! Ping payloads are gzip-encoded by default.
+//! Other encodings are available behind the `zstd` and `brotli` features
+//! and are selected per ping or by the encodings the uploader accepts.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use malloc_size_of_derive::MallocSizeOf;
+
+/// A content encoding for ping payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MallocSizeOf)]
+pub enum ContentEncoding {
+    /// gzip, supported by all Glean endpoints.
+    Gzip,
+    /// Zstandard.
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// Brotli.
+    #[cfg(feature = "brotli")]
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// The encoding's name, as used in the `Content-Encoding` header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => "zstd",
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// Looks up an encoding by its name, as used in the `Content-Encoding` header.
+    ///
+    /// Returns `None` for unknown encodings and those not compiled in.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" => Some(ContentEncoding::Gzip),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(ContentEncoding::Zstd),
+            #[cfg(feature = "brotli")]
+            "br" => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Encodes the content.
+    pub fn encode(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            ContentEncoding::Gzip => {
+                let mut gzipper = GzEncoder::new(Vec::new(), Compression::default());
+                gzipper.write_all(content)?;
+                gzipper.finish()
+            }
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => zstd::encode_all(content, zstd::DEFAULT_COMPRESSION_LEVEL),
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => {
+                let mut encoded = Vec::new();
+                {
+                    // Buffer size, quality (0-11) and window size (log2) as recommended by brotli.
+                    let mut writer = brotli::CompressorWriter::new(&mut encoded, 4096, 9, 22);
+                    writer.write_all(content)?;
+                }
+                Ok(encoded)
+            }
+        }
+    }
+
+    /// Decodes the content.
+    pub fn decode(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoded = Vec::with_capacity(content.len());
+        match self {
+            ContentEncoding::Gzip => {
+                GzDecoder::new(content).read_to_end(&mut decoded)?;
+            }
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd => {
+                decoded = zstd::decode_all(content)?;
+            }
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli => {
+                brotli::Decompressor::new(content, 4096).read_to_end(&mut decoded)?;
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+/// Which content encoding to use for which ping.
+#[derive(Debug, Default, MallocSizeOf)]
+pub struct EncodingPreferences {
+    /// The encodings the uploader accepts, in order of preference.
+    accepted: Vec<ContentEncoding>,
+    /// Encodings chosen for individual pings, by ping name.
+    per_ping: HashMap<String, ContentEncoding>,
+}
+
+impl EncodingPreferences {
+    /// Sets the encodings the uploader accepts, in order of preference.
+    ///
+    /// Unknown encodings are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `encodings` - The names of the accepted encodings, as used in the `Content-Encoding` header.
+    pub fn set_accepted(&mut self, encodings: &[String]) {
+        self.accepted = encodings
+            .iter()
+            .filter_map(|name| {
+                let encoding = ContentEncoding::from_name(name);
+                if encoding.is_none() {
+                    log::warn!("Ignoring unsupported content encoding {:?}.", name);
+                }
+                encoding
+            })
+            .collect();
+    }
+
+    /// Sets the encoding for a single ping, overriding the accepted encodings.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `encoding` - The encoding to use, `None` to go back to the accepted encodings.
+    pub fn set_for_ping(&mut self, ping_name: &str, encoding: Option<ContentEncoding>) {
+        match encoding {
+            Some(encoding) => {
+                self.per_ping.insert(ping_name.to_string(), encoding);
+            }
+            None => {
+                self.per_ping.remove(ping_name);
+            }
+        }
+    }
+
+    /// The encoding to use for a ping.
+    ///
+    /// That is the one set for this ping, or else the uploader's preferred encoding,
+    /// falling back to gzip.
+    pub fn select(&self, ping_name: &str) -> ContentEncoding {
+        self.per_ping
+            .get(ping_name)
+            .or_else(|| self.accepted.first())
+            .copied()
+            .unwrap_or(ContentEncoding::Gzip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn available() -> Vec<ContentEncoding> {
+        vec![
+            ContentEncoding::Gzip,
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd,
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli,
+        ]
+    }
+
+    #[test]
+    fn encodings_roundtrip() {
+        let content = br#"{"ping_info":{"seq":1}}"#.repeat(10);
+        for encoding in available() {
+            let encoded = encoding.encode(&content).unwrap();
+            assert_ne!(content, encoded);
+            assert_eq!(content, encoding.decode(&encoded).unwrap());
+            assert_eq!(Some(encoding), ContentEncoding::from_name(encoding.name()));
+        }
+    }
+
+    #[test]
+    fn per_ping_encodings_take_precedence() {
+        let mut preferences = EncodingPreferences::default();
+        assert_eq!(ContentEncoding::Gzip, preferences.select("metrics"));
+
+        preferences.set_accepted(&["unknown".to_string(), "gzip".to_string()]);
+        assert_eq!(ContentEncoding::Gzip, preferences.select("metrics"));
+
+        let encoding = *available().last().unwrap();
+        preferences.set_for_ping("metrics", Some(encoding));
+        assert_eq!(encoding, preferences.select("metrics"));
+        assert_eq!(ContentEncoding::Gzip, preferences.select("baseline"));
+
+        preferences.set_for_ping("metrics", None);
+        assert_eq!(ContentEncoding::Gzip, preferences.select("metrics"));
+    }
+}
@@ -12,10 +12,10 @@
 //!   API to check the HTTP response from the ping upload and either delete the
 //!   corresponding ping from disk or re-enqueue it for sending.
 
-use std::collections::HashMap;
 use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, RwLock, RwLockWriteGuard};
 use std::time::{Duration, Instant};
@@ -24,24 +24,40 @@ use chrono::Utc;
 use malloc_size_of::MallocSizeOf;
 use malloc_size_of_derive::MallocSizeOf;
 
-use crate::error::ErrorKind;
-use crate::TimerId;
+use crate::encryption::Cipher;
+use crate::error::{ErrorKind, Result};
 use crate::{internal_metrics::UploadMetrics, Glean};
+use crate::{TimerId, UploadBackoff};
+use backoff::PingBackoff;
+use constraints::UploadConstraintState;
 pub use directory::process_metadata;
+pub(crate) use directory::{read_ping_file, PingFileContents};
 use directory::{PingDirectoryManager, PingPayloadsByDirectory};
+use encoding::EncodingPreferences;
 use policy::Policy;
+use quarantine::PingQuarantine;
 use request::create_date_header_value;
 
+pub use archive::{export_pending_pings, read_archive, write_archive, ArchivedPing};
+pub use constraints::{NetworkType, UploadConstraints, UploadWindow};
 pub use directory::{PingMetadata, PingPayload};
+pub use encoding::ContentEncoding;
+pub use quarantine::QuarantinedPing;
 pub use request::{HeaderMap, PingRequest};
 pub use result::{UploadResult, UploadTaskAction};
 
+mod archive;
+mod backoff;
+mod constraints;
 mod directory;
+mod encoding;
 mod policy;
+mod quarantine;
 mod request;
 mod result;
 
 const WAIT_TIME_FOR_PING_PROCESSING: u64 = 1000; // in milliseconds
+const WAIT_TIME_FOR_UPLOAD_CONSTRAINTS: u64 = 60 * 1000; // in milliseconds
 
 #[derive(Debug, MallocSizeOf)]
 struct RateLimiter {
@@ -55,18 +71,6 @@ struct RateLimiter {
     max_count: u32,
 }
 
-/// An enum to represent the current state of the RateLimiter.
-#[derive(PartialEq)]
-enum RateLimiterState {
-    /// The RateLimiter has not reached the maximum count and is still incrementing.
-    Incrementing,
-    /// The RateLimiter has reached the maximum count for the  current interval.
-    ///
-    /// This variant contains the remaining time (in milliseconds)
-    /// until the rate limiter is not throttled anymore.
-    Throttled(u64),
-}
-
 impl RateLimiter {
     pub fn new(interval: Duration, max_count: u32) -> Self {
         Self {
@@ -104,29 +108,32 @@ impl RateLimiter {
         false
     }
 
-    /// Tries to increment the internal counter.
+    /// Tries to increment the internal counter by up to `count`.
     ///
     /// # Returns
     ///
-    /// The current state of the RateLimiter.
-    pub fn get_state(&mut self) -> RateLimiterState {
+    /// The number the counter was incremented by,
+    /// which might be less than `count` if the maximum count is reached.
+    /// If the maximum count for the current interval was already reached,
+    /// returns the remaining time (in milliseconds) until the rate limiter
+    /// is not throttled anymore.
+    pub fn reserve(&mut self, count: u32) -> Result<u32, u64> {
         if self.should_reset() {
             self.reset();
         }
 
-        if self.count == self.max_count {
+        if self.count >= self.max_count {
             // Note that `remining` can't be a negative number because we just called `reset`,
             // which will check if it is and reset if so.
             let remaining = self.interval.as_millis() - self.elapsed().as_millis();
-            return RateLimiterState::Throttled(
-                remaining
-                    .try_into()
-                    .unwrap_or(self.interval.as_secs() * 1000),
-            );
+            return Err(remaining
+                .try_into()
+                .unwrap_or(self.interval.as_secs() * 1000));
         }
 
-        self.count += 1;
-        RateLimiterState::Incrementing
+        let reserved = count.min(self.max_count - self.count);
+        self.count += reserved;
+        Ok(reserved)
     }
 }
 
@@ -144,6 +151,7 @@ pub enum PingUploadTask {
     },
 
     /// A flag signaling that the pending pings directories are not done being processed,
+    /// or that all pending pings wait for their upload constraints to be met,
     /// thus the requester should wait and come back later.
     Wait {
         /// The time in milliseconds
@@ -153,12 +161,14 @@ pub enum PingUploadTask {
 
     /// A flag signaling that requester doesn't need to request any more upload tasks at this moment.
     ///
-    /// There are three possibilities for this scenario:
+    /// There are four possibilities for this scenario:
     /// * Pending pings queue is empty, no more pings to request;
     /// * Requester has gotten more than MAX_WAIT_ATTEMPTS (3, by default) `PingUploadTask::Wait` responses in a row;
     /// * Requester has reported more than MAX_RECOVERABLE_FAILURES_PER_UPLOADING_WINDOW
     ///   recoverable upload failures on the same uploading window (see below)
-    ///   and should stop requesting at this moment.
+    ///   and should stop requesting at this moment;
+    /// * All pending pings are backing off from previous upload failures.
+    ///   They are handed out again on a later request once their backoff delay elapsed.
     ///
     /// An "uploading window" starts when a requester gets a new
     /// `PingUploadTask::Upload(PingRequest)` response and finishes when they
@@ -186,6 +196,54 @@ impl PingUploadTask {
     }
 }
 
+/// An enum representing the possible batch upload tasks to be performed by an uploader.
+///
+/// This is the batch equivalent of [`PingUploadTask`],
+/// returned by [`get_upload_batch`](PingUploadManager::get_upload_batch).
+#[derive(PartialEq, Eq, Debug)]
+pub enum PingUploadBatchTask {
+    /// An upload task for one or more pings.
+    Upload {
+        /// The ping requests for upload, in upload order.
+        /// See [`PingRequest`](struct.PingRequest.html) for more information.
+        requests: Vec<PingRequest>,
+    },
+
+    /// A flag signaling that the requester should wait and come back later.
+    ///
+    /// See [`PingUploadTask::Wait`].
+    Wait {
+        /// The time in milliseconds
+        /// the requester should wait before requesting a new task.
+        time: u64,
+    },
+
+    /// A flag signaling that requester doesn't need to request any more upload tasks at this moment.
+    ///
+    /// See [`PingUploadTask::Done`].
+    Done {
+        #[doc(hidden)]
+        /// Unused field. Required because UniFFI can't handle variants without fields.
+        unused: i8,
+    },
+}
+
+impl PingUploadBatchTask {
+    /// Whether the current task is an upload task.
+    pub fn is_upload(&self) -> bool {
+        matches!(self, PingUploadBatchTask::Upload { .. })
+    }
+
+    /// Whether the current task is wait task.
+    pub fn is_wait(&self) -> bool {
+        matches!(self, PingUploadBatchTask::Wait { .. })
+    }
+
+    pub(crate) fn done() -> Self {
+        PingUploadBatchTask::Done { unused: 0 }
+    }
+}
+
 /// Manages the pending pings queue and directory.
 #[derive(Debug)]
 pub struct PingUploadManager {
@@ -201,6 +259,8 @@ pub struct PingUploadManager {
     recoverable_failure_count: AtomicU32,
     /// The number or times in a row a user has received a `PingUploadTask::Wait` response.
     wait_attempt_count: AtomicU32,
+    /// Until when uploads are paused, as requested by the server.
+    paused_until: RwLock<Option<Instant>>,
     /// A ping counter to help rate limit the ping uploads.
     ///
     /// To keep resource usage in check,
@@ -214,6 +274,14 @@ pub struct PingUploadManager {
     upload_metrics: UploadMetrics,
     /// Policies for ping storage, uploading and requests.
     policy: Policy,
+    /// The backoff state of pings that failed to upload.
+    backoff: RwLock<PingBackoff>,
+    /// The content encodings to use for ping payloads.
+    encodings: RwLock<EncodingPreferences>,
+    /// Pings that permanently failed to upload.
+    quarantine: RwLock<PingQuarantine>,
+    /// When pings may be uploaded.
+    constraints: RwLock<UploadConstraintState>,
 
     in_flight: RwLock<HashMap<String, (TimerId, TimerId)>>,
 }
@@ -249,7 +317,11 @@ impl MallocSizeOf for PingUploadManager {
             }).unwrap_or(0)
             + self.language_binding_name.size_of(ops)
             + self.upload_metrics.size_of(ops)
-            + self.policy.size_of(ops);
+            + self.policy.size_of(ops)
+            + self.backoff.read().unwrap().size_of(ops)
+            + self.encodings.read().unwrap().size_of(ops)
+            + self.quarantine.read().unwrap().size_of(ops)
+            + self.constraints.read().unwrap().size_of(ops);
 
         let in_flight = self.in_flight.read().unwrap();
         n += in_flight.size_of(ops);
@@ -270,17 +342,54 @@ impl PingUploadManager {
     ///
     /// Will panic if unable to spawn a new thread.
     pub fn new<P: Into<PathBuf>>(data_path: P, language_binding_name: &str) -> Self {
+        let data_path = data_path.into();
+        Self::with_storage(
+            PingDirectoryManager::new(&data_path),
+            PingBackoff::load(&data_path),
+            PingQuarantine::new(&data_path),
+            language_binding_name,
+        )
+    }
+
+    /// Creates a new PingUploadManager that keeps pending pings in memory only.
+    ///
+    /// Nothing is read from or written to disk: the upload backoff state isn't persisted
+    /// and pings that permanently failed to upload are never quarantined.
+    ///
+    /// # Arguments
+    ///
+    /// * `language_binding_name` - The name of the language binding calling this managers instance.
+    pub fn new_in_memory(language_binding_name: &str) -> Self {
+        Self::with_storage(
+            PingDirectoryManager::new_in_memory(),
+            PingBackoff::in_memory(),
+            PingQuarantine::new(Path::new("")),
+            language_binding_name,
+        )
+    }
+
+    fn with_storage(
+        directory_manager: PingDirectoryManager,
+        backoff: PingBackoff,
+        quarantine: PingQuarantine,
+        language_binding_name: &str,
+    ) -> Self {
         Self {
             queue: RwLock::new(VecDeque::new()),
-            directory_manager: PingDirectoryManager::new(data_path),
+            backoff: RwLock::new(backoff),
+            quarantine: RwLock::new(quarantine),
+            directory_manager,
             processed_pending_pings: Arc::new(AtomicBool::new(false)),
             cached_pings: Arc::new(RwLock::new(PingPayloadsByDirectory::default())),
             recoverable_failure_count: AtomicU32::new(0),
             wait_attempt_count: AtomicU32::new(0),
+            paused_until: RwLock::new(None),
             rate_limiter: None,
             language_binding_name: language_binding_name.into(),
             upload_metrics: UploadMetrics::new(),
             policy: Policy::default(),
+            encodings: RwLock::new(EncodingPreferences::default()),
+            constraints: RwLock::new(UploadConstraintState::default()),
             in_flight: RwLock::new(HashMap::default()),
         }
     }
@@ -332,10 +441,12 @@ impl PingUploadManager {
         upload_manager.policy.set_max_recoverable_failures(None);
         upload_manager.policy.set_max_wait_attempts(None);
         upload_manager.policy.set_max_ping_body_size(None);
+        upload_manager.policy.set_max_retry_after(None);
         upload_manager
             .policy
             .set_max_pending_pings_directory_size(None);
         upload_manager.policy.set_max_pending_pings_count(None);
+        upload_manager.policy.set_backoff(None);
 
         // When building for tests, always scan the pending pings directories and do it sync.
         upload_manager
@@ -371,7 +482,9 @@ impl PingUploadManager {
             body_has_info_sections,
             ping_name,
             uploader_capabilities,
+            priority,
         } = ping;
+        let content_encoding = self.encodings.read().unwrap().select(&ping_name);
         let mut request = PingRequest::builder(
             &self.language_binding_name,
             self.policy.max_ping_body_size(),
@@ -379,9 +492,11 @@ impl PingUploadManager {
         .document_id(&document_id)
         .path(path)
         .body(body)
+        .content_encoding(content_encoding)
         .body_has_info_sections(body_has_info_sections)
         .ping_name(ping_name)
-        .uploader_capabilities(uploader_capabilities);
+        .uploader_capabilities(uploader_capabilities)
+        .priority(priority);
 
         if let Some(headers) = headers {
             request = request.headers(headers);
@@ -407,6 +522,9 @@ impl PingUploadManager {
     }
 
     /// Enqueue a ping for upload.
+    ///
+    /// The ping is queued behind all pings of the same or a higher priority,
+    /// but ahead of pings of a lower priority.
     pub fn enqueue_ping(&self, glean: &Glean, ping: PingPayload) {
         let mut queue = self
             .queue
@@ -448,7 +566,11 @@ impl PingUploadManager {
 
         log::trace!("Enqueuing ping {} at {}", document_id, path);
         if let Some(request) = self.build_ping_request(glean, ping) {
-            queue.push_back(request)
+            let position = queue
+                .iter()
+                .rposition(|queued| queued.priority <= request.priority)
+                .map_or(0, |i| i + 1);
+            queue.insert(position, request)
         }
     }
 
@@ -495,10 +617,15 @@ impl PingUploadManager {
 
             // The pending pings vector is sorted by date in ascending order (oldest -> newest).
             // We need to calculate the size of the pending pings directory
-            // and delete the **oldest** pings in case quota is reached.
-            // Thus, we reverse the order of the pending pings vector,
-            // so that we iterate in descending order (newest -> oldest).
+            // and delete the **oldest** pings of the **lowest** priority in case quota is reached.
+            // Thus, we reverse the order of the pending pings vector
+            // and stably sort it by priority,
+            // so that we iterate from the highest priority to the lowest,
+            // each in descending order (newest -> oldest).
             cached_pings.pending_pings.reverse();
+            cached_pings
+                .pending_pings
+                .sort_by_key(|(_, ping)| ping.priority);
             cached_pings.pending_pings.retain(|(file_size, PingPayload {document_id, ..})| {
                 pending_pings_count += 1;
                 pending_pings_directory_size += file_size;
@@ -539,8 +666,12 @@ impl PingUploadManager {
                 true
             });
             // After calculating the size of the pending pings directory,
-            // we record the calculated number and reverse the pings array back for enqueueing.
+            // we record the calculated number and put the pings array back
+            // in ascending order (oldest -> newest) per priority for enqueueing.
             cached_pings.pending_pings.reverse();
+            cached_pings
+                .pending_pings
+                .sort_by_key(|(_, ping)| ping.priority);
             self.upload_metrics
                 .pending_pings_directory_size
                 .accumulate_sync(glean, pending_pings_directory_size as i64 / 1024);
@@ -555,6 +686,20 @@ impl PingUploadManager {
                 .pending_pings
                 .drain(..)
                 .for_each(|(_, ping)| self.enqueue_ping(glean, ping));
+
+            // Forget the backoff state of pings that no longer exist.
+            let queue = self
+                .queue
+                .read()
+                .expect("Can't read from pending pings queue.");
+            self.backoff
+                .write()
+                .expect("Can't write to the upload backoff state.")
+                .retain(|document_id| {
+                    queue
+                        .iter()
+                        .any(|request| request.document_id == document_id)
+                });
         }
     }
 
@@ -585,6 +730,92 @@ impl PingUploadManager {
         self.policy.set_max_pending_pings_directory_size(Some(n));
     }
 
+    pub(crate) fn set_backoff(&mut self, backoff: UploadBackoff) {
+        self.policy.set_backoff(Some(backoff));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_max_ping_body_size(&mut self, n: usize) {
+        self.policy.set_max_ping_body_size(Some(n));
+    }
+
+    /// Whether a ping body is larger than the maximum ping body size, once encoded for upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping, used to select its content encoding.
+    /// * `body` - The serialized JSON body of the ping.
+    pub(crate) fn exceeds_max_ping_body_size(&self, ping_name: &str, body: &str) -> bool {
+        let max_size = self.policy.max_ping_body_size();
+        // Encoding does not make a JSON body any larger in practice,
+        // so only bodies above the limit need to be encoded to know for sure.
+        if body.len() <= max_size {
+            return false;
+        }
+
+        let encoding = self.encodings.read().unwrap().select(ping_name);
+        match encoding.encode(body.as_bytes()) {
+            Ok(encoded) => encoded.len() > max_size,
+            // Requests fall back to the unencoded body.
+            Err(_) => true,
+        }
+    }
+
+    /// Sets the content encodings the uploader accepts, in order of preference.
+    ///
+    /// Only affects pings enqueued from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `encodings` - The names of the accepted encodings, as used in the `Content-Encoding` header.
+    pub fn set_content_encodings(&self, encodings: &[String]) {
+        self.encodings.write().unwrap().set_accepted(encodings);
+    }
+
+    /// Sets the content encoding for a single ping,
+    /// overriding the encodings the uploader accepts.
+    ///
+    /// Only affects pings enqueued from now on.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `encoding` - The encoding to use, `None` to go back to the accepted encodings.
+    pub fn set_ping_content_encoding(&self, ping_name: &str, encoding: Option<ContentEncoding>) {
+        self.encodings
+            .write()
+            .unwrap()
+            .set_for_ping(ping_name, encoding);
+    }
+
+    /// Sets the constraints on when a ping may be uploaded.
+    ///
+    /// Applies to all pending pings of that name.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_name` - The name of the ping.
+    /// * `constraints` - The constraints, `None` to upload the ping whenever possible.
+    pub fn set_ping_upload_constraints(
+        &self,
+        ping_name: &str,
+        constraints: Option<UploadConstraints>,
+    ) {
+        self.constraints
+            .write()
+            .unwrap()
+            .set_for_ping(ping_name, constraints);
+    }
+
+    /// Sets the kind of network the device is currently connected to.
+    ///
+    /// # Returns
+    ///
+    /// Whether the network type changed.
+    pub fn set_network_type(&self, network: NetworkType) -> bool {
+        self.constraints.write().unwrap().set_network(network)
+    }
+
     /// Reads a ping file, creates a `PingRequest` and adds it to the queue.
     ///
     /// Duplicate requests won't be added.
@@ -599,6 +830,87 @@ impl PingUploadManager {
         }
     }
 
+    /// Exports all pending pings that are not currently being uploaded into an archive
+    /// and deletes them from the pings directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_path` - Path to the archive file to write.
+    ///
+    /// # Returns
+    ///
+    /// The document ids of the exported pings.
+    pub fn export_pending_pings(&self, archive_path: &Path) -> Result<Vec<String>> {
+        // Hold the queue lock, so that no exported ping is handed out to an uploader meanwhile.
+        let mut queue = self
+            .queue
+            .write()
+            .expect("Can't write to pending pings queue.");
+        let in_flight: HashSet<String> = self.in_flight.read().unwrap().keys().cloned().collect();
+
+        let exported: HashSet<String> =
+            archive::export_pings(&self.directory_manager, archive_path, |document_id| {
+                !in_flight.contains(document_id)
+            })?
+            .into_iter()
+            .collect();
+
+        queue.retain(|request| !exported.contains(&request.document_id));
+        let mut cached_pings = self
+            .cached_pings
+            .write()
+            .expect("Can't write to pending pings cache.");
+        cached_pings
+            .pending_pings
+            .retain(|(_, ping)| !exported.contains(&ping.document_id));
+        cached_pings
+            .deletion_request_pings
+            .retain(|(_, ping)| !exported.contains(&ping.document_id));
+        self.backoff
+            .write()
+            .expect("Can't write to the upload backoff state.")
+            .retain(|document_id| !exported.contains(document_id));
+
+        Ok(exported.into_iter().collect())
+    }
+
+    /// Stores a ping file in memory, for in-memory upload managers.
+    ///
+    /// See [`PingUploadManager::new_in_memory`].
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The name of the pings directory the ping belongs in.
+    /// * `document_id` - The UUID of the ping.
+    /// * `contents` - The contents of the ping file.
+    pub(crate) fn store_ping_in_memory(
+        &self,
+        directory: &'static str,
+        document_id: &str,
+        contents: Vec<u8>,
+    ) {
+        self.directory_manager
+            .store_in_memory(directory, document_id, contents);
+    }
+
+    /// Deletes the pending pings of the given names kept in memory.
+    ///
+    /// Like deleting their files from the pending pings directory,
+    /// deletion-request pings and pings already in the queue are left alone.
+    pub(crate) fn delete_pending_pings_in_memory(&self, ping_names: &[&str]) {
+        let pings = self.directory_manager.process_dirs();
+        for (_, ping) in pings.pending_pings {
+            if ping_names.contains(&&ping.ping_name[..]) {
+                self.directory_manager.delete_file(&ping.document_id);
+            }
+        }
+    }
+
+    /// Writes the pending pings kept in memory into the pings directories of a data directory.
+    pub(crate) fn write_pending_pings_in_memory(&self, data_path: &Path) -> std::io::Result<()> {
+        self.directory_manager.write_memory_files(data_path)
+    }
+
     /// Clears the pending pings queue, leaves the deletion-request pings.
     pub fn clear_ping_queue(&self) -> RwLockWriteGuard<'_, VecDeque<PingRequest>> {
         log::trace!("Clearing ping queue");
@@ -615,7 +927,12 @@ impl PingUploadManager {
         queue
     }
 
-    fn get_upload_task_internal(&self, glean: &Glean, log_ping: bool) -> PingUploadTask {
+    fn get_upload_batch_internal(
+        &self,
+        glean: &Glean,
+        max_pings: usize,
+        log_ping: bool,
+    ) -> PingUploadBatchTask {
         // Helper to decide whether to return PingUploadTask::Wait or PingUploadTask::Done.
         //
         // We want to limit the amount of PingUploadTask::Wait returned in a row,
@@ -623,9 +940,9 @@ impl PingUploadManager {
         let wait_or_done = |time: u64| {
             self.wait_attempt_count.fetch_add(1, Ordering::SeqCst);
             if self.wait_attempt_count() > self.policy.max_wait_attempts() {
-                PingUploadTask::done()
+                PingUploadBatchTask::done()
             } else {
-                PingUploadTask::Wait { time }
+                PingUploadBatchTask::Wait { time }
             }
         };
 
@@ -643,27 +960,110 @@ impl PingUploadManager {
             log::warn!(
                 "Reached maximum recoverable failures for the current uploading window. You are done."
             );
-            return PingUploadTask::done();
+            return PingUploadBatchTask::done();
         }
 
         let mut queue = self
             .queue
             .write()
             .expect("Can't write to pending pings queue.");
-        match queue.front() {
-            Some(request) => {
-                if let Some(rate_limiter) = &self.rate_limiter {
-                    let mut rate_limiter = rate_limiter
-                        .write()
-                        .expect("Can't write to the rate limiter.");
-                    if let RateLimiterState::Throttled(remaining) = rate_limiter.get_state() {
-                        log::info!(
-                            "Tried getting an upload task, but we are throttled at the moment."
-                        );
-                        return wait_or_done(remaining);
-                    }
+        if queue.is_empty() {
+            log::info!("No more pings to upload! You are done.");
+            return PingUploadBatchTask::done();
+        }
+
+        if let Some(remaining) = self.remaining_pause() {
+            log::info!("Tried getting an upload task, but the server asked us to wait.");
+            return wait_or_done(remaining.as_millis().try_into().unwrap_or(u64::MAX));
+        }
+
+        // Take as many pings as requested, as long as their bodies together
+        // don't exceed the maximum body size.
+        // The first ping always fits, its body size was checked when building the request.
+        // Pings backing off from a previous failure
+        // or waiting for their upload constraints to be met are skipped, they stay in the queue.
+        let mut batch = Vec::new();
+        let mut batch_body_size = 0;
+        let mut constrained = false;
+        {
+            let backoff = self
+                .backoff
+                .read()
+                .expect("Can't read from the upload backoff state.");
+            let constraints = self.constraints.read().unwrap();
+            let constraints_met = constraints.checker();
+            for (index, request) in queue.iter().enumerate() {
+                if batch.len() >= max_pings.max(1) {
+                    break;
+                }
+
+                if !constraints_met(&request.ping_name) {
+                    log::debug!(
+                        "Ping {} waits for its upload constraints to be met.",
+                        request.document_id
+                    );
+                    constrained = true;
+                    continue;
+                }
+
+                if let Some(remaining) = self
+                    .policy
+                    .backoff()
+                    .and_then(|policy| backoff.remaining(&request.document_id, policy))
+                {
+                    log::debug!(
+                        "Ping {} is backing off for another {}ms.",
+                        request.document_id,
+                        remaining
+                    );
+                    continue;
+                }
+
+                batch_body_size += request.body.len();
+                if !batch.is_empty() && batch_body_size > self.policy.max_ping_body_size() {
+                    break;
+                }
+                batch.push(index);
+            }
+        }
+
+        if batch.is_empty() {
+            if constrained {
+                log::info!("All pending pings wait for their upload constraints to be met.");
+                return wait_or_done(WAIT_TIME_FOR_UPLOAD_CONSTRAINTS);
+            }
+            log::info!(
+                "All pending pings are backing off from failed uploads. You are done for now."
+            );
+            return PingUploadBatchTask::done();
+        }
+        let mut batch_size = batch.len();
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let mut rate_limiter = rate_limiter
+                .write()
+                .expect("Can't write to the rate limiter.");
+            // Every document in the batch counts against the rate limit.
+            match rate_limiter.reserve(batch_size.try_into().unwrap_or(u32::MAX)) {
+                Ok(reserved) => batch_size = reserved as usize,
+                Err(remaining) => {
+                    log::info!("Tried getting an upload task, but we are throttled at the moment.");
+                    return wait_or_done(remaining);
                 }
+            }
+        }
+
+        // Take the pings out of the queue, keeping their order.
+        batch.truncate(batch_size);
+        let mut requests = Vec::with_capacity(batch_size);
+        for index in batch.into_iter().rev() {
+            requests.extend(queue.remove(index));
+        }
 
+        let requests = requests
+            .into_iter()
+            .rev()
+            .map(|mut request| {
                 log::info!(
                     "New upload task with id {} (path: {})",
                     request.document_id,
@@ -688,19 +1088,63 @@ impl PingUploadManager {
                     in_flight.insert(request.document_id.clone(), (success_id, failure_id));
                 }
 
-                let mut request = queue.pop_front().unwrap();
-
                 // Adding the `Date` header just before actual upload happens.
                 request
                     .headers
                     .insert("Date".to_string(), create_date_header_value(Utc::now()));
 
-                PingUploadTask::Upload { request }
-            }
-            None => {
-                log::info!("No more pings to upload! You are done.");
-                PingUploadTask::done()
-            }
+                request
+            })
+            .collect();
+
+        PingUploadBatchTask::Upload { requests }
+    }
+
+    /// The remaining time uploads are paused for, as requested by the server.
+    ///
+    /// Returns `None` if uploads are not paused.
+    fn remaining_pause(&self) -> Option<Duration> {
+        let mut paused_until = self
+            .paused_until
+            .write()
+            .expect("Can't write to the upload pause.");
+        let remaining = paused_until.and_then(|until| until.checked_duration_since(Instant::now()));
+        if remaining.is_none() {
+            *paused_until = None;
+        }
+        remaining
+    }
+
+    /// Pauses all uploads for the time the server asked for, bounded by the policy.
+    ///
+    /// An existing, longer pause is kept.
+    fn pause_uploads(&self, retry_after_ms: u64) {
+        let pause = Duration::from_millis(retry_after_ms.min(self.policy.max_retry_after()));
+        let Some(until) = Instant::now().checked_add(pause) else {
+            return;
+        };
+
+        let mut paused_until = self
+            .paused_until
+            .write()
+            .expect("Can't write to the upload pause.");
+        if paused_until.is_none_or(|current| current < until) {
+            log::info!(
+                "Server asked to retry later, pausing uploads for {:?}.",
+                pause
+            );
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Resets the per-uploading-window counters, depending on the task handed out.
+    fn update_uploading_window(&self, is_wait: bool, is_upload: bool) {
+        if !is_wait && self.wait_attempt_count() > 0 {
+            self.wait_attempt_count.store(0, Ordering::SeqCst);
+        }
+
+        if !is_upload && self.recoverable_failure_count() > 0 {
+            self.recoverable_failure_count.store(0, Ordering::SeqCst);
         }
     }
 
@@ -715,16 +1159,41 @@ impl PingUploadManager {
     ///
     /// The next [`PingUploadTask`](enum.PingUploadTask.html).
     pub fn get_upload_task(&self, glean: &Glean, log_ping: bool) -> PingUploadTask {
-        let task = self.get_upload_task_internal(glean, log_ping);
-
-        if !task.is_wait() && self.wait_attempt_count() > 0 {
-            self.wait_attempt_count.store(0, Ordering::SeqCst);
-        }
+        let task = match self.get_upload_batch_internal(glean, 1, log_ping) {
+            PingUploadBatchTask::Upload { mut requests } => PingUploadTask::Upload {
+                request: requests.remove(0),
+            },
+            PingUploadBatchTask::Wait { time } => PingUploadTask::Wait { time },
+            PingUploadBatchTask::Done { .. } => PingUploadTask::done(),
+        };
 
-        if !task.is_upload() && self.recoverable_failure_count() > 0 {
-            self.recoverable_failure_count.store(0, Ordering::SeqCst);
-        }
+        self.update_uploading_window(task.is_wait(), task.is_upload());
+        task
+    }
 
+    /// Gets the next `PingUploadBatchTask`, containing up to `max_pings` ping requests.
+    ///
+    /// The bodies of all pings in a batch together don't exceed the maximum ping body size,
+    /// except for a single ping, which is always handed out.
+    /// Each ping in the batch counts against the rate limit individually.
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - The Glean object holding the database.
+    /// * `max_pings` - The maximum number of pings to include in the batch.
+    /// * `log_ping` - Whether to log the pings before returning.
+    ///
+    /// # Returns
+    ///
+    /// The next [`PingUploadBatchTask`](enum.PingUploadBatchTask.html).
+    pub fn get_upload_batch(
+        &self,
+        glean: &Glean,
+        max_pings: usize,
+        log_ping: bool,
+    ) -> PingUploadBatchTask {
+        let task = self.get_upload_batch_internal(glean, max_pings, log_ping);
+        self.update_uploading_window(task.is_wait(), task.is_upload());
         task
     }
 
@@ -740,7 +1209,7 @@ impl PingUploadManager {
     ///   * 200 - OK. Request accepted into the pipeline.
     ///
     /// * **400 - 499 Unrecoverable error**
-    ///   Any status on the 4XX range means something our client did is not correct.
+    ///   Any status on the 4XX range (except for 429) means something our client did is not correct.
     ///   It is unlikely that the client is going to recover from this by retrying,
     ///   so in this case the corresponding ping file can also be deleted.
     ///   _Known 4XX status:_
@@ -753,8 +1222,16 @@ impl PingUploadManager {
     ///
     /// * **Any other error**
     ///   For any other error, a warning is logged and the ping is re-enqueued.
+    ///   It is not handed out for upload again until its backoff delay elapsed,
+    ///   which grows with every failed attempt.
     ///   _Known other errors:_
+    ///   * 429 - too many requests
     ///   * 500 - internal error
+    ///   * 503 - service unavailable
+    ///
+    /// If the server hinted when to retry (e.g. in a `Retry-After` header) of a failed upload,
+    /// all uploads are paused until then instead of backing off from this ping only.
+    /// Requests for upload tasks get a [`PingUploadTask::Wait`] in the meantime.
     ///
     /// # Note
     ///
@@ -790,8 +1267,15 @@ impl PingUploadManager {
             self.upload_metrics.missing_send_ids.add_sync(glean, 1);
         }
 
+        let retry_after = match status {
+            HttpStatusWithRetryAfter { retry_after_ms, .. } => Some(retry_after_ms),
+            _ => None,
+        };
+
         match status {
-            HttpStatus { code } if (200..=299).contains(&code) => {
+            HttpStatus { code } | HttpStatusWithRetryAfter { code, .. }
+                if (200..=299).contains(&code) =>
+            {
                 log::info!("Ping {} successfully sent {}.", document_id, code);
                 if let Some((success_id, failure_id)) = send_ids {
                     self.upload_metrics
@@ -800,9 +1284,18 @@ impl PingUploadManager {
                     self.upload_metrics.send_failure.cancel_sync(failure_id);
                 }
                 self.directory_manager.delete_file(document_id);
+                self.forget_backoff(document_id);
             }
 
-            UnrecoverableFailure { .. } | HttpStatus { code: 400..=499 } | Incapable { .. } => {
+            UnrecoverableFailure { .. }
+            | HttpStatus {
+                code: 400..=428 | 430..=499,
+            }
+            | HttpStatusWithRetryAfter {
+                code: 400..=428 | 430..=499,
+                ..
+            }
+            | Incapable { .. } => {
                 log::warn!(
                     "Unrecoverable upload failure while attempting to send ping {}. Error was {:?}",
                     document_id,
@@ -814,10 +1307,13 @@ impl PingUploadManager {
                         .send_failure
                         .set_stop_and_accumulate(glean, failure_id, stop_time);
                 }
-                self.directory_manager.delete_file(document_id);
+                if !self.quarantine_ping(document_id, &status) {
+                    self.directory_manager.delete_file(document_id);
+                }
+                self.forget_backoff(document_id);
             }
 
-            RecoverableFailure { .. } | HttpStatus { .. } => {
+            RecoverableFailure { .. } | HttpStatus { .. } | HttpStatusWithRetryAfter { .. } => {
                 log::warn!(
                     "Recoverable upload failure while attempting to send ping {}, will retry. Error was {:?}",
                     document_id,
@@ -829,6 +1325,17 @@ impl PingUploadManager {
                         .send_failure
                         .set_stop_and_accumulate(glean, failure_id, stop_time);
                 }
+                if let Some(retry_after) = retry_after {
+                    // The server is overloaded, not just this ping failing.
+                    self.pause_uploads(retry_after);
+                } else if let Some(policy) = self.policy.backoff() {
+                    let delay = self
+                        .backoff
+                        .write()
+                        .expect("Can't write to the upload backoff state.")
+                        .record_failure(document_id, policy);
+                    log::info!("Ping {} will be retried in {}ms.", document_id, delay);
+                }
                 self.enqueue_ping_from_file(glean, document_id);
                 self.recoverable_failure_count
                     .fetch_add(1, Ordering::SeqCst);
@@ -846,39 +1353,186 @@ impl PingUploadManager {
 
         UploadTaskAction::Next
     }
-}
 
-/// Splits log message into chunks on Android.
-#[cfg(target_os = "android")]
-pub fn chunked_log_info(path: &str, payload: &str) {
-    // Since the logcat ring buffer size is configurable, but it's 'max payload' size is not,
-    // we must break apart long pings into chunks no larger than the max payload size of 4076b.
-    // We leave some head space for our prefix.
-    const MAX_LOG_PAYLOAD_SIZE_BYTES: usize = 4000;
+    /// Moves a ping that permanently failed to upload into the quarantine, if enabled.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ping was quarantined.
+    fn quarantine_ping(&self, document_id: &str, status: &UploadResult) -> bool {
+        let quarantine = self
+            .quarantine
+            .write()
+            .expect("Can't write to the ping quarantine.");
+        if !quarantine.is_enabled() {
+            return false;
+        }
 
-    // If the length of the ping will fit within one logcat payload, then we can
-    // short-circuit here and avoid some overhead, otherwise we must split up the
-    // message so that we don't truncate it.
-    if path.len() + payload.len() <= MAX_LOG_PAYLOAD_SIZE_BYTES {
-        log::info!("Glean ping to URL: {}\n{}", path, payload);
-        return;
+        let (Some(path), Some(ping)) = (
+            self.directory_manager.get_file_path(document_id),
+            self.directory_manager.process_file(document_id),
+        ) else {
+            return false;
+        };
+        let status_code = match status {
+            UploadResult::HttpStatus { code }
+            | UploadResult::HttpStatusWithRetryAfter { code, .. } => Some(*code),
+            _ => None,
+        };
+        quarantine.quarantine(
+            &path,
+            document_id,
+            &ping.ping_name,
+            status.get_label().unwrap_or("unknown"),
+            status_code,
+        )
     }
 
-    // Otherwise we break it apart into chunks of smaller size,
-    // prefixing it with the path and a counter.
-    let mut start = 0;
-    let mut end = MAX_LOG_PAYLOAD_SIZE_BYTES;
-    let mut chunk_idx = 1;
-    // Might be off by 1 on edge cases, but do we really care?
-    let total_chunks = payload.len() / MAX_LOG_PAYLOAD_SIZE_BYTES + 1;
+    /// Sets the maximum number of pings that permanently failed to upload to keep.
+    ///
+    /// `0` disables the quarantine, pings that failed to upload are deleted then.
+    pub(crate) fn set_max_quarantined_pings(&mut self, max_count: u32) {
+        self.quarantine
+            .write()
+            .expect("Can't write to the ping quarantine.")
+            .set_max_count(max_count as usize);
+    }
 
-    while end < payload.len() {
-        // Find char boundary from the end.
-        // It's UTF-8, so it is within 4 bytes from here.
-        for _ in 0..4 {
-            if payload.is_char_boundary(end) {
-                break;
-            }
+    /// Sets the cipher ping files are encrypted with, if encryption at rest is enabled.
+    pub(crate) fn set_cipher(&mut self, cipher: Option<Arc<Cipher>>) {
+        self.directory_manager.set_cipher(cipher);
+    }
+
+    /// Encrypts all pending and quarantined ping files stored in plaintext.
+    pub(crate) fn encrypt_plaintext_ping_files(&self, cipher: &Cipher) -> std::io::Result<()> {
+        self.directory_manager.encrypt_plaintext_files()?;
+        self.quarantine
+            .read()
+            .expect("Can't read the ping quarantine.")
+            .encrypt_plaintext_files(cipher)
+    }
+
+    /// Lists the pings that permanently failed to upload.
+    pub fn quarantined_pings(&self) -> Vec<QuarantinedPing> {
+        if self.directory_manager.is_in_memory() {
+            return Vec::new();
+        }
+        self.quarantine
+            .read()
+            .expect("Can't read the ping quarantine.")
+            .list()
+    }
+
+    /// Moves a quarantined ping back into the pings directory and enqueues it for upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - The Glean object holding the database.
+    /// * `document_id` - The UUID of the ping in question.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ping was enqueued.
+    pub fn reenqueue_quarantined_ping(&self, glean: &Glean, document_id: &str) -> bool {
+        if self.directory_manager.is_in_memory() {
+            return false;
+        }
+        let restored = self
+            .quarantine
+            .write()
+            .expect("Can't write to the ping quarantine.")
+            .restore(document_id);
+        if restored {
+            self.enqueue_ping_from_file(glean, document_id);
+        }
+        restored
+    }
+
+    /// Deletes quarantined pings.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_names` - The names of the pings to delete, `None` to delete all of them.
+    pub fn purge_quarantined_pings(&self, ping_names: Option<&[&str]>) {
+        if self.directory_manager.is_in_memory() {
+            return;
+        }
+        self.quarantine
+            .write()
+            .expect("Can't write to the ping quarantine.")
+            .purge(ping_names);
+    }
+
+    /// Forgets the backoff state of a ping that doesn't need to be retried anymore.
+    fn forget_backoff(&self, document_id: &str) {
+        self.backoff
+            .write()
+            .expect("Can't write to the upload backoff state.")
+            .remove(document_id);
+    }
+
+    /// Processes the responses from an attempt to upload a batch of pings.
+    ///
+    /// Each ping's result is processed as in
+    /// [`process_ping_upload_response`](PingUploadManager::process_ping_upload_response).
+    ///
+    /// # Arguments
+    ///
+    /// * `glean` - The Glean object holding the database.
+    /// * `results` - The upload result for each ping, by document id.
+    ///
+    /// # Returns
+    ///
+    /// [`UploadTaskAction::End`] if the uploader signaled it is done for any of the pings,
+    /// [`UploadTaskAction::Next`] otherwise.
+    pub fn process_ping_upload_batch_response(
+        &self,
+        glean: &Glean,
+        results: HashMap<String, UploadResult>,
+    ) -> UploadTaskAction {
+        let mut action = UploadTaskAction::Next;
+        for (document_id, status) in results {
+            if let UploadTaskAction::End =
+                self.process_ping_upload_response(glean, &document_id, status)
+            {
+                action = UploadTaskAction::End;
+            }
+        }
+        action
+    }
+}
+
+/// Splits log message into chunks on Android.
+#[cfg(target_os = "android")]
+pub fn chunked_log_info(path: &str, payload: &str) {
+    // Since the logcat ring buffer size is configurable, but it's 'max payload' size is not,
+    // we must break apart long pings into chunks no larger than the max payload size of 4076b.
+    // We leave some head space for our prefix.
+    const MAX_LOG_PAYLOAD_SIZE_BYTES: usize = 4000;
+
+    // If the length of the ping will fit within one logcat payload, then we can
+    // short-circuit here and avoid some overhead, otherwise we must split up the
+    // message so that we don't truncate it.
+    if path.len() + payload.len() <= MAX_LOG_PAYLOAD_SIZE_BYTES {
+        log::info!("Glean ping to URL: {}\n{}", path, payload);
+        return;
+    }
+
+    // Otherwise we break it apart into chunks of smaller size,
+    // prefixing it with the path and a counter.
+    let mut start = 0;
+    let mut end = MAX_LOG_PAYLOAD_SIZE_BYTES;
+    let mut chunk_idx = 1;
+    // Might be off by 1 on edge cases, but do we really care?
+    let total_chunks = payload.len() / MAX_LOG_PAYLOAD_SIZE_BYTES + 1;
+
+    while end < payload.len() {
+        // Find char boundary from the end.
+        // It's UTF-8, so it is within 4 bytes from here.
+        for _ in 0..4 {
+            if payload.is_char_boundary(end) {
+                break;
+            }
             end -= 1;
         }
 
@@ -917,10 +1571,12 @@ pub fn chunked_log_info(_path: &str, payload: &str) {
 #[cfg(test)]
 mod test {
     use std::thread;
+
+    use chrono::Timelike;
     use uuid::Uuid;
 
     use super::*;
-    use crate::metrics::PingType;
+    use crate::metrics::{PingPriority, PingType};
     use crate::{tests::new_glean, PENDING_PINGS_DIRECTORY};
 
     const PATH: &str = "/submit/app_id/ping_name/schema_version/doc_id";
@@ -951,6 +1607,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "ping-name".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -979,6 +1636,7 @@ mod test {
                     body_has_info_sections: true,
                     ping_name: "ping-name".into(),
                     uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
                 },
             );
         }
@@ -1018,6 +1676,7 @@ mod test {
                     body_has_info_sections: true,
                     ping_name: "ping-name".into(),
                     uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
                 },
             );
         }
@@ -1039,6 +1698,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "ping-name".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -1055,6 +1715,221 @@ mod test {
         assert!(task.is_upload());
     }
 
+    #[test]
+    fn batches_are_limited_by_max_pings_and_body_size() {
+        let (glean, dir) = new_glean(None);
+
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        // Gzipped, the bodies below are ~25 bytes each.
+        upload_manager.policy.set_max_ping_body_size(Some(60));
+
+        for _ in 0..5 {
+            upload_manager.enqueue_ping(
+                &glean,
+                PingPayload {
+                    document_id: Uuid::new_v4().to_string(),
+                    upload_path: PATH.into(),
+                    json_body: "{}".into(),
+                    headers: None,
+                    body_has_info_sections: true,
+                    ping_name: "ping-name".into(),
+                    uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
+                },
+            );
+        }
+
+        // Only two pings fit into the maximum body size.
+        match upload_manager.get_upload_batch(&glean, 10, false) {
+            PingUploadBatchTask::Upload { requests } => {
+                assert_eq!(2, requests.len());
+                assert!(requests.iter().all(|r| r.headers.contains_key("Date")));
+            }
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+
+        // Only one ping is requested.
+        match upload_manager.get_upload_batch(&glean, 1, false) {
+            PingUploadBatchTask::Upload { requests } => assert_eq!(1, requests.len()),
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+
+        // The remaining pings.
+        match upload_manager.get_upload_batch(&glean, 10, false) {
+            PingUploadBatchTask::Upload { requests } => assert_eq!(2, requests.len()),
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+
+        assert_eq!(
+            upload_manager.get_upload_batch(&glean, 10, false),
+            PingUploadBatchTask::done()
+        );
+    }
+
+    #[test]
+    fn rate_limiting_counts_each_ping_in_a_batch() {
+        let (glean, dir) = new_glean(None);
+
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        // Add a rate limiter to the upload mangager with max of 3 pings every 3 seconds.
+        upload_manager.set_rate_limiter(3, 3);
+
+        for _ in 0..5 {
+            upload_manager.enqueue_ping(
+                &glean,
+                PingPayload {
+                    document_id: Uuid::new_v4().to_string(),
+                    upload_path: PATH.into(),
+                    json_body: "".into(),
+                    headers: None,
+                    body_has_info_sections: true,
+                    ping_name: "ping-name".into(),
+                    uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
+                },
+            );
+        }
+
+        // The batch is cut down to what the rate limiter allows.
+        match upload_manager.get_upload_batch(&glean, 5, false) {
+            PingUploadBatchTask::Upload { requests } => assert_eq!(3, requests.len()),
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+
+        // Verify that we are indeed told to wait because we are at capacity
+        match upload_manager.get_upload_batch(&glean, 5, false) {
+            PingUploadBatchTask::Wait { time } => {
+                // Wait for the uploading window to reset
+                thread::sleep(Duration::from_millis(time));
+            }
+            _ => panic!("Expected upload manager to return a wait task!"),
+        };
+
+        match upload_manager.get_upload_batch(&glean, 5, false) {
+            PingUploadBatchTask::Upload { requests } => assert_eq!(2, requests.len()),
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+    }
+
+    #[test]
+    fn processes_batch_upload_responses_per_document() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+
+        // Submit two pings
+        ping_type.submit_sync(&glean, None);
+        ping_type.submit_sync(&glean, None);
+
+        // Get the pending ping directory path
+        let pending_pings_dir = dir.path().join(PENDING_PINGS_DIRECTORY);
+
+        let requests = match glean.get_upload_batch(10) {
+            PingUploadBatchTask::Upload { requests } => requests,
+            _ => panic!("Expected upload manager to return a batch!"),
+        };
+        assert_eq!(2, requests.len());
+        let sent = requests[0].document_id.clone();
+        let failed = requests[1].document_id.clone();
+
+        let mut results = HashMap::new();
+        results.insert(sent.clone(), UploadResult::http_status(200));
+        results.insert(failed.clone(), UploadResult::recoverable_failure());
+        assert!(matches!(
+            glean.process_ping_upload_batch_response(results),
+            UploadTaskAction::Next
+        ));
+
+        // The successfully sent ping is gone, the other one is re-enqueued.
+        assert!(!pending_pings_dir.join(&sent).exists());
+        assert!(pending_pings_dir.join(&failed).exists());
+        match glean.get_upload_batch(10) {
+            PingUploadBatchTask::Upload { requests } => {
+                assert_eq!(1, requests.len());
+                assert_eq!(failed, requests[0].document_id);
+            }
+            _ => panic!("Expected upload manager to return a batch!"),
+        }
+    }
+
+    #[test]
+    fn oversized_pings_are_split_into_linked_parts() {
+        let (mut glean, _t) = new_glean(None);
+        glean.upload_manager.set_max_ping_body_size(1024);
+
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ false,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+
+        let event = crate::metrics::EventMetric::new(
+            crate::CommonMetricData {
+                name: "event".into(),
+                category: "test".into(),
+                send_in_pings: vec!["test".into()],
+                ..Default::default()
+            },
+            vec!["value".into()],
+        );
+        // Random values keep the events from compressing into a single small part.
+        for timestamp in 0..100 {
+            let extra = HashMap::from([("value".to_string(), Uuid::new_v4().to_string())]);
+            event.record_sync(&glean, timestamp, extra, 0);
+        }
+
+        assert!(ping_type.submit_sync(&glean, None));
+
+        let mut parts = vec![];
+        while let PingUploadTask::Upload { request } = glean.get_upload_task() {
+            assert!(request.body.len() <= 1024);
+            let body = encoding::ContentEncoding::Gzip
+                .decode(&request.body)
+                .unwrap();
+            parts.push(serde_json::from_slice::<serde_json::Value>(&body).unwrap());
+        }
+        assert!(parts.len() > 1);
+
+        let parent_id = &parts[0]["metrics"]["uuid"]["glean.ping.split_parent_id"];
+        assert!(parent_id.is_string());
+        let mut timestamps = vec![];
+        for (index, part) in parts.iter().enumerate() {
+            let metrics = &part["metrics"];
+            assert_eq!(parent_id, &metrics["uuid"]["glean.ping.split_parent_id"]);
+            assert_eq!(index + 1, metrics["quantity"]["glean.ping.split_part"]);
+            assert_eq!(parts.len(), metrics["quantity"]["glean.ping.split_parts"]);
+            assert!(part["client_info"].is_object());
+            // The linkage is carried in metrics, the schema doesn't allow extra `ping_info` fields.
+            assert!(part["ping_info"].get("parent_id").is_none());
+            for event in part["events"].as_array().unwrap() {
+                timestamps.push(event["timestamp"].as_u64().unwrap());
+            }
+        }
+        // Every event is in exactly one part, in order.
+        assert_eq!((0..100).collect::<Vec<_>>(), timestamps);
+    }
+
     #[test]
     fn clearing_the_queue_works_correctly() {
         let (glean, dir) = new_glean(None);
@@ -1073,6 +1948,7 @@ mod test {
                     body_has_info_sections: true,
                     ping_name: "ping-name".into(),
                     uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
                 },
             );
         }
@@ -1363,6 +2239,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "test-ping".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -1384,6 +2261,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "test-ping".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -1470,6 +2348,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "test-ping".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
         upload_manager.enqueue_ping(
@@ -1482,6 +2361,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "test-ping".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -1558,7 +2438,7 @@ mod test {
     }
 
     #[test]
-    fn quota_is_enforced_when_enqueueing_cached_pings() {
+    fn server_retry_hints_pause_all_uploads() {
         let (mut glean, dir) = new_glean(None);
 
         // Register a ping for testing
@@ -1576,59 +2456,359 @@ mod test {
         );
         glean.register_ping_type(&ping_type);
 
-        // Submit the ping multiple times
-        let n = 10;
-        for _ in 0..n {
-            ping_type.submit_sync(&glean, None);
-        }
-
-        let directory_manager = PingDirectoryManager::new(dir.path());
-        let pending_pings = directory_manager.process_dirs().pending_pings;
-        // The pending pings array is sorted by date in ascending order,
-        // the newest element is the last one.
-        let (_, newest_ping) = &pending_pings.last().unwrap();
-        let PingPayload {
-            document_id: newest_ping_id,
-            ..
-        } = &newest_ping;
+        // Submit the ping twice
+        ping_type.submit_sync(&glean, None);
+        ping_type.submit_sync(&glean, None);
 
-        // Create a new upload manager pointing to the same data_path as the glean instance.
+        // Bound the pause, this is usually disabled for tests.
         let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.policy.set_max_retry_after(Some(100));
 
-        // Set the quota to just a little over the size on an empty ping file.
-        // This way we can check that one ping is kept and all others are deleted.
-        //
-        // From manual testing I figured out an empty ping file is 324bytes,
-        // I am setting this a little over just so that minor changes to the ping structure
-        // don't immediatelly break this.
-        upload_manager
-            .policy
-            .set_max_pending_pings_directory_size(Some(500));
+        let throttled = match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => request.document_id,
+            _ => panic!("Expected upload manager to return the next request!"),
+        };
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &throttled,
+            UploadResult::http_status_with_retry_after(503, 60 * 1000),
+        );
 
-        // Get a task once
-        // One ping should have been enqueued.
-        // Make sure it is the newest ping.
+        // No ping is handed out while uploads are paused.
         match upload_manager.get_upload_task(&glean, false) {
-            PingUploadTask::Upload { request } => assert_eq!(&request.document_id, newest_ping_id),
-            _ => panic!("Expected upload manager to return the next request!"),
+            PingUploadTask::Wait { time } => assert!(time <= 100),
+            _ => panic!("Expected upload manager to return a wait task!"),
         }
 
-        // Verify that no other requests were returned,
-        // they should all have been deleted because pending pings quota was hit.
+        // Once the pause is over both pings are handed out again.
+        thread::sleep(Duration::from_millis(150));
+        for _ in 0..2 {
+            match upload_manager.get_upload_task(&glean, false) {
+                PingUploadTask::Upload { request } => {
+                    upload_manager.process_ping_upload_response(
+                        &glean,
+                        &request.document_id,
+                        UploadResult::http_status(200),
+                    );
+                }
+                _ => panic!("Expected upload manager to return the next request!"),
+            }
+        }
         assert_eq!(
             upload_manager.get_upload_task(&glean, false),
             PingUploadTask::done()
         );
+    }
 
-        // Verify that the correct number of deleted pings was recorded
-        assert_eq!(
-            n - 1,
-            upload_manager
-                .upload_metrics
-                .deleted_pings_after_quota_hit
-                .get_value(&glean, Some("metrics"))
-                .unwrap()
-        );
+    #[test]
+    fn too_many_requests_are_retried() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+        ping_type.submit_sync(&glean, None);
+
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+        let document_id = match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => request.document_id,
+            _ => panic!("Expected upload manager to return the next request!"),
+        };
+
+        // A 429 without a hint is not a client error, the ping is kept and retried.
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &document_id,
+            UploadResult::http_status(429),
+        );
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!(document_id, request.document_id),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+    }
+
+    #[test]
+    fn failed_pings_are_quarantined_and_can_be_reenqueued() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+        ping_type.submit_sync(&glean, None);
+
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.set_max_quarantined_pings(1);
+        let document_id = match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => request.document_id,
+            _ => panic!("Expected upload manager to return the next request!"),
+        };
+
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &document_id,
+            UploadResult::http_status(400),
+        );
+        assert!(!dir
+            .path()
+            .join(PENDING_PINGS_DIRECTORY)
+            .join(&document_id)
+            .exists());
+        let quarantined = upload_manager.quarantined_pings();
+        assert_eq!(1, quarantined.len());
+        assert_eq!(document_id, quarantined[0].document_id);
+        assert_eq!("test", quarantined[0].ping_name);
+        assert_eq!("status_code_4xx", quarantined[0].reason);
+        assert_eq!(Some(400), quarantined[0].status_code);
+
+        assert!(upload_manager.reenqueue_quarantined_ping(&glean, &document_id));
+        assert!(upload_manager.quarantined_pings().is_empty());
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!(document_id, request.document_id),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+
+        // Once purged, quarantined pings are gone for good.
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &document_id,
+            UploadResult::unrecoverable_failure(),
+        );
+        upload_manager.purge_quarantined_pings(None);
+        assert!(upload_manager.quarantined_pings().is_empty());
+        assert!(!upload_manager.reenqueue_quarantined_ping(&glean, &document_id));
+    }
+
+    #[test]
+    fn exported_pings_are_no_longer_uploaded() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+        ping_type.submit_sync(&glean, None);
+        ping_type.submit_sync(&glean, None);
+
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+        let in_flight = match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => request.document_id,
+            _ => panic!("Expected upload manager to return the next request!"),
+        };
+
+        // The ping being uploaded is left alone.
+        let archive = dir.path().join("pings.archive");
+        let exported = upload_manager.export_pending_pings(&archive).unwrap();
+        assert_eq!(1, exported.len());
+        assert_ne!(in_flight, exported[0]);
+        assert_eq!(exported[0], read_archive(&archive).unwrap()[0].document_id);
+
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &in_flight,
+            UploadResult::http_status(200),
+        );
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+    }
+
+    #[test]
+    fn pings_backing_off_dont_block_other_pings() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+
+        // Submit the ping twice
+        ping_type.submit_sync(&glean, None);
+        ping_type.submit_sync(&glean, None);
+
+        // Set a backoff policy, this is usually disabled for tests.
+        let backoff = UploadBackoff {
+            base_delay_ms: 60 * 60 * 1000,
+            multiplier: 2.0,
+            max_delay_ms: 2 * 60 * 60 * 1000,
+            jitter: 0.0,
+        };
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.set_backoff(backoff.clone());
+
+        // Fail the first ping.
+        let failed = match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => request.document_id,
+            _ => panic!("Expected upload manager to return the next request!"),
+        };
+        upload_manager.process_ping_upload_response(
+            &glean,
+            &failed,
+            UploadResult::recoverable_failure(),
+        );
+
+        // The second ping is handed out, the failed one is backing off.
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => {
+                assert_ne!(failed, request.document_id);
+                upload_manager.process_ping_upload_response(
+                    &glean,
+                    &request.document_id,
+                    UploadResult::http_status(200),
+                );
+            }
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+
+        // The backoff survives a restart.
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.set_backoff(backoff.clone());
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+
+        // Without a backoff policy the failed ping is still pending.
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => {
+                assert_eq!(failed, request.document_id);
+                upload_manager.process_ping_upload_response(
+                    &glean,
+                    &request.document_id,
+                    UploadResult::http_status(200),
+                );
+            }
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+
+        // Once uploaded, the backoff state is gone.
+        assert!(upload_manager
+            .backoff
+            .read()
+            .unwrap()
+            .remaining(&failed, &backoff)
+            .is_none());
+    }
+
+    #[test]
+    fn quota_is_enforced_when_enqueueing_cached_pings() {
+        let (mut glean, dir) = new_glean(None);
+
+        // Register a ping for testing
+        let ping_type = PingType::new(
+            "test",
+            true,
+            /* send_if_empty */ true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        glean.register_ping_type(&ping_type);
+
+        // Submit the ping multiple times
+        let n = 10;
+        for _ in 0..n {
+            ping_type.submit_sync(&glean, None);
+        }
+
+        let directory_manager = PingDirectoryManager::new(dir.path());
+        let pending_pings = directory_manager.process_dirs().pending_pings;
+        // The pending pings array is sorted by date in ascending order,
+        // the newest element is the last one.
+        let (_, newest_ping) = &pending_pings.last().unwrap();
+        let PingPayload {
+            document_id: newest_ping_id,
+            ..
+        } = &newest_ping;
+
+        // Create a new upload manager pointing to the same data_path as the glean instance.
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+
+        // Set the quota to just a little over the size on an empty ping file.
+        // This way we can check that one ping is kept and all others are deleted.
+        //
+        // From manual testing I figured out an empty ping file is 324bytes,
+        // I am setting this a little over just so that minor changes to the ping structure
+        // don't immediatelly break this.
+        upload_manager
+            .policy
+            .set_max_pending_pings_directory_size(Some(500));
+
+        // Get a task once
+        // One ping should have been enqueued.
+        // Make sure it is the newest ping.
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!(&request.document_id, newest_ping_id),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+
+        // Verify that no other requests were returned,
+        // they should all have been deleted because pending pings quota was hit.
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+
+        // Verify that the correct number of deleted pings was recorded
+        assert_eq!(
+            n - 1,
+            upload_manager
+                .upload_metrics
+                .deleted_pings_after_quota_hit
+                .get_value(&glean, Some("metrics"))
+                .unwrap()
+        );
         assert_eq!(
             n,
             upload_manager
@@ -1722,6 +2902,229 @@ mod test {
         );
     }
 
+    #[test]
+    fn pings_are_queued_by_priority() {
+        let (glean, dir) = new_glean(None);
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+
+        let priorities = [
+            PingPriority::Bulk,
+            PingPriority::Normal,
+            PingPriority::Critical,
+            PingPriority::Normal,
+            PingPriority::Bulk,
+        ];
+        let mut document_ids = Vec::new();
+        for priority in priorities {
+            let document_id = Uuid::new_v4().to_string();
+            upload_manager.enqueue_ping(
+                &glean,
+                PingPayload {
+                    document_id: document_id.clone(),
+                    upload_path: PATH.into(),
+                    json_body: "".into(),
+                    headers: None,
+                    body_has_info_sections: true,
+                    ping_name: "ping-name".into(),
+                    uploader_capabilities: vec![],
+                    priority,
+                },
+            );
+            document_ids.push(document_id);
+        }
+
+        // Higher priorities first, in the order they were enqueued otherwise.
+        for i in [2, 1, 3, 0, 4] {
+            match upload_manager.get_upload_task(&glean, false) {
+                PingUploadTask::Upload { request } => {
+                    assert_eq!(document_ids[i], request.document_id);
+                    assert_eq!(priorities[i], request.priority);
+                }
+                _ => panic!("Expected upload manager to return the next request!"),
+            }
+        }
+    }
+
+    #[test]
+    fn pings_wait_for_their_upload_constraints() {
+        let (glean, dir) = new_glean(None);
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+
+        // A window that won't open during this test, even if the hour changes.
+        let hour = crate::util::local_now_with_offset().hour();
+        upload_manager.set_ping_upload_constraints(
+            "nightly",
+            Some(UploadConstraints {
+                window: Some(UploadWindow {
+                    start_hour: (hour + 2) % 24,
+                    end_hour: (hour + 3) % 24,
+                }),
+                unmetered_only: false,
+            }),
+        );
+        upload_manager.set_ping_upload_constraints(
+            "wifi",
+            Some(UploadConstraints {
+                window: None,
+                unmetered_only: true,
+            }),
+        );
+
+        for ping_name in ["nightly", "wifi", "unconstrained"] {
+            upload_manager.enqueue_ping(
+                &glean,
+                PingPayload {
+                    document_id: Uuid::new_v4().to_string(),
+                    upload_path: PATH.into(),
+                    json_body: "".into(),
+                    headers: None,
+                    body_has_info_sections: true,
+                    ping_name: ping_name.into(),
+                    uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
+                },
+            );
+        }
+
+        // Pings with unmet constraints are skipped.
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!("unconstrained", request.ping_name),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+        assert_eq!(
+            PingUploadTask::Wait {
+                time: WAIT_TIME_FOR_UPLOAD_CONSTRAINTS
+            },
+            upload_manager.get_upload_task(&glean, false)
+        );
+
+        assert!(!upload_manager.set_network_type(NetworkType::Unknown));
+        assert!(upload_manager.set_network_type(NetworkType::Unmetered));
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!("wifi", request.ping_name),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+
+        // Without constraints the ping is uploaded right away.
+        upload_manager.set_ping_upload_constraints("nightly", None);
+        match upload_manager.get_upload_task(&glean, false) {
+            PingUploadTask::Upload { request } => assert_eq!("nightly", request.ping_name),
+            _ => panic!("Expected upload manager to return the next request!"),
+        }
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+    }
+
+    #[test]
+    fn upload_constraints_out_of_range_are_ignored() {
+        let (glean, _dir) = new_glean(None);
+
+        // A window that won't open during this test, even if the hour changes.
+        let hour = crate::util::local_now_with_offset().hour();
+        let window = |start_hour, end_hour| UploadConstraints {
+            window: Some(UploadWindow {
+                start_hour,
+                end_hour,
+            }),
+            unmetered_only: false,
+        };
+        glean
+            .set_ping_upload_constraints("nightly", Some(window((hour + 2) % 24, (hour + 3) % 24)));
+        // Ignored, the previous constraints still apply.
+        glean.set_ping_upload_constraints("nightly", Some(window(24, 5)));
+
+        glean.upload_manager.enqueue_ping(
+            &glean,
+            PingPayload {
+                document_id: Uuid::new_v4().to_string(),
+                upload_path: PATH.into(),
+                json_body: "".into(),
+                headers: None,
+                body_has_info_sections: true,
+                ping_name: "nightly".into(),
+                uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
+            },
+        );
+        assert_eq!(
+            PingUploadTask::Wait {
+                time: WAIT_TIME_FOR_UPLOAD_CONSTRAINTS
+            },
+            glean.upload_manager.get_upload_task(&glean, false)
+        );
+    }
+
+    #[test]
+    fn quota_deletes_lower_priority_pings_first() {
+        let (mut glean, dir) = new_glean(None);
+
+        let critical = PingType::new(
+            "critical",
+            true,
+            true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        critical.set_priority(PingPriority::Critical);
+        glean.register_ping_type(&critical);
+        let bulk = PingType::new(
+            "bulk",
+            true,
+            true,
+            true,
+            true,
+            true,
+            vec![],
+            vec![],
+            true,
+            vec![],
+        );
+        bulk.set_priority(PingPriority::Bulk);
+        glean.register_ping_type(&bulk);
+
+        // The critical pings are the oldest ones.
+        for _ in 0..2 {
+            critical.submit_sync(&glean, None);
+        }
+        for _ in 0..3 {
+            bulk.submit_sync(&glean, None);
+        }
+
+        let directory_manager = PingDirectoryManager::new(dir.path());
+        let pending_pings = directory_manager.process_dirs().pending_pings;
+        let ids_of = |name: &str| {
+            pending_pings
+                .iter()
+                .filter(|(_, ping)| ping.ping_name == name)
+                .map(|(_, ping)| ping.document_id.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut expected_pings = ids_of("critical");
+        expected_pings.push(ids_of("bulk").pop().unwrap());
+
+        let mut upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.policy.set_max_pending_pings_count(Some(3));
+
+        // Only the newest bulk ping is kept, behind the critical pings.
+        for ping_id in &expected_pings {
+            match upload_manager.get_upload_task(&glean, false) {
+                PingUploadTask::Upload { request } => assert_eq!(&request.document_id, ping_id),
+                _ => panic!("Expected upload manager to return the next request!"),
+            }
+        }
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+    }
+
     #[test]
     fn size_and_count_quota_work_together_size_first() {
         let (mut glean, dir) = new_glean(None);
@@ -2021,6 +3424,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "ping-name".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
         upload_manager.enqueue_ping(
@@ -2033,6 +3437,7 @@ mod test {
                 body_has_info_sections: true,
                 ping_name: "ping-name".into(),
                 uploader_capabilities: vec![],
+                priority: PingPriority::Normal,
             },
         );
 
@@ -2099,6 +3504,7 @@ mod test {
             body_has_info_sections: true,
             ping_name: "ping-name".into(),
             uploader_capabilities: vec![],
+            priority: PingPriority::Normal,
         };
         upload_manager.enqueue_ping(&glean, ping);
         assert!(upload_manager.get_upload_task(&glean, false).is_upload());
@@ -2112,6 +3518,7 @@ mod test {
             body_has_info_sections: true,
             ping_name: "ping-name".into(),
             uploader_capabilities: vec![],
+            priority: PingPriority::Normal,
         };
         upload_manager.enqueue_ping(&glean, ping);
 
@@ -2128,4 +3535,66 @@ mod test {
             UploadResult::http_status(200),
         );
     }
+
+    #[test]
+    fn ping_payloads_use_the_selected_content_encoding() {
+        let (glean, dir) = new_glean(None);
+
+        let available = [
+            ContentEncoding::Gzip,
+            #[cfg(feature = "zstd")]
+            ContentEncoding::Zstd,
+            #[cfg(feature = "brotli")]
+            ContentEncoding::Brotli,
+        ];
+        // The uploader prefers the last available encoding,
+        // each of the other pings overrides it with an encoding of its own.
+        let accepted = *available.last().unwrap();
+        let upload_manager = PingUploadManager::no_policy(dir.path());
+        upload_manager.set_content_encodings(&["unknown".into(), accepted.name().into()]);
+        for encoding in available {
+            upload_manager.set_ping_content_encoding(encoding.name(), Some(encoding));
+        }
+
+        let json_body = r#"{"ping_info":{"seq":1}}"#;
+        let expected: Vec<_> = available
+            .iter()
+            .map(|encoding| (encoding.name(), *encoding))
+            .chain([("accepted", accepted)])
+            .collect();
+        for (ping_name, _) in &expected {
+            upload_manager.enqueue_ping(
+                &glean,
+                PingPayload {
+                    document_id: Uuid::new_v4().to_string(),
+                    upload_path: PATH.into(),
+                    json_body: json_body.into(),
+                    headers: None,
+                    body_has_info_sections: true,
+                    ping_name: ping_name.to_string(),
+                    uploader_capabilities: vec![],
+                    priority: PingPriority::Normal,
+                },
+            );
+        }
+
+        for (ping_name, encoding) in expected {
+            match upload_manager.get_upload_task(&glean, false) {
+                PingUploadTask::Upload { request } => {
+                    assert_eq!(ping_name, request.ping_name);
+                    assert_eq!(encoding.name(), request.headers["Content-Encoding"]);
+                    assert_ne!(json_body.as_bytes(), &request.body[..]);
+                    assert_eq!(
+                        json_body.as_bytes(),
+                        &encoding.decode(&request.body).unwrap()[..]
+                    );
+                }
+                _ => panic!("Expected upload manager to return the next request!"),
+            }
+        }
+        assert_eq!(
+            upload_manager.get_upload_task(&glean, false),
+            PingUploadTask::done()
+        );
+    }
 }
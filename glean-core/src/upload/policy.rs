@@ -6,10 +6,16 @@
 
 use malloc_size_of_derive::MallocSizeOf;
 
+use crate::UploadBackoff;
+
 const MAX_RECOVERABLE_FAILURES: u32 = 3;
 const MAX_WAIT_ATTEMPTS: u32 = 3;
 const MAX_PING_BODY_SIZE: usize = 1024 * 1024; // 1 MB
 
+// A pause requested by a server is handed to uploaders as `Wait` tasks, which they block on.
+// Don't let a misbehaving server stall uploaders for too long.
+const MAX_RETRY_AFTER_MS: u64 = 5 * 60 * 1000;
+
 // Directory safety limit for managing pending pings, along with `MAX_PENDING_PINGS_COUNT`.
 // The default of 50MB is sized more for outage survival than normal day-to-day ping volumes.
 const MAX_PENDING_PINGS_DIRECTORY_SIZE: u64 = 50 * 1024 * 1024;
@@ -18,6 +24,15 @@ const MAX_PENDING_PINGS_DIRECTORY_SIZE: u64 = 50 * 1024 * 1024;
 // are deleted. Integrating apps may override this via Configuration if needed.
 const MAX_PENDING_PINGS_COUNT: u64 = 500;
 
+// The default backoff schedule, used when opting into backing off:
+// Retry a failing ping after 1 minute at first, doubling the delay with every failure,
+// but waiting no longer than 6 hours.
+// The delays are spread by up to 20% to avoid many clients retrying in lockstep.
+const BACKOFF_BASE_DELAY_MS: u64 = 60 * 1000;
+const BACKOFF_MULTIPLIER: f64 = 2.0;
+const BACKOFF_MAX_DELAY_MS: u64 = 6 * 60 * 60 * 1000;
+const BACKOFF_JITTER: f64 = 0.2;
+
 /// A struct holding the values for all the policies related to ping storage, uploading and requests.
 #[derive(Debug, MallocSizeOf)]
 pub struct Policy {
@@ -32,10 +47,16 @@ pub struct Policy {
     max_wait_attempts: Option<u32>,
     /// The maximum size in bytes a ping body may have to be eligible for upload.
     max_ping_body_size: Option<usize>,
+    /// The maximum time in milliseconds uploads are paused for when a server asks to retry later.
+    max_retry_after: Option<u64>,
     /// The maximum size in byte the pending pings directory may have on disk.
     max_pending_pings_directory_size: Option<u64>,
     /// The maximum number of pending pings on disk.
     max_pending_pings_count: Option<u64>,
+    /// The backoff schedule for pings that failed to upload with a recoverable failure.
+    ///
+    /// Pings are retried without delay if this is not set.
+    backoff: Option<UploadBackoff>,
 }
 
 impl Default for Policy {
@@ -44,8 +65,21 @@ impl Default for Policy {
             max_recoverable_failures: Some(MAX_RECOVERABLE_FAILURES),
             max_wait_attempts: Some(MAX_WAIT_ATTEMPTS),
             max_ping_body_size: Some(MAX_PING_BODY_SIZE),
+            max_retry_after: Some(MAX_RETRY_AFTER_MS),
             max_pending_pings_directory_size: Some(MAX_PENDING_PINGS_DIRECTORY_SIZE),
             max_pending_pings_count: Some(MAX_PENDING_PINGS_COUNT),
+            backoff: None,
+        }
+    }
+}
+
+impl Default for UploadBackoff {
+    fn default() -> Self {
+        UploadBackoff {
+            base_delay_ms: BACKOFF_BASE_DELAY_MS,
+            multiplier: BACKOFF_MULTIPLIER,
+            max_delay_ms: BACKOFF_MAX_DELAY_MS,
+            jitter: BACKOFF_JITTER,
         }
     }
 }
@@ -87,6 +121,18 @@ impl Policy {
         self.max_ping_body_size = v;
     }
 
+    pub fn max_retry_after(&self) -> u64 {
+        match &self.max_retry_after {
+            Some(v) => *v,
+            None => u64::MAX,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_max_retry_after(&mut self, v: Option<u64>) {
+        self.max_retry_after = v;
+    }
+
     pub fn max_pending_pings_directory_size(&self) -> u64 {
         match &self.max_pending_pings_directory_size {
             Some(v) => *v,
@@ -108,4 +154,12 @@ impl Policy {
     pub fn set_max_pending_pings_count(&mut self, v: Option<u64>) {
         self.max_pending_pings_count = v;
     }
+
+    pub fn backoff(&self) -> Option<&UploadBackoff> {
+        self.backoff.as_ref().filter(|b| b.base_delay_ms > 0)
+    }
+
+    pub fn set_backoff(&mut self, v: Option<UploadBackoff>) {
+        self.backoff = v;
+    }
 }
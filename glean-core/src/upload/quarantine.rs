@@ -0,0 +1,382 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Quarantine for pings that permanently failed to upload.
+//!
+//! Instead of deleting them, the last few pings that failed to upload
+//! are moved to a separate directory, together with a record of why they failed.
+//! This allows diagnosing ingestion rejections after the fact.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use malloc_size_of_derive::MallocSizeOf;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::encryption::{self, Cipher};
+use crate::{DELETION_REQUEST_PINGS_DIRECTORY, PENDING_PINGS_DIRECTORY};
+
+/// The name of the directory, in the data directory, quarantined pings are kept in.
+pub(crate) const QUARANTINED_PINGS_DIRECTORY: &str = "quarantined_pings";
+
+/// The file extension of the failure records kept alongside quarantined ping files.
+const RECORD_EXTENSION: &str = "json";
+
+/// A ping that permanently failed to upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedPing {
+    /// The ping's document id.
+    pub document_id: String,
+    /// The ping's name.
+    pub ping_name: String,
+    /// Why the ping failed to upload, as labeled in `glean.upload.ping_upload_failure`.
+    pub reason: String,
+    /// The HTTP status code the upload failed with, if any.
+    pub status_code: Option<i32>,
+    /// When the ping was quarantined, in milliseconds since the UNIX epoch.
+    pub quarantined_at: i64,
+}
+
+/// The failure record of a quarantined ping, as persisted on disk.
+#[derive(Debug, Deserialize, Serialize)]
+struct FailureRecord {
+    ping_name: String,
+    reason: String,
+    status_code: Option<i32>,
+    quarantined_at: i64,
+    /// Whether the ping came from the deletion-request pings directory.
+    deletion_request: bool,
+}
+
+/// Manages the quarantined pings directory.
+#[derive(Debug, MallocSizeOf)]
+pub struct PingQuarantine {
+    /// Path to the data directory.
+    #[ignore_malloc_size_of = "not worth measuring"]
+    data_path: PathBuf,
+    /// The maximum number of pings to keep. `0` disables the quarantine.
+    max_count: usize,
+}
+
+impl PingQuarantine {
+    /// Creates a new, disabled, quarantine.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_path` - Path to the data directory.
+    pub fn new(data_path: &Path) -> Self {
+        Self {
+            data_path: data_path.to_path_buf(),
+            max_count: 0,
+        }
+    }
+
+    /// Sets the maximum number of pings to keep. `0` disables the quarantine.
+    pub fn set_max_count(&mut self, max_count: usize) {
+        self.max_count = max_count;
+    }
+
+    /// Whether failed pings are quarantined.
+    pub fn is_enabled(&self) -> bool {
+        self.max_count > 0
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.data_path.join(QUARANTINED_PINGS_DIRECTORY)
+    }
+
+    fn record_path(&self, document_id: &str) -> PathBuf {
+        self.dir()
+            .join(document_id)
+            .with_extension(RECORD_EXTENSION)
+    }
+
+    /// Moves a ping file into the quarantine, evicting the oldest pings beyond the maximum.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_file` - Path to the ping file.
+    /// * `document_id` - The ping's document id.
+    /// * `ping_name` - The ping's name.
+    /// * `reason` - Why the ping failed to upload.
+    /// * `status_code` - The HTTP status code the upload failed with, if any.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ping was quarantined.
+    /// If not, the ping file is left in place.
+    pub fn quarantine(
+        &self,
+        ping_file: &Path,
+        document_id: &str,
+        ping_name: &str,
+        reason: &str,
+        status_code: Option<i32>,
+    ) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        let record = FailureRecord {
+            ping_name: ping_name.to_string(),
+            reason: reason.to_string(),
+            status_code,
+            quarantined_at: Utc::now().timestamp_millis(),
+            deletion_request: ping_file
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == DELETION_REQUEST_PINGS_DIRECTORY),
+        };
+        if let Err(e) = self.store(ping_file, document_id, &record) {
+            log::warn!("Failed to quarantine ping {}. Error: {}", document_id, e);
+            self.remove(document_id);
+            return false;
+        }
+        log::info!("Ping {} quarantined.", document_id);
+
+        let mut pings = self.list();
+        if pings.len() > self.max_count {
+            // Oldest first.
+            pings.sort_by_key(|ping| ping.quarantined_at);
+            let excess = pings.len() - self.max_count;
+            for ping in &pings[..excess] {
+                self.remove(&ping.document_id);
+            }
+        }
+
+        true
+    }
+
+    fn store(&self, ping_file: &Path, document_id: &str, record: &FailureRecord) -> io::Result<()> {
+        fs::create_dir_all(self.dir())?;
+        {
+            let mut file = File::create(self.record_path(document_id))?;
+            file.write_all(serde_json::to_string(record)?.as_bytes())?;
+        }
+        fs::rename(ping_file, self.dir().join(document_id))
+    }
+
+    /// Lists all quarantined pings, in no particular order.
+    pub fn list(&self) -> Vec<QuarantinedPing> {
+        let entries = match self.dir().read_dir() {
+            Ok(entries) => entries,
+            // The directory doesn't exist if no pings were quarantined yet.
+            Err(_) => return Vec::new(),
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                let document_id = path.file_name()?.to_str()?;
+                if Uuid::parse_str(document_id).is_err() {
+                    return None;
+                }
+                let record = self.read_record(document_id)?;
+                Some(QuarantinedPing {
+                    document_id: document_id.to_string(),
+                    ping_name: record.ping_name,
+                    reason: record.reason,
+                    status_code: record.status_code,
+                    quarantined_at: record.quarantined_at,
+                })
+            })
+            .collect()
+    }
+
+    fn read_record(&self, document_id: &str) -> Option<FailureRecord> {
+        let contents = fs::read_to_string(self.record_path(document_id)).ok()?;
+        serde_json::from_str(&contents)
+            .map_err(|e| log::warn!("Invalid quarantine record for {}: {}", document_id, e))
+            .ok()
+    }
+
+    /// Moves a quarantined ping back into the pings directory it came from.
+    ///
+    /// # Arguments
+    ///
+    /// * `document_id` - The ping's document id.
+    ///
+    /// # Returns
+    ///
+    /// Whether the ping was restored.
+    pub fn restore(&self, document_id: &str) -> bool {
+        // Don't let arbitrary input point outside of the quarantine.
+        if Uuid::parse_str(document_id).is_err() {
+            log::warn!("Not a quarantined ping: {}", document_id);
+            return false;
+        }
+        let Some(record) = self.read_record(document_id) else {
+            log::warn!("Cannot find quarantined ping {}", document_id);
+            return false;
+        };
+
+        let pings_dir = self.data_path.join(if record.deletion_request {
+            DELETION_REQUEST_PINGS_DIRECTORY
+        } else {
+            PENDING_PINGS_DIRECTORY
+        });
+        let restored = fs::create_dir_all(&pings_dir)
+            .and_then(|_| fs::rename(self.dir().join(document_id), pings_dir.join(document_id)));
+        if let Err(e) = restored {
+            log::warn!(
+                "Failed to restore quarantined ping {}. Error: {}",
+                document_id,
+                e
+            );
+            return false;
+        }
+
+        self.remove(document_id);
+        true
+    }
+
+    /// Deletes quarantined pings.
+    ///
+    /// # Arguments
+    ///
+    /// * `ping_names` - The names of the pings to delete, `None` to delete all of them.
+    pub fn purge(&self, ping_names: Option<&[&str]>) {
+        for ping in self.list() {
+            if ping_names.is_none_or(|names| names.contains(&&ping.ping_name[..])) {
+                self.remove(&ping.document_id);
+            }
+        }
+    }
+
+    /// Encrypts all quarantined ping files stored in plaintext.
+    ///
+    /// The failure records are kept in plaintext.
+    pub fn encrypt_plaintext_files(&self, cipher: &Cipher) -> io::Result<()> {
+        for ping in self.list() {
+            encryption::encrypt_file(cipher, &self.dir().join(&ping.document_id))?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a quarantined ping and its record, ignoring any missing files.
+    fn remove(&self, document_id: &str) {
+        for path in [self.dir().join(document_id), self.record_path(document_id)] {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    log::warn!("Error deleting file {}. {}", path.display(), e);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_ping(dir: &Path, directory: &str) -> (PathBuf, String) {
+        let document_id = Uuid::new_v4().to_string();
+        let pings_dir = dir.join(directory);
+        fs::create_dir_all(&pings_dir).unwrap();
+        let path = pings_dir.join(&document_id);
+        fs::write(&path, "/submit/app/test/1/id\n{}\n").unwrap();
+        (path, document_id)
+    }
+
+    #[test]
+    fn disabled_quarantine_keeps_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine = PingQuarantine::new(dir.path());
+
+        let (path, document_id) = write_ping(dir.path(), PENDING_PINGS_DIRECTORY);
+        assert!(!quarantine.quarantine(&path, &document_id, "test", "unrecoverable", None));
+        assert!(path.exists());
+        assert!(quarantine.list().is_empty());
+    }
+
+    #[test]
+    fn keeps_the_most_recent_failed_pings() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut quarantine = PingQuarantine::new(dir.path());
+        quarantine.set_max_count(2);
+
+        let mut document_ids = Vec::new();
+        for code in [400, 401, 402] {
+            let (path, document_id) = write_ping(dir.path(), PENDING_PINGS_DIRECTORY);
+            assert!(quarantine.quarantine(
+                &path,
+                &document_id,
+                "test",
+                "status_code_4xx",
+                Some(code)
+            ));
+            assert!(!path.exists());
+            document_ids.push(document_id);
+            // Make sure the pings are quarantined at different times.
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let mut pings = quarantine.list();
+        pings.sort_by_key(|ping| ping.quarantined_at);
+        assert_eq!(2, pings.len());
+        assert_eq!(document_ids[1], pings[0].document_id);
+        assert_eq!(Some(401), pings[0].status_code);
+        assert_eq!("status_code_4xx", pings[0].reason);
+        assert_eq!(document_ids[2], pings[1].document_id);
+        assert!(!dir
+            .path()
+            .join(QUARANTINED_PINGS_DIRECTORY)
+            .join(&document_ids[0])
+            .exists());
+    }
+
+    #[test]
+    fn restores_pings_to_their_original_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut quarantine = PingQuarantine::new(dir.path());
+        quarantine.set_max_count(10);
+
+        let (pending, pending_id) = write_ping(dir.path(), PENDING_PINGS_DIRECTORY);
+        let (deletion, deletion_id) = write_ping(dir.path(), DELETION_REQUEST_PINGS_DIRECTORY);
+        quarantine.quarantine(&pending, &pending_id, "test", "unrecoverable", None);
+        quarantine.quarantine(
+            &deletion,
+            &deletion_id,
+            "deletion-request",
+            "incapable",
+            None,
+        );
+        assert!(!pending.exists());
+        assert!(!deletion.exists());
+
+        assert!(quarantine.restore(&pending_id));
+        assert!(quarantine.restore(&deletion_id));
+        assert!(pending.exists());
+        assert!(deletion.exists());
+        assert!(quarantine.list().is_empty());
+
+        assert!(!quarantine.restore(&pending_id));
+        assert!(!quarantine.restore("../pending_pings/nope"));
+    }
+
+    #[test]
+    fn purges_pings_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut quarantine = PingQuarantine::new(dir.path());
+        quarantine.set_max_count(10);
+
+        for ping_name in ["a", "b", "b"] {
+            let (path, document_id) = write_ping(dir.path(), PENDING_PINGS_DIRECTORY);
+            quarantine.quarantine(&path, &document_id, ping_name, "unrecoverable", None);
+        }
+
+        quarantine.purge(Some(&["b"]));
+        let pings = quarantine.list();
+        assert_eq!(1, pings.len());
+        assert_eq!("a", pings[0].ping_name);
+
+        quarantine.purge(None);
+        assert!(quarantine.list().is_empty());
+    }
+}
@@ -7,11 +7,11 @@
 use std::collections::HashMap;
 
 use chrono::prelude::{DateTime, Utc};
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use serde_json::Value as JsonValue;
-use std::io::prelude::*;
 
+use super::encoding::ContentEncoding;
 use crate::error::{ErrorKind, Result};
+use crate::metrics::PingPriority;
 use crate::system;
 
 /// A representation for request headers.
@@ -43,28 +43,33 @@ fn create_x_telemetry_agent_header_value(
     )
 }
 
-/// Attempt to gzip the contents of a ping.
-fn gzip_content(path: &str, content: &[u8]) -> Option<Vec<u8>> {
-    let mut gzipper = GzEncoder::new(Vec::new(), Compression::default());
-
-    // Attempt to add the content to the gzipper.
-    if let Err(e) = gzipper.write_all(content) {
-        log::warn!("Failed to write to the gzipper: {} - {:?}", path, e);
-        return None;
+/// Attempt to encode the contents of a ping.
+fn encode_content(path: &str, content: &[u8], encoding: ContentEncoding) -> Option<Vec<u8>> {
+    match encoding.encode(content) {
+        Ok(encoded) => Some(encoded),
+        Err(e) => {
+            log::warn!(
+                "Failed to {}-encode the content: {} - {:?}",
+                encoding.name(),
+                path,
+                e
+            );
+            None
+        }
     }
-
-    gzipper.finish().ok()
 }
 
 pub struct Builder {
     document_id: Option<String>,
     path: Option<String>,
-    body: Option<Vec<u8>>,
+    body: Option<String>,
+    content_encoding: ContentEncoding,
     headers: HeaderMap,
     body_max_size: usize,
     body_has_info_sections: Option<bool>,
     ping_name: Option<String>,
     uploader_capabilities: Option<Vec<String>>,
+    priority: PingPriority,
 }
 
 impl Builder {
@@ -88,11 +93,13 @@ impl Builder {
             document_id: None,
             path: None,
             body: None,
+            content_encoding: ContentEncoding::Gzip,
             headers,
             body_max_size,
             body_has_info_sections: None,
             ping_name: None,
             uploader_capabilities: None,
+            priority: PingPriority::default(),
         }
     }
 
@@ -110,37 +117,23 @@ impl Builder {
 
     /// Sets the body for this request.
     ///
-    /// This method will also attempt to gzip the body contents
-    /// and add headers related to the body that was just added.
-    ///
-    /// Namely these headers are the "Content-Length" with the length of the body
-    /// and in case we are successfull on gzipping the contents, the "Content-Encoding"="gzip".
-    ///
-    /// **Important**
-    /// If we are unable to gzip we don't panic and instead just set the uncompressed body.
+    /// The body is encoded with the request's content encoding when building the request.
     ///
     /// # Panics
     ///
     /// This method will panic in case we try to set the body before setting the path.
     pub fn body<S: Into<String>>(mut self, value: S) -> Self {
-        // Attempt to gzip the body contents.
-        let original_as_string = value.into();
-        let gzipped_content = gzip_content(
-            self.path
-                .as_ref()
-                .expect("Path must be set before attempting to set the body"),
-            original_as_string.as_bytes(),
+        assert!(
+            self.path.is_some(),
+            "Path must be set before attempting to set the body"
         );
-        let add_gzip_header = gzipped_content.is_some();
-        let body = gzipped_content.unwrap_or_else(|| original_as_string.into_bytes());
-
-        // Include headers related to body
-        self = self.header("Content-Length", &body.len().to_string());
-        if add_gzip_header {
-            self = self.header("Content-Encoding", "gzip");
-        }
+        self.body = Some(value.into());
+        self
+    }
 
-        self.body = Some(body);
+    /// Sets the content encoding of the body. Default: gzip.
+    pub fn content_encoding(mut self, encoding: ContentEncoding) -> Self {
+        self.content_encoding = encoding;
         self
     }
 
@@ -174,16 +167,48 @@ impl Builder {
         self
     }
 
+    /// Sets the priority to upload this ping with.
+    ///
+    /// Defaults to [`PingPriority::Normal`].
+    pub fn priority(mut self, priority: PingPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     /// Consumes the builder and create a PingRequest.
     ///
+    /// This will also attempt to encode the body contents
+    /// and add headers related to the body.
+    ///
+    /// Namely these headers are the "Content-Length" with the length of the body
+    /// and in case we are successfull on encoding the contents, the "Content-Encoding".
+    ///
+    /// **Important**
+    /// If we are unable to encode we don't panic and instead just set the unencoded body.
+    ///
     /// # Panics
     ///
     /// This method will panic if any of the required fields are missing:
     /// `document_id`, `path` and `body`.
-    pub fn build(self) -> Result<PingRequest> {
-        let body = self
+    pub fn build(mut self) -> Result<PingRequest> {
+        let original = self
             .body
+            .take()
             .expect("body must be set before attempting to build PingRequest");
+        let encoded = encode_content(
+            self.path.as_deref().unwrap_or_default(),
+            original.as_bytes(),
+            self.content_encoding,
+        );
+        let add_encoding_header = encoded.is_some();
+        let body = encoded.unwrap_or_else(|| original.into_bytes());
+
+        // Include headers related to body
+        self = self.header("Content-Length", &body.len().to_string());
+        if add_encoding_header {
+            let name = self.content_encoding.name();
+            self = self.header("Content-Encoding", name);
+        }
 
         if body.len() > self.body_max_size {
             return Err(ErrorKind::PingBodyOverflow(body.len()).into());
@@ -207,6 +232,7 @@ impl Builder {
             uploader_capabilities: self
                 .uploader_capabilities
                 .expect("uploader_capabilities must be set before attempting to build PingRequest"),
+            priority: self.priority,
         })
     }
 }
@@ -219,9 +245,9 @@ pub struct PingRequest {
     pub document_id: String,
     /// The path for the server to upload the ping to.
     pub path: String,
-    /// The body of the request, as a byte array. If gzip encoded, then
+    /// The body of the request, as a byte array. If encoded, then
     /// the `headers` list will contain a `Content-Encoding` header with
-    /// the value `gzip`.
+    /// the encoding's name, e.g. `gzip`.
     pub body: Vec<u8>,
     /// A map with all the headers to be sent with the request.
     pub headers: HeaderMap,
@@ -231,6 +257,8 @@ pub struct PingRequest {
     pub ping_name: String,
     /// The capabilities required during this ping's upload.
     pub uploader_capabilities: Vec<String>,
+    /// The priority to upload this ping with.
+    pub priority: PingPriority,
 }
 
 impl PingRequest {
@@ -253,15 +281,17 @@ impl PingRequest {
     /// Decompresses and pretty-format the ping payload
     ///
     /// Should be used for logging when required.
-    /// This decompresses the payload in memory.
+    /// This decompresses the payload in memory,
+    /// according to its `Content-Encoding` header.
     pub fn pretty_body(&self) -> Option<String> {
-        let mut gz = GzDecoder::new(&self.body[..]);
-        let mut s = String::with_capacity(self.body.len());
+        let decoded = self
+            .headers
+            .get("Content-Encoding")
+            .and_then(|name| ContentEncoding::from_name(name))
+            .and_then(|encoding| encoding.decode(&self.body).ok());
 
-        gz.read_to_string(&mut s)
+        std::str::from_utf8(decoded.as_deref().unwrap_or(&self.body))
             .ok()
-            .map(|_| &s[..])
-            .or_else(|| std::str::from_utf8(&self.body).ok())
             .and_then(|payload| serde_json::from_str::<JsonValue>(payload).ok())
             .and_then(|json| serde_json::to_string_pretty(&json).ok())
     }
@@ -325,4 +355,36 @@ mod test {
 
         assert!(request.is_err());
     }
+
+    #[test]
+    fn encodes_the_body_and_decodes_it_for_logging() {
+        #[cfg(feature = "zstd")]
+        let encoding = ContentEncoding::Zstd;
+        #[cfg(not(feature = "zstd"))]
+        let encoding = ContentEncoding::Gzip;
+
+        let request = PingRequest::builder(/* language_binding_name */ "Rust", 1024 * 1024)
+            .document_id("woop")
+            .path("/random/path/doesnt/matter")
+            .body(r#"{"ping_info":{"seq":1}}"#)
+            .content_encoding(encoding)
+            .body_has_info_sections(true)
+            .ping_name("whatevs")
+            .uploader_capabilities(vec![])
+            .build()
+            .unwrap();
+
+        assert_eq!(encoding.name(), request.headers["Content-Encoding"]);
+        assert_eq!(
+            request.body.len().to_string(),
+            request.headers["Content-Length"]
+        );
+        assert_eq!(
+            encoding.encode(br#"{"ping_info":{"seq":1}}"#).unwrap(),
+            request.body
+        );
+
+        let pretty = request.pretty_body().unwrap();
+        assert!(pretty.contains(r#""seq": 1"#));
+    }
 }
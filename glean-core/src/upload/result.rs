@@ -43,6 +43,18 @@ pub enum UploadResult {
         code: i32,
     },
 
+    /// A HTTP response code, along with the server's hint when to retry.
+    ///
+    /// Usually a `429 Too Many Requests` or `503 Service Unavailable` response
+    /// carrying a `Retry-After` header.
+    /// No pings will be uploaded until the hinted time.
+    HttpStatusWithRetryAfter {
+        /// The HTTP status code
+        code: i32,
+        /// The time in milliseconds the server asked to wait before uploading again.
+        retry_after_ms: u64,
+    },
+
     /// Signal that this uploader is done with work
     /// and won't accept new work.
     Done {
@@ -59,10 +71,21 @@ impl UploadResult {
     /// Failures are recorded in the `ping_upload_failure` metric.
     pub fn get_label(&self) -> Option<&str> {
         match self {
-            UploadResult::HttpStatus { code: 200..=299 } => None,
-            UploadResult::HttpStatus { code: 400..=499 } => Some("status_code_4xx"),
-            UploadResult::HttpStatus { code: 500..=599 } => Some("status_code_5xx"),
-            UploadResult::HttpStatus { .. } => Some("status_code_unknown"),
+            UploadResult::HttpStatus { code: 200..=299 }
+            | UploadResult::HttpStatusWithRetryAfter {
+                code: 200..=299, ..
+            } => None,
+            UploadResult::HttpStatus { code: 400..=499 }
+            | UploadResult::HttpStatusWithRetryAfter {
+                code: 400..=499, ..
+            } => Some("status_code_4xx"),
+            UploadResult::HttpStatus { code: 500..=599 }
+            | UploadResult::HttpStatusWithRetryAfter {
+                code: 500..=599, ..
+            } => Some("status_code_5xx"),
+            UploadResult::HttpStatus { .. } | UploadResult::HttpStatusWithRetryAfter { .. } => {
+                Some("status_code_unknown")
+            }
             UploadResult::UnrecoverableFailure { .. } => Some("unrecoverable"),
             UploadResult::RecoverableFailure { .. } => Some("recoverable"),
             UploadResult::Incapable { .. } => Some("incapable"),
@@ -101,6 +124,20 @@ impl UploadResult {
         Self::HttpStatus { code }
     }
 
+    /// A HTTP response code, along with the server's hint when to retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The HTTP status code.
+    /// * `retry_after_ms` - The time in milliseconds the server asked to wait before uploading again,
+    ///   e.g. taken from the `Retry-After` header.
+    pub fn http_status_with_retry_after(code: i32, retry_after_ms: u64) -> Self {
+        Self::HttpStatusWithRetryAfter {
+            code,
+            retry_after_ms,
+        }
+    }
+
     /// This uploader is done.
     pub fn done() -> Self {
         Self::Done { unused: 0 }
@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::fs;
+
+use glean_core::metrics::*;
+use glean_core::{backup, CommonMetricData, ErrorKind, Lifetime};
+
+fn clientid_metric() -> UuidMetric {
+    UuidMetric::new(CommonMetricData {
+        name: "client_id".into(),
+        category: "".into(),
+        send_in_pings: vec!["glean_client_info".into()],
+        lifetime: Lifetime::User,
+        ..Default::default()
+    })
+}
+
+fn counter() -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: "counter".into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime: Lifetime::User,
+        ..Default::default()
+    })
+}
+
+fn event_metric() -> EventMetric {
+    EventMetric::new(
+        CommonMetricData {
+            name: "clicked".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store2".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    )
+}
+
+#[test]
+fn backup_restores_into_another_data_directory() {
+    let (glean, dir) = new_glean(None);
+    let client_id = clientid_metric().get_value(&glean, None).unwrap();
+    counter().add_sync(&glean, 3);
+    event_metric().record_sync(&glean, 1, HashMap::new(), 0);
+    assert!(glean
+        .get_ping_by_name("store1")
+        .unwrap()
+        .submit_sync(&glean, None));
+    let pending = get_queued_pings(dir.path()).unwrap();
+    assert!(!pending.is_empty());
+
+    let (_b, backup_dir) = tempdir();
+    let backup_path = std::path::Path::new(&backup_dir).join("backup");
+    glean.backup(&backup_path).unwrap();
+    assert!(backup_path.join("db").join("glean.sqlite").exists());
+    assert!(backup_path.join("client_id.txt").exists());
+
+    // The backup is a snapshot, later changes are not in it.
+    counter().add_sync(&glean, 1);
+    drop(glean);
+
+    let restore_dir = tempfile::tempdir().unwrap();
+    backup::restore(&backup_path, restore_dir.path()).unwrap();
+    assert!(!restore_dir.path().join("restore").exists());
+    assert_eq!(
+        pending.len(),
+        get_queued_pings(restore_dir.path()).unwrap().len()
+    );
+
+    let (glean, _restore_dir) = new_glean(Some(restore_dir));
+    assert_eq!(
+        client_id,
+        clientid_metric().get_value(&glean, None).unwrap()
+    );
+    // Recorded before the ping was submitted and not cleared, because of its user lifetime.
+    assert_eq!(3, counter().get_value(&glean, Some("store1")).unwrap());
+    assert_eq!(
+        1,
+        event_metric()
+            .get_value(&glean, Some("store2"))
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn backup_never_overwrites() {
+    let (glean, _t) = new_glean(None);
+    let (_b, backup_dir) = tempdir();
+    let backup_path = std::path::Path::new(&backup_dir);
+
+    assert!(glean.backup(backup_path).is_err());
+    assert_eq!(0, fs::read_dir(backup_path).unwrap().count());
+}
+
+#[test]
+fn backups_are_not_created_inside_the_data_directory() {
+    let (glean, dir) = new_glean(None);
+
+    let inside = [
+        dir.path().join("backup"),
+        dir.path().join("db").join("backup"),
+        // Not normalized, but still inside.
+        dir.path()
+            .join("..")
+            .join(dir.path().file_name().unwrap())
+            .join("backup"),
+    ];
+    for backup_path in inside {
+        let err = glean.backup(&backup_path).unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::IoError(e) if e.kind() == std::io::ErrorKind::InvalidInput)
+        );
+        assert!(!backup_path.exists());
+    }
+}
+
+#[test]
+fn invalid_backups_are_not_restored() {
+    let (glean, dir) = new_glean(None);
+    counter().add_sync(&glean, 3);
+    let (_b, backup_dir) = tempdir();
+    let backup_path = std::path::Path::new(&backup_dir).join("backup");
+    glean.backup(&backup_path).unwrap();
+    drop(glean);
+
+    // A backup from a newer, incompatible version.
+    let db_path = backup_path.join("db").join("glean.sqlite");
+    rusqlite::Connection::open(&db_path)
+        .unwrap()
+        .execute_batch("PRAGMA user_version = 999")
+        .unwrap();
+    let err = backup::restore(&backup_path, dir.path()).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Schema(_)));
+
+    // Not a database at all.
+    fs::write(&db_path, b"not a database").unwrap();
+    assert!(backup::restore(&backup_path, dir.path()).is_err());
+
+    // No database.
+    fs::remove_file(&db_path).unwrap();
+    assert!(backup::restore(&backup_path, dir.path()).is_err());
+
+    // The data is untouched.
+    assert!(!dir.path().join("restore").exists());
+    let (glean, _t) = new_glean(Some(dir));
+    assert_eq!(3, counter().get_value(&glean, Some("store1")).unwrap());
+}
+
+/// Moves all entries of a directory into a new one, like an interrupted restore does.
+fn move_into(from: &std::path::Path, to: &std::path::Path) {
+    fs::create_dir(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        if entry.path() != to {
+            fs::rename(entry.path(), to.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+#[test]
+fn interrupted_restores_are_rolled_back() {
+    let (glean, dir) = new_glean(None);
+    counter().add_sync(&glean, 3);
+    let (_b, backup_dir) = tempdir();
+    let backup_path = std::path::Path::new(&backup_dir).join("backup");
+    glean.backup(&backup_path).unwrap();
+    counter().add_sync(&glean, 2);
+    drop(glean);
+
+    // Interrupted while moving the backup in: the current data was moved aside,
+    // some of the backup is in place, the rest is still staged.
+    let data_path = dir.path();
+    move_into(data_path, &data_path.join("replaced"));
+    fs::create_dir(data_path.join("restoring")).unwrap();
+    copy(&backup_path.join("db"), &data_path.join("db"));
+    fs::copy(
+        backup_path.join("client_id.txt"),
+        data_path.join("restoring").join("client_id.txt"),
+    )
+    .unwrap();
+
+    let (glean, dir) = new_glean(Some(dir));
+    assert_eq!(5, counter().get_value(&glean, Some("store1")).unwrap());
+    for leftover in ["restore", "restoring", "replaced"] {
+        assert!(!dir.path().join(leftover).exists());
+    }
+    drop(glean);
+
+    // Interrupted while moving the current data aside.
+    let data_path = dir.path();
+    fs::create_dir(data_path.join("restore")).unwrap();
+    fs::create_dir(data_path.join("replaced")).unwrap();
+    fs::rename(
+        data_path.join("client_id.txt"),
+        data_path.join("replaced").join("client_id.txt"),
+    )
+    .unwrap();
+
+    // A restore first rolls back the interrupted one.
+    backup::restore(&backup_path, data_path).unwrap();
+    for leftover in ["restore", "restoring", "replaced"] {
+        assert!(!dir.path().join(leftover).exists());
+    }
+    let (glean, _t) = new_glean(Some(dir));
+    assert_eq!(3, counter().get_value(&glean, Some("store1")).unwrap());
+}
+
+#[test]
+fn completed_restores_are_kept() {
+    let (glean, dir) = new_glean(None);
+    counter().add_sync(&glean, 3);
+    let (_b, backup_dir) = tempdir();
+    let backup_path = std::path::Path::new(&backup_dir).join("backup");
+    glean.backup(&backup_path).unwrap();
+    counter().add_sync(&glean, 2);
+    drop(glean);
+
+    // Interrupted before removing the replaced data.
+    let data_path = dir.path();
+    move_into(data_path, &data_path.join("replaced"));
+    copy(&backup_path, data_path);
+
+    let (glean, dir) = new_glean(Some(dir));
+    assert_eq!(3, counter().get_value(&glean, Some("store1")).unwrap());
+    assert!(!dir.path().join("replaced").exists());
+}
+
+/// Recursively copies a directory.
+fn copy(from: &std::path::Path, to: &std::path::Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let dest = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy(&entry.path(), &dest);
+        } else {
+            fs::copy(entry.path(), dest).unwrap();
+        }
+    }
+}
@@ -63,6 +63,8 @@ pub fn new_glean_with_upload(
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: false,
         experimentation_id: None,
         enable_internal_pings: true,
@@ -75,6 +77,11 @@ pub fn new_glean_with_upload(
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
     let mut glean = Glean::new(cfg).unwrap();
 
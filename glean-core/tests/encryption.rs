@@ -0,0 +1,268 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![cfg(feature = "encryption")]
+
+mod common;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use glean_core::metrics::*;
+use glean_core::{
+    CommonMetricData, EncryptionError, EncryptionKeyProvider, ErrorKind, Glean, Lifetime,
+    PingUploadTask,
+};
+
+struct Key(Option<Vec<u8>>);
+
+impl EncryptionKeyProvider for Key {
+    fn get_key(&self) -> Option<Vec<u8>> {
+        self.0.clone()
+    }
+}
+
+fn cfg(dir: &Path, key: Option<Option<[u8; 32]>>) -> glean_core::InternalConfiguration {
+    glean_core::InternalConfiguration {
+        data_path: dir.display().to_string(),
+        application_id: GLOBAL_APPLICATION_ID.into(),
+        language_binding_name: "Rust".into(),
+        upload_enabled: true,
+        max_events: None,
+        delay_ping_lifetime_io: false,
+        app_build: "Unknown".into(),
+        use_core_mps: false,
+        trim_data_to_registered_pings: false,
+        log_level: None,
+        rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
+        enable_event_timestamps: false,
+        experimentation_id: None,
+        enable_internal_pings: true,
+        ping_schedule: Default::default(),
+        ping_lifetime_threshold: 0,
+        ping_lifetime_max_time: 0,
+        max_pending_pings_count: None,
+        max_pending_pings_directory_size: None,
+        session_mode: glean_core::SessionMode::Auto,
+        session_sample_rate: 1.0,
+        session_inactivity_timeout_ms: 1_800_000,
+        events_ping_acceleration_factor: None,
+        encryption_key_provider: key
+            .map(|key| Arc::new(Key(key.map(|k| k.to_vec()))) as Arc<dyn EncryptionKeyProvider>),
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
+    }
+}
+
+fn string_metric() -> StringMetric {
+    StringMetric::new(CommonMetricData {
+        name: "secret".into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime: Lifetime::User,
+        ..Default::default()
+    })
+}
+
+fn event_metric() -> EventMetric {
+    EventMetric::new(
+        CommonMetricData {
+            name: "clicked".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store2".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    )
+}
+
+fn init(dir: &Path, key: Option<Option<[u8; 32]>>) -> glean_core::Result<Glean> {
+    let mut glean = Glean::new(cfg(dir, key))?;
+    new_test_ping(&mut glean, "store1");
+    new_test_ping(&mut glean, "store2");
+    Ok(glean)
+}
+
+fn encryption_error(result: glean_core::Result<Glean>) -> EncryptionError {
+    match result.map(|_| ()).unwrap_err().kind() {
+        ErrorKind::Encryption(e) => match e {
+            EncryptionError::KeyUnavailable => EncryptionError::KeyUnavailable,
+            EncryptionError::KeyMismatch => EncryptionError::KeyMismatch,
+            EncryptionError::InvalidKey => EncryptionError::InvalidKey,
+            e => panic!("unexpected error: {e}"),
+        },
+        e => panic!("unexpected error: {e:?}"),
+    }
+}
+
+/// Whether no file in the directory contains the needle.
+fn nowhere_in(dir: &Path, needle: &[u8]) -> bool {
+    fs::read_dir(dir).unwrap().all(|entry| {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            return nowhere_in(&path, needle);
+        }
+        let data = fs::read(&path).unwrap();
+        !data.windows(needle.len()).any(|window| window == needle)
+    })
+}
+
+#[test]
+fn existing_plaintext_data_is_encrypted_and_stays_readable() {
+    let (_t, tmpname) = tempdir();
+    let dir = Path::new(&tmpname);
+    {
+        let glean = init(dir, None).unwrap();
+        string_metric().set_sync(&glean, "hunter2");
+        event_metric().record_sync(&glean, 1, HashMap::new(), 0);
+        // Leaves a pending ping behind.
+        string_metric().set_sync(&glean, "hunter2");
+        assert!(glean
+            .get_ping_by_name("store1")
+            .unwrap()
+            .submit_sync(&glean, None));
+    }
+    assert!(!nowhere_in(dir, b"hunter2"));
+
+    let glean = init(dir, Some(Some([1; 32]))).unwrap();
+    assert!(nowhere_in(dir, b"hunter2"));
+    assert!(nowhere_in(dir, b"clicked"));
+
+    assert_eq!(
+        "hunter2",
+        string_metric().get_value(&glean, Some("store1")).unwrap()
+    );
+    glean
+        .event_storage()
+        .flush_pending_events_on_startup(&glean, false);
+    assert_eq!(
+        1,
+        event_metric()
+            .get_value(&glean, Some("store2"))
+            .unwrap()
+            .len()
+    );
+
+    let upload = (0..100).find_map(|_| match glean.get_upload_task() {
+        PingUploadTask::Upload { request } => Some(request),
+        _ => {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            None
+        }
+    });
+    assert_eq!("store1", upload.unwrap().ping_name);
+}
+
+#[test]
+fn new_data_is_encrypted() {
+    let (_t, tmpname) = tempdir();
+    let dir = Path::new(&tmpname);
+    let glean = init(dir, Some(Some([1; 32]))).unwrap();
+
+    string_metric().set_sync(&glean, "hunter2");
+    event_metric().record_sync(&glean, 1, HashMap::new(), 0);
+    assert!(glean
+        .get_ping_by_name("store1")
+        .unwrap()
+        .submit_sync(&glean, None));
+
+    assert!(nowhere_in(dir, b"hunter2"));
+    assert!(nowhere_in(dir, b"clicked"));
+    assert_eq!(
+        1,
+        event_metric()
+            .get_value(&glean, Some("store2"))
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn encrypted_data_requires_the_same_key() {
+    let (_t, tmpname) = tempdir();
+    let dir = Path::new(&tmpname);
+    {
+        let glean = init(dir, Some(Some([1; 32]))).unwrap();
+        string_metric().set_sync(&glean, "hunter2");
+    }
+
+    assert_eq!(
+        EncryptionError::KeyUnavailable,
+        encryption_error(init(dir, None))
+    );
+    assert_eq!(
+        EncryptionError::KeyUnavailable,
+        encryption_error(init(dir, Some(None)))
+    );
+    assert_eq!(
+        EncryptionError::KeyMismatch,
+        encryption_error(init(dir, Some(Some([2; 32]))))
+    );
+
+    // Failing to initialize doesn't touch the data.
+    let glean = init(dir, Some(Some([1; 32]))).unwrap();
+    assert_eq!(
+        "hunter2",
+        string_metric().get_value(&glean, Some("store1")).unwrap()
+    );
+}
+
+#[test]
+fn exporting_encrypted_pings_requires_the_key() {
+    let (_t, tmpname) = tempdir();
+    let dir = Path::new(&tmpname);
+    {
+        let glean = init(dir, Some(Some([1; 32]))).unwrap();
+        string_metric().set_sync(&glean, "hunter2");
+        assert!(glean
+            .get_ping_by_name("store1")
+            .unwrap()
+            .submit_sync(&glean, None));
+    }
+    let pending_files = || fs::read_dir(dir.join("pending_pings")).unwrap().count();
+    let pending = pending_files();
+    assert!(pending > 0);
+    let archive = dir.join("pings.archive");
+
+    let export = |key: Option<[u8; 32]>| {
+        let key = key.map(|key| Key(Some(key.to_vec())));
+        glean_core::upload::export_pending_pings(
+            dir,
+            &archive,
+            key.as_ref().map(|key| key as &dyn EncryptionKeyProvider),
+        )
+    };
+    for (key, expected) in [
+        (None, EncryptionError::KeyUnavailable),
+        (Some([2; 32]), EncryptionError::KeyMismatch),
+    ] {
+        match export(key).unwrap_err().kind() {
+            ErrorKind::Encryption(e) => assert_eq!(&expected, e),
+            e => panic!("unexpected error: {e:?}"),
+        }
+        // Nothing was exported or deleted.
+        assert!(!archive.exists());
+        assert_eq!(pending, pending_files());
+    }
+
+    let exported = export(Some([1; 32])).unwrap();
+    assert_eq!(pending, exported.len());
+    assert_eq!(0, pending_files());
+    let pings = glean_core::upload::read_archive(&archive).unwrap();
+    assert!(pings.iter().any(|ping| ping.ping_name == "store1"
+        && ping
+            .to_request("Rust")
+            .unwrap()
+            .pretty_body()
+            .unwrap()
+            .contains("hunter2")));
+}
@@ -128,13 +128,6 @@ fn snapshot_correctly_clears_the_stores() {
         .snapshot_as_json(&glean, "store1", false)
         .is_none());
 
-    let files: Vec<fs::DirEntry> = fs::read_dir(&glean.event_storage().path)
-        .unwrap()
-        .filter_map(|x| x.ok())
-        .collect();
-    assert_eq!(1, files.len());
-    assert_eq!("store2", files[0].file_name());
-
     let snapshot2 = glean
         .event_storage()
         .snapshot_as_json(&glean, "store2", false);
@@ -547,6 +540,8 @@ fn with_event_timestamps() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: None, // Enabling event timestamps
         enable_internal_pings: true,
@@ -559,6 +554,11 @@ fn with_event_timestamps() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
     let mut glean = Glean::new(cfg).unwrap();
     let ping = PingBuilder::new("store1").build();
@@ -873,3 +873,106 @@ fn events_factor_discounts_startup_ping() {
         assert_eq!(20.to_string(), event["extra"]["test_event_number"]);
     }
 }
+
+#[test]
+fn events_recorded_without_a_database_are_imported_on_startup() {
+    let store_name = "store1";
+    let event = EventMetric::new(
+        CommonMetricData {
+            name: "name".into(),
+            category: "category".into(),
+            send_in_pings: vec![store_name.into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    );
+
+    let tempdir = {
+        let (mut glean, dir) = new_glean(None);
+        glean.close_db();
+        event.record_sync(&glean, 10, HashMap::new(), 0);
+        dir
+    };
+    let event_path = tempdir.path().join("events").join(store_name);
+    assert!(event_path.exists());
+
+    let (glean, _t) = new_glean(Some(tempdir));
+    glean
+        .event_storage()
+        .flush_pending_events_on_startup(&glean, false);
+
+    assert!(!event_path.exists());
+    let events = event.get_value(&glean, store_name).unwrap();
+    assert_eq!(1, events.len());
+    assert_eq!(10, events[0].timestamp);
+}
+
+#[test]
+fn recorded_events_persist_across_restarts() {
+    let store_name = "store1";
+    let event = EventMetric::new(
+        CommonMetricData {
+            name: "name".into(),
+            category: "category".into(),
+            send_in_pings: vec![store_name.into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    );
+
+    let tempdir = {
+        let (glean, dir) = new_glean(None);
+        event.record_sync(&glean, 10, HashMap::new(), 0);
+        event.record_sync(&glean, 20, HashMap::new(), 0);
+        dir
+    };
+    // Events live in the database, not in files.
+    assert_eq!(
+        0,
+        fs::read_dir(tempdir.path().join("events")).unwrap().count()
+    );
+
+    let (glean, _t) = new_glean(Some(tempdir));
+    let events = event.get_value(&glean, store_name).unwrap();
+    assert_eq!(
+        vec![10, 20],
+        events.iter().map(|e| e.timestamp).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn events_ping_reaching_both_capacities_is_submitted_once() {
+    let (mut glean, _t) = new_glean(None);
+
+    // Empty pings would be sent as well, so a second submission shows.
+    let events_ping = PingBuilder::new("events")
+        .with_reasons(vec!["max_capacity".to_string()])
+        .with_send_if_empty(true)
+        .build();
+    glean.register_ping_type(&events_ping);
+    let max_events = glean.get_max_events();
+    events_ping.set_capacity(Some(PingCapacity {
+        max_events: Some(max_events as u32),
+        ..Default::default()
+    }));
+
+    let click = EventMetric::new(
+        CommonMetricData {
+            name: "click".into(),
+            category: "ui".into(),
+            send_in_pings: vec!["events".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    );
+    for i in 0..max_events {
+        click.record_sync(&glean, i as u64, HashMap::new(), 0);
+    }
+
+    let pings = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(1, pings.len());
+    assert_eq!(max_events, pings[0].1["events"].as_array().unwrap().len());
+}
@@ -0,0 +1,243 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::env;
+use std::process::Command;
+
+use glean_core::metrics::*;
+use glean_core::{CommonMetricData, Glean, Lifetime};
+use serde_json::Value as JsonValue;
+
+/// Set for the helper processes spawned by the tests, to the data path of the primary process.
+const HELPER_DATA_PATH: &str = "GLEAN_TEST_HELPER_DATA_PATH";
+const HELPERS: i32 = 4;
+const RECORDS: i32 = 50;
+
+fn clientid_metric() -> UuidMetric {
+    UuidMetric::new(CommonMetricData {
+        name: "client_id".into(),
+        category: "".into(),
+        send_in_pings: vec!["glean_client_info".into()],
+        lifetime: Lifetime::User,
+        ..Default::default()
+    })
+}
+
+fn counter() -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: "counter".into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime: Lifetime::Ping,
+        ..Default::default()
+    })
+}
+
+fn event_metric() -> EventMetric {
+    EventMetric::new(
+        CommonMetricData {
+            name: "clicked".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store1".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    )
+}
+
+fn helper_configuration(data_path: String) -> glean_core::InternalConfiguration {
+    glean_core::InternalConfiguration {
+        data_path,
+        application_id: GLOBAL_APPLICATION_ID.into(),
+        language_binding_name: "Rust".into(),
+        upload_enabled: true,
+        max_events: None,
+        delay_ping_lifetime_io: false,
+        app_build: "Unknown".into(),
+        use_core_mps: false,
+        trim_data_to_registered_pings: false,
+        log_level: None,
+        rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
+        enable_event_timestamps: false,
+        experimentation_id: None,
+        enable_internal_pings: true,
+        ping_schedule: Default::default(),
+        ping_lifetime_threshold: 0,
+        ping_lifetime_max_time: 0,
+        max_pending_pings_count: None,
+        max_pending_pings_directory_size: None,
+        session_mode: glean_core::SessionMode::Auto,
+        session_sample_rate: 1.0,
+        session_inactivity_timeout_ms: 1_800_000,
+        events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
+    }
+}
+
+fn new_helper(data_path: String) -> Glean {
+    let mut glean = Glean::new_for_helper_process(&helper_configuration(data_path)).unwrap();
+    new_test_ping(&mut glean, "store1");
+    glean
+}
+
+fn record(glean: &Glean) {
+    counter().add_sync(glean, 1);
+    event_metric().record_sync(glean, glean_core::get_timestamp_ms(), HashMap::new(), 0);
+}
+
+/// The body of the helper processes, a no-op when run as a test.
+#[test]
+fn helper_process_main() {
+    let Ok(data_path) = env::var(HELPER_DATA_PATH) else {
+        return;
+    };
+
+    let glean = new_helper(data_path);
+    for _ in 0..RECORDS {
+        record(&glean);
+    }
+    assert!(!glean
+        .get_ping_by_name("store1")
+        .unwrap()
+        .submit_sync(&glean, None));
+}
+
+fn store1_pings(data_path: &std::path::Path) -> Vec<JsonValue> {
+    get_queued_pings(data_path)
+        .unwrap()
+        .into_iter()
+        .filter(|(url, ..)| url.contains("/store1/"))
+        .map(|(_, payload, _)| payload)
+        .collect()
+}
+
+#[test]
+fn helper_processes_record_into_the_primary_database() {
+    let (glean, dir) = new_glean(None);
+    let ping = glean.get_ping_by_name("store1").unwrap().clone();
+    record(&glean);
+
+    let helpers: Vec<_> = (0..HELPERS)
+        .map(|_| {
+            Command::new(env::current_exe().unwrap())
+                .args(["--exact", "helper_process_main", "--test-threads=1"])
+                .env(HELPER_DATA_PATH, dir.path())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+
+    // The primary process keeps recording and submitting while the helpers run.
+    for i in 0..RECORDS {
+        record(&glean);
+        if i % 10 == 0 {
+            ping.submit_sync(&glean, None);
+        }
+    }
+    for mut helper in helpers {
+        assert!(helper.wait().unwrap().success());
+    }
+    assert!(ping.submit_sync(&glean, None));
+
+    // Nothing is lost or sent twice.
+    let pings = store1_pings(dir.path());
+    let counted: i64 = pings
+        .iter()
+        .filter_map(|payload| payload["metrics"]["counter"]["local.counter"].as_i64())
+        .sum();
+    assert_eq!(i64::from((HELPERS + 1) * RECORDS + 1), counted);
+
+    let clicks = pings
+        .iter()
+        .filter_map(|payload| payload["events"].as_array())
+        .flatten()
+        .filter(|event| event["name"] == "clicked")
+        .count();
+    assert_eq!(((HELPERS + 1) * RECORDS + 1) as usize, clicks);
+
+    // Helpers don't mark a restart in the primary's stores.
+    let restarts = pings
+        .iter()
+        .filter_map(|payload| payload["events"].as_array())
+        .flatten()
+        .filter(|event| event["category"] == "glean" && event["name"] == "restarted")
+        .count();
+    assert_eq!(0, restarts);
+
+    // Ping sequence numbers are the primary's only.
+    let mut seqs: Vec<_> = pings
+        .iter()
+        .map(|payload| payload["ping_info"]["seq"].as_i64().unwrap())
+        .collect();
+    seqs.sort();
+    seqs.dedup();
+    assert_eq!(pings.len(), seqs.len());
+}
+
+#[test]
+fn helper_processes_do_not_change_primary_state() {
+    let (mut glean, dir) = new_glean(None);
+    glean.handle_client_active();
+    let client_id = clientid_metric().get_value(&glean, None);
+    let pending = get_queued_pings(dir.path()).unwrap().len();
+
+    let mut helper = new_helper(dir.path().display().to_string());
+    assert!(helper.is_helper_process());
+    helper.handle_client_inactive();
+    assert!(!helper.set_upload_enabled(false));
+    record(&helper);
+    drop(helper);
+
+    // No pings, the primary's dirty flag and client ID are untouched.
+    assert_eq!(pending, get_queued_pings(dir.path()).unwrap().len());
+    assert!(glean.is_dirty_flag_set());
+    assert_eq!(client_id, clientid_metric().get_value(&glean, None));
+    assert_eq!(Some(1), counter().get_value(&glean, Some("store1")));
+}
+
+#[test]
+fn helper_processes_respect_disabled_upload() {
+    let (mut glean, dir) = new_glean(None);
+    glean.set_upload_enabled(false);
+
+    let helper = new_helper(dir.path().display().to_string());
+    assert!(!helper.is_upload_enabled());
+    record(&helper);
+    assert_eq!(None, counter().get_value(&glean, Some("store1")));
+}
+
+#[test]
+fn helper_processes_stop_recording_when_upload_is_disabled_later() {
+    let (mut glean, dir) = new_glean(None);
+
+    let helper = new_helper(dir.path().display().to_string());
+    assert!(helper.is_upload_enabled());
+    glean.set_upload_enabled(false);
+
+    record(&helper);
+    assert_eq!(None, counter().get_value(&glean, Some("store1")));
+    assert_eq!(None, event_metric().get_value(&glean, Some("store1")));
+
+    // Nothing recorded by the helper while upload was disabled ends up in a ping.
+    glean.set_upload_enabled(true);
+    record(&glean);
+    assert!(glean
+        .get_ping_by_name("store1")
+        .unwrap()
+        .submit_sync(&glean, None));
+    let pings = store1_pings(dir.path());
+    assert_eq!(1, pings.len());
+    assert_eq!(1, pings[0]["metrics"]["counter"]["local.counter"]);
+}
@@ -0,0 +1,195 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use glean_core::metrics::*;
+use glean_core::upload::{read_archive, PingRequest, PingUploadTask, UploadResult};
+use glean_core::{CommonMetricData, Glean, Lifetime, UploadBackoff};
+use serde_json::Value as JsonValue;
+
+fn counter() -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: "counter".into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime: Lifetime::User,
+        ..Default::default()
+    })
+}
+
+fn event_metric() -> EventMetric {
+    EventMetric::new(
+        CommonMetricData {
+            name: "clicked".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store1".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    )
+}
+
+fn new_in_memory_glean(data_path: &Path) -> Glean {
+    let cfg = glean_core::InternalConfiguration {
+        data_path: data_path.display().to_string(),
+        application_id: GLOBAL_APPLICATION_ID.into(),
+        language_binding_name: "Rust".into(),
+        upload_enabled: true,
+        max_events: None,
+        delay_ping_lifetime_io: false,
+        app_build: "Unknown".into(),
+        use_core_mps: false,
+        trim_data_to_registered_pings: false,
+        log_level: None,
+        rate_limit: None,
+        // Retry failed uploads right away.
+        upload_backoff: Some(UploadBackoff {
+            base_delay_ms: 0,
+            multiplier: 1.0,
+            max_delay_ms: 0,
+            jitter: 0.0,
+        }),
+        max_quarantined_pings: Some(10),
+        enable_event_timestamps: false,
+        experimentation_id: None,
+        enable_internal_pings: true,
+        ping_schedule: Default::default(),
+        ping_lifetime_threshold: 0,
+        ping_lifetime_max_time: 0,
+        max_pending_pings_count: None,
+        max_pending_pings_directory_size: None,
+        session_mode: glean_core::SessionMode::Auto,
+        session_sample_rate: 1.0,
+        session_inactivity_timeout_ms: 1_800_000,
+        events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: true,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
+    };
+    let mut glean = Glean::new(cfg).unwrap();
+    new_test_ping(&mut glean, "store1");
+    glean
+}
+
+fn record_and_submit(glean: &Glean) {
+    counter().add_sync(glean, 3);
+    event_metric().record_sync(glean, 1, HashMap::new(), 0);
+    assert!(glean
+        .get_ping_by_name("store1")
+        .unwrap()
+        .submit_sync(glean, None));
+}
+
+/// Gets the next ping to upload, waiting for the pending pings to be scanned.
+fn next_upload(glean: &Glean) -> Option<PingRequest> {
+    (0..50).find_map(|_| match glean.get_upload_task() {
+        PingUploadTask::Upload { request } => Some(request),
+        _ => {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            None
+        }
+    })
+}
+
+#[test]
+fn nothing_is_written_to_disk() {
+    let (_t, tmpname) = tempdir();
+    let data_path = Path::new(&tmpname).join("glean");
+    let glean = new_in_memory_glean(&data_path);
+    assert!(glean.is_in_memory());
+
+    record_and_submit(&glean);
+    let request = next_upload(&glean).unwrap();
+    assert_eq!("store1", request.ping_name);
+    let payload: JsonValue = serde_json::from_str(&request.pretty_body().unwrap()).unwrap();
+    assert_eq!(3, payload["metrics"]["counter"]["local.counter"]);
+    assert_eq!("clicked", payload["events"][0]["name"]);
+
+    glean.process_ping_upload_response(&request.document_id, UploadResult::http_status(200));
+    assert!(next_upload(&glean).is_none());
+    drop(glean);
+
+    assert!(!data_path.exists());
+}
+
+#[test]
+fn failed_uploads_are_retried_from_memory() {
+    let (_t, tmpname) = tempdir();
+    let data_path = Path::new(&tmpname).join("glean");
+    let glean = new_in_memory_glean(&data_path);
+
+    record_and_submit(&glean);
+    let request = next_upload(&glean).unwrap();
+    glean.process_ping_upload_response(&request.document_id, UploadResult::http_status(500));
+    let retried = next_upload(&glean).unwrap();
+    assert_eq!(request.document_id, retried.document_id);
+
+    // Permanently failed pings are never quarantined.
+    glean.process_ping_upload_response(&retried.document_id, UploadResult::http_status(400));
+    assert!(glean.quarantined_pings().is_empty());
+    assert!(next_upload(&glean).is_none());
+    assert!(!data_path.exists());
+}
+
+#[test]
+fn data_is_dropped_on_shutdown() {
+    let (_t, tmpname) = tempdir();
+    let data_path = Path::new(&tmpname).join("glean");
+    {
+        let glean = new_in_memory_glean(&data_path);
+        record_and_submit(&glean);
+    }
+
+    let glean = new_in_memory_glean(&data_path);
+    assert_eq!(None, counter().get_value(&glean, None));
+    assert!(next_upload(&glean).is_none());
+}
+
+#[test]
+fn pending_pings_are_exported_into_an_archive() {
+    let (_t, tmpname) = tempdir();
+    let data_path = Path::new(&tmpname).join("glean");
+    let glean = new_in_memory_glean(&data_path);
+    record_and_submit(&glean);
+
+    let archive = Path::new(&tmpname).join("pings.archive");
+    assert_eq!(1, glean.export_pending_pings(&archive).unwrap());
+    let pings = read_archive(&archive).unwrap();
+    assert_eq!(1, pings.len());
+    assert_eq!("store1", pings[0].ping_name);
+
+    // Exported pings are not uploaded anymore.
+    assert!(next_upload(&glean).is_none());
+    assert!(!data_path.exists());
+}
+
+#[test]
+fn backup_writes_out_a_data_directory() {
+    let (_t, tmpname) = tempdir();
+    let glean = new_in_memory_glean(&Path::new(&tmpname).join("glean"));
+    record_and_submit(&glean);
+    counter().add_sync(&glean, 2);
+
+    // The backup directory must not exist yet.
+    let backup_dir = tempfile::tempdir().unwrap();
+    std::fs::remove_dir(backup_dir.path()).unwrap();
+    glean.backup(backup_dir.path()).unwrap();
+    drop(glean);
+
+    let pending = get_queued_pings(backup_dir.path()).unwrap();
+    assert_eq!(1, pending.len());
+    assert_eq!(3, pending[0].1["metrics"]["counter"]["local.counter"]);
+
+    // It can be used like any other data directory.
+    let (glean, _dir) = new_glean(Some(backup_dir));
+    assert_eq!(Some(5), counter().get_value(&glean, None));
+}
@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod common;
+use crate::common::*;
+
+use std::collections::HashMap;
+use std::fs;
+
+use glean_core::inspect::Inspector;
+use glean_core::metrics::*;
+use glean_core::{CommonMetricData, Lifetime};
+
+fn counter(name: &str, store: &str, lifetime: Lifetime) -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: name.into(),
+        category: "local".into(),
+        send_in_pings: vec![store.into()],
+        lifetime,
+        ..Default::default()
+    })
+}
+
+fn event_metric() -> EventMetric {
+    EventMetric::new(
+        CommonMetricData {
+            name: "clicked".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store2".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec!["button".into()],
+    )
+}
+
+#[test]
+fn inspects_metrics_events_and_state() {
+    let (mut glean, dir) = new_glean(None);
+    new_test_ping(&mut glean, "store1");
+    new_test_ping(&mut glean, "store2");
+
+    counter("ping_counter", "store1", Lifetime::Ping).add_sync(&glean, 3);
+    counter("user_counter", "store1", Lifetime::User).add_sync(&glean, 1);
+    let labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "labeled".into(),
+                category: "local".into(),
+                send_in_pings: vec!["store1".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            },
+        },
+        None,
+    );
+    labeled.get("label1").add_sync(&glean, 2);
+
+    let extra: HashMap<_, _> = [("button".to_string(), "ok".to_string())].into();
+    event_metric().record_sync(&glean, 1, extra.clone(), 0);
+    event_metric().record_sync(&glean, 2, HashMap::new(), 0);
+
+    glean.set_experiment_active("exp".into(), "branch_a".into(), HashMap::new());
+    glean.handle_client_active();
+
+    let inspector = Inspector::open(dir.path(), None).unwrap();
+
+    let stores = inspector.stores().unwrap();
+    assert!(stores.contains(&"store1".to_string()));
+    assert!(stores.contains(&"store2".to_string()));
+
+    let metrics = inspector.metrics(Some("store1")).unwrap();
+    let find = |id: &str| metrics.iter().find(|m| m.id == id).unwrap();
+    assert_eq!(Lifetime::Ping, find("local.ping_counter").lifetime);
+    assert_eq!(Metric::Counter(3), find("local.ping_counter").value);
+    assert_eq!(Lifetime::User, find("local.user_counter").lifetime);
+    assert_eq!(vec!["label1".to_string()], find("local.labeled").labels);
+    assert_eq!(Metric::Counter(2), find("local.labeled").value);
+    assert!(metrics.iter().all(|m| m.store == "store1"));
+
+    let events = inspector.events(Some("store2")).unwrap();
+    assert_eq!(2, events.len());
+    assert_eq!("clicked", events[0].event.name);
+    assert_eq!(Some(extra), events[0].event.extra);
+    assert!(events[0].event.timestamp <= events[1].event.timestamp);
+    assert!(!events[0].pending_import);
+
+    let experiments = inspector.experiments().unwrap();
+    assert_eq!(1, experiments.len());
+    assert_eq!("exp", experiments[0].0);
+    assert_eq!("branch_a", experiments[0].1.branch);
+
+    let session = inspector.session().unwrap();
+    assert_eq!(Some(1), session.seq);
+    assert!(session.id.is_some());
+    assert!(session.start_time.is_some());
+}
+
+#[test]
+fn inspects_pending_pings() {
+    let (mut glean, dir) = new_glean(None);
+    let ping = new_test_ping(&mut glean, "store1");
+    counter("ping_counter", "store1", Lifetime::Ping).add_sync(&glean, 1);
+    assert!(ping.submit_sync(&glean, None));
+
+    let inspector = Inspector::open(dir.path(), None).unwrap();
+    let pings = inspector.pending_pings().unwrap();
+    let queued = get_queued_pings(dir.path()).unwrap();
+    assert_eq!(queued.len(), pings.len());
+
+    let ping = pings
+        .iter()
+        .find(|p| p.path.contains("/store1/"))
+        .expect("store1 ping should be pending");
+    assert!(!ping.deletion_request);
+    assert!(ping.path.contains(&ping.document_id));
+    assert_eq!(1, ping.body["metrics"]["counter"]["local.ping_counter"]);
+}
+
+#[test]
+fn inspecting_does_not_modify_the_data() {
+    let (mut glean, dir) = new_glean(None);
+    new_test_ping(&mut glean, "store1");
+    counter("ping_counter", "store1", Lifetime::Ping).add_sync(&glean, 1);
+    glean.close_db();
+    drop(glean);
+
+    let db_path = dir.path().join("db").join("glean.sqlite");
+    let before = fs::read(&db_path).unwrap();
+
+    let inspector = Inspector::open(dir.path(), None).unwrap();
+    let metrics = inspector.metrics(Some("store1")).unwrap();
+    assert_eq!("local.ping_counter", metrics[0].id);
+    assert!(inspector.events(None).unwrap().is_empty());
+    drop(inspector);
+
+    assert_eq!(before, fs::read(&db_path).unwrap());
+}
+
+#[test]
+fn fails_without_a_database() {
+    let (_t, tmpname) = tempdir();
+    assert!(Inspector::open(&tmpname, None).is_err());
+    assert!(!std::path::Path::new(&tmpname).join("db").exists());
+}
@@ -23,6 +23,9 @@ static DEFINITION_ONLY: &[&str] = &[
     "glean.internal.metrics.telemetry_sdk_build",
     /* adhoc in src/ping/mod.rs */
     "glean.ping.uploader_capabilities",
+    "glean.ping.split_parent_id",
+    "glean.ping.split_part",
+    "glean.ping.split_parts",
     /* adhoc events */
     "glean.restarted",
     "glean.session_end",
@@ -377,6 +377,8 @@ fn clearing_storage_by_prefix_doesnt_clear_unrelated_delayed_ping_io() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: false,
         experimentation_id: None,
         enable_internal_pings: true,
@@ -389,6 +391,11 @@ fn clearing_storage_by_prefix_doesnt_clear_unrelated_delayed_ping_io() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
     let mut glean = glean_core::Glean::new(cfg).unwrap();
 
@@ -424,3 +431,171 @@ fn clearing_storage_by_prefix_doesnt_clear_unrelated_delayed_ping_io() {
     assert_eq!(1, counter.get_value(&glean, Some(&*prefix)).unwrap());
     assert_eq!(None, counter.get_value(&glean, Some(&*other)));
 }
+
+#[test]
+fn pings_are_submitted_when_reaching_their_capacity() {
+    let (mut glean, _t) = new_glean(None);
+
+    let ping = PingBuilder::new("store1")
+        .with_reasons(vec!["max_capacity".into()])
+        .build();
+    glean.register_ping_type(&ping);
+    ping.set_capacity(Some(PingCapacity {
+        max_events: Some(3),
+        max_labels: Some(3),
+        ..Default::default()
+    }));
+
+    let labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "labeled_metric".into(),
+                category: "telemetry".into(),
+                send_in_pings: vec!["store1".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            },
+        },
+        None,
+    );
+    // User-lifetime data isn't cleared by submitting the ping, so it doesn't count.
+    let user_labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "user_labeled_metric".into(),
+                category: "telemetry".into(),
+                send_in_pings: vec!["store1".into()],
+                lifetime: Lifetime::User,
+                ..Default::default()
+            },
+        },
+        None,
+    );
+    for label in ["a", "b", "c", "d"] {
+        user_labeled.get(label).add_sync(&glean, 1);
+    }
+    labeled.get("a").add_sync(&glean, 1);
+    labeled.get("b").add_sync(&glean, 1);
+    assert!(get_queued_pings(glean.get_data_path()).is_err());
+
+    labeled.get("c").add_sync(&glean, 1);
+    let pings = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(1, pings.len());
+    let payload = &pings[0].1;
+    assert_eq!("max_capacity", payload["ping_info"]["reason"]);
+    assert_eq!(
+        1,
+        payload["metrics"]["labeled_counter"]["telemetry.labeled_metric"]["c"]
+    );
+
+    let event = EventMetric::new(
+        CommonMetricData {
+            name: "event".into(),
+            category: "telemetry".into(),
+            send_in_pings: vec!["store1".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    );
+    event.record_sync(&glean, 1000, HashMap::new(), 0);
+    event.record_sync(&glean, 1001, HashMap::new(), 0);
+    assert_eq!(1, get_queued_pings(glean.get_data_path()).unwrap().len());
+    assert!(event.record_sync(&glean, 1002, HashMap::new(), 0));
+    let pings = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(2, pings.len());
+    assert!(pings
+        .iter()
+        .any(|(_, payload, _)| payload["events"].as_array().map(Vec::len) == Some(3)));
+}
+
+#[test]
+fn pings_are_submitted_when_reaching_their_payload_size() {
+    let (mut glean, _t) = new_glean(None);
+
+    let ping = PingBuilder::new("store1")
+        .with_reasons(vec!["max_capacity".into()])
+        .build();
+    glean.register_ping_type(&ping);
+    ping.set_capacity(Some(PingCapacity {
+        max_payload_bytes: Some(1024),
+        ..Default::default()
+    }));
+
+    let metric = StringListMetric::new(CommonMetricData {
+        name: "string_list".into(),
+        category: "telemetry".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime: Lifetime::Ping,
+        ..Default::default()
+    });
+    for _ in 0..10 {
+        metric.add_sync(&glean, "0123456789".repeat(5));
+    }
+    assert!(get_queued_pings(glean.get_data_path()).is_err());
+
+    for _ in 0..10 {
+        metric.add_sync(&glean, "0123456789".repeat(5));
+    }
+    let pings = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(1, pings.len());
+    assert_eq!("max_capacity", pings[0].1["ping_info"]["reason"]);
+}
+
+#[test]
+fn previewing_a_ping_keeps_its_data() {
+    let (mut glean, _t) = new_glean(None);
+
+    let ping = new_test_ping(&mut glean, "store1");
+    assert_eq!(None, glean.preview_ping("store1"));
+    assert_eq!(None, glean.preview_ping("unknown"));
+
+    let counter = CounterMetric::new(CommonMetricData {
+        name: "counter".into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        ..Default::default()
+    });
+    counter.add_sync(&glean, 1);
+
+    let event = EventMetric::new(
+        CommonMetricData {
+            name: "event".into(),
+            category: "local".into(),
+            send_in_pings: vec!["store1".into()],
+            ..Default::default()
+        },
+        vec![],
+    );
+    event.record_sync(&glean, 0, HashMap::new(), 0);
+
+    let preview: serde_json::Value =
+        serde_json::from_str(&glean.preview_ping("store1").unwrap()).unwrap();
+    assert_eq!(0, preview["ping_info"]["seq"]);
+    assert_eq!(1, preview["metrics"]["counter"]["local.counter"]);
+    assert_eq!(1, preview["events"].as_array().unwrap().len());
+    assert!(preview["client_info"].is_object());
+
+    // Previewing neither clears the data nor advances the sequence number.
+    let again: serde_json::Value =
+        serde_json::from_str(&glean.preview_ping("store1").unwrap()).unwrap();
+    assert_eq!(preview["metrics"], again["metrics"]);
+    assert_eq!(preview["events"], again["events"]);
+    assert_eq!(preview["ping_info"]["seq"], again["ping_info"]["seq"]);
+    assert_eq!(
+        preview["ping_info"]["start_time"],
+        again["ping_info"]["start_time"]
+    );
+    assert!(get_queued_pings(glean.get_data_path()).is_err());
+
+    // The submitted ping contains what was previewed.
+    assert!(ping.submit_sync(&glean, None));
+    let queued = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(1, queued.len());
+    let payload = &queued[0].1;
+    assert_eq!(preview["metrics"], payload["metrics"]);
+    assert_eq!(preview["events"], payload["events"]);
+    assert_eq!(preview["ping_info"]["seq"], payload["ping_info"]["seq"]);
+
+    assert_eq!(None, glean.preview_ping("store1"));
+}
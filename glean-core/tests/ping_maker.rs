@@ -90,6 +90,8 @@ fn test_metrics_must_report_experimentation_id() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: Some("test-experimentation-id".to_string()),
         enable_internal_pings: true,
@@ -102,6 +104,11 @@ fn test_metrics_must_report_experimentation_id() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     })
     .unwrap();
     let ping_maker = PingMaker::new();
@@ -151,6 +158,8 @@ fn experimentation_id_is_removed_if_send_if_empty_is_false() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: true,
         experimentation_id: Some("test-experimentation-id".to_string()),
         enable_internal_pings: true,
@@ -163,6 +172,11 @@ fn experimentation_id_is_removed_if_send_if_empty_is_false() {
         session_sample_rate: 1.0,
         session_inactivity_timeout_ms: 1_800_000,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     })
     .unwrap();
     let ping_maker = PingMaker::new();
@@ -282,7 +296,7 @@ fn clear_pending_pings() {
 
     let disabled_pings = &["store1"][..];
     assert!(ping_maker
-        .clear_pending_pings(glean.get_data_path(), disabled_pings)
+        .clear_pending_pings(&glean, disabled_pings)
         .is_ok());
     assert_eq!(0, get_queued_pings(glean.get_data_path()).unwrap().len());
 }
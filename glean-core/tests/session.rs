@@ -35,6 +35,8 @@ fn session_cfg(
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: false,
         experimentation_id: None,
         enable_internal_pings: true,
@@ -47,6 +49,11 @@ fn session_cfg(
         session_sample_rate: sample_rate,
         session_inactivity_timeout_ms: timeout_ms,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     }
 }
 
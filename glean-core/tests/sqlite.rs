@@ -10,8 +10,10 @@ use crate::common::*;
 use glean_core::metrics::*;
 use glean_core::CommonMetricData;
 use glean_core::Glean;
+use glean_core::LabeledMetricData;
 use glean_core::Lifetime;
 use glean_core::SessionMode;
+use glean_core::{test_get_num_recorded_errors, ErrorType};
 use rusqlite::params;
 use rusqlite::TransactionBehavior;
 use uuid::uuid;
@@ -38,10 +40,11 @@ fn load_error_metric() -> StringMetric {
 
 #[test]
 fn database_file_is_not_sqlite() {
-    let temp = {
+    let (first_client_id, temp) = {
         let (glean, temp) = new_glean(None);
+        let client_id = clientid_metric().get_value(&glean, None).unwrap();
         drop(glean);
-        temp
+        (client_id, temp)
     };
 
     {
@@ -52,8 +55,13 @@ fn database_file_is_not_sqlite() {
 
     let (glean, _temp) = new_glean(Some(temp));
 
+    // Nothing could be salvaged, the client ID is recovered from `client_id.txt`.
     let client_id = clientid_metric().get_value(&glean, None);
-    assert!(client_id.is_some());
+    assert_eq!(Some(first_client_id), client_id);
+    assert_eq!(
+        None,
+        database_counter("salvaged_rows").get_value(&glean, Some("metrics"))
+    );
 
     let load_error = load_error_metric().get_value(&glean, None).unwrap();
     assert_eq!("database file corrupt", load_error);
@@ -193,6 +201,8 @@ mod unix {
             trim_data_to_registered_pings: false,
             log_level: None,
             rate_limit: None,
+            upload_backoff: None,
+            max_quarantined_pings: None,
             enable_event_timestamps: false,
             experimentation_id: None,
             enable_internal_pings: true,
@@ -205,6 +215,11 @@ mod unix {
             session_sample_rate: 1.0,
             session_inactivity_timeout_ms: 1_800_000,
             events_ping_acceleration_factor: None,
+            encryption_key_provider: None,
+            max_database_size: None,
+            in_memory: false,
+            database_maintenance_interval_ms: None,
+            metrics_ping_schedule: None,
         };
         let glean = Glean::new(cfg);
         assert!(glean.is_err());
@@ -241,6 +256,8 @@ fn database_externally_locked() {
         trim_data_to_registered_pings: false,
         log_level: None,
         rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
         enable_event_timestamps: false,
         experimentation_id: None,
         enable_internal_pings: true,
@@ -253,6 +270,11 @@ fn database_externally_locked() {
         session_mode: SessionMode::Auto,
         session_sample_rate: 1.0,
         events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
     };
     let glean = Glean::new(cfg);
     assert!(glean.is_err());
@@ -286,10 +308,650 @@ fn schema_v2_is_applied() {
     let cur_user_version: u32 = conn
         .query_one("PRAGMA user_version", [], |row| row.get(0))
         .unwrap();
-    assert_eq!(cur_user_version, 2);
+    assert_eq!(cur_user_version, 3);
 
     let migration_state: String = conn
         .query_one("SELECT state FROM migration", [], |row| row.get(0))
         .unwrap();
     assert_eq!(migration_state, "done");
 }
+
+#[test]
+fn schema_v3_is_applied() {
+    let event = EventMetric::new(
+        CommonMetricData {
+            name: "name".into(),
+            category: "category".into(),
+            send_in_pings: vec!["store1".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        vec![],
+    );
+
+    let temp = {
+        let (glean, temp) = new_glean(None);
+        drop(glean);
+
+        let db_path = temp.path().join("db").join("glean.sqlite");
+        let conn = rusqlite::Connection::open(db_path).unwrap();
+        conn.execute("DROP TABLE events", []).unwrap();
+        conn.execute("PRAGMA user_version = 2", []).unwrap();
+
+        // Schema 2 kept events in files.
+        fs::write(
+            temp.path().join("events").join("store1"),
+            "{\"timestamp\":10,\"category\":\"category\",\"name\":\"name\"}\n",
+        )
+        .unwrap();
+
+        temp
+    };
+
+    let (glean, temp) = new_glean(Some(temp));
+    glean
+        .event_storage()
+        .flush_pending_events_on_startup(&glean, false);
+    assert_eq!(1, event.get_value(&glean, "store1").unwrap().len());
+    assert!(!temp.path().join("events").join("store1").exists());
+
+    let conn = rusqlite::Connection::open(temp.path().join("db").join("glean.sqlite")).unwrap();
+    let cur_user_version: u32 = conn
+        .query_one("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(cur_user_version, 3);
+}
+
+fn new_glean_with_delayed_io(
+    temp: &tempfile::TempDir,
+    ping_lifetime_threshold: u64,
+    ping_lifetime_max_time: u64,
+) -> Glean {
+    let cfg = glean_core::InternalConfiguration {
+        data_path: temp.path().display().to_string(),
+        application_id: GLOBAL_APPLICATION_ID.into(),
+        language_binding_name: "Rust".into(),
+        upload_enabled: true,
+        max_events: None,
+        delay_ping_lifetime_io: true,
+        app_build: "Unknown".into(),
+        use_core_mps: false,
+        trim_data_to_registered_pings: false,
+        log_level: None,
+        rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
+        enable_event_timestamps: false,
+        experimentation_id: None,
+        enable_internal_pings: true,
+        ping_schedule: Default::default(),
+        ping_lifetime_threshold,
+        ping_lifetime_max_time,
+        max_pending_pings_count: None,
+        max_pending_pings_directory_size: None,
+        session_mode: SessionMode::Auto,
+        session_sample_rate: 1.0,
+        session_inactivity_timeout_ms: 1_800_000,
+        events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: None,
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
+    };
+    Glean::new(cfg).unwrap()
+}
+
+fn delayed_counter(send_in_pings: &[&str]) -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: "delayed".into(),
+        category: "local".into(),
+        send_in_pings: send_in_pings.iter().map(|s| s.to_string()).collect(),
+        lifetime: Lifetime::Ping,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn buffered_metrics_count_towards_ping_capacity() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut glean = new_glean_with_delayed_io(&temp, 0, 0);
+    let ping = PingBuilder::new("store1")
+        .with_reasons(vec!["max_capacity".into()])
+        .build();
+    glean.register_ping_type(&ping);
+    ping.set_capacity(Some(PingCapacity {
+        max_labels: Some(3),
+        ..Default::default()
+    }));
+
+    let labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "labeled".into(),
+                category: "local".into(),
+                send_in_pings: vec!["store1".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            },
+        },
+        // Only metrics with static labels are buffered.
+        Some(vec!["a".into(), "b".into(), "c".into()]),
+    );
+    labeled.get("a").add_sync(&glean, 1);
+    glean.persist_ping_lifetime_data().unwrap();
+    // Overwrites of persisted entries aren't counted twice.
+    labeled.get("a").add_sync(&glean, 1);
+    labeled.get("b").add_sync(&glean, 1);
+    labeled.get("b").add_sync(&glean, 1);
+    assert!(get_queued_pings(glean.get_data_path()).is_err());
+
+    labeled.get("c").add_sync(&glean, 1);
+    let pings = get_queued_pings(glean.get_data_path()).unwrap();
+    assert_eq!(1, pings.len());
+    let payload = &pings[0].1;
+    assert_eq!("max_capacity", payload["ping_info"]["reason"]);
+    assert_eq!(
+        2,
+        payload["metrics"]["labeled_counter"]["local.labeled"]["a"]
+    );
+    assert_eq!(
+        2,
+        payload["metrics"]["labeled_counter"]["local.labeled"]["b"]
+    );
+    assert_eq!(
+        1,
+        payload["metrics"]["labeled_counter"]["local.labeled"]["c"]
+    );
+}
+
+/// Returns the counter value persisted to the database file, bypassing Glean.
+fn persisted_counter(temp: &tempfile::TempDir, ping: &str) -> Option<i32> {
+    let path = temp.path().join("db").join("glean.sqlite");
+    let conn = rusqlite::Connection::open(path).unwrap();
+    let value: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT value FROM telemetry WHERE id = 'local.delayed' AND ping = ?1",
+            params![ping],
+            |row| row.get(0),
+        )
+        .ok();
+    value.map(|blob| match rmp_serde::from_slice(&blob).unwrap() {
+        Metric::Counter(v) => v,
+        _ => panic!("unexpected metric type"),
+    })
+}
+
+#[test]
+fn delayed_ping_lifetime_data_is_persisted_at_the_threshold() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut glean = new_glean_with_delayed_io(&temp, 3, 0);
+    new_test_ping(&mut glean, "store1");
+    let counter = delayed_counter(&["store1"]);
+    // Start counting from an empty buffer, initialization records metrics too.
+    glean.persist_ping_lifetime_data().unwrap();
+
+    counter.add_sync(&glean, 1);
+    counter.add_sync(&glean, 1);
+    // Buffered, but visible to readers.
+    assert_eq!(Some(2), counter.get_value(&glean, Some("store1")));
+    assert_eq!(None, persisted_counter(&temp, "store1"));
+
+    counter.add_sync(&glean, 1);
+    assert_eq!(Some(3), persisted_counter(&temp, "store1"));
+
+    counter.add_sync(&glean, 1);
+    assert_eq!(Some(4), counter.get_value(&glean, Some("store1")));
+    assert_eq!(Some(3), persisted_counter(&temp, "store1"));
+
+    glean.persist_ping_lifetime_data().unwrap();
+    assert_eq!(Some(4), persisted_counter(&temp, "store1"));
+}
+
+#[test]
+fn delayed_ping_lifetime_data_is_persisted_after_max_time() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut glean = new_glean_with_delayed_io(&temp, 0, 10);
+    new_test_ping(&mut glean, "store1");
+    let counter = delayed_counter(&["store1"]);
+
+    counter.add_sync(&glean, 1);
+    assert_eq!(None, persisted_counter(&temp, "store1"));
+
+    // The timer is only checked on the next write.
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert_eq!(None, persisted_counter(&temp, "store1"));
+    counter.add_sync(&glean, 1);
+    assert_eq!(Some(2), persisted_counter(&temp, "store1"));
+}
+
+#[test]
+fn delayed_ping_lifetime_data_is_persisted_on_ping_collection() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut glean = new_glean_with_delayed_io(&temp, 0, 0);
+    let store1 = new_test_ping(&mut glean, "store1");
+    new_test_ping(&mut glean, "store2");
+    let counter = delayed_counter(&["store1", "store2"]);
+
+    counter.add_sync(&glean, 1);
+    assert_eq!(None, persisted_counter(&temp, "store2"));
+
+    assert!(store1.submit_sync(&glean, None));
+    assert_eq!(None, counter.get_value(&glean, Some("store1")));
+    assert_eq!(None, persisted_counter(&temp, "store1"));
+    assert_eq!(Some(1), persisted_counter(&temp, "store2"));
+}
+
+#[test]
+fn delayed_ping_lifetime_data_is_lost_without_persisting() {
+    let temp = tempfile::tempdir().unwrap();
+    {
+        let mut glean = new_glean_with_delayed_io(&temp, 0, 0);
+        new_test_ping(&mut glean, "store1");
+        let counter = delayed_counter(&["store1"]);
+
+        counter.add_sync(&glean, 1);
+        glean.persist_ping_lifetime_data().unwrap();
+        counter.add_sync(&glean, 1);
+
+        // Simulate a crash: nothing is persisted on the way out.
+        drop(glean);
+    }
+
+    let mut glean = new_glean_with_delayed_io(&temp, 0, 0);
+    new_test_ping(&mut glean, "store1");
+    let counter = delayed_counter(&["store1"]);
+    assert_eq!(Some(1), counter.get_value(&glean, Some("store1")));
+}
+
+#[test]
+fn delayed_ping_lifetime_data_is_recorded_without_writing() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut glean = new_glean_with_delayed_io(&temp, 0, 0);
+    new_test_ping(&mut glean, "store1");
+    let counter = delayed_counter(&["store1"]);
+
+    // Another process holding the write lock doesn't keep metrics from being buffered.
+    let mut conn = rusqlite::Connection::open(temp.path().join("db").join("glean.sqlite")).unwrap();
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .unwrap();
+    counter.add_sync(&glean, 1);
+    counter.add_sync(&glean, 2);
+    drop(tx);
+
+    assert_eq!(Some(3), counter.get_value(&glean, Some("store1")));
+    assert_eq!(None, persisted_counter(&temp, "store1"));
+}
+
+fn new_glean_with_quota(
+    temp: &tempfile::TempDir,
+    max_database_size: u64,
+    delay_ping_lifetime_io: bool,
+) -> Glean {
+    let cfg = glean_core::InternalConfiguration {
+        data_path: temp.path().display().to_string(),
+        application_id: GLOBAL_APPLICATION_ID.into(),
+        language_binding_name: "Rust".into(),
+        upload_enabled: true,
+        max_events: None,
+        delay_ping_lifetime_io,
+        app_build: "Unknown".into(),
+        use_core_mps: false,
+        trim_data_to_registered_pings: false,
+        log_level: None,
+        rate_limit: None,
+        upload_backoff: None,
+        max_quarantined_pings: None,
+        enable_event_timestamps: false,
+        experimentation_id: None,
+        enable_internal_pings: true,
+        ping_schedule: Default::default(),
+        ping_lifetime_threshold: 0,
+        ping_lifetime_max_time: 0,
+        max_pending_pings_count: None,
+        max_pending_pings_directory_size: None,
+        session_mode: SessionMode::Auto,
+        session_sample_rate: 1.0,
+        session_inactivity_timeout_ms: 1_800_000,
+        events_ping_acceleration_factor: None,
+        encryption_key_provider: None,
+        max_database_size: Some(max_database_size),
+        in_memory: false,
+        database_maintenance_interval_ms: None,
+        metrics_ping_schedule: None,
+    };
+    let mut glean = Glean::new(cfg).unwrap();
+    new_test_ping(&mut glean, "store1");
+    glean
+}
+
+fn text_metric(name: &str, lifetime: Lifetime) -> TextMetric {
+    TextMetric::new(CommonMetricData {
+        name: name.into(),
+        category: "local".into(),
+        send_in_pings: vec!["store1".into()],
+        lifetime,
+        ..Default::default()
+    })
+}
+
+fn database_counter(name: &str) -> CounterMetric {
+    CounterMetric::new(CommonMetricData {
+        name: name.into(),
+        category: "glean.database".into(),
+        send_in_pings: vec!["metrics".into()],
+        lifetime: Lifetime::Ping,
+        ..Default::default()
+    })
+}
+
+/// The entries of Glean's own metrics in the `telemetry` table.
+fn glean_entries(temp: &tempfile::TempDir) -> Vec<(String, String, String)> {
+    let path = temp.path().join("db").join("glean.sqlite");
+    let conn = rusqlite::Connection::open(path).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, ping, labels FROM telemetry WHERE id LIKE 'glean.%'")
+        .unwrap();
+    stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+}
+
+/// The size of the `telemetry` table as measured for the quota.
+fn telemetry_size(temp: &tempfile::TempDir) -> u64 {
+    let path = temp.path().join("db").join("glean.sqlite");
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.query_row(
+        "SELECT SUM(length(id) + length(ping) + length(labels) + length(value)) FROM telemetry",
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .unwrap() as u64
+}
+
+#[test]
+fn metrics_are_evicted_when_the_quota_is_exceeded() {
+    let temp = tempfile::tempdir().unwrap();
+    let glean = new_glean_with_quota(&temp, 20_000, false);
+
+    let value = "x".repeat(5_000);
+    let user_text = text_metric("user", Lifetime::User);
+    user_text.set_sync(&glean, value.clone());
+
+    let labeled = LabeledCounter::new(
+        LabeledMetricData::Common {
+            cmd: CommonMetricData {
+                name: "labeled".into(),
+                category: "local".into(),
+                send_in_pings: vec!["store1".into()],
+                lifetime: Lifetime::Ping,
+                ..Default::default()
+            },
+        },
+        None,
+    );
+    for label in ["a", "b", "c"] {
+        labeled.get(label).add_sync(&glean, 1);
+    }
+    let glean_metrics = glean_entries(&temp);
+    assert!(!glean_metrics.is_empty());
+
+    let texts: Vec<_> = (0..3)
+        .map(|i| text_metric(&format!("ping{i}"), Lifetime::Ping))
+        .collect();
+    for text in &texts {
+        text.set_sync(&glean, value.clone());
+    }
+
+    assert!(telemetry_size(&temp) <= 20_000);
+
+    // User-lifetime metrics are never evicted.
+    assert_eq!(Some(value), user_text.get_value(&glean, Some("store1")));
+
+    // Labeled entries go first, then the largest entries until within the quota.
+    for label in ["a", "b", "c"] {
+        assert_eq!(None, labeled.get(label).get_value(&glean, Some("store1")));
+    }
+    let evicted: Vec<_> = texts
+        .iter()
+        .filter(|text| text.get_value(&glean, Some("store1")).is_none())
+        .collect();
+    assert_eq!(1, evicted.len());
+
+    assert_eq!(
+        Ok(1),
+        test_get_num_recorded_errors(&glean, evicted[0].meta(), ErrorType::InvalidOverflow)
+    );
+    assert_eq!(
+        Ok(3),
+        test_get_num_recorded_errors(&glean, labeled.get("a").meta(), ErrorType::InvalidOverflow)
+    );
+
+    assert_eq!(
+        Some(1),
+        database_counter("quota_exceeded").get_value(&glean, Some("metrics"))
+    );
+    assert_eq!(
+        Some(4),
+        database_counter("quota_evicted").get_value(&glean, Some("metrics"))
+    );
+
+    // Glean's own metrics are never evicted.
+    let remaining = glean_entries(&temp);
+    for entry in &glean_metrics {
+        assert!(remaining.contains(entry), "{entry:?} was evicted");
+    }
+}
+
+#[test]
+fn quota_is_enforced_when_persisting_buffered_metrics() {
+    let temp = tempfile::tempdir().unwrap();
+    let glean = new_glean_with_quota(&temp, 20_000, true);
+
+    let texts: Vec<_> = (0..4)
+        .map(|i| text_metric(&format!("ping{i}"), Lifetime::Ping))
+        .collect();
+    for text in &texts {
+        text.set_sync(&glean, "x".repeat(5_000));
+    }
+    glean.persist_ping_lifetime_data().unwrap();
+
+    assert!(telemetry_size(&temp) <= 20_000);
+    let evicted: Vec<_> = texts
+        .iter()
+        .filter(|text| text.get_value(&glean, Some("store1")).is_none())
+        .collect();
+    assert!(!evicted.is_empty());
+    for text in evicted {
+        assert_eq!(
+            Ok(1),
+            test_get_num_recorded_errors(&glean, text.meta(), ErrorType::InvalidOverflow)
+        );
+    }
+    assert_eq!(
+        Some(1),
+        database_counter("quota_exceeded").get_value(&glean, Some("metrics"))
+    );
+}
+
+#[test]
+fn metrics_are_not_evicted_within_the_quota() {
+    let temp = tempfile::tempdir().unwrap();
+    let glean = new_glean_with_quota(&temp, 1_000_000, false);
+
+    let texts: Vec<_> = (0..3)
+        .map(|i| text_metric(&format!("ping{i}"), Lifetime::Ping))
+        .collect();
+    for text in &texts {
+        text.set_sync(&glean, "x".repeat(5_000));
+    }
+
+    for text in &texts {
+        assert!(text.get_value(&glean, Some("store1")).is_some());
+    }
+    assert_eq!(
+        None,
+        database_counter("quota_exceeded").get_value(&glean, Some("metrics"))
+    );
+}
+
+/// The metrics of [`write_corrupt_database`], with the value of each.
+fn corruptible_texts() -> (Vec<TextMetric>, impl Fn(usize) -> String) {
+    let texts = (0..200)
+        .map(|i| text_metric(&format!("text{i:04}"), Lifetime::User))
+        .collect();
+    (texts, |i: usize| format!("value-{i:04}-").repeat(80))
+}
+
+/// Records the texts of [`corruptible_texts`] and corrupts the page holding the 100th one.
+///
+/// # Returns
+///
+/// The client ID and the data directory.
+fn write_corrupt_database() -> (uuid::Uuid, tempfile::TempDir) {
+    let (texts, value) = corruptible_texts();
+    let (first_client_id, temp) = {
+        let (glean, temp) = new_glean(None);
+        for (i, text) in texts.iter().enumerate() {
+            text.set_sync(&glean, value(i));
+        }
+        let client_id = clientid_metric().get_value(&glean, None).unwrap();
+        drop(glean);
+        (client_id, temp)
+    };
+
+    // Overwrite the page holding one of the values with garbage.
+    let path = temp.path().join("db").join("glean.sqlite");
+    let mut data = fs::read(&path).unwrap();
+    let needle = value(100);
+    let offset = data
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())
+        .unwrap();
+    let page_size = 4096;
+    let page = offset / page_size * page_size;
+    data[page..page + page_size].fill(0xAB);
+    fs::write(&path, data).unwrap();
+
+    (first_client_id, temp)
+}
+
+#[test]
+fn readable_rows_are_salvaged_from_a_corrupt_database() {
+    let (texts, value) = corruptible_texts();
+    let (first_client_id, temp) = write_corrupt_database();
+    let path = temp.path().join("db").join("glean.sqlite");
+
+    // Corrupt pages aren't checked for on every start, but during maintenance.
+    let (glean, temp) = new_glean(Some(temp));
+    assert_eq!(None, load_error_metric().get_value(&glean, None));
+    glean.run_database_maintenance();
+    assert!(path.with_file_name("glean.sqlite.check").exists());
+    drop(glean);
+
+    let (glean, _temp) = new_glean(Some(temp));
+    assert!(!path.with_file_name("glean.sqlite.check").exists());
+
+    let load_error = load_error_metric().get_value(&glean, None).unwrap();
+    assert_eq!("database file corrupt", load_error);
+
+    assert_eq!(
+        Some(first_client_id),
+        clientid_metric().get_value(&glean, None)
+    );
+    assert_eq!(None, texts[100].get_value(&glean, Some("store1")));
+    let salvaged = texts
+        .iter()
+        .enumerate()
+        .filter(|(i, text)| text.get_value(&glean, Some("store1")) == Some(value(*i)))
+        .count();
+    assert!(salvaged > 150, "salvaged {salvaged}");
+
+    assert!(
+        database_counter("salvaged_rows")
+            .get_value(&glean, Some("metrics"))
+            .unwrap()
+            > 150
+    );
+    assert!(
+        database_counter("lost_rows")
+            .get_value(&glean, Some("metrics"))
+            .unwrap()
+            >= 1
+    );
+    // The corrupt database is removed after salvaging.
+    assert!(!path.with_file_name("glean.sqlite.corrupt").exists());
+}
+
+#[test]
+fn corrupt_databases_are_recovered_after_a_read_error() {
+    let (texts, _) = corruptible_texts();
+    let (_, temp) = write_corrupt_database();
+    let path = temp.path().join("db").join("glean.sqlite");
+
+    let (glean, temp) = new_glean(Some(temp));
+    assert!(!path.with_file_name("glean.sqlite.check").exists());
+    assert_eq!(None, texts[100].get_value(&glean, Some("store1")));
+    assert!(path.with_file_name("glean.sqlite.check").exists());
+    drop(glean);
+
+    let (glean, _temp) = new_glean(Some(temp));
+    let load_error = load_error_metric().get_value(&glean, None).unwrap();
+    assert_eq!("database file corrupt", load_error);
+    assert!(
+        database_counter("salvaged_rows")
+            .get_value(&glean, Some("metrics"))
+            .unwrap()
+            > 150
+    );
+}
+
+/// The number of unused pages in the database file, bypassing Glean.
+fn freelist_count(temp: &tempfile::TempDir) -> i64 {
+    let path = temp.path().join("db").join("glean.sqlite");
+    let conn = rusqlite::Connection::open(path).unwrap();
+    conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[test]
+fn database_maintenance_runs_when_the_client_becomes_inactive() {
+    let (mut glean, temp) = new_glean(None);
+    let texts: Vec<_> = (0..50)
+        .map(|i| text_metric(&format!("text{i}"), Lifetime::User))
+        .collect();
+    for text in &texts {
+        text.set_sync(&glean, "x".repeat(5_000));
+    }
+    glean.test_clear_all_stores();
+    assert!(freelist_count(&temp) > 0);
+
+    glean.handle_client_inactive();
+    assert_eq!(0, freelist_count(&temp));
+
+    let duration = TimingDistributionMetric::new(
+        CommonMetricData {
+            name: "maintenance_duration".into(),
+            category: "glean.database".into(),
+            send_in_pings: vec!["metrics".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        TimeUnit::Millisecond,
+    );
+    assert_eq!(1, duration.get_value(&glean, "metrics").unwrap().count);
+
+    let reclaimed = MemoryDistributionMetric::new(
+        CommonMetricData {
+            name: "maintenance_reclaimed".into(),
+            category: "glean.database".into(),
+            send_in_pings: vec!["metrics".into()],
+            lifetime: Lifetime::Ping,
+            ..Default::default()
+        },
+        MemoryUnit::Byte,
+    );
+    assert!(reclaimed.get_value(&glean, "metrics").unwrap().sum > 0);
+}
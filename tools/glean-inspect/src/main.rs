@@ -0,0 +1,305 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Inspect the data directory of a Glean-instrumented application.
+//!
+//! Opens the data read-only: it's safe to run against the data directory of a running application.
+//!
+//! ```text
+//! glean-inspect [--json] [--key-file <path>] <data-path> [<command> [<store>]]
+//! ```
+//!
+//! Commands: `stores`, `metrics [<store>]`, `events [<store>]`, `pings`, `session`, `experiments`
+//! and `all` (the default).
+
+use std::env;
+use std::fs;
+use std::process;
+
+use glean_core::EncryptionKeyProvider;
+use glean_core::inspect::{Inspector, PendingPing, SessionState, StoredEventRecord, StoredMetric};
+use glean_core::metrics::RecordedExperiment;
+use serde_json::{Value as JsonValue, json};
+
+const USAGE: &str = "\
+Usage: glean-inspect [--json] [--key-file <path>] <data-path> [<command> [<store>]]
+
+Commands:
+    stores              List all stores with metrics or events
+    metrics [<store>]   List stored metrics, grouped by lifetime
+    events [<store>]    List events waiting to be sent
+    pings               List pings waiting to be uploaded
+    session             Show the current session state
+    experiments         List active experiments
+    all                 All of the above (default)
+
+Options:
+    --json              Output JSON
+    --key-file <path>   Read the key for encrypted data from a file";
+
+struct KeyFile(Vec<u8>);
+
+impl EncryptionKeyProvider for KeyFile {
+    fn get_key(&self) -> Option<Vec<u8>> {
+        Some(self.0.clone())
+    }
+}
+
+fn exit_with(msg: &str) -> ! {
+    eprintln!("{msg}");
+    process::exit(1);
+}
+
+fn main() {
+    let mut json_output = false;
+    let mut key = None;
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json_output = true,
+            "--key-file" => {
+                let Some(path) = args.next() else {
+                    exit_with(USAGE);
+                };
+                match fs::read(&path) {
+                    Ok(data) => key = Some(KeyFile(data)),
+                    Err(e) => exit_with(&format!("Can't read key file {path}: {e}")),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let Some(data_path) = positional.next() else {
+        exit_with(USAGE);
+    };
+    let command = positional.next().unwrap_or_else(|| "all".to_string());
+    let store = positional.next();
+
+    let inspector = match Inspector::open(
+        &data_path,
+        key.as_ref().map(|k| k as &dyn EncryptionKeyProvider),
+    ) {
+        Ok(inspector) => inspector,
+        Err(e) => exit_with(&format!("Can't open {data_path}: {e}")),
+    };
+
+    let result = match command.as_str() {
+        "stores" => inspector.stores().map(|stores| {
+            if json_output {
+                println!("{}", json!(stores));
+            } else {
+                stores.iter().for_each(|store| println!("{store}"));
+            }
+        }),
+        "metrics" => inspector.metrics(store.as_deref()).map(|metrics| {
+            output(json_output, metrics_json(&metrics), || {
+                print_metrics(&metrics)
+            })
+        }),
+        "events" => inspector
+            .events(store.as_deref())
+            .map(|events| output(json_output, events_json(&events), || print_events(&events))),
+        "pings" => inspector
+            .pending_pings()
+            .map(|pings| output(json_output, pings_json(&pings), || print_pings(&pings))),
+        "session" => inspector.session().map(|session| {
+            output(json_output, session_json(&session), || {
+                print_session(&session)
+            })
+        }),
+        "experiments" => inspector.experiments().map(|experiments| {
+            output(json_output, experiments_json(&experiments), || {
+                print_experiments(&experiments)
+            })
+        }),
+        "all" => all(&inspector, json_output),
+        _ => exit_with(USAGE),
+    };
+
+    if let Err(e) = result {
+        exit_with(&format!("Can't read {data_path}: {e}"));
+    }
+}
+
+fn output(json_output: bool, json: JsonValue, print: impl FnOnce()) {
+    if json_output {
+        println!("{json:#}");
+    } else {
+        print();
+    }
+}
+
+fn all(inspector: &Inspector, json_output: bool) -> glean_core::Result<()> {
+    let metrics = inspector.metrics(None)?;
+    let events = inspector.events(None)?;
+    let pings = inspector.pending_pings()?;
+    let session = inspector.session()?;
+    let experiments = inspector.experiments()?;
+
+    if json_output {
+        let all = json!({
+            "schema_version": inspector.schema_version(),
+            "stores": inspector.stores()?,
+            "metrics": metrics_json(&metrics),
+            "events": events_json(&events),
+            "pending_pings": pings_json(&pings),
+            "session": session_json(&session),
+            "experiments": experiments_json(&experiments),
+        });
+        println!("{all:#}");
+        return Ok(());
+    }
+
+    println!("Schema version: {}", inspector.schema_version());
+    println!("\n# Metrics\n");
+    print_metrics(&metrics);
+    println!("\n# Events\n");
+    print_events(&events);
+    println!("\n# Pending pings\n");
+    print_pings(&pings);
+    println!("\n# Session\n");
+    print_session(&session);
+    println!("\n# Experiments\n");
+    print_experiments(&experiments);
+    Ok(())
+}
+
+fn metric_name(metric: &StoredMetric) -> String {
+    match &metric.labels[..] {
+        [] => metric.id.clone(),
+        labels => format!("{}[{}]", metric.id, labels.join("][")),
+    }
+}
+
+fn metrics_json(metrics: &[StoredMetric]) -> JsonValue {
+    metrics
+        .iter()
+        .map(|metric| {
+            json!({
+                "lifetime": metric.lifetime.as_str(),
+                "store": metric.store,
+                "id": metric.id,
+                "labels": metric.labels,
+                "type": metric.value.ping_section(),
+                "value": metric.value.as_json(),
+            })
+        })
+        .collect()
+}
+
+fn print_metrics(metrics: &[StoredMetric]) {
+    for metric in metrics {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            metric.lifetime.as_str(),
+            metric.store,
+            metric_name(metric),
+            metric.value.ping_section(),
+            metric.value.as_json()
+        );
+    }
+}
+
+fn events_json(events: &[StoredEventRecord]) -> JsonValue {
+    events
+        .iter()
+        .map(|event| {
+            json!({
+                "store": event.store,
+                "event": event.event,
+                "execution_counter": event.execution_counter,
+                "pending_import": event.pending_import,
+            })
+        })
+        .collect()
+}
+
+fn print_events(events: &[StoredEventRecord]) {
+    for event in events {
+        let extra = event
+            .event
+            .extra
+            .as_ref()
+            .map(|extra| json!(extra).to_string())
+            .unwrap_or_default();
+        println!(
+            "{}\t{}\t{}.{}\t{}{}",
+            event.store,
+            event.event.timestamp,
+            event.event.category,
+            event.event.name,
+            extra,
+            if event.pending_import {
+                "\t(pending import)"
+            } else {
+                ""
+            }
+        );
+    }
+}
+
+fn pings_json(pings: &[PendingPing]) -> JsonValue {
+    pings
+        .iter()
+        .map(|ping| {
+            json!({
+                "document_id": ping.document_id,
+                "deletion_request": ping.deletion_request,
+                "path": ping.path,
+                "body": ping.body,
+                "metadata": ping.metadata,
+            })
+        })
+        .collect()
+}
+
+fn print_pings(pings: &[PendingPing]) {
+    for ping in pings {
+        println!("{}\t{}", ping.document_id, ping.path);
+    }
+}
+
+fn session_json(session: &SessionState) -> JsonValue {
+    json!({
+        "seq": session.seq,
+        "id": session.id,
+        "start_time": session.start_time,
+        "inactive_since": session.inactive_since,
+        "event_seq": session.event_seq,
+    })
+}
+
+fn print_session(session: &SessionState) {
+    let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    println!("seq\t{}", show(session.seq.map(|s| s.to_string())));
+    println!("id\t{}", show(session.id.clone()));
+    println!("start_time\t{}", show(session.start_time.clone()));
+    println!("inactive_since\t{}", show(session.inactive_since.clone()));
+    println!(
+        "event_seq\t{}",
+        show(session.event_seq.map(|s| s.to_string()))
+    );
+}
+
+fn experiments_json(experiments: &[(String, RecordedExperiment)]) -> JsonValue {
+    experiments
+        .iter()
+        .map(|(id, experiment)| (id.clone(), experiment.as_json()))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn print_experiments(experiments: &[(String, RecordedExperiment)]) {
+    for (id, experiment) in experiments {
+        println!("{id}\t{}", experiment.branch);
+    }
+}